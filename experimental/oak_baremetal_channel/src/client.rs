@@ -20,8 +20,13 @@ use crate::{
     message::{InvocationId, RequestMessage, ResponseMessage},
     Channel, InvocationChannel,
 };
-use alloc::{boxed::Box, string::String, vec::Vec};
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec::Vec};
 
+/// Client side of a channel, which writes request messages and reads response messages.
+///
+/// Responses are read in the order they arrive, which may differ from the order of the requests,
+/// and can be matched to their invocations with [`PendingInvocations`]. Requests can be written
+/// while responses are read by using separate handles for each direction of the channel.
 pub struct ClientChannelHandle {
     inner: InvocationChannel,
 }
@@ -35,26 +40,84 @@ impl ClientChannelHandle {
     pub fn write_request(&mut self, request: RequestMessage) -> anyhow::Result<()> {
         self.inner.write_message(request)
     }
+    /// Queues the request to be written frame by frame with
+    /// [`ClientChannelHandle::write_next_frame`], interleaved with the frames of other queued
+    /// requests, and returns its invocation id.
+    pub fn queue_request(&mut self, request: RequestMessage) -> anyhow::Result<InvocationId> {
+        self.inner.queue_message(request)
+    }
+    pub fn write_next_frame(&mut self) -> anyhow::Result<()> {
+        self.inner.write_next_frame()
+    }
+    /// Returns whether frames of the request of the given invocation are waiting to be written.
+    pub fn is_queued(&self, invocation_id: InvocationId) -> bool {
+        self.inner.is_queued(invocation_id)
+    }
     pub fn read_response(&mut self) -> anyhow::Result<ResponseMessage> {
         let message = self.inner.read_message()?;
         Ok(message)
     }
 }
 
-#[derive(Default)]
-pub struct RequestEncoder {
+/// Invocations in flight on a channel, keyed by their [`InvocationId`].
+///
+/// Requests may be sent before the responses to preceding requests are received, and the service
+/// may respond to them out of order. Each invocation is tracked with a responder provided by the
+/// caller (e.g. the sending half of a channel to the invoking thread), which is returned when the
+/// response that concludes the invocation arrives.
+pub struct PendingInvocations<T> {
     invocation_id_counter: InvocationIdCounter,
+    responders: BTreeMap<InvocationId, T>,
+}
+
+impl<T> Default for PendingInvocations<T> {
+    fn default() -> Self {
+        Self {
+            invocation_id_counter: InvocationIdCounter::default(),
+            responders: BTreeMap::new(),
+        }
+    }
 }
 
-impl RequestEncoder {
-    pub fn encode_request(&mut self, request: oak_idl::Request) -> RequestMessage {
-        let invocation_id = self.invocation_id_counter.next_invocation_id();
+impl<T> PendingInvocations<T> {
+    /// Starts tracking a new invocation, and returns the request message that initiates it.
+    pub fn start(&mut self, request: oak_idl::Request, responder: T) -> RequestMessage {
+        // Skip the ids of invocations that are still in flight after the counter wrapped around.
+        let invocation_id = loop {
+            let invocation_id = self.invocation_id_counter.next_invocation_id();
+            if !self.responders.contains_key(&invocation_id) {
+                break invocation_id;
+            }
+        };
+        self.responders.insert(invocation_id, responder);
         RequestMessage {
             invocation_id,
             method_id: request.method_id,
             body: request.body.to_vec(),
         }
     }
+
+    /// Stops tracking the invocation concluded by the given response message, and returns its
+    /// responder.
+    pub fn complete(&mut self, response: &ResponseMessage) -> anyhow::Result<T> {
+        self.responders
+            .remove(&response.invocation_id)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "received a response for an unknown invocation: {}",
+                    response.invocation_id
+                )
+            })
+    }
+
+    /// Returns the number of invocations in flight.
+    pub fn len(&self) -> usize {
+        self.responders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.responders.is_empty()
+    }
 }
 
 #[derive(Default)]
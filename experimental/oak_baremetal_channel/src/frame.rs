@@ -19,10 +19,17 @@
 extern crate alloc;
 
 use crate::Channel;
-use alloc::{boxed::Box, format, vec, vec::Vec};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    format, vec,
+    vec::Vec,
+};
 use bitflags::bitflags;
 
-pub const PADDING_SIZE: usize = 4;
+pub type StreamId = u32;
+pub const STREAM_ID_SIZE: usize = 4;
+static_assertions::assert_eq_size!([u8; STREAM_ID_SIZE], StreamId);
 
 type Length = u16;
 pub const LENGTH_SIZE: usize = 2;
@@ -41,16 +48,20 @@ static_assertions::assert_eq_size!([u8; FLAGS_SIZE], Flags);
 pub const BODY_OFFSET: usize = 8;
 static_assertions::assert_eq_size!(
     [u8; BODY_OFFSET],
-    [u8; PADDING_SIZE + LENGTH_SIZE + FLAGS_SIZE]
+    [u8; STREAM_ID_SIZE + LENGTH_SIZE + FLAGS_SIZE]
 );
 
 pub const MAX_SIZE: usize = 4000;
 pub const MAX_BODY_SIZE: usize = MAX_SIZE - BODY_OFFSET;
 
+/// Maximum total size of the messages whose frames have only partially been received.
+pub const MAX_REASSEMBLY_SIZE: usize = 64 * 1024 * 1024;
+
 /// Rust implementation of the Frame structure defined in
 /// /experimental/oak_baremetal_channel/SPEC.MD
 #[derive(Clone, Default, Debug)]
 pub struct Frame {
+    pub stream_id: StreamId,
     pub flags: Flags,
     pub body: Vec<u8>,
 }
@@ -61,7 +72,7 @@ impl TryFrom<Frame> for Vec<u8> {
         let length = BODY_OFFSET + frame.body.len();
         let mut frame_bytes: Vec<u8> = Vec::with_capacity(length);
 
-        frame_bytes.extend_from_slice(&[0; PADDING_SIZE]);
+        frame_bytes.extend_from_slice(&frame.stream_id.to_le_bytes());
         {
             let frame_length = Length::try_from(length).map_err(|_error| {
                 anyhow::Error::msg(format!(
@@ -88,9 +99,10 @@ impl Framed {
     }
 
     pub fn read_frame(&mut self) -> anyhow::Result<Frame> {
-        {
-            let mut padding_bytes = [0; PADDING_SIZE];
-            self.inner.read(&mut padding_bytes)?;
+        let stream_id = {
+            let mut stream_id_bytes = [0; STREAM_ID_SIZE];
+            self.inner.read(&mut stream_id_bytes)?;
+            StreamId::from_le_bytes(stream_id_bytes)
         };
         let length: usize = {
             let mut length_bytes = [0; LENGTH_SIZE];
@@ -114,7 +126,11 @@ impl Framed {
             body
         };
 
-        Ok(Frame { flags, body })
+        Ok(Frame {
+            stream_id,
+            flags,
+            body,
+        })
     }
 
     pub fn write_frame(&mut self, frame: Frame) -> anyhow::Result<()> {
@@ -125,7 +141,7 @@ impl Framed {
     }
 }
 
-pub fn bytes_into_frames(data: Vec<u8>) -> anyhow::Result<Vec<Frame>> {
+pub fn bytes_into_frames(stream_id: StreamId, data: Vec<u8>) -> anyhow::Result<Vec<Frame>> {
     if data.is_empty() {
         anyhow::bail!("cannot convert empty payloads into frames")
     }
@@ -137,6 +153,7 @@ pub fn bytes_into_frames(data: Vec<u8>) -> anyhow::Result<Vec<Frame>> {
         // slices and lifetimes. Or alternatively use the Bytes crate for
         // reference counting.
         .map(|frame_body| Frame {
+            stream_id,
             flags: Flags::default(),
             body: frame_body.to_vec(),
         })
@@ -156,3 +173,120 @@ pub fn bytes_into_frames(data: Vec<u8>) -> anyhow::Result<Vec<Frame>> {
 
     Ok(frames)
 }
+
+/// Frames of the messages waiting to be written.
+///
+/// Messages take turns: each frame returned by [`FrameScheduler::pop`] belongs to the message
+/// queued after the message of the preceding frame, so that a large message doesn't delay the
+/// messages queued after it until all of its frames are written.
+#[derive(Default)]
+pub struct FrameScheduler {
+    streams: VecDeque<VecDeque<Frame>>,
+}
+
+impl FrameScheduler {
+    /// Queues the frames of a message, as returned by [`bytes_into_frames`].
+    pub fn push(&mut self, frames: Vec<Frame>) -> anyhow::Result<()> {
+        let stream_id = match frames.first() {
+            Some(frame) => frame.stream_id,
+            None => anyhow::bail!("cannot queue a message without frames"),
+        };
+        if self.contains(stream_id) {
+            anyhow::bail!("a message of stream {} is already queued", stream_id);
+        }
+        self.streams.push_back(frames.into());
+        Ok(())
+    }
+
+    /// Returns the next frame to be written, if any.
+    pub fn pop(&mut self) -> Option<Frame> {
+        let mut frames = self.streams.pop_front()?;
+        let frame = frames.pop_front();
+        if !frames.is_empty() {
+            self.streams.push_back(frames);
+        }
+        frame
+    }
+
+    /// Returns whether frames of the given stream are waiting to be written.
+    pub fn contains(&self, stream_id: StreamId) -> bool {
+        self.streams
+            .iter()
+            .any(|frames| frames.front().map(|frame| frame.stream_id) == Some(stream_id))
+    }
+}
+
+/// Reassembles messages from frames, whose streams may be interleaved.
+///
+/// The total size of the messages that are only partially received is bounded, so that a peer
+/// can't exhaust the memory of the recipient by starting many messages without ending them.
+pub struct Reassembler {
+    partial_messages: BTreeMap<StreamId, Vec<u8>>,
+    partial_messages_size: usize,
+    max_partial_messages_size: usize,
+}
+
+impl Reassembler {
+    pub fn new(max_partial_messages_size: usize) -> Self {
+        Self {
+            partial_messages: BTreeMap::new(),
+            partial_messages_size: 0,
+            max_partial_messages_size,
+        }
+    }
+
+    /// Appends the body of the frame to the message of its stream, and returns the message if the
+    /// frame is its last one.
+    pub fn push(&mut self, mut frame: Frame) -> anyhow::Result<Option<Vec<u8>>> {
+        if frame.flags.contains(Flags::START) {
+            if self.partial_messages.contains_key(&frame.stream_id) {
+                anyhow::bail!(
+                    "received two frames with the START flag set for stream {}.",
+                    frame.stream_id
+                );
+            }
+            if frame.flags.contains(Flags::END) {
+                return Ok(Some(frame.body));
+            }
+            self.reserve(frame.body.len())?;
+            self.partial_messages.insert(frame.stream_id, frame.body);
+            return Ok(None);
+        }
+
+        if frame.flags.contains(Flags::END) {
+            let mut message = self
+                .partial_messages
+                .remove(&frame.stream_id)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "expected a frame with the START flag set for stream {}.",
+                        frame.stream_id
+                    )
+                })?;
+            self.partial_messages_size -= message.len();
+            message.append(&mut frame.body);
+            return Ok(Some(message));
+        }
+
+        if !self.partial_messages.contains_key(&frame.stream_id) {
+            anyhow::bail!(
+                "expected a frame with the START flag set for stream {}.",
+                frame.stream_id
+            );
+        }
+        self.reserve(frame.body.len())?;
+        self.partial_messages
+            .get_mut(&frame.stream_id)
+            .expect("partial message should exist")
+            .append(&mut frame.body);
+        Ok(None)
+    }
+
+    fn reserve(&mut self, size: usize) -> anyhow::Result<()> {
+        if self.partial_messages_size + size > self.max_partial_messages_size {
+            anyhow::bail!("partially received messages exceed the maximum reassembly size");
+        }
+        self.partial_messages_size += size;
+        Ok(())
+    }
+}
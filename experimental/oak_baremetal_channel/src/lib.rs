@@ -35,6 +35,8 @@ mod message;
 #[cfg(test)]
 mod tests;
 
+pub use message::{InvocationId, RequestMessage, ResponseMessage};
+
 extern crate alloc;
 use alloc::{boxed::Box, vec::Vec};
 use anyhow::Context;
@@ -52,72 +54,73 @@ pub trait Channel: Read + Write + Send + Sync {}
 
 impl<T: Read + Write + Send + Sync> Channel for T {}
 
+/// Sends and receives messages over a channel, as sequences of frames.
+///
+/// The frames of different messages are interleaved, with each message identified by the id of
+/// the invocation it belongs to, so that a large message doesn't delay the messages sent after it.
 struct InvocationChannel {
     inner: frame::Framed,
+    outgoing_frames: frame::FrameScheduler,
+    reassembler: frame::Reassembler,
 }
 
 impl InvocationChannel {
     pub fn new(socket: Box<dyn Channel>) -> Self {
+        Self::from_framed(frame::Framed::new(socket))
+    }
+
+    fn from_framed(inner: frame::Framed) -> Self {
         Self {
-            inner: frame::Framed::new(socket),
+            inner,
+            outgoing_frames: frame::FrameScheduler::default(),
+            reassembler: frame::Reassembler::new(frame::MAX_REASSEMBLY_SIZE),
         }
     }
 
+    /// Reads frames until a message is complete, and returns it. Frames of other messages that are
+    /// read in the meantime are buffered until their messages are complete.
     pub fn read_message<M: message::Message>(&mut self) -> anyhow::Result<M> {
-        let mut encoded_message: Vec<u8> = Vec::new();
-        let mut first_frame = self.inner.read_frame().context("failed to read frame.")?;
-
-        if first_frame.flags.contains(frame::Flags::START) {
-            if first_frame.flags.contains(frame::Flags::END) {
-                return Ok(M::decode(first_frame.body));
-            } else {
-                let required_additional_capacity_to_hold_message = {
-                    let message_length: usize = {
-                        let mut message_length_bytes: [u8; message::LENGTH_SIZE] =
-                            [0; message::LENGTH_SIZE];
-                        let message_length_offset = frame::BODY_OFFSET + message::LENGTH_OFFSET;
-                        let message_length_range =
-                            message_length_offset..(message_length_offset + message::LENGTH_SIZE);
-                        message_length_bytes
-                            .copy_from_slice(&first_frame.body[message_length_range]);
-                        usize::try_from(message::Length::from_le_bytes(message_length_bytes))
-                            .expect("couldn't convert message lemgth to usize")
-                    };
-                    message_length - encoded_message.capacity()
-                };
-                if required_additional_capacity_to_hold_message > 0 {
-                    encoded_message.reserve_exact(required_additional_capacity_to_hold_message);
-                }
-
-                encoded_message.append(&mut first_frame.body);
+        loop {
+            let frame = self.inner.read_frame().context("failed to read frame.")?;
+            if let Some(encoded_message) = self.reassembler.push(frame)? {
+                return Ok(M::decode(encoded_message));
             }
-        } else {
-            anyhow::bail!("expected a frame with the START flag set.");
         }
+    }
 
-        loop {
-            let mut frame = self.inner.read_frame().context("failed to read frame.")?;
-
-            if frame.flags.contains(frame::Flags::START) {
-                anyhow::bail!("received two frames with the START flag set.");
-            } else {
-                encoded_message.append(&mut frame.body);
-                if frame.flags.contains(frame::Flags::END) {
-                    break;
-                }
-            };
+    /// Writes the message, interleaving its frames with those of other queued messages.
+    pub fn write_message<M: message::Message>(&mut self, message: M) -> anyhow::Result<()> {
+        let invocation_id = self.queue_message(message)?;
+        while self.is_queued(invocation_id) {
+            self.write_next_frame()?;
         }
+        Ok(())
+    }
 
-        Ok(M::decode(encoded_message))
+    /// Queues the message to be written by [`InvocationChannel::write_next_frame`], and returns
+    /// the id of the invocation it belongs to.
+    pub fn queue_message<M: message::Message>(
+        &mut self,
+        message: M,
+    ) -> anyhow::Result<message::InvocationId> {
+        let invocation_id = message.invocation_id();
+        let frames = frame::bytes_into_frames(invocation_id, message.encode())?;
+        self.outgoing_frames.push(frames)?;
+        Ok(invocation_id)
     }
 
-    pub fn write_message<M: message::Message>(&mut self, message: M) -> anyhow::Result<()> {
-        let frames: Vec<frame::Frame> = frame::bytes_into_frames(message.encode())?;
-        for frame in frames.into_iter() {
+    /// Writes the next frame of the queued messages, if any.
+    pub fn write_next_frame(&mut self) -> anyhow::Result<()> {
+        if let Some(frame) = self.outgoing_frames.pop() {
             self.inner
                 .write_frame(frame)
                 .context("failed to write frame.")?
         }
         Ok(())
     }
+
+    /// Returns whether frames of the message of the given invocation are waiting to be written.
+    pub fn is_queued(&self, invocation_id: message::InvocationId) -> bool {
+        self.outgoing_frames.contains(invocation_id)
+    }
 }
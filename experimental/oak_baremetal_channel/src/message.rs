@@ -20,7 +20,6 @@ use alloc::vec::Vec;
 
 pub type Length = u32;
 pub const LENGTH_SIZE: usize = 4;
-pub const LENGTH_OFFSET: usize = 0;
 static_assertions::assert_eq_size!([u8; LENGTH_SIZE], Length);
 
 pub type InvocationId = u32;
@@ -43,6 +42,7 @@ pub const PADDING_SIZE: usize = 4;
 pub const BODY_OFFSET: usize = 16;
 
 pub trait Message {
+    fn invocation_id(&self) -> InvocationId;
     fn len(&self) -> usize;
     fn encode(self) -> Vec<u8>;
     fn decode(frames: Vec<u8>) -> Self;
@@ -58,6 +58,10 @@ pub struct RequestMessage {
 }
 
 impl Message for RequestMessage {
+    fn invocation_id(&self) -> InvocationId {
+        self.invocation_id
+    }
+
     fn len(&self) -> usize {
         LENGTH_SIZE + INVOCATION_ID_SIZE + METHOD_ID_SIZE + PADDING_SIZE + self.body.len()
     }
@@ -116,6 +120,10 @@ pub struct ResponseMessage {
 }
 
 impl Message for ResponseMessage {
+    fn invocation_id(&self) -> InvocationId {
+        self.invocation_id
+    }
+
     fn len(&self) -> usize {
         LENGTH_SIZE + INVOCATION_ID_SIZE + STATUS_CODE_SIZE + PADDING_SIZE + self.body.len()
     }
@@ -40,8 +40,9 @@ fn mock_payload() -> Vec<u8> {
 fn test_fragmenting_bytes_into_frames() {
     let payload = mock_payload();
 
-    let mut frames = frame::bytes_into_frames(payload.clone()).unwrap();
+    let mut frames = frame::bytes_into_frames(3, payload.clone()).unwrap();
     assert_eq!(frames.len(), BODY_LEN_MULTIPLIER);
+    assert!(frames.iter().all(|frame| frame.stream_id == 3));
 
     let mut reconstructed_payload: Vec<u8> = Vec::new();
     frames.iter_mut().for_each(|frame: &mut frame::Frame| {
@@ -120,7 +121,7 @@ fn test_invocation_channel_double_start_frame() {
             invocation_id: 0,
             body: mock_payload(),
         };
-        let start_frame = frame::bytes_into_frames(message.encode())
+        let start_frame = frame::bytes_into_frames(0, message.encode())
             .unwrap()
             .first()
             .unwrap()
@@ -128,7 +129,7 @@ fn test_invocation_channel_double_start_frame() {
         let mut frame_store = frame::Framed::new(Box::new(MessageStore::default()));
         frame_store.write_frame(start_frame.clone()).unwrap();
         frame_store.write_frame(start_frame).unwrap();
-        InvocationChannel { inner: frame_store }
+        InvocationChannel::from_framed(frame_store)
     };
 
     invocation_channel
@@ -144,17 +145,136 @@ fn test_invocation_channel_expected_start_frame() {
             invocation_id: 0,
             body: mock_payload(),
         };
-        let end_frame = frame::bytes_into_frames(message.encode())
+        let end_frame = frame::bytes_into_frames(0, message.encode())
             .unwrap()
             .last()
             .unwrap()
             .clone();
         let mut frame_store = frame::Framed::new(Box::new(MessageStore::default()));
         frame_store.write_frame(end_frame).unwrap();
-        InvocationChannel { inner: frame_store }
+        InvocationChannel::from_framed(frame_store)
     };
 
     invocation_channel
         .read_message::<message::RequestMessage>()
         .unwrap_err();
 }
+
+#[test]
+fn test_pipelined_invocations() {
+    let mut invocation_channel = InvocationChannel::new(Box::new(MessageStore::default()));
+    let mut pending_invocations = client::PendingInvocations::default();
+
+    // Send several requests before receiving any response.
+    let bodies: Vec<Vec<u8>> = vec![vec![1], mock_payload(), vec![3]];
+    for body in bodies.iter() {
+        let request = oak_idl::Request { method_id: 7, body };
+        let request_message = pending_invocations.start(request, body.clone());
+        invocation_channel.write_message(request_message).unwrap();
+    }
+    assert_eq!(pending_invocations.len(), bodies.len());
+
+    let request_messages: Vec<RequestMessage> = bodies
+        .iter()
+        .map(|_| invocation_channel.read_message().unwrap())
+        .collect();
+
+    // Respond out of order, echoing the body of each request.
+    for request_message in request_messages.into_iter().rev() {
+        let response_message = server::message_from_response_and_id(
+            Ok(request_message.body),
+            request_message.invocation_id,
+        );
+        invocation_channel.write_message(response_message).unwrap();
+    }
+
+    // Each response is matched to the invocation it concludes.
+    for _ in bodies.iter() {
+        let response_message: message::ResponseMessage = invocation_channel.read_message().unwrap();
+        let expected_body = pending_invocations.complete(&response_message).unwrap();
+        assert_eq!(response_message.body, expected_body);
+    }
+    assert!(pending_invocations.is_empty());
+
+    // Responses for invocations that are not in flight are rejected.
+    let response_message = server::message_from_response_and_id(Ok(vec![]), 0);
+    assert!(pending_invocations.complete(&response_message).is_err());
+}
+
+#[test]
+fn test_interleaved_messages() {
+    let mut invocation_channel = InvocationChannel::new(Box::new(MessageStore::default()));
+
+    let large_message = message::RequestMessage {
+        method_id: 0,
+        invocation_id: 1,
+        body: mock_payload(),
+    };
+    let small_message = message::RequestMessage {
+        method_id: 0,
+        invocation_id: 2,
+        body: vec![1, 2, 3],
+    };
+
+    // The small message is queued after the large one, but is written after its first frame.
+    assert_eq!(
+        invocation_channel
+            .queue_message(large_message.clone())
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        invocation_channel
+            .queue_message(small_message.clone())
+            .unwrap(),
+        2
+    );
+    invocation_channel.write_next_frame().unwrap();
+    invocation_channel.write_next_frame().unwrap();
+    assert!(invocation_channel.is_queued(1));
+    assert!(!invocation_channel.is_queued(2));
+    while invocation_channel.is_queued(1) {
+        invocation_channel.write_next_frame().unwrap();
+    }
+
+    let first_message: RequestMessage = invocation_channel.read_message().unwrap();
+    assert_eq!(first_message, small_message);
+    let second_message: RequestMessage = invocation_channel.read_message().unwrap();
+    assert_eq!(second_message, large_message);
+}
+
+#[test]
+fn test_frame_scheduler_takes_turns() {
+    let mut frame_scheduler = frame::FrameScheduler::default();
+    frame_scheduler
+        .push(frame::bytes_into_frames(1, vec![0; frame::MAX_BODY_SIZE * 3]).unwrap())
+        .unwrap();
+    frame_scheduler
+        .push(frame::bytes_into_frames(2, vec![0; frame::MAX_BODY_SIZE * 2]).unwrap())
+        .unwrap();
+    // Only one message per stream may be queued at a time.
+    frame_scheduler
+        .push(frame::bytes_into_frames(2, vec![0]).unwrap())
+        .unwrap_err();
+
+    let stream_ids: Vec<frame::StreamId> =
+        core::iter::from_fn(|| frame_scheduler.pop().map(|frame| frame.stream_id)).collect();
+    assert_eq!(stream_ids, vec![1, 2, 1, 2, 1]);
+}
+
+#[test]
+fn test_reassembly_size_limit() {
+    let frames = frame::bytes_into_frames(0, mock_payload()).unwrap();
+    let mut reassembler = frame::Reassembler::new(frame::MAX_BODY_SIZE * 2);
+
+    assert!(reassembler.push(frames[0].clone()).unwrap().is_none());
+    assert!(reassembler.push(frames[1].clone()).unwrap().is_none());
+    reassembler.push(frames[2].clone()).unwrap_err();
+
+    // Messages sent as a single frame are not buffered.
+    let frames = frame::bytes_into_frames(1, vec![1, 2, 3]).unwrap();
+    assert_eq!(
+        reassembler.push(frames[0].clone()).unwrap(),
+        Some(vec![1, 2, 3])
+    );
+}
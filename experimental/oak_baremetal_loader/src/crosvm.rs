@@ -108,3 +108,9 @@ impl Vmm for Crosvm {
         timeout(Duration::from_millis(1000), task).await?
     }
 }
+
+impl ReadWrite for VsockStream {
+    fn try_clone_box(&self) -> std::io::Result<Box<dyn ReadWrite>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+}
@@ -19,8 +19,8 @@
 use clap::Parser;
 use crosvm::Crosvm;
 use oak_baremetal_communication_channel::{
-    client::{ClientChannelHandle, RequestEncoder},
-    schema,
+    client::{ClientChannelHandle, PendingInvocations},
+    schema, RequestMessage, ResponseMessage,
 };
 use qemu::Qemu;
 use std::{
@@ -28,6 +28,7 @@ use std::{
     io::{BufRead, BufReader, Read, Write},
     os::unix::net::UnixStream,
     path::PathBuf,
+    sync::{mpsc, Arc, Mutex},
 };
 use tokio::signal;
 use vmm::{Params, Vmm};
@@ -37,6 +38,10 @@ mod qemu;
 mod server;
 mod vmm;
 
+/// The maximum number of user requests in flight on the comms channel at once, each of which
+/// occupies a blocking thread until its response arrives.
+const MAX_IN_FLIGHT_REQUESTS: usize = 64;
+
 #[derive(clap::ArgEnum, Clone, Debug, PartialEq)]
 enum Mode {
     Qemu,
@@ -70,9 +75,17 @@ fn path_exists(s: &str) -> Result<(), String> {
     }
 }
 
-pub trait ReadWrite: Read + Write + Send + Sync {}
+pub trait ReadWrite: Read + Write + Send + Sync {
+    /// Creates a new handle to the same stream, so that one handle can be read from while the
+    /// other one is written to.
+    fn try_clone_box(&self) -> std::io::Result<Box<dyn ReadWrite>>;
+}
 
-impl<T> ReadWrite for T where T: Read + Write + Send + Sync {}
+impl ReadWrite for UnixStream {
+    fn try_clone_box(&self) -> std::io::Result<Box<dyn ReadWrite>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+}
 
 struct CommsChannel {
     inner: Box<dyn ReadWrite>,
@@ -94,45 +107,151 @@ impl oak_baremetal_communication_channel::Read for CommsChannel {
     }
 }
 
+/// Invocations in flight, each with the sender that its response is handed to.
+type Invocations = PendingInvocations<mpsc::SyncSender<ResponseMessage>>;
+
+/// Invokes methods of the runtime over the comms channel.
+///
+/// Many invocations may be in flight at once: the request of an invocation is written as soon as
+/// the method is invoked, and a separate thread reads the responses as they arrive, in any order,
+/// and hands each of them to the invocation it concludes. Clones of the handler share the same
+/// channel, so that methods can be invoked concurrently from different threads.
+#[derive(Clone)]
 pub struct ClientHandler {
-    inner: ClientChannelHandle,
-    request_encoder: RequestEncoder,
+    request_writer: Arc<Mutex<ClientChannelHandle>>,
+    /// Invocations in flight, or `None` once the channel failed.
+    invocations: Arc<Mutex<Option<Invocations>>>,
 }
 
 impl ClientHandler {
-    pub fn new(inner: Box<dyn oak_baremetal_communication_channel::Channel>) -> Self {
+    /// Creates a handler that writes requests to `request_channel`, and reads responses from
+    /// `response_channel`, which must be handles to the same underlying stream.
+    pub fn new(
+        request_channel: Box<dyn oak_baremetal_communication_channel::Channel>,
+        response_channel: Box<dyn oak_baremetal_communication_channel::Channel>,
+    ) -> Self {
+        let invocations = Arc::new(Mutex::new(Some(Invocations::default())));
+        let response_reader = ClientChannelHandle::new(response_channel);
+        let reader_invocations = invocations.clone();
+        std::thread::spawn(move || read_responses(response_reader, reader_invocations));
         Self {
-            inner: ClientChannelHandle::new(inner),
-            request_encoder: RequestEncoder::default(),
+            request_writer: Arc::new(Mutex::new(ClientChannelHandle::new(request_channel))),
+            invocations,
+        }
+    }
+
+    /// Writes the request frame by frame. The request writer is released after every frame, so
+    /// that the frames of requests written concurrently are interleaved, rather than a large
+    /// request (e.g. a lookup data update) delaying all others until it is written.
+    fn write_request(&self, request_message: RequestMessage) -> anyhow::Result<()> {
+        let invocation_id = self
+            .request_writer
+            .lock()
+            .expect("couldn't lock request writer mutex")
+            .queue_request(request_message)?;
+        loop {
+            let mut request_writer = self
+                .request_writer
+                .lock()
+                .expect("couldn't lock request writer mutex");
+            // The frames of this request may also have been written by other invocations, while
+            // writing the frames of their own requests.
+            if !request_writer.is_queued(invocation_id) {
+                return Ok(());
+            }
+            request_writer.write_next_frame()?;
         }
     }
 }
 
+/// Reads responses from the channel, and hands each of them to the invocation it concludes.
+///
+/// If the channel fails, all invocations in flight fail, as do all later invocations.
+fn read_responses(
+    mut response_reader: ClientChannelHandle,
+    invocations: Arc<Mutex<Option<Invocations>>>,
+) {
+    let error = loop {
+        let response_message = match response_reader.read_response() {
+            Ok(response_message) => response_message,
+            Err(error) => break error,
+        };
+        let responder = match invocations
+            .lock()
+            .expect("couldn't lock invocations mutex")
+            .as_mut()
+        {
+            Some(invocations) => invocations.complete(&response_message),
+            // The request writer failed, and the channel is no longer used.
+            None => return,
+        };
+        match responder {
+            // The invocation fails if its caller went away, so the response can be ignored.
+            Ok(responder) => {
+                let _ = responder.send(response_message);
+            }
+            Err(error) => break error,
+        }
+    };
+    log::error!("failed to read response from the runtime: {:?}", error);
+    // Dropping the responders fails the invocations in flight.
+    invocations
+        .lock()
+        .expect("couldn't lock invocations mutex")
+        .take();
+}
+
 impl oak_idl::Handler for ClientHandler {
     fn invoke(&mut self, request: oak_idl::Request) -> Result<Vec<u8>, oak_idl::Status> {
-        let request_message = self.request_encoder.encode_request(request);
-        let request_message_invocation_id = request_message.invocation_id;
-        self.inner
-            .write_request(request_message)
-            .map_err(|_| oak_idl::Status::new(oak_idl::StatusCode::Internal))?;
+        let (response_sender, response_receiver) = mpsc::sync_channel(1);
+        // The invocation is tracked before its request is written, so that its response can be
+        // handed to it whenever it arrives.
+        let request_message = self
+            .invocations
+            .lock()
+            .expect("couldn't lock invocations mutex")
+            .as_mut()
+            .ok_or_else(|| oak_idl::Status::new(oak_idl::StatusCode::Internal))?
+            .start(request, response_sender);
+
+        if self.write_request(request_message).is_err() {
+            // A partially written request corrupts the channel for all invocations, so they all
+            // fail.
+            self.invocations
+                .lock()
+                .expect("couldn't lock invocations mutex")
+                .take();
+            return Err(oak_idl::Status::new(oak_idl::StatusCode::Internal));
+        }
 
-        let response_message = self
-            .inner
-            .read_response()
+        let response_message = response_receiver
+            .recv()
             .map_err(|_| oak_idl::Status::new(oak_idl::StatusCode::Internal))?;
 
-        // For now all messages are sent in sequence, hence we expect that the
-        // id of the next response matches the preceeding request.
-        // TODO(#2848): Allow messages to be sent and received out of order.
-        assert_eq!(
-            request_message_invocation_id,
-            response_message.invocation_id
-        );
-
         response_message.into()
     }
 }
 
+/// Handles a user request, and returns the body of the response.
+fn handle_user_request(
+    client: &mut schema::TrustedRuntimeClient<ClientHandler>,
+    input: Vec<u8>,
+) -> Result<Vec<u8>, oak_idl::Status> {
+    let request_message =
+        oak_idl::utils::Message::<schema::UserRequest>::from_vec(input).map_err(|err| {
+            oak_idl::Status::new_with_message(oak_idl::StatusCode::Internal, err.to_string())
+        })?;
+
+    let response_message = client.handle_user_request(request_message.buf())?;
+
+    let response_body = response_message
+        .get()
+        .body()
+        .ok_or_else(|| oak_idl::Status::new(oak_idl::StatusCode::Internal))?;
+
+    Ok(response_body.to_vec())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Args::parse();
@@ -165,18 +284,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 
     let comms = vmm.create_comms_channel().await?;
-
-    // Use a bmrng channel for serial communication. The good thing about spawning a separate
-    // task for the serial communication is that it serializes (no pun intended) the communication,
-    // as right now we don't have any mechanisms to track multiple requests in flight.
+    let client_handler = ClientHandler::new(
+        Box::new(CommsChannel {
+            inner: comms.try_clone_box()?,
+        }),
+        Box::new(CommsChannel { inner: comms }),
+    );
+
+    // Use a bmrng channel to pass user requests from the server to the runtime.
     let (tx, mut rx) = bmrng::unbounded_channel::<Vec<u8>, Result<Vec<u8>, oak_idl::Status>>();
 
     tokio::spawn(async move {
-        let mut client = {
-            let comms_channel = Box::new(CommsChannel { inner: comms });
-            let client_handler = ClientHandler::new(comms_channel);
-            schema::TrustedRuntimeClient::new(client_handler)
-        };
+        let mut client = schema::TrustedRuntimeClient::new(client_handler.clone());
 
         let lookup_data = {
             let mut builder = oak_idl::utils::MessageBuilder::default();
@@ -223,25 +342,28 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             panic!("failed to initialize the runtime: {:?}", err)
         }
 
-        let mut respond = |input: Vec<u8>| -> Result<Vec<u8>, oak_idl::Status> {
-            let request_message = oak_idl::utils::Message::<schema::UserRequest>::from_vec(input)
-                .map_err(|err| {
-                oak_idl::Status::new_with_message(oak_idl::StatusCode::Internal, err.to_string())
-            })?;
-
-            let response_message = client.handle_user_request(request_message.buf())?;
-
-            let response_body = response_message
-                .get()
-                .body()
-                .ok_or_else(|| oak_idl::Status::new(oak_idl::StatusCode::Internal))?;
-
-            Ok(response_body.to_vec())
-        };
-
-        while let Ok((input, responder)) = rx.recv().await {
-            let response = respond(input);
-            responder.respond(response).unwrap();
+        // User requests are handled concurrently, each on a blocking thread that waits for its
+        // response, so that they are all in flight on the comms channel at once. At most
+        // `MAX_IN_FLIGHT_REQUESTS` are handled at a time: once the limit is reached, no further
+        // request is received until one of them has been responded to.
+        let in_flight = Arc::new(tokio::sync::Semaphore::new(MAX_IN_FLIGHT_REQUESTS));
+        loop {
+            let permit = in_flight
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("couldn't acquire in-flight request permit");
+            let (input, responder) = match rx.recv().await {
+                Ok(request) => request,
+                Err(_) => break,
+            };
+            let mut client = schema::TrustedRuntimeClient::new(client_handler.clone());
+            tokio::task::spawn_blocking(move || {
+                let response = handle_user_request(&mut client, input);
+                responder.respond(response).unwrap();
+                // Frees the slot of this request only once it has been responded to.
+                drop(permit);
+            });
         }
     });
 
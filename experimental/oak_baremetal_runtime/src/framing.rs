@@ -19,7 +19,7 @@ use crate::{
     remote_attestation::{AttestationHandler, AttestationSessionHandler},
     wasm,
 };
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{boxed::Box, format, sync::Arc};
 use anyhow::Context;
 use oak_baremetal_communication_channel::{
     schema,
@@ -27,7 +27,7 @@ use oak_baremetal_communication_channel::{
     server::{message_from_response_and_id, ServerChannelHandle},
     Channel,
 };
-use oak_functions_abi::Request;
+use oak_functions_abi::{Request, Response, StatusCode};
 use oak_functions_lookup::LookupDataManager;
 use oak_idl::Handler;
 use oak_remote_attestation::handshaker::{
@@ -85,11 +85,15 @@ where
                         .map_err(|_err| oak_idl::Status::new(oak_idl::StatusCode::Internal))?;
                 let attestation_handler = Box::new(AttestationSessionHandler::create(
                     move |decrypted_request| {
-                        wasm_handler
-                            .handle_invoke(Request {
-                                body: decrypted_request,
-                            })
-                            .map(|decrypted_response| decrypted_response.encode_to_vec())
+                        // Removes the padding of the request, if any.
+                        let response = match Request::decode(&decrypted_request) {
+                            Ok(request) => wasm_handler.handle_invoke(request)?,
+                            Err(err) => Response::create(
+                                StatusCode::BadRequest,
+                                format!("Reason: {}", err).as_bytes().to_vec(),
+                            ),
+                        };
+                        Ok(response.encode_to_vec())
                     },
                     attestation_behavior,
                 ));
@@ -199,9 +203,9 @@ pub fn handle_frames<G: 'static + AttestationGenerator, V: 'static + Attestation
             .context("couldn't receive message")?;
         let request_message_invocation_id = request_message.invocation_id;
         let response = invocation_handler.invoke((&request_message).into());
-        // For now all messages are sent in sequence, hence the id of the next
-        // response always matches that of the preceeding request.
-        // TODO(#2848): Allow messages to be sent and received out of order.
+        // Requests are handled in the order they are received. The client may
+        // have further invocations in flight, and matches each response to
+        // its invocation by the invocation id.
         let response_message =
             message_from_response_and_id(response, request_message_invocation_id);
         channel_handle.write_response(response_message)?
@@ -62,14 +62,13 @@ where
     V: AttestationVerifier,
 {
     fn message(&mut self, session_id: SessionId, body: &[u8]) -> anyhow::Result<Vec<u8>> {
-        let mut session_state = {
-            self.session_tracker
-                .pop_or_create_session_state(session_id)
-                .expect("Couldn't pop session state")
-        };
+        let session_state = self
+            .session_tracker
+            .get_or_create_session_state(session_id)
+            .expect("Couldn't get session state");
         let response_body = match session_state {
-            SessionState::HandshakeInProgress(ref mut handshaker) => {
-                handshaker
+            SessionState::HandshakeInProgress(mut handshaker) => {
+                let response_body = handshaker
                     .next_step(body)
                     .context("Couldn't process handshake message")?
                     // After receiving a valid `ClientIdentity` message
@@ -78,24 +77,26 @@ where
                     // request we do want to send an explicit confirmation in
                     // the form of a status message. Hence in case of `None`
                     // fallback to a default (empty) response.
-                    .unwrap_or_default()
+                    .unwrap_or_default();
+                self.session_tracker
+                    .put_handshake_state(session_id, handshaker);
+                response_body
             }
-            SessionState::EncryptedMessageExchange(ref mut encryptor) => {
+            SessionState::EncryptedMessageExchange(encryptor) => {
                 let decrypted_request = encryptor
+                    .lock()
                     .decrypt(body)
                     .context("Couldn't decrypt response")?;
 
                 let response = (self.request_handler.clone())(decrypted_request)?;
 
                 encryptor
+                    .lock()
                     .encrypt(&response)
                     .context("Couldn't encrypt response")?
             }
         };
 
-        self.session_tracker
-            .put_session_state(session_id, session_state);
-
         Ok(response_body)
     }
 }
@@ -23,7 +23,7 @@ use clap::Parser;
 use log::{debug, info, trace};
 use maplit::hashmap;
 use oak_functions_abi::Request;
-use oak_functions_client::Client;
+use oak_functions_client::{Client, ClientConfig};
 use prost::Message;
 use proto::{
     oak::encap::GrpcRequest,
@@ -73,7 +73,7 @@ async fn main() -> anyhow::Result<()> {
     env_logger::init();
     let opt = Opt::parse();
 
-    let mut client = Client::new(&opt.uri)
+    let client = Client::new_without_endorsement_verification(&opt.uri, ClientConfig::default())
         .await
         .context("couldn't create client")?;
 
@@ -61,8 +61,11 @@ pub async fn handle_request(
     mut client: Grpc<Channel>,
     cleartext_request: Vec<u8>,
 ) -> anyhow::Result<Vec<u8>> {
+    // Strip the length prefix and any padding added by the client.
+    let request = oak_functions_abi::Request::decode(&cleartext_request)
+        .context("couldn't decode request")?;
     let encapsulated_request =
-        GrpcRequest::decode(&*cleartext_request).context("couldn't decode encapsulated request")?;
+        GrpcRequest::decode(&*request.body).context("couldn't decode encapsulated request")?;
     let bytes = Request::new(encapsulated_request.req_msg.clone());
     let codec = BytesCodec::default();
     let path = encapsulated_request
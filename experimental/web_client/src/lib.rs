@@ -21,16 +21,19 @@ extern crate web_sys;
 use crate::proto::{UnaryRequest, UnaryResponse};
 use anyhow::Context;
 use async_trait::async_trait;
-use oak_functions_abi::Response;
-use oak_remote_attestation::handshaker::{AttestationBehavior, EmptyAttestationGenerator};
+use oak_functions_abi::{proto::AttestationEvidence, Request, Response, StatusCode};
+use oak_remote_attestation::handshaker::{
+    hash_concat_hash, AttestationBehavior, AttestationVerifier, EmptyAttestationGenerator,
+};
 use oak_remote_attestation_amd::PlaceholderAmdAttestationVerifier;
 use oak_remote_attestation_sessions::SessionId;
 use oak_remote_attestation_sessions_client::{GenericAttestationClient, UnaryClient};
-use std::rc::Rc;
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 use wasm_bindgen::prelude::*;
 
-mod grpc_web;
-
 #[cfg(test)]
 mod tests;
 
@@ -41,12 +44,14 @@ mod proto {
 
 /// gRPC-web implementation of a [`UnaryClient`].
 struct GrpcWebClient {
+    inner: oak_grpc_web::Client,
     uri: String,
 }
 
 impl GrpcWebClient {
     pub fn create(uri: &str) -> Self {
         Self {
+            inner: oak_grpc_web::Client::new(),
             uri: format!("{}/oak.session.unary.v1.UnarySession/Message", uri),
         }
     }
@@ -57,22 +62,155 @@ impl GrpcWebClient {
 #[async_trait(?Send)]
 impl UnaryClient for GrpcWebClient {
     async fn message(&mut self, session_id: SessionId, body: Vec<u8>) -> anyhow::Result<Vec<u8>> {
-        let reply = grpc_web::grpc_web_unary::<UnaryRequest, UnaryResponse>(
-            &self.uri,
-            UnaryRequest {
-                session_id: session_id.to_vec(),
-                body,
-            },
-        )
-        .await?;
+        let reply = self
+            .inner
+            .unary::<UnaryRequest, UnaryResponse>(
+                &self.uri,
+                UnaryRequest {
+                    session_id: session_id.to_vec(),
+                    body,
+                },
+            )
+            .await?;
         Ok(reply.body)
     }
 }
 
-// The underlying [`GenericAttestationClient`] is held in a [`wasm_mutex::Mutex`],
-// which provides a queue of invocations. Ref: https://lib.rs/crates/wasm_mutex
-// TODO(#2908): Support concurrent invocations.
-type WebClientInner = Rc<wasm_mutex::Mutex<GenericAttestationClient<GrpcWebClient>>>;
+/// Verifier of the [`AttestationEvidence`] sent by Oak Functions servers, which accepts a
+/// placeholder TEE attestation bound to any configuration report.
+// TODO(#2842): Check the configuration report against a verification policy.
+#[derive(Clone)]
+struct PlaceholderEvidenceVerifier;
+
+impl AttestationVerifier for PlaceholderEvidenceVerifier {
+    fn verify_attestation(
+        &self,
+        attestation: &[u8],
+        expected_attested_data: &[u8],
+    ) -> anyhow::Result<()> {
+        use prost::Message;
+        let evidence = AttestationEvidence::decode(attestation)
+            .map_err(|error| anyhow::anyhow!("Couldn't decode evidence: {:?}", error))?;
+        let configuration = evidence
+            .configuration
+            .context("Evidence is missing the configuration report")?;
+        PlaceholderAmdAttestationVerifier.verify_attestation(
+            &evidence.tee_attestation,
+            &hash_concat_hash(&[expected_attested_data, &configuration.encode_to_vec()]),
+        )
+    }
+}
+
+/// Maximum number of sessions that concurrent invocations are sent in. Once all sessions are
+/// used, further invocations wait for the least busy session.
+const MAX_SESSIONS: usize = 4;
+
+/// An attested session of a [`SessionPool`].
+struct PooledSession<T: UnaryClient> {
+    // The session is held in a [`wasm_mutex::Mutex`], which provides a queue of the invocations
+    // waiting for it. Ref: https://lib.rs/crates/wasm_mutex
+    client: wasm_mutex::Mutex<GenericAttestationClient<T>>,
+    /// Number of invocations using or waiting for the session.
+    pending_invocations: Cell<usize>,
+}
+
+/// Pool of attested sessions, which allows concurrent invocations.
+///
+/// Sessions are created when all existing sessions are busy, up to [`MAX_SESSIONS`]. Since the
+/// server erases the state of a session after any error, a session is removed from the pool once an
+/// invocation in it failed.
+struct SessionPool<T: UnaryClient> {
+    uri: String,
+    /// Creates the transport of a new session to the server at the given URI.
+    create_transport: Box<dyn Fn(&str) -> T>,
+    sessions: RefCell<Vec<Rc<PooledSession<T>>>>,
+    /// Number of sessions whose handshake is in progress.
+    pending_sessions: Cell<usize>,
+    /// Size that every encoded request is padded to a multiple of, if set.
+    request_bucket_size: Cell<Option<usize>>,
+}
+
+impl<T: UnaryClient> SessionPool<T> {
+    async fn create(uri: String, create_transport: Box<dyn Fn(&str) -> T>) -> anyhow::Result<Self> {
+        let pool = Self {
+            uri,
+            create_transport,
+            sessions: RefCell::new(Vec::new()),
+            pending_sessions: Cell::new(0),
+            request_bucket_size: Cell::new(None),
+        };
+        // The first session is created right away, so that a server that is not reachable or not
+        // accepted is reported when creating the client.
+        pool.create_session().await?;
+        Ok(pool)
+    }
+
+    /// Returns the session to send an invocation in, which is an idle session if there is one.
+    async fn session(&self) -> anyhow::Result<Rc<PooledSession<T>>> {
+        let least_busy_session = self
+            .sessions
+            .borrow()
+            .iter()
+            .min_by_key(|session| session.pending_invocations.get())
+            .cloned();
+        let session_count = self.sessions.borrow().len() + self.pending_sessions.get();
+        match least_busy_session {
+            Some(session)
+                if session.pending_invocations.get() == 0 || session_count >= MAX_SESSIONS =>
+            {
+                Ok(session)
+            }
+            _ => self.create_session().await,
+        }
+    }
+
+    /// Creates a new session, in which the client performs a handshake with the server.
+    async fn create_session(&self) -> anyhow::Result<Rc<PooledSession<T>>> {
+        self.pending_sessions.set(self.pending_sessions.get() + 1);
+        let client = GenericAttestationClient::create(
+            (self.create_transport)(&self.uri),
+            AttestationBehavior::create(EmptyAttestationGenerator, PlaceholderEvidenceVerifier),
+        )
+        .await;
+        self.pending_sessions.set(self.pending_sessions.get() - 1);
+
+        let session = Rc::new(PooledSession {
+            client: wasm_mutex::Mutex::new(client.context("Couldn't create session")?),
+            pending_invocations: Cell::new(0),
+        });
+        self.sessions.borrow_mut().push(session.clone());
+        Ok(session)
+    }
+
+    /// Invokes the server with a request, in a session of the pool.
+    async fn invoke(&self, request: Vec<u8>) -> anyhow::Result<Response> {
+        let request = Request { body: request };
+        let encoded_request = match self.request_bucket_size.get() {
+            Some(bucket_size) => request.encode_padded(bucket_size),
+            None => request.encode_to_vec(),
+        };
+        let session = self.session().await?;
+        session
+            .pending_invocations
+            .set(session.pending_invocations.get() + 1);
+        let encoded_response = session.client.lock().await.message(&encoded_request).await;
+        session
+            .pending_invocations
+            .set(session.pending_invocations.get() - 1);
+        if encoded_response.is_err() {
+            self.sessions
+                .borrow_mut()
+                .retain(|pooled_session| !Rc::ptr_eq(pooled_session, &session));
+        }
+        let encoded_response = encoded_response.context("Error invoking Oak Functions instance")?;
+
+        Response::decode(encoded_response.as_ref())
+            .map_err(anyhow::Error::msg)
+            .context("Couldn't decode response")
+    }
+}
+
+type WebClientInner = Rc<SessionPool<GrpcWebClient>>;
 
 #[wasm_bindgen]
 pub struct WebClient {
@@ -83,19 +221,19 @@ pub struct WebClient {
 impl WebClient {
     #[wasm_bindgen(constructor)]
     pub async fn new(uri: String) -> Result<WebClient, String> {
-        let grpc_web_client = GrpcWebClient::create(&uri);
-        let inner = GenericAttestationClient::create(
-            grpc_web_client,
-            AttestationBehavior::create(
-                EmptyAttestationGenerator,
-                PlaceholderAmdAttestationVerifier,
-            ),
-        )
-        .await
-        .context("Could not create Oak Functions client")
-        .map_err(|error| error.to_string())?;
-        let inner = Rc::new(wasm_mutex::Mutex::new(inner));
-        Ok(WebClient { inner })
+        let inner = SessionPool::create(uri, Box::new(GrpcWebClient::create))
+            .await
+            .context("Could not create Oak Functions client")
+            .map_err(|error| error.to_string())?;
+        Ok(WebClient {
+            inner: Rc::new(inner),
+        })
+    }
+
+    /// Pads requests so that the size of every encoded request is a multiple of `bucket_size`,
+    /// which hides the exact size of the request from the network.
+    pub fn set_request_bucket_size(&self, bucket_size: usize) {
+        self.inner.request_bucket_size.set(Some(bucket_size));
     }
 
     // Typically this would be an async function. Instead it's a sync function
@@ -107,7 +245,8 @@ impl WebClient {
         let inner = self.inner.clone();
 
         wasm_bindgen_futures::future_to_promise(async move {
-            let response = WebClient::inner_invoke(inner, request)
+            let response = inner
+                .invoke(request)
                 .await
                 .map_err(|error| error.to_string())?;
 
@@ -135,17 +274,37 @@ impl WebClient {
             Ok(JsValue::from(js_response_object))
         })
     }
-    async fn inner_invoke(inner: WebClientInner, request: Vec<u8>) -> anyhow::Result<Response> {
-        let encoded_response = inner
-            .lock()
-            .await
-            .message(&request)
-            .await
-            .context("Error invoking Oak Functions instance")?;
 
-        Response::decode(encoded_response.as_ref())
-            .map_err(anyhow::Error::msg)
-            .context("Couldn't decode response")
+    /// Invokes the application with a request encoded as JSON, e.g. for the `weather_lookup`
+    /// example.
+    ///
+    /// The returned promise resolves to the decoded JSON response. If the response is not
+    /// successful, it rejects with an `Error` named after the status of the response, e.g.
+    /// `PolicySizeViolation`.
+    pub fn invoke_json(&self, request: JsValue) -> js_sys::Promise {
+        let inner = self.inner.clone();
+
+        wasm_bindgen_futures::future_to_promise(async move {
+            let request: String = js_sys::JSON::stringify(&request)?.into();
+            let response = inner
+                .invoke(request.into_bytes())
+                .await
+                .map_err(|error| error.to_string())?;
+
+            if response.status != StatusCode::Success {
+                let error = js_sys::Error::new(&format!(
+                    "Unsuccessful response status: {}",
+                    response.status
+                ));
+                error.set_name(&response.status.to_string());
+                return Err(error.into());
+            }
+            let body = response
+                .body()
+                .map_err(|_| "Could not fit the response length into usize")?;
+            let body = std::str::from_utf8(body).map_err(|_| "Response body is not valid UTF-8")?;
+            js_sys::JSON::parse(body)
+        })
     }
 }
 
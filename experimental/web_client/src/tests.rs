@@ -17,8 +17,12 @@
 #![cfg(target_arch = "wasm32")]
 
 extern crate wasm_bindgen_test;
-use crate::WebClient;
-use wasm_bindgen::JsCast;
+use crate::{GrpcWebClient, SessionPool, WebClient};
+use async_trait::async_trait;
+use oak_remote_attestation_sessions::SessionId;
+use oak_remote_attestation_sessions_client::UnaryClient;
+use std::{cell::Cell, rc::Rc};
+use wasm_bindgen::{JsCast, JsValue};
 use wasm_bindgen_test::*;
 
 wasm_bindgen_test_configure!(run_in_browser);
@@ -26,6 +30,15 @@ wasm_bindgen_test_configure!(run_in_browser);
 const URI: &str = "http://localhost:8080";
 const TEST_DATA: [u8; 4] = [1, 2, 3, 4];
 
+/// Returns the body of a response object returned by [`WebClient::invoke`].
+fn response_body(js_result: &JsValue) -> Vec<u8> {
+    js_sys::Reflect::get(js_result, &"body".into())
+        .unwrap()
+        .dyn_into::<js_sys::Uint8Array>()
+        .unwrap()
+        .to_vec()
+}
+
 #[wasm_bindgen_test]
 async fn pass() {
     let client = WebClient::new(URI.to_string()).await.unwrap();
@@ -33,10 +46,78 @@ async fn pass() {
     let js_result = wasm_bindgen_futures::JsFuture::from(js_promise)
         .await
         .unwrap();
-    let result_as_vec = js_sys::Reflect::get(&js_result, &"body".into())
-        .unwrap()
-        .dyn_into::<js_sys::Uint8Array>()
-        .unwrap()
-        .to_vec();
-    assert_eq!(result_as_vec, TEST_DATA.to_vec());
+    assert_eq!(response_body(&js_result), TEST_DATA.to_vec());
+}
+
+#[wasm_bindgen_test]
+async fn json_invocation() {
+    let client = WebClient::new(URI.to_string()).await.unwrap();
+    let request = js_sys::JSON::parse(r#"{"lat":0,"lng":0}"#).unwrap();
+    let js_promise = client.invoke_json(request.clone());
+    let js_result = wasm_bindgen_futures::JsFuture::from(js_promise)
+        .await
+        .unwrap();
+    assert_eq!(
+        js_sys::JSON::stringify(&js_result).unwrap(),
+        js_sys::JSON::stringify(&request).unwrap()
+    );
+}
+
+#[wasm_bindgen_test]
+async fn concurrent_invocations() {
+    let client = WebClient::new(URI.to_string()).await.unwrap();
+    let requests: Vec<Vec<u8>> = (0..8).map(|index| vec![index; 4]).collect();
+    let js_promises: js_sys::Array = requests
+        .iter()
+        .map(|request| JsValue::from(client.invoke(request.clone())))
+        .collect();
+    let js_results = wasm_bindgen_futures::JsFuture::from(js_sys::Promise::all(&js_promises))
+        .await
+        .unwrap();
+    let responses: Vec<Vec<u8>> = js_sys::Array::from(&js_results)
+        .iter()
+        .map(|js_result| response_body(&js_result))
+        .collect();
+    assert_eq!(responses, requests);
+}
+
+/// Transport whose messages fail while `fail` is set, e.g. like a server that is unreachable.
+struct FailingClient {
+    inner: GrpcWebClient,
+    fail: Rc<Cell<bool>>,
+}
+
+#[async_trait(?Send)]
+impl UnaryClient for FailingClient {
+    async fn message(&mut self, session_id: SessionId, body: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        anyhow::ensure!(!self.fail.get(), "Couldn't reach server");
+        self.inner.message(session_id, body).await
+    }
+}
+
+#[wasm_bindgen_test]
+async fn failed_session_is_removed() {
+    let fail = Rc::new(Cell::new(false));
+    let transport_fail = fail.clone();
+    let pool = SessionPool::create(
+        URI.to_string(),
+        Box::new(move |uri: &str| FailingClient {
+            inner: GrpcWebClient::create(uri),
+            fail: transport_fail.clone(),
+        }),
+    )
+    .await
+    .unwrap();
+    assert!(pool.invoke(TEST_DATA.to_vec()).await.is_ok());
+    assert_eq!(pool.sessions.borrow().len(), 1);
+
+    fail.set(true);
+    assert!(pool.invoke(TEST_DATA.to_vec()).await.is_err());
+    assert!(pool.sessions.borrow().is_empty());
+
+    // The next invocation creates a new session.
+    fail.set(false);
+    let response = pool.invoke(TEST_DATA.to_vec()).await.unwrap();
+    assert_eq!(response.body().unwrap(), &TEST_DATA[..]);
+    assert_eq!(pool.sessions.borrow().len(), 1);
 }
@@ -0,0 +1,169 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::proto::{
+    streaming_session_client::StreamingSessionClient, StreamingRequest, StreamingResponse,
+};
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use oak_remote_attestation::{
+    evidence::HandshakeEvidence,
+    handshaker::{
+        AttestationBehavior, AttestationGenerator, AttestationVerifier, EmptyAttestationGenerator,
+    },
+};
+use oak_remote_attestation_amd::PlaceholderAmdAttestationVerifier;
+use oak_remote_attestation_sessions::SessionId;
+use oak_remote_attestation_sessions_client::{GenericAttestationClient, UnaryClient};
+use tokio::sync::mpsc;
+use tonic::{transport::Channel, Streaming};
+
+/// Open gRPC stream that carries a single session.
+struct SessionStream {
+    session_id: SessionId,
+    requests: mpsc::Sender<StreamingRequest>,
+    responses: Streaming<StreamingResponse>,
+}
+
+/// Streaming gRPC implementation of [`UnaryClient`]. Serves as an inner of the public
+/// [`AttestationClient`].
+///
+/// Each session is carried by its own stream: a new stream is opened whenever the
+/// [`GenericAttestationClient`] starts a new session (i.e. uses a new session id), and the
+/// messages of the session are sent over this stream without their session id.
+struct GrpcStreamingClient {
+    inner: StreamingSessionClient<Channel>,
+    stream: Option<SessionStream>,
+}
+
+impl GrpcStreamingClient {
+    pub async fn create(uri: &str) -> anyhow::Result<Self> {
+        let channel = Channel::from_shared(uri.to_string())
+            .context("Couldn't create gRPC channel")?
+            .connect()
+            .await?;
+        let inner = StreamingSessionClient::new(channel);
+        Ok(Self {
+            inner,
+            stream: None,
+        })
+    }
+
+    async fn open_stream(&mut self, session_id: SessionId) -> anyhow::Result<SessionStream> {
+        // Requests are sent one at a time, each waiting for its response.
+        let (requests, mut receiver) = mpsc::channel(1);
+        let request_stream = async_stream::stream! {
+            while let Some(request) = receiver.recv().await {
+                yield request;
+            }
+        };
+        let responses = self
+            .inner
+            .stream(request_stream)
+            .await
+            .context("Couldn't open stream")?
+            .into_inner();
+        Ok(SessionStream {
+            session_id,
+            requests,
+            responses,
+        })
+    }
+}
+
+// Async trait requires the definition and all implementations to be marked as
+// optionally [`Send`] if one implementation is not.
+#[async_trait(?Send)]
+impl UnaryClient for GrpcStreamingClient {
+    async fn message(&mut self, session_id: SessionId, body: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        let mut stream = match self.stream.take() {
+            Some(stream) if stream.session_id == session_id => stream,
+            // Dropping the stream of the previous session closes it.
+            _ => self.open_stream(session_id).await?,
+        };
+
+        stream
+            .requests
+            .send(StreamingRequest { body })
+            .await
+            .map_err(|_| anyhow!("Couldn't send message: stream closed"))?;
+        let response = stream
+            .responses
+            .message()
+            .await
+            .context("Couldn't receive response")?
+            .ok_or_else(|| anyhow!("Stream closed by the server"))?
+            .body;
+
+        // The stream is only kept if the message was exchanged successfully, since the server
+        // closes the stream after any error.
+        self.stream = Some(stream);
+        Ok(response)
+    }
+}
+
+/// Streaming gRPC Attestation Service client implementation.
+pub struct AttestationClient {
+    inner: GenericAttestationClient<GrpcStreamingClient>,
+}
+
+impl AttestationClient {
+    /// Create an [`AttestationClient`] with a [`PlaceholderAmdAttestationVerifier`].
+    pub async fn create(uri: &str) -> anyhow::Result<Self> {
+        Self::create_with_attestation_verifier(uri, PlaceholderAmdAttestationVerifier).await
+    }
+
+    /// Create an [`AttestationClient`] with the provided [`AttestationVerifier`].
+    ///
+    /// Clients don't usually generate attestations, so this method implies an
+    /// [`EmptyAttestationGenerator`].
+    pub async fn create_with_attestation_verifier<V: 'static + AttestationVerifier>(
+        uri: &str,
+        attestation_verifier: V,
+    ) -> anyhow::Result<Self> {
+        Self::create_with_attestation_behavior(
+            uri,
+            AttestationBehavior::create(EmptyAttestationGenerator, attestation_verifier),
+        )
+        .await
+    }
+
+    /// Create an [`AttestationClient`] with the provided [`AttestationBehavior`].
+    ///
+    /// Allows clients running in a TEE to attest themselves to servers that require bidirectional
+    /// attestation, by providing an [`AttestationGenerator`].
+    pub async fn create_with_attestation_behavior<
+        G: 'static + AttestationGenerator,
+        V: 'static + AttestationVerifier,
+    >(
+        uri: &str,
+        attestation_behavior: AttestationBehavior<G, V>,
+    ) -> anyhow::Result<Self> {
+        let grpc_client = GrpcStreamingClient::create(uri).await?;
+        let inner = GenericAttestationClient::create(grpc_client, attestation_behavior).await?;
+
+        Ok(Self { inner })
+    }
+
+    pub async fn send(&mut self, payload: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.inner.message(payload).await
+    }
+
+    /// Returns the attestation evidence of the server in the last handshake.
+    pub fn evidence(&self) -> Option<&HandshakeEvidence> {
+        self.inner.evidence()
+    }
+}
@@ -0,0 +1,31 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Remote Attestation handshake protocol implemented using bidirectional streaming gRPC.
+//!
+//! Unlike `grpc_unary_attestation`, each session is carried by a single gRPC stream, so requests
+//! don't carry a session id and the server doesn't have to look up the session state of each
+//! request.
+
+pub mod proto {
+    #![allow(clippy::return_self_not_must_use)]
+    tonic::include_proto!("oak.session.stream.v1");
+}
+pub mod client;
+pub mod server;
+
+#[cfg(test)]
+mod tests;
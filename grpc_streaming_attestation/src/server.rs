@@ -0,0 +1,323 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Server-side implementation of the bidirectional streaming gRPC remote attestation handshake
+//! protocol.
+
+use crate::proto::{
+    streaming_session_server::StreamingSession, StreamingRequest, StreamingResponse,
+};
+use anyhow::Context;
+use futures::Stream;
+use oak_remote_attestation::{
+    handshaker::{
+        AttestationBehavior, AttestationGenerator, AttestationVerifier, EmptyAttestationVerifier,
+        ServerHandshaker,
+    },
+    message::{Serializable, SessionExpired},
+    resumption::{SessionTicketKey, SessionTickets},
+};
+use oak_remote_attestation_amd::PlaceholderAmdAttestationGenerator;
+use oak_remote_attestation_sessions::{SessionConfig, SessionLifetime, SessionState};
+use oak_utils::LogError;
+use spin::Mutex;
+use std::{
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Absolute lifetime of sessions, after which clients have to perform a new handshake.
+const SESSION_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Returns the current time as the duration since the Unix epoch.
+fn current_time() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// gRPC Attestation Service implementation.
+///
+/// Each stream carries a single session, whose state is kept by the stream. The server attests
+/// itself to clients with `G`, and verifies the attestation of clients with `V`. By default the
+/// server sends a placeholder attestation, and clients are not attested.
+pub struct AttestationServer<
+    F,
+    L: LogError,
+    V: AttestationVerifier = EmptyAttestationVerifier,
+    G: AttestationGenerator = PlaceholderAmdAttestationGenerator,
+> {
+    /// Business logic processor, accepts decrypted request and returns responses.
+    request_handler: F,
+    /// Error logging function that is required for logging attestation protocol errors.
+    /// Errors are only logged on server side and are not sent to clients.
+    error_logger: L,
+    attestation_behavior: AttestationBehavior<G, V>,
+    config: SessionConfig,
+}
+
+impl<F, S, L> AttestationServer<F, L>
+where
+    F: Send + Sync + Clone + FnOnce(Vec<u8>) -> S,
+    S: std::future::Future<Output = anyhow::Result<Vec<u8>>> + Send + Sync,
+    L: Send + Sync + Clone + LogError,
+{
+    /// Creates a server that sends a placeholder attestation and accepts any client. Sessions end
+    /// with their stream.
+    pub fn create(request_handler: F, error_logger: L) -> anyhow::Result<Self> {
+        Self::create_with_session_tickets(request_handler, error_logger, None)
+    }
+
+    /// Creates a server that issues session tickets sealed with `session_ticket_key`, if provided.
+    ///
+    /// Session tickets allow clients to resume their session in a new stream, e.g. after a
+    /// connection was lost, with this server or any other server sharing the same key. Tickets
+    /// expire together with the session they were issued for. Without a key, no tickets are
+    /// issued.
+    pub fn create_with_session_tickets(
+        request_handler: F,
+        error_logger: L,
+        session_ticket_key: Option<SessionTicketKey>,
+    ) -> anyhow::Result<Self> {
+        Self::create_with_attestation_verifier(
+            request_handler,
+            error_logger,
+            EmptyAttestationVerifier,
+            session_ticket_key,
+        )
+    }
+}
+
+impl<F, S, L, V> AttestationServer<F, L, V>
+where
+    F: Send + Sync + Clone + FnOnce(Vec<u8>) -> S,
+    S: std::future::Future<Output = anyhow::Result<Vec<u8>>> + Send + Sync,
+    L: Send + Sync + Clone + LogError,
+    V: AttestationVerifier,
+{
+    /// Creates a server that, unlike [`AttestationServer::create_with_session_tickets`], requires
+    /// clients to attest themselves, and verifies their attestation with the provided
+    /// [`AttestationVerifier`] during the handshake (i.e. bidirectional attestation). The server
+    /// itself still sends a placeholder attestation.
+    pub fn create_with_attestation_verifier(
+        request_handler: F,
+        error_logger: L,
+        attestation_verifier: V,
+        session_ticket_key: Option<SessionTicketKey>,
+    ) -> anyhow::Result<Self> {
+        Self::create_with_attestation_behavior(
+            request_handler,
+            error_logger,
+            AttestationBehavior::create(PlaceholderAmdAttestationGenerator, attestation_verifier),
+            session_ticket_key,
+        )
+    }
+}
+
+impl<F, S, L, V, G> AttestationServer<F, L, V, G>
+where
+    F: Send + Sync + Clone + FnOnce(Vec<u8>) -> S,
+    S: std::future::Future<Output = anyhow::Result<Vec<u8>>> + Send + Sync,
+    L: Send + Sync + Clone + LogError,
+    V: AttestationVerifier,
+    G: AttestationGenerator,
+{
+    /// Creates a server that, unlike [`AttestationServer::create_with_attestation_verifier`],
+    /// attests itself with the [`AttestationGenerator`] of the provided [`AttestationBehavior`]
+    /// instead of a placeholder attestation. Clients are verified with its [`AttestationVerifier`].
+    pub fn create_with_attestation_behavior(
+        request_handler: F,
+        error_logger: L,
+        attestation_behavior: AttestationBehavior<G, V>,
+        session_ticket_key: Option<SessionTicketKey>,
+    ) -> anyhow::Result<Self> {
+        let session_tickets = session_ticket_key.map(|key| SessionTickets {
+            key,
+            lifetime: SESSION_LIFETIME,
+            clock: current_time,
+        });
+        // Streams deliver messages in order, so the default strict replay protection applies.
+        let config = SessionConfig {
+            lifetime: Some(SessionLifetime {
+                duration: SESSION_LIFETIME,
+                clock: current_time,
+            }),
+            session_tickets,
+            ..Default::default()
+        };
+        config
+            .key_rotation
+            .validate()
+            .context("Couldn't validate key rotation")?;
+        Ok(Self {
+            request_handler,
+            error_logger,
+            attestation_behavior,
+            config,
+        })
+    }
+
+    /// Creates the initial state of the session carried by a new stream.
+    fn create_session_state(&self) -> anyhow::Result<SessionState<G, V>> {
+        let mut handshaker = ServerHandshaker::new(self.attestation_behavior.clone())?;
+        if let Some(session_tickets) = &self.config.session_tickets {
+            handshaker.set_session_tickets(session_tickets.clone());
+        }
+        Ok(SessionState::HandshakeInProgress(Box::new(handshaker)))
+    }
+}
+
+/// Processes a handshake message, and returns the next state of the session together with the
+/// response to send to the client.
+///
+/// If the handshake resumed a session, `expiration_time` is limited to the expiration time of the
+/// session ticket.
+fn handshake_step<G: AttestationGenerator, V: AttestationVerifier>(
+    mut handshaker: Box<ServerHandshaker<G, V>>,
+    message: &[u8],
+    config: &SessionConfig,
+    expiration_time: &mut Option<Duration>,
+) -> anyhow::Result<(SessionState<G, V>, Vec<u8>)> {
+    let response = handshaker
+        .next_step(message)
+        .context("Couldn't process handshake message")?
+        // After receiving a valid `ClientIdentity` message (the last step of the key exchange)
+        // ServerHandshaker.next_step returns `None`. Since every request is answered with a
+        // response, an explicit confirmation in the form of an empty response is sent instead.
+        .unwrap_or_default();
+    if !handshaker.is_completed() {
+        return Ok((SessionState::HandshakeInProgress(handshaker), response));
+    }
+    if let (Some(expiration_time), Some(ticket_expiration_time)) = (
+        expiration_time.as_mut(),
+        handshaker.get_resumed_session_expiration_time(),
+    ) {
+        *expiration_time = (*expiration_time).min(ticket_expiration_time);
+    }
+
+    let mut encryptor = handshaker
+        .get_encryptor()
+        .context("Couldn't get encryptor")?;
+    encryptor
+        .set_replay_protection(config.replay_protection)
+        .context("Couldn't set replay protection")?;
+    encryptor
+        .set_key_rotation(config.key_rotation)
+        .context("Couldn't set key rotation")?;
+    Ok((
+        SessionState::EncryptedMessageExchange(Arc::new(Mutex::new(encryptor))),
+        response,
+    ))
+}
+
+type ResponseStream =
+    Pin<Box<dyn Stream<Item = Result<StreamingResponse, tonic::Status>> + Send + 'static>>;
+
+#[tonic::async_trait]
+impl<F, S, L, V, G> StreamingSession for AttestationServer<F, L, V, G>
+where
+    F: 'static + Send + Sync + Clone + FnOnce(Vec<u8>) -> S,
+    S: std::future::Future<Output = anyhow::Result<Vec<u8>>> + Send + Sync,
+    L: Send + Sync + Clone + LogError + 'static,
+    V: AttestationVerifier + 'static,
+    G: AttestationGenerator + 'static,
+{
+    type StreamStream = ResponseStream;
+
+    async fn stream(
+        &self,
+        request: tonic::Request<tonic::Streaming<StreamingRequest>>,
+    ) -> anyhow::Result<tonic::Response<Self::StreamStream>, tonic::Status> {
+        let error_logger = self.error_logger.clone();
+        let request_handler = self.request_handler.clone();
+        let config = self.config.clone();
+        let mut requests = request.into_inner();
+
+        let mut session_state = self.create_session_state().map_err(|error| {
+            error_logger.log_error(&format!("Couldn't create session state: {:?}", error));
+            tonic::Status::internal("")
+        })?;
+        let mut expiration_time = config
+            .lifetime
+            .map(|lifetime| (lifetime.clock)().saturating_add(lifetime.duration));
+
+        let responses = async_stream::try_stream! {
+            while let Some(request) = requests.message().await? {
+                if let (Some(lifetime), Some(expiration_time)) = (config.lifetime, expiration_time) {
+                    if (lifetime.clock)() >= expiration_time {
+                        // The client performs a new handshake in a new stream.
+                        let session_expired = SessionExpired.serialize().map_err(|error| {
+                            error_logger.log_error(&format!(
+                                "Couldn't serialize session expired message: {:?}",
+                                error
+                            ));
+                            tonic::Status::internal("")
+                        })?;
+                        yield StreamingResponse {
+                            body: session_expired,
+                        };
+                        break;
+                    }
+                }
+
+                // Errors close the stream, so that the client has to negotiate a new handshake.
+                let response_body = match session_state {
+                    SessionState::HandshakeInProgress(handshaker) => {
+                        let (next_session_state, response_body) =
+                            handshake_step(handshaker, &request.body, &config, &mut expiration_time)
+                                .map_err(|error| {
+                                error_logger.log_error(&format!(
+                                    "Couldn't process handshake message: {:?}",
+                                    error
+                                ));
+                                tonic::Status::aborted("")
+                            })?;
+                        session_state = next_session_state;
+                        response_body
+                    }
+                    SessionState::EncryptedMessageExchange(ref encryptor) => {
+                        let decrypted_request =
+                            encryptor.lock().decrypt(&request.body).map_err(|error| {
+                                error_logger
+                                    .log_error(&format!("Couldn't decrypt request: {:?}", error));
+                                tonic::Status::aborted("")
+                            })?;
+
+                        let response = (request_handler.clone())(decrypted_request)
+                            .await
+                            .map_err(|error| {
+                                error_logger
+                                    .log_error(&format!("Couldn't handle request: {:?}", error));
+                                tonic::Status::aborted("")
+                            })?;
+
+                        encryptor.lock().encrypt(&response).map_err(|error| {
+                            error_logger
+                                .log_error(&format!("Couldn't encrypt response: {:?}", error));
+                            tonic::Status::aborted("")
+                        })?
+                    }
+                };
+                yield StreamingResponse {
+                    body: response_body,
+                };
+            }
+        };
+
+        Ok(tonic::Response::new(Box::pin(responses)))
+    }
+}
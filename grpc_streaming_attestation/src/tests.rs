@@ -0,0 +1,140 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{
+    client::AttestationClient,
+    proto::{
+        streaming_session_client::StreamingSessionClient,
+        streaming_session_server::StreamingSessionServer, StreamingRequest,
+    },
+    server::AttestationServer,
+};
+use oak_remote_attestation::handshaker::{AttestationBehavior, EmptyAttestationGenerator};
+use oak_remote_attestation_amd::{
+    PlaceholderAmdAttestationGenerator, PlaceholderAmdAttestationVerifier,
+};
+use oak_utils::testing::{TestLogger, TestServer};
+
+/// Starts a server that requires clients to be attested with a placeholder AMD attestation, and
+/// echoes the requests of attested clients.
+async fn start_attested_server() -> TestServer {
+    let server = AttestationServer::create_with_attestation_verifier(
+        |request| async move { Ok(request) },
+        TestLogger,
+        PlaceholderAmdAttestationVerifier,
+        None,
+    )
+    .expect("Couldn't create attestation server");
+    TestServer::start(
+        tonic::transport::Server::builder().add_service(StreamingSessionServer::new(server)),
+        None,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_bidirectional_attestation() {
+    let server = start_attested_server().await;
+
+    let mut client = AttestationClient::create_with_attestation_behavior(
+        &server.uri(),
+        AttestationBehavior::create(
+            PlaceholderAmdAttestationGenerator,
+            PlaceholderAmdAttestationVerifier,
+        ),
+    )
+    .await
+    .expect("Couldn't create attested client");
+    // All messages of the session are sent over the same stream.
+    assert_eq!(client.send(b"first").await.unwrap(), b"first");
+    assert_eq!(client.send(b"second").await.unwrap(), b"second");
+    assert_eq!(client.send(b"").await.unwrap(), b"");
+
+    // The attestation of the server can be verified again from the recorded evidence.
+    let evidence = client.evidence().expect("Missing handshake evidence");
+    assert!(evidence.is_accepted());
+    assert!(evidence.verify(&PlaceholderAmdAttestationVerifier).is_ok());
+
+    drop(client);
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_unattested_client() {
+    let server = start_attested_server().await;
+
+    // The server rejects the handshake of a client that doesn't attest itself.
+    let result = AttestationClient::create_with_attestation_behavior(
+        &server.uri(),
+        AttestationBehavior::create(EmptyAttestationGenerator, PlaceholderAmdAttestationVerifier),
+    )
+    .await;
+    assert!(result.is_err());
+
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_concurrent_streams() {
+    let server = start_attested_server().await;
+
+    // Each client has its own stream, so their sessions are independent.
+    let uri = server.uri();
+    let attestation_behavior = AttestationBehavior::create(
+        PlaceholderAmdAttestationGenerator,
+        PlaceholderAmdAttestationVerifier,
+    );
+    let mut first_client =
+        AttestationClient::create_with_attestation_behavior(&uri, attestation_behavior.clone())
+            .await
+            .expect("Couldn't create first client");
+    let mut second_client =
+        AttestationClient::create_with_attestation_behavior(&uri, attestation_behavior)
+            .await
+            .expect("Couldn't create second client");
+    assert_eq!(first_client.send(b"first").await.unwrap(), b"first");
+    assert_eq!(second_client.send(b"second").await.unwrap(), b"second");
+    assert_eq!(first_client.send(b"third").await.unwrap(), b"third");
+
+    drop(first_client);
+    drop(second_client);
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_invalid_message() {
+    let server = start_attested_server().await;
+
+    let mut client = StreamingSessionClient::connect(server.uri())
+        .await
+        .expect("Couldn't connect to server");
+    let mut responses = client
+        .stream(futures::stream::iter(vec![StreamingRequest {
+            body: b"invalid".to_vec(),
+        }]))
+        .await
+        .expect("Couldn't open stream")
+        .into_inner();
+
+    // The server closes the stream if a message cannot be processed.
+    let status = responses
+        .message()
+        .await
+        .expect_err("Invalid message was accepted");
+    assert_eq!(status.code(), tonic::Code::Aborted);
+
+    server.stop().await;
+}
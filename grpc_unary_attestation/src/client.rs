@@ -14,15 +14,20 @@
 // limitations under the License.
 //
 
-use crate::proto::{unary_session_client::UnarySessionClient, UnaryRequest};
+use crate::proto::{unary_session_client::UnarySessionClient, UnaryRequest, UnaryResponse};
 use anyhow::Context;
 use async_trait::async_trait;
-use oak_remote_attestation::handshaker::{
-    AttestationBehavior, AttestationVerifier, EmptyAttestationGenerator,
+use oak_remote_attestation::{
+    evidence::HandshakeEvidence,
+    handshaker::{
+        AttestationBehavior, AttestationGenerator, AttestationVerifier, EmptyAttestationGenerator,
+    },
 };
 use oak_remote_attestation_amd::PlaceholderAmdAttestationVerifier;
 use oak_remote_attestation_sessions::SessionId;
 use oak_remote_attestation_sessions_client::{GenericAttestationClient, UnaryClient};
+use prost::Message;
+use std::str::FromStr;
 use tonic::transport::Channel;
 
 /// gRPC implementation of of [`UnaryClient`]. Serves as an inner of the
@@ -62,9 +67,162 @@ impl UnaryClient for GrpcClient {
     }
 }
 
+/// gRPC-web implementation of [`UnaryClient`], which only requires HTTP/1.1.
+struct GrpcWebClient {
+    inner: oak_grpc_web::Client,
+    uri: String,
+}
+
+impl GrpcWebClient {
+    pub fn create(uri: &str) -> Self {
+        Self {
+            inner: oak_grpc_web::Client::new(),
+            uri: format!("{}/oak.session.unary.v1.UnarySession/Message", uri),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl UnaryClient for GrpcWebClient {
+    async fn message(&mut self, session_id: SessionId, body: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        let response = self
+            .inner
+            .unary::<UnaryRequest, UnaryResponse>(
+                &self.uri,
+                UnaryRequest {
+                    body,
+                    session_id: session_id.to_vec(),
+                },
+            )
+            .await
+            .context("Couldn't send message")?
+            .body;
+
+        Ok(response)
+    }
+}
+
+/// Content type of the protobuf envelope of the HTTP gateway of the Oak Functions loader.
+const HTTP_GATEWAY_CONTENT_TYPE: &str = "application/x-protobuf";
+
+/// Plain HTTP/1.1 implementation of [`UnaryClient`], for the HTTP gateway of the Oak Functions
+/// loader, which carries serialized [`UnaryRequest`] and [`UnaryResponse`] messages without any
+/// gRPC framing.
+struct HttpClient {
+    inner: reqwest::Client,
+    uri: String,
+}
+
+impl HttpClient {
+    pub fn create(uri: &str) -> anyhow::Result<Self> {
+        let inner = reqwest::Client::builder()
+            .http1_only()
+            .build()
+            .context("Couldn't create HTTP client")?;
+        Ok(Self {
+            inner,
+            uri: format!("{}/oak.session.unary.v1.UnarySession/Message", uri),
+        })
+    }
+}
+
+#[async_trait(?Send)]
+impl UnaryClient for HttpClient {
+    async fn message(&mut self, session_id: SessionId, body: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        let request = UnaryRequest {
+            body,
+            session_id: session_id.to_vec(),
+        };
+        let response = self
+            .inner
+            .post(&self.uri)
+            .header(reqwest::header::CONTENT_TYPE, HTTP_GATEWAY_CONTENT_TYPE)
+            .body(request.encode_to_vec())
+            .send()
+            .await
+            .map_err(|error| tonic::Status::unavailable(format!("{:?}", error)))
+            .context("Couldn't send message")?;
+        // The gateway reports errors with the HTTP status code only, which are mapped back to the
+        // status returned by the `UnarySession` service.
+        match response.status() {
+            reqwest::StatusCode::OK => {}
+            reqwest::StatusCode::BAD_REQUEST => {
+                return Err(tonic::Status::invalid_argument("").into())
+            }
+            reqwest::StatusCode::CONFLICT => return Err(tonic::Status::aborted("").into()),
+            status => return Err(tonic::Status::unknown(status.to_string()).into()),
+        }
+        let response_bytes = response
+            .bytes()
+            .await
+            .map_err(|error| tonic::Status::unavailable(format!("{:?}", error)))
+            .context("Couldn't get response bytes")?;
+        let response = UnaryResponse::decode(response_bytes).context("Couldn't decode response")?;
+
+        Ok(response.body)
+    }
+}
+
+/// Transport used by an [`AttestationClient`] to send messages to the server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transport {
+    /// gRPC over HTTP/2.
+    Grpc,
+    /// gRPC-web, which can also be sent over HTTP/1.1, e.g. through proxies that don't support
+    /// HTTP/2. The server must accept gRPC-web requests.
+    GrpcWeb,
+    /// Plain HTTP/1.1 requests to the HTTP gateway of the Oak Functions loader, for proxies that
+    /// only support HTTP/1.1 and don't accept gRPC-web.
+    Http,
+}
+
+impl FromStr for Transport {
+    type Err = anyhow::Error;
+
+    fn from_str(transport: &str) -> anyhow::Result<Self> {
+        match transport {
+            "grpc" => Ok(Self::Grpc),
+            "grpc-web" => Ok(Self::GrpcWeb),
+            "http" => Ok(Self::Http),
+            _ => Err(anyhow::anyhow!(
+                "Unknown transport {:?}, expected grpc, grpc-web or http",
+                transport
+            )),
+        }
+    }
+}
+
+/// [`UnaryClient`] of the selected [`Transport`].
+enum TransportClient {
+    Grpc(GrpcClient),
+    GrpcWeb(GrpcWebClient),
+    Http(HttpClient),
+}
+
+impl TransportClient {
+    async fn create(uri: &str, transport: Transport) -> anyhow::Result<Self> {
+        match transport {
+            Transport::Grpc => Ok(Self::Grpc(GrpcClient::create(uri).await?)),
+            Transport::GrpcWeb => Ok(Self::GrpcWeb(GrpcWebClient::create(uri))),
+            Transport::Http => Ok(Self::Http(HttpClient::create(uri)?)),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl UnaryClient for TransportClient {
+    async fn message(&mut self, session_id: SessionId, body: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Self::Grpc(client) => client.message(session_id, body).await,
+            Self::GrpcWeb(client) => client.message(session_id, body).await,
+            Self::Http(client) => client.message(session_id, body).await,
+        }
+    }
+}
+
 /// gRPC Attestation Service client implementation.
 pub struct AttestationClient {
-    inner: GenericAttestationClient<GrpcClient>,
+    inner: GenericAttestationClient<TransportClient>,
 }
 
 impl AttestationClient {
@@ -77,16 +235,44 @@ impl AttestationClient {
     ///
     /// Clients don't usually generate attestations, so this method implies an
     /// [`EmptyAttestationGenerator`].
-    pub async fn create_with_attestation_verifier<V: AttestationVerifier>(
+    pub async fn create_with_attestation_verifier<V: 'static + AttestationVerifier>(
         uri: &str,
         attestation_verifier: V,
     ) -> anyhow::Result<Self> {
-        let grpc_client = GrpcClient::create(uri).await?;
-        let inner = GenericAttestationClient::create(
-            grpc_client,
+        Self::create_with_attestation_behavior(
+            uri,
             AttestationBehavior::create(EmptyAttestationGenerator, attestation_verifier),
         )
-        .await?;
+        .await
+    }
+
+    /// Create an [`AttestationClient`] with the provided [`AttestationBehavior`].
+    ///
+    /// Allows clients running in a TEE to attest themselves to servers that require bidirectional
+    /// attestation, by providing an [`AttestationGenerator`].
+    pub async fn create_with_attestation_behavior<
+        G: 'static + AttestationGenerator,
+        V: 'static + AttestationVerifier,
+    >(
+        uri: &str,
+        attestation_behavior: AttestationBehavior<G, V>,
+    ) -> anyhow::Result<Self> {
+        Self::create_with_transport(uri, Transport::Grpc, attestation_behavior).await
+    }
+
+    /// Create an [`AttestationClient`] that sends messages with the given [`Transport`], with the
+    /// provided [`AttestationBehavior`].
+    pub async fn create_with_transport<
+        G: 'static + AttestationGenerator,
+        V: 'static + AttestationVerifier,
+    >(
+        uri: &str,
+        transport: Transport,
+        attestation_behavior: AttestationBehavior<G, V>,
+    ) -> anyhow::Result<Self> {
+        let transport_client = TransportClient::create(uri, transport).await?;
+        let inner =
+            GenericAttestationClient::create(transport_client, attestation_behavior).await?;
 
         Ok(Self { inner })
     }
@@ -94,4 +280,9 @@ impl AttestationClient {
     pub async fn send(&mut self, payload: &[u8]) -> anyhow::Result<Vec<u8>> {
         self.inner.message(payload).await
     }
+
+    /// Returns the attestation evidence of the server in the last handshake.
+    pub fn evidence(&self) -> Option<&HandshakeEvidence> {
+        self.inner.evidence()
+    }
 }
@@ -22,3 +22,6 @@ pub mod proto {
 }
 pub mod client;
 pub mod server;
+
+#[cfg(test)]
+mod tests;
@@ -18,25 +18,61 @@
 //! protocol.
 
 use crate::proto::{unary_session_server::UnarySession, UnaryRequest, UnaryResponse};
-use oak_remote_attestation::handshaker::{AttestationBehavior, EmptyAttestationVerifier};
+use oak_remote_attestation::{
+    crypto::ReplayProtection,
+    handshaker::{
+        AttestationBehavior, AttestationGenerator, AttestationVerifier, EmptyAttestationVerifier,
+    },
+    resumption::{SessionTicketKey, SessionTickets},
+};
 use oak_remote_attestation_amd::PlaceholderAmdAttestationGenerator;
-use oak_remote_attestation_sessions::{SessionId, SessionState, SessionTracker};
+use oak_remote_attestation_sessions::{
+    SessionConfig, SessionId, SessionLifetime, SessionState, SessionTracker,
+};
 use oak_utils::LogError;
-use std::{convert::TryInto, sync::Mutex};
+use std::{
+    convert::TryInto,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tonic;
 
 /// Number of sessions that will be kept in memory.
 const SESSIONS_CACHE_SIZE: usize = 10000;
 
+/// Absolute lifetime of sessions, after which clients have to perform a new handshake.
+const SESSION_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Number of requests of a session that are accepted out of order.
+///
+/// Unary requests are independent calls, which may be delivered out of order (e.g. if a client
+/// sends them over different connections, or retries them), and requests of the same session are
+/// handled concurrently.
+const REPLAY_WINDOW_SIZE: u8 = 32;
+
+/// Returns the current time as the duration since the Unix epoch.
+fn current_time() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
 /// gRPC Attestation Service implementation.
-pub struct AttestationServer<F, L: LogError> {
+///
+/// The server attests itself to clients with `G`, and verifies the attestation of clients with `V`.
+/// By default the server sends a placeholder attestation, and clients are not attested.
+pub struct AttestationServer<
+    F,
+    L: LogError,
+    V: AttestationVerifier = EmptyAttestationVerifier,
+    G: AttestationGenerator = PlaceholderAmdAttestationGenerator,
+> {
     /// Business logic processor, accepts decrypted request and returns responses.
     request_handler: F,
     /// Error logging function that is required for logging attestation protocol errors.
     /// Errors are only logged on server side and are not sent to clients.
     error_logger: L,
-    session_tracker:
-        Mutex<SessionTracker<PlaceholderAmdAttestationGenerator, EmptyAttestationVerifier>>,
+    session_tracker: Mutex<SessionTracker<G, V>>,
 }
 
 impl<F, S, L> AttestationServer<F, L>
@@ -45,14 +81,93 @@ where
     S: std::future::Future<Output = anyhow::Result<Vec<u8>>> + Send + Sync,
     L: Send + Sync + Clone + LogError,
 {
+    /// Creates a server that sends a placeholder attestation and accepts any client. Sessions are
+    /// only known to this server.
     pub fn create(request_handler: F, error_logger: L) -> anyhow::Result<Self> {
-        let session_tracker = Mutex::new(SessionTracker::create(
+        Self::create_with_session_tickets(request_handler, error_logger, None)
+    }
+
+    /// Creates a server that issues session tickets sealed with `session_ticket_key`, if provided.
+    ///
+    /// Servers sharing the same key (e.g. replicas behind a load balancer) can resume each other's
+    /// sessions, so that requests of a session don't have to be routed to the same server. Tickets
+    /// expire together with the session they were issued for, so resuming a session doesn't extend
+    /// its lifetime. Without a key, no tickets are issued.
+    pub fn create_with_session_tickets(
+        request_handler: F,
+        error_logger: L,
+        session_ticket_key: Option<SessionTicketKey>,
+    ) -> anyhow::Result<Self> {
+        Self::create_with_attestation_verifier(
+            request_handler,
+            error_logger,
+            EmptyAttestationVerifier,
+            session_ticket_key,
+        )
+    }
+}
+
+impl<F, S, L, V> AttestationServer<F, L, V>
+where
+    F: Send + Sync + Clone + FnOnce(Vec<u8>) -> S,
+    S: std::future::Future<Output = anyhow::Result<Vec<u8>>> + Send + Sync,
+    L: Send + Sync + Clone + LogError,
+    V: AttestationVerifier,
+{
+    /// Creates a server that, unlike [`AttestationServer::create_with_session_tickets`], requires
+    /// clients to attest themselves, and verifies their attestation with the provided
+    /// [`AttestationVerifier`] during the handshake (i.e. bidirectional attestation). The server
+    /// itself still sends a placeholder attestation.
+    pub fn create_with_attestation_verifier(
+        request_handler: F,
+        error_logger: L,
+        attestation_verifier: V,
+        session_ticket_key: Option<SessionTicketKey>,
+    ) -> anyhow::Result<Self> {
+        Self::create_with_attestation_behavior(
+            request_handler,
+            error_logger,
+            AttestationBehavior::create(PlaceholderAmdAttestationGenerator, attestation_verifier),
+            session_ticket_key,
+        )
+    }
+}
+
+impl<F, S, L, V, G> AttestationServer<F, L, V, G>
+where
+    F: Send + Sync + Clone + FnOnce(Vec<u8>) -> S,
+    S: std::future::Future<Output = anyhow::Result<Vec<u8>>> + Send + Sync,
+    L: Send + Sync + Clone + LogError,
+    V: AttestationVerifier,
+    G: AttestationGenerator,
+{
+    /// Creates a server that, unlike [`AttestationServer::create_with_attestation_verifier`],
+    /// attests itself with the [`AttestationGenerator`] of the provided [`AttestationBehavior`]
+    /// instead of a placeholder attestation. Clients are verified with its [`AttestationVerifier`].
+    pub fn create_with_attestation_behavior(
+        request_handler: F,
+        error_logger: L,
+        attestation_behavior: AttestationBehavior<G, V>,
+        session_ticket_key: Option<SessionTicketKey>,
+    ) -> anyhow::Result<Self> {
+        let session_tickets = session_ticket_key.map(|key| SessionTickets {
+            key,
+            lifetime: SESSION_LIFETIME,
+            clock: current_time,
+        });
+        let session_tracker = Mutex::new(SessionTracker::create_with_config(
             SESSIONS_CACHE_SIZE,
-            AttestationBehavior::create(
-                PlaceholderAmdAttestationGenerator,
-                EmptyAttestationVerifier,
-            ),
-        ));
+            attestation_behavior,
+            SessionConfig {
+                replay_protection: ReplayProtection::SlidingWindow(REPLAY_WINDOW_SIZE),
+                lifetime: Some(SessionLifetime {
+                    duration: SESSION_LIFETIME,
+                    clock: current_time,
+                }),
+                session_tickets,
+                ..Default::default()
+            },
+        )?);
         Ok(Self {
             request_handler,
             error_logger,
@@ -62,11 +177,13 @@ where
 }
 
 #[tonic::async_trait]
-impl<F, S, L> UnarySession for AttestationServer<F, L>
+impl<F, S, L, V, G> UnarySession for AttestationServer<F, L, V, G>
 where
     F: 'static + Send + Sync + Clone + FnOnce(Vec<u8>) -> S,
     S: std::future::Future<Output = anyhow::Result<Vec<u8>>> + Send + Sync,
     L: Send + Sync + Clone + LogError + 'static,
+    V: AttestationVerifier + 'static,
+    G: AttestationGenerator + 'static,
 {
     async fn message(
         &self,
@@ -79,20 +196,19 @@ where
             tonic::Status::invalid_argument("")
         })?;
 
-        let mut session_state = {
-            self.session_tracker
-                .lock()
-                .expect("Couldn't lock session_state mutex")
-                .pop_or_create_session_state(session_id)
-                .map_err(|error| {
-                    error_logger.log_error(&format!("Couldn't pop session state: {:?}", error));
-                    tonic::Status::internal("")
-                })?
-        };
+        let session_state = self
+            .session_tracker
+            .lock()
+            .expect("Couldn't lock session_state mutex")
+            .get_or_create_session_state(session_id)
+            .map_err(|error| {
+                error_logger.log_error(&format!("Couldn't get session state: {:?}", error));
+                tonic::Status::internal("")
+            })?;
 
         let response_body = match session_state {
-            SessionState::HandshakeInProgress(ref mut handshaker) => {
-                handshaker
+            SessionState::HandshakeInProgress(mut handshaker) => {
+                let response_body = handshaker
                     .next_step(&request_inner.body)
                     .map_err(|error| {
                         error_logger
@@ -105,14 +221,29 @@ where
                     // request we do want to send an explicit confirmation in
                     // the form of a status message. Hence in case of `None`
                     // fallback to a default (empty) response.
-                    .unwrap_or_default()
+                    .unwrap_or_default();
+                // Note that we only get here if the handshake message was processed. Otherwise
+                // the state of the handshake is erased, which allows the client to negotiate a
+                // new handshake.
+                self.session_tracker
+                    .lock()
+                    .expect("Couldn't lock session_state mutex")
+                    .put_handshake_state(session_id, handshaker);
+                response_body
             }
-            SessionState::EncryptedMessageExchange(ref mut encryptor) => {
+            SessionState::EncryptedMessageExchange(encryptor) => {
+                // The session stays in the tracker while the request is handled, so that other
+                // requests of the session can be handled concurrently. A request that can't be
+                // decrypted (e.g. a replayed request) doesn't change the session.
                 let decrypted_request =
-                    encryptor.decrypt(&request_inner.body).map_err(|error| {
-                        error_logger.log_error(&format!("Couldn't decrypt request: {:?}", error));
-                        tonic::Status::aborted("")
-                    })?;
+                    encryptor
+                        .lock()
+                        .decrypt(&request_inner.body)
+                        .map_err(|error| {
+                            error_logger
+                                .log_error(&format!("Couldn't decrypt request: {:?}", error));
+                            tonic::Status::aborted("")
+                        })?;
 
                 let response = (self.request_handler.clone())(decrypted_request)
                     .await
@@ -121,21 +252,13 @@ where
                         tonic::Status::aborted("")
                     })?;
 
-                encryptor.encrypt(&response).map_err(|error| {
+                encryptor.lock().encrypt(&response).map_err(|error| {
                     error_logger.log_error(&format!("Couldn't encrypt response: {:?}", error));
                     tonic::Status::aborted("")
                 })?
             }
         };
 
-        // Note that we only get here if no errors occured during the preceding
-        // steps. If errors do occur the session state as tracked by the server
-        // is effectively erased. This allows the client to negotiate a new
-        // handshake.
-        self.session_tracker
-            .lock()
-            .expect("Couldn't lock session_state mutex")
-            .put_session_state(session_id, session_state);
         Ok(tonic::Response::new(UnaryResponse {
             body: response_body,
         }))
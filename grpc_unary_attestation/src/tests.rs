@@ -0,0 +1,379 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{
+    client::{AttestationClient, Transport},
+    proto::{
+        unary_session_server::{UnarySession, UnarySessionServer},
+        UnaryRequest,
+    },
+    server::AttestationServer,
+};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body,
+};
+use oak_remote_attestation::{
+    crypto::ReplayProtection,
+    handshaker::{AttestationBehavior, ClientHandshaker, EmptyAttestationGenerator, Encryptor},
+};
+use oak_remote_attestation_amd::{
+    PlaceholderAmdAttestationGenerator, PlaceholderAmdAttestationVerifier,
+};
+use oak_utils::testing::{TestLogger, TestServer};
+use prost::Message;
+use std::{
+    convert::Infallible,
+    net::{Ipv4Addr, SocketAddr, TcpListener},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Starts a server that requires clients to be attested with a placeholder AMD attestation, and
+/// echoes the requests of attested clients.
+async fn start_attested_server() -> TestServer {
+    let server = AttestationServer::create_with_attestation_verifier(
+        |request| async move { Ok(request) },
+        TestLogger,
+        PlaceholderAmdAttestationVerifier,
+        None,
+    )
+    .expect("Couldn't create attestation server");
+    TestServer::start(
+        tonic::transport::Server::builder().add_service(UnarySessionServer::new(server)),
+        None,
+    )
+    .await
+}
+
+/// Encodes a frame of a gRPC-web body.
+fn grpc_web_frame(flags: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![flags];
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Starts a server that only accepts gRPC-web requests over HTTP/1.1, like a server behind a proxy
+/// without HTTP/2 support, and echoes the requests of clients. Returns the address of the server.
+fn start_grpc_web_server() -> SocketAddr {
+    let listener =
+        TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).expect("Couldn't bind to a free port");
+    let address = listener.local_addr().unwrap();
+    let server = Arc::new(
+        AttestationServer::create(|request| async move { Ok(request) }, TestLogger)
+            .expect("Couldn't create attestation server"),
+    );
+    let make_service = make_service_fn(move |_connection| {
+        let server = server.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                let server = server.clone();
+                async move {
+                    let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                    // Skip the header of the frame that contains the request.
+                    let request = UnaryRequest::decode(&body[5..]).unwrap();
+                    let mut response_body = Vec::new();
+                    let code = match server.message(tonic::Request::new(request)).await {
+                        Ok(response) => {
+                            response_body
+                                .extend(grpc_web_frame(0, &response.into_inner().encode_to_vec()));
+                            tonic::Code::Ok
+                        }
+                        Err(status) => status.code(),
+                    };
+                    let trailers = format!("grpc-status:{}\r\n", code as i32);
+                    response_body.extend(grpc_web_frame(0x80, trailers.as_bytes()));
+                    Ok::<_, Infallible>(hyper::Response::new(Body::from(response_body)))
+                }
+            }))
+        }
+    });
+    tokio::spawn(
+        hyper::Server::from_tcp(listener)
+            .unwrap()
+            .http1_only(true)
+            .serve(make_service),
+    );
+    address
+}
+
+/// Starts an HTTP/1.1-only server that, like the HTTP gateway of the Oak Functions loader, accepts
+/// serialized `UnaryRequest` messages and echoes the requests of clients. Returns the address of
+/// the server.
+fn start_http_gateway_server() -> SocketAddr {
+    let listener =
+        TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).expect("Couldn't bind to a free port");
+    let address = listener.local_addr().unwrap();
+    let server = Arc::new(
+        AttestationServer::create(|request| async move { Ok(request) }, TestLogger)
+            .expect("Couldn't create attestation server"),
+    );
+    let make_service = make_service_fn(move |_connection| {
+        let server = server.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                let server = server.clone();
+                async move {
+                    assert_eq!(
+                        request.headers()[hyper::header::CONTENT_TYPE],
+                        "application/x-protobuf"
+                    );
+                    let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                    let response = match UnaryRequest::decode(body) {
+                        Ok(request) => match server.message(tonic::Request::new(request)).await {
+                            Ok(response) => hyper::Response::new(Body::from(
+                                response.into_inner().encode_to_vec(),
+                            )),
+                            Err(_) => empty_response(hyper::StatusCode::CONFLICT),
+                        },
+                        Err(_) => empty_response(hyper::StatusCode::BAD_REQUEST),
+                    };
+                    Ok::<_, Infallible>(response)
+                }
+            }))
+        }
+    });
+    tokio::spawn(
+        hyper::Server::from_tcp(listener)
+            .unwrap()
+            .http1_only(true)
+            .serve(make_service),
+    );
+    address
+}
+
+fn empty_response(status: hyper::StatusCode) -> hyper::Response<Body> {
+    let mut response = hyper::Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+/// Sends a message of the session with the given ID directly to the server, without a transport.
+async fn send_message<S: UnarySession>(
+    server: &S,
+    session_id: &[u8],
+    body: Vec<u8>,
+) -> Result<Vec<u8>, tonic::Status> {
+    server
+        .message(tonic::Request::new(UnaryRequest {
+            body,
+            session_id: session_id.to_vec(),
+        }))
+        .await
+        .map(|response| response.into_inner().body)
+}
+
+/// Performs a handshake with the server in the session with the given ID, and returns the
+/// [`Encryptor`] of the client.
+async fn handshake<S: UnarySession>(server: &S, session_id: &[u8]) -> Encryptor {
+    let mut handshaker = ClientHandshaker::new(AttestationBehavior::create(
+        EmptyAttestationGenerator,
+        PlaceholderAmdAttestationVerifier,
+    ))
+    .unwrap();
+    let client_hello = handshaker.create_client_hello().unwrap();
+    let mut response = send_message(server, session_id, client_hello)
+        .await
+        .unwrap();
+    while !handshaker.is_completed() {
+        if let Some(request) = handshaker.next_step(&response).unwrap() {
+            response = send_message(server, session_id, request).await.unwrap();
+        }
+    }
+    handshaker.get_encryptor().unwrap()
+}
+
+#[tokio::test]
+async fn test_bidirectional_attestation() {
+    let server = start_attested_server().await;
+
+    let mut client = AttestationClient::create_with_attestation_behavior(
+        &server.uri(),
+        AttestationBehavior::create(
+            PlaceholderAmdAttestationGenerator,
+            PlaceholderAmdAttestationVerifier,
+        ),
+    )
+    .await
+    .expect("Couldn't create attested client");
+    assert_eq!(client.send(b"first").await.unwrap(), b"first");
+    assert_eq!(client.send(b"second").await.unwrap(), b"second");
+
+    // The attestation of the server can be verified again from the recorded evidence.
+    let evidence = client.evidence().expect("Missing handshake evidence");
+    assert!(evidence.is_accepted());
+    assert!(evidence.verify(&PlaceholderAmdAttestationVerifier).is_ok());
+
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_unattested_client() {
+    let server = start_attested_server().await;
+
+    // The server rejects the handshake of a client that doesn't attest itself.
+    let result = AttestationClient::create_with_attestation_behavior(
+        &server.uri(),
+        AttestationBehavior::create(EmptyAttestationGenerator, PlaceholderAmdAttestationVerifier),
+    )
+    .await;
+    assert!(result.is_err());
+
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_grpc_web_transport() {
+    let address = start_grpc_web_server();
+    let uri = format!("http://{}", address);
+
+    let mut client = AttestationClient::create_with_transport(
+        &uri,
+        Transport::GrpcWeb,
+        AttestationBehavior::create(EmptyAttestationGenerator, PlaceholderAmdAttestationVerifier),
+    )
+    .await
+    .expect("Couldn't create gRPC-web client");
+    assert_eq!(client.send(b"first").await.unwrap(), b"first");
+    assert_eq!(client.send(b"second").await.unwrap(), b"second");
+
+    // gRPC requires HTTP/2, which the server doesn't support.
+    assert!(AttestationClient::create(&uri).await.is_err());
+}
+
+#[tokio::test]
+async fn test_http_transport() {
+    let address = start_http_gateway_server();
+    let uri = format!("http://{}", address);
+
+    let mut client = AttestationClient::create_with_transport(
+        &uri,
+        Transport::Http,
+        AttestationBehavior::create(EmptyAttestationGenerator, PlaceholderAmdAttestationVerifier),
+    )
+    .await
+    .expect("Couldn't create HTTP client");
+    assert_eq!(client.send(b"first").await.unwrap(), b"first");
+    assert_eq!(client.send(b"second").await.unwrap(), b"second");
+
+    // A handshake that fails in the server is reported as an aborted session.
+    let error = AttestationClient::create_with_transport(
+        &uri,
+        Transport::Http,
+        AttestationBehavior::create(
+            PlaceholderAmdAttestationGenerator,
+            PlaceholderAmdAttestationVerifier,
+        ),
+    )
+    .await
+    .err()
+    .expect("Server accepted an unexpected client attestation");
+    assert!(error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<tonic::Status>())
+        .any(|status| status.code() == tonic::Code::Aborted));
+
+    assert_eq!("http".parse::<Transport>().unwrap(), Transport::Http);
+}
+
+#[tokio::test]
+async fn test_reordered_and_replayed_requests() {
+    let server = AttestationServer::create(|request| async move { Ok(request) }, TestLogger)
+        .expect("Couldn't create attestation server");
+    let session_id = [1; 8];
+    let mut encryptor = handshake(&server, &session_id).await;
+
+    let first = encryptor.encrypt(b"first").unwrap();
+    let second = encryptor.encrypt(b"second").unwrap();
+
+    // Unary requests may arrive out of order, e.g. when sent over different connections.
+    let response = send_message(&server, &session_id, second.clone())
+        .await
+        .expect("Reordered request was rejected");
+    assert_eq!(encryptor.decrypt(&response).unwrap(), b"second");
+    let response = send_message(&server, &session_id, first)
+        .await
+        .expect("Reordered request was rejected");
+    assert_eq!(encryptor.decrypt(&response).unwrap(), b"first");
+
+    // Requests that were already received are rejected.
+    let result = send_message(&server, &session_id, second).await;
+    assert_eq!(result.unwrap_err().code(), tonic::Code::Aborted);
+}
+
+#[tokio::test]
+async fn test_replayed_request_keeps_session() {
+    let server = AttestationServer::create(|request| async move { Ok(request) }, TestLogger)
+        .expect("Couldn't create attestation server");
+    let session_id = [1; 8];
+    let mut encryptor = handshake(&server, &session_id).await;
+
+    let first = encryptor.encrypt(b"first").unwrap();
+    let response = send_message(&server, &session_id, first.clone())
+        .await
+        .unwrap();
+    assert_eq!(encryptor.decrypt(&response).unwrap(), b"first");
+
+    // Neither a replayed request nor a message that can't be decrypted tear down the session.
+    let result = send_message(&server, &session_id, first).await;
+    assert_eq!(result.unwrap_err().code(), tonic::Code::Aborted);
+    let result = send_message(&server, &session_id, b"garbage".to_vec()).await;
+    assert_eq!(result.unwrap_err().code(), tonic::Code::Aborted);
+
+    let second = encryptor.encrypt(b"second").unwrap();
+    let response = send_message(&server, &session_id, second)
+        .await
+        .expect("Session was torn down");
+    assert_eq!(encryptor.decrypt(&response).unwrap(), b"second");
+}
+
+#[tokio::test]
+async fn test_concurrent_requests_of_session() {
+    let server = AttestationServer::create(
+        |request| async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok(request)
+        },
+        TestLogger,
+    )
+    .expect("Couldn't create attestation server");
+    let session_id = [1; 8];
+    let mut encryptor = handshake(&server, &session_id).await;
+    // The responses may be completed in any order.
+    encryptor
+        .set_replay_protection(ReplayProtection::SlidingWindow(2))
+        .unwrap();
+
+    let first = encryptor.encrypt(b"first").unwrap();
+    let second = encryptor.encrypt(b"second").unwrap();
+    let (first_response, second_response) = tokio::join!(
+        send_message(&server, &session_id, first),
+        send_message(&server, &session_id, second),
+    );
+    assert_eq!(
+        encryptor
+            .decrypt(&first_response.expect("Concurrent request was rejected"))
+            .unwrap(),
+        b"first"
+    );
+    assert_eq!(
+        encryptor
+            .decrypt(&second_response.expect("Concurrent request was rejected"))
+            .unwrap(),
+        b"second"
+    );
+}
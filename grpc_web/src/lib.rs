@@ -0,0 +1,326 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! A gRPC-web client for unary requests, usable both in browsers (when compiled to Wasm, requests
+//! are sent with the Fetch API) and in native code (requests are sent with reqwest). Since gRPC-web
+//! doesn't require HTTP/2, native clients can also reach servers behind proxies that only support
+//! HTTP/1.1.
+//!
+//! Manually written, as while a web-compatible port of the tonic gRPC client for exists, it
+//! (at the time of writing) is not well maintained and has version conflicts with the newest
+//! tonic.
+
+use anyhow::{anyhow, Context};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use reqwest::{header::HeaderMap, StatusCode};
+
+#[cfg(test)]
+mod tests;
+
+/// gRPC status codes used by [`Status`].
+///
+/// Ref: https://github.com/grpc/grpc/blob/8558f46d35cedc3ea31787aebf8d9cb07a3fc547/doc/statuscodes.md
+pub mod code {
+    pub const OK: u32 = 0;
+    pub const UNKNOWN: u32 = 2;
+    pub const PERMISSION_DENIED: u32 = 7;
+    pub const ABORTED: u32 = 10;
+    pub const UNIMPLEMENTED: u32 = 12;
+    pub const INTERNAL: u32 = 13;
+    pub const UNAVAILABLE: u32 = 14;
+    pub const UNAUTHENTICATED: u32 = 16;
+}
+
+/// Unsuccessful gRPC status of a request, returned (wrapped in an [`anyhow::Error`]) when the
+/// server couldn't be reached, or didn't handle the request successfully.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    code: u32,
+    message: String,
+}
+
+impl Status {
+    pub fn new(code: u32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Returns the gRPC status code, as defined in [`code`].
+    pub fn code(&self) -> u32 {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the status of a response whose HTTP status code is not successful, e.g. because it
+    /// was returned by a proxy rather than the gRPC-web server.
+    ///
+    /// Ref: https://github.com/grpc/grpc/blob/8558f46d35cedc3ea31787aebf8d9cb07a3fc547/doc/http-grpc-status-mapping.md
+    fn from_http_status(status: StatusCode) -> Self {
+        let code = match status.as_u16() {
+            400 => code::INTERNAL,
+            401 => code::UNAUTHENTICATED,
+            403 => code::PERMISSION_DENIED,
+            404 => code::UNIMPLEMENTED,
+            429 | 502 | 503 | 504 => code::UNAVAILABLE,
+            _ => code::UNKNOWN,
+        };
+        Self::new(code, format!("Received HTTP status {}", status))
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "gRPC status {}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for Status {}
+
+/// Client for unary gRPC-web services, which reuses connections across requests.
+#[derive(Clone, Default)]
+pub struct Client {
+    http_client: reqwest::Client,
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Invokes the unary gRPC-web method at `uri`, which consists of the URI of the server
+    /// followed by the path of the method (e.g. `/package.Service/Method`).
+    ///
+    /// If the server couldn't be reached, or responded with an unsuccessful status, the returned
+    /// error contains a [`Status`]. A response without a grpc-status, neither in its trailers nor
+    /// in its headers, is not known to be successful, and is reported with an `INTERNAL` status.
+    pub async fn unary<A: prost::Message, B: Default + prost::Message>(
+        &self,
+        uri: &str,
+        message: A,
+    ) -> anyhow::Result<B> {
+        let request_bytes = encode_body(message).context("failed to encode message")?;
+        let (header_status, response_bytes) = self
+            .send(uri, request_bytes)
+            .await
+            .context("failed to send message")?;
+        let frames = decode_frames(response_bytes).context("failed to decode response")?;
+        let status = match frames.trailers {
+            Some(trailers) => parse_trailers(&trailers).context("failed to decode trailers")?,
+            None => header_status.ok_or_else(|| {
+                Status::new(code::INTERNAL, "Response doesn't contain a grpc-status")
+            })?,
+        };
+        if status.code != code::OK {
+            return Err(status.into());
+        }
+        let reply = B::decode(
+            frames
+                .message
+                .context("response doesn't contain a message")?,
+        )
+        .map_err(|error| anyhow!("Couldn't decode message {:?}", error))?;
+        Ok(reply)
+    }
+
+    /// Sends the request, and returns the status in the headers of the response (if any) together
+    /// with the body of the response.
+    async fn send(
+        &self,
+        uri: &str,
+        request_bytes: Bytes,
+    ) -> anyhow::Result<(Option<Status>, Bytes)> {
+        let response = self
+            .http_client
+            .post(uri)
+            .header(reqwest::header::CONTENT_TYPE, "application/grpc-web")
+            // Setting this custom header includes its key in preflight request's
+            // `access-control-request-headers` header, marking the preflight
+            // request as related to grpc-web.
+            // Ref: https://github.com/hyperium/tonic/blob/8084f4ea26cccf9bd2d96d2a81eaea490aaf603b/tonic-web/src/service.rs#L43
+            .header("x-grpc-web", "1")
+            .body(request_bytes)
+            .send()
+            .await
+            // As with gRPC over HTTP/2, a server that can't be reached is reported as unavailable.
+            .map_err(|error| {
+                Status::new(
+                    code::UNAVAILABLE,
+                    format!("Couldn't get response {:?}", error),
+                )
+            })?;
+        if !response.status().is_success() {
+            return Err(Status::from_http_status(response.status()).into());
+        }
+        // Responses without a message (e.g. errors) may contain the status in their headers,
+        // rather than in a trailers frame.
+        let status = header_status(response.headers())?;
+        if let Some(status) = &status {
+            if status.code != code::OK {
+                return Err(status.clone().into());
+            }
+        }
+        let response_bytes = response.bytes().await.map_err(|error| {
+            Status::new(
+                code::UNAVAILABLE,
+                format!("Couldn't get response bytes {:?}", error),
+            )
+        })?;
+        Ok((status, response_bytes))
+    }
+}
+
+/// Invokes unary gRPC-web service, with a new [`Client`].
+pub async fn grpc_web_unary<A: prost::Message, B: Default + prost::Message>(
+    uri: &str,
+    message: A,
+) -> anyhow::Result<B> {
+    Client::new().unary(uri, message).await
+}
+
+// One byte for the compression flag plus four bytes for the length.
+// Ref: https://github.com/grpc/grpc/blob/8558f46d35cedc3ea31787aebf8d9cb07a3fc547/doc/PROTOCOL-HTTP2.md
+const GRPC_HEADER_SIZE: usize = 5;
+
+// Flags in the first byte of a frame.
+// Ref: https://github.com/grpc/grpc/blob/8558f46d35cedc3ea31787aebf8d9cb07a3fc547/doc/PROTOCOL-WEB.md
+const COMPRESSED_FLAG: u8 = 0x01;
+const TRAILERS_FLAG: u8 = 0x80;
+
+// Based off https://github.com/hyperium/tonic/blob/91b73f9fc3c1bc281e85177808721b3efe37ece0/examples/src/grpc-web/client.rs
+fn encode_body<T>(msg: T) -> anyhow::Result<Bytes>
+where
+    T: prost::Message,
+{
+    let mut buf = BytesMut::with_capacity(1024);
+
+    // first skip past the header by writing placeholder bytes
+    buf.reserve(GRPC_HEADER_SIZE);
+    buf.put_bytes(0, GRPC_HEADER_SIZE);
+
+    // write the message
+    msg.encode(&mut buf)
+        .map_err(|error| anyhow!("Couldn't encode message {:?}", error))?;
+
+    // now we know the size of encoded message and can write the
+    // header
+    let len = buf.len() - GRPC_HEADER_SIZE;
+    {
+        let mut buf = &mut buf[..GRPC_HEADER_SIZE];
+
+        // compression flag, 0 means "no compression"
+        buf.put_u8(0);
+
+        buf.put_u32(len as u32);
+    }
+
+    Ok(buf.split_to(len + GRPC_HEADER_SIZE).freeze())
+}
+
+/// Frames of a gRPC-web response body.
+#[derive(Debug, Default, PartialEq)]
+struct ResponseFrames {
+    /// The encoded message, if any.
+    message: Option<Bytes>,
+    /// The trailers, encoded as HTTP/1 headers.
+    trailers: Option<Bytes>,
+}
+
+fn decode_frames(mut body: Bytes) -> anyhow::Result<ResponseFrames> {
+    let mut frames = ResponseFrames::default();
+    while body.has_remaining() {
+        if body.remaining() < GRPC_HEADER_SIZE {
+            anyhow::bail!("Incomplete frame header");
+        }
+        let flags = body.get_u8();
+        let len = body.get_u32() as usize;
+        if body.remaining() < len {
+            anyhow::bail!("Incomplete frame");
+        }
+        let frame = body.split_to(len);
+        if flags & TRAILERS_FLAG != 0 {
+            frames.trailers = Some(frame);
+        } else if flags & COMPRESSED_FLAG != 0 {
+            anyhow::bail!("Compressed messages are not supported");
+        } else {
+            frames.message = Some(frame);
+        }
+    }
+    Ok(frames)
+}
+
+/// Returns the status contained in the given trailers.
+fn parse_trailers(trailers: &[u8]) -> anyhow::Result<Status> {
+    let trailers = std::str::from_utf8(trailers).context("Trailers are not valid UTF-8")?;
+    let mut code = None;
+    let mut message = String::new();
+    for line in trailers.split("\r\n").filter(|line| !line.is_empty()) {
+        let (name, value) = line
+            .split_once(':')
+            .with_context(|| format!("Invalid trailer {:?}", line))?;
+        match name.trim().to_ascii_lowercase().as_str() {
+            "grpc-status" => code = Some(parse_code(value)?),
+            "grpc-message" => message = percent_decode(value.trim())?,
+            _ => {}
+        }
+    }
+    Ok(Status::new(
+        code.context("Trailers don't contain a grpc-status")?,
+        message,
+    ))
+}
+
+/// Returns the status contained in the given headers, if any.
+fn header_status(headers: &HeaderMap) -> anyhow::Result<Option<Status>> {
+    let code = match headers.get("grpc-status") {
+        Some(code) => parse_code(code.to_str().context("Invalid grpc-status header")?)?,
+        None => return Ok(None),
+    };
+    let message = match headers.get("grpc-message") {
+        Some(message) => percent_decode(message.to_str().context("Invalid grpc-message header")?)?,
+        None => String::new(),
+    };
+    Ok(Some(Status::new(code, message)))
+}
+
+fn parse_code(code: &str) -> anyhow::Result<u32> {
+    code.trim()
+        .parse()
+        .with_context(|| format!("Invalid grpc-status {:?}", code))
+}
+
+/// Decodes a percent-encoded grpc-message.
+fn percent_decode(message: &str) -> anyhow::Result<String> {
+    let mut bytes = message.bytes();
+    let mut decoded = Vec::with_capacity(message.len());
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let hex = [
+                bytes.next().context("Incomplete percent-encoding")?,
+                bytes.next().context("Incomplete percent-encoding")?,
+            ];
+            let hex = std::str::from_utf8(&hex).context("Invalid percent-encoding")?;
+            decoded.push(u8::from_str_radix(hex, 16).context("Invalid percent-encoding")?);
+        } else {
+            decoded.push(byte);
+        }
+    }
+    String::from_utf8(decoded).context("Message is not valid UTF-8")
+}
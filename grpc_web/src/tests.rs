@@ -0,0 +1,193 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use super::*;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Response, Server,
+};
+use std::{
+    convert::Infallible,
+    net::{Ipv4Addr, SocketAddr, TcpListener},
+};
+
+/// Encodes a frame of a gRPC-web response body.
+fn frame(flags: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![flags];
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Starts an HTTP/1.1-only server that responds to every request with the given response, and
+/// returns its address.
+fn start_server(response: fn(Bytes) -> Response<Body>) -> SocketAddr {
+    let listener =
+        TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).expect("Couldn't bind to a free port");
+    let address = listener.local_addr().unwrap();
+    let make_service = make_service_fn(move |_connection| async move {
+        Ok::<_, Infallible>(service_fn(
+            move |request: hyper::Request<Body>| async move {
+                let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                Ok::<_, Infallible>(response(body))
+            },
+        ))
+    });
+    let server = Server::from_tcp(listener)
+        .unwrap()
+        .http1_only(true)
+        .serve(make_service);
+    tokio::spawn(server);
+    address
+}
+
+/// Returns a response echoing the message of the request, followed by an OK status.
+fn echo(request: Bytes) -> Response<Body> {
+    let mut body = request.to_vec();
+    body.extend(frame(TRAILERS_FLAG, b"grpc-status:0\r\ngrpc-message:\r\n"));
+    Response::new(Body::from(body))
+}
+
+fn status_code(error: &anyhow::Error) -> Option<u32> {
+    error.downcast_ref::<Status>().map(Status::code)
+}
+
+#[test]
+fn test_encode_decode_frames() {
+    let body = encode_body("Hello".to_string()).unwrap();
+    let message = prost::Message::encode_to_vec(&"Hello".to_string());
+    assert_eq!(body.to_vec(), frame(0, &message));
+
+    let mut body = body.to_vec();
+    body.extend(frame(TRAILERS_FLAG, b"grpc-status:0\r\n"));
+    assert_eq!(
+        decode_frames(Bytes::from(body)).unwrap(),
+        ResponseFrames {
+            message: Some(Bytes::from(message)),
+            trailers: Some(Bytes::from_static(b"grpc-status:0\r\n")),
+        }
+    );
+
+    assert!(decode_frames(Bytes::from(vec![0, 0, 0])).is_err());
+    assert!(decode_frames(Bytes::from(vec![0, 0, 0, 0, 2, 1])).is_err());
+    assert!(decode_frames(Bytes::from(frame(COMPRESSED_FLAG, b"Hello"))).is_err());
+}
+
+#[test]
+fn test_parse_trailers() {
+    assert_eq!(
+        parse_trailers(b"grpc-status:14\r\ngrpc-message:Session%20lost\r\n").unwrap(),
+        Status::new(code::UNAVAILABLE, "Session lost")
+    );
+    assert_eq!(
+        parse_trailers(b"Grpc-Status: 0\r\n").unwrap(),
+        Status::new(code::OK, "")
+    );
+    assert!(parse_trailers(b"grpc-message:Hello\r\n").is_err());
+    assert!(parse_trailers(b"grpc-status:OK\r\n").is_err());
+    assert!(parse_trailers(b"grpc-status:2\r\ngrpc-message:%2\r\n").is_err());
+}
+
+#[tokio::test]
+async fn test_unary_over_http1() {
+    let address = start_server(echo);
+    let client = Client::new();
+    let uri = format!("http://{}/test.Echo/Echo", address);
+
+    let reply: String = client.unary(&uri, "Hello".to_string()).await.unwrap();
+    assert_eq!(reply, "Hello");
+    // The connection is reused by later requests.
+    let reply: String = client.unary(&uri, "World".to_string()).await.unwrap();
+    assert_eq!(reply, "World");
+}
+
+#[tokio::test]
+async fn test_unary_status() {
+    let client = Client::new();
+
+    // Status in the trailers frame.
+    let address = start_server(|_request| {
+        Response::new(Body::from(frame(
+            TRAILERS_FLAG,
+            b"grpc-status:10\r\ngrpc-message:Aborted\r\n",
+        )))
+    });
+    let uri = format!("http://{}/test.Echo/Echo", address);
+    let error = client
+        .unary::<_, String>(&uri, String::new())
+        .await
+        .unwrap_err();
+    assert_eq!(status_code(&error), Some(code::ABORTED));
+
+    // Status in the headers of a response without a body.
+    let address = start_server(|_request| {
+        Response::builder()
+            .header("grpc-status", "13")
+            .header("grpc-message", "Internal%20error")
+            .body(Body::empty())
+            .unwrap()
+    });
+    let uri = format!("http://{}/test.Echo/Echo", address);
+    let error = client
+        .unary::<_, String>(&uri, String::new())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<Status>(),
+        Some(&Status::new(code::INTERNAL, "Internal error"))
+    );
+
+    // Status in the headers of a response with a message and without trailers.
+    let address = start_server(|request| {
+        Response::builder()
+            .header("grpc-status", "0")
+            .body(Body::from(request))
+            .unwrap()
+    });
+    let uri = format!("http://{}/test.Echo/Echo", address);
+    let reply: String = client.unary(&uri, "Hello".to_string()).await.unwrap();
+    assert_eq!(reply, "Hello");
+
+    // A response without a status, e.g. a truncated response, is not successful.
+    let address = start_server(|request| Response::new(Body::from(request)));
+    let uri = format!("http://{}/test.Echo/Echo", address);
+    let error = client
+        .unary::<_, String>(&uri, "Hello".to_string())
+        .await
+        .unwrap_err();
+    assert_eq!(status_code(&error), Some(code::INTERNAL));
+
+    // Unsuccessful HTTP status, e.g. returned by a proxy.
+    let address =
+        start_server(|_request| Response::builder().status(503).body(Body::empty()).unwrap());
+    let uri = format!("http://{}/test.Echo/Echo", address);
+    let error = client
+        .unary::<_, String>(&uri, String::new())
+        .await
+        .unwrap_err();
+    assert_eq!(status_code(&error), Some(code::UNAVAILABLE));
+
+    // Unreachable server.
+    let address = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|listener| listener.local_addr())
+        .unwrap();
+    let uri = format!("http://{}/test.Echo/Echo", address);
+    let error = client
+        .unary::<_, String>(&uri, String::new())
+        .await
+        .unwrap_err();
+    assert_eq!(status_code(&error), Some(code::UNAVAILABLE));
+}
@@ -34,6 +34,7 @@ pub mod proto {
 /// See REQUEST_RESPONSE_ENCODING.MD in the crate root.
 #[derive(Clone, PartialEq, Debug)]
 pub struct Request {
+    /// body (without padding)
     pub body: alloc::vec::Vec<u8>,
 }
 
@@ -59,6 +60,13 @@ pub enum StatusCode {
     InternalServerError = 5,
 }
 
+// As defined in REQUEST_RESPONSE_ENCODING.MD in the crate root.
+const REQUEST_PADDING_MARKER: &[u8; REQUEST_PADDING_MARKER_SIZE] = b"\0OAKPAD\0";
+const REQUEST_PADDING_MARKER_SIZE: usize = 8;
+type RequestLength = u64;
+const REQUEST_LENGTH_SIZE: usize = 8;
+static_assertions::assert_eq_size!([u8; REQUEST_LENGTH_SIZE], RequestLength);
+
 // As defined in REQUEST_RESPONSE_ENCODING.MD in the crate root.
 const RESPONSE_STATUS_CODE_SIZE: usize = 4;
 const RESPONSE_STATUS_CODE_OFFSET: usize = 0;
@@ -73,6 +81,69 @@ static_assertions::assert_eq_size!([u8; RESPONSE_LENGTH_SIZE], ResponseLength);
 // As defined in REQUEST_RESPONSE_ENCODING.MD in the crate root.
 const RESPONSE_BODY_OFFSET: usize = RESPONSE_STATUS_CODE_SIZE + RESPONSE_LENGTH_SIZE;
 
+impl Request {
+    /// Encodes the request without padding, i.e. as its raw body.
+    ///
+    /// A body that starts with the padding marker is encoded with an empty padding instead, so
+    /// that it is not mistaken for a padded request.
+    pub fn encode_to_vec(&self) -> Vec<u8> {
+        if self.body.starts_with(REQUEST_PADDING_MARKER) {
+            self.encode_padded(1)
+        } else {
+            self.body.clone()
+        }
+    }
+
+    /// Encodes the request with the padding marker and the length of the body, and adds trailing
+    /// 0s to make the length of the encoded request a multiple of `bucket_size`, so that requests
+    /// with bodies of similar lengths cannot be distinguished by their size.
+    pub fn encode_padded(&self, bucket_size: usize) -> Vec<u8> {
+        let bucket_size = bucket_size.max(1);
+        let length = REQUEST_PADDING_MARKER_SIZE + REQUEST_LENGTH_SIZE + self.body.len();
+        let padded_length = length + (bucket_size - length % bucket_size) % bucket_size;
+        let mut vec: Vec<u8> = Vec::with_capacity(padded_length);
+        vec.extend_from_slice(REQUEST_PADDING_MARKER);
+        vec.extend_from_slice(&(self.body.len() as RequestLength).to_le_bytes());
+        vec.extend_from_slice(&self.body);
+        vec.resize(padded_length, 0);
+        vec
+    }
+
+    /// Decodes a request, and removes its padding if it starts with the padding marker. Any other
+    /// request is decoded as its raw body.
+    ///
+    /// Returns an error if the effective length of the body of a padded request exceeds the
+    /// encoded request, or if its padding contains anything but 0s.
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let length_body_and_padding = match bytes.strip_prefix(REQUEST_PADDING_MARKER) {
+            Some(length_body_and_padding) => length_body_and_padding,
+            None => {
+                return Ok(Self {
+                    body: bytes.to_vec(),
+                })
+            }
+        };
+        if length_body_and_padding.len() < REQUEST_LENGTH_SIZE {
+            anyhow::bail!("Invalid request: request too short.")
+        }
+        let (length_bytes, body_and_padding) =
+            length_body_and_padding.split_at(REQUEST_LENGTH_SIZE);
+        let mut length_buffer = [0; REQUEST_LENGTH_SIZE];
+        length_buffer.copy_from_slice(length_bytes);
+        let length = usize::try_from(RequestLength::from_le_bytes(length_buffer))
+            .ok()
+            .filter(|length| *length <= body_and_padding.len())
+            .ok_or_else(|| anyhow::Error::msg("Invalid request: length exceeds the request."))?;
+        let (body, padding) = body_and_padding.split_at(length);
+        if padding.iter().any(|byte| *byte != 0) {
+            anyhow::bail!("Invalid request: padding contains non-zero bytes.")
+        }
+        Ok(Self {
+            body: body.to_vec(),
+        })
+    }
+}
+
 impl Response {
     /// Creates a new instance of Response.
     ///
@@ -64,6 +64,49 @@ public class AttestationClient {
   private UnarySessionGrpc.UnarySessionBlockingStub stub;
   private AeadEncryptor encryptor;
 
+  /** A request sent to the trusted runtime */
+  public static class Request {
+    private static final byte[] PADDING_MARKER = {0, 'O', 'A', 'K', 'P', 'A', 'D', 0};
+    private static final int LENGTH_BYTE_LENGTH = 8;
+
+    /**
+     * Encodes the request body without padding, i.e. as the raw body, unless it starts with the
+     * padding marker.
+     */
+    public static byte[] encode(byte[] body) {
+      if (body.length >= PADDING_MARKER.length
+          && Arrays.equals(Arrays.copyOf(body, PADDING_MARKER.length), PADDING_MARKER)) {
+        return encodePadded(body, 1);
+      }
+      return body;
+    }
+
+    /**
+     * Encodes the request body with the padding marker and its length, and adds trailing 0s to make
+     * the length of the encoded request a multiple of {@code bucketSize}.
+     *
+     * @throws IllegalArgumentException if {@code bucketSize} is not positive, or if the padded
+     *     request would be too large for an array.
+     */
+    public static byte[] encodePadded(byte[] body, int bucketSize) {
+      if (bucketSize < 1) {
+        throw new IllegalArgumentException("Bucket size must be positive");
+      }
+      // Computed as longs, since rounding up to the bucket size may overflow an int.
+      long length = (long) PADDING_MARKER.length + LENGTH_BYTE_LENGTH + body.length;
+      long paddedLength = ((length + bucketSize - 1) / bucketSize) * bucketSize;
+      if (paddedLength > Integer.MAX_VALUE) {
+        throw new IllegalArgumentException("Padded request is too large");
+      }
+      ByteBuffer buffer = ByteBuffer.allocate((int) paddedLength);
+      buffer.order(ByteOrder.LITTLE_ENDIAN);
+      buffer.put(PADDING_MARKER);
+      buffer.putLong(body.length);
+      buffer.put(body);
+      return buffer.array();
+    }
+  }
+
   /** A response received from the trusted runtime */
   public static class Response {
     StatusCode statusCode;
@@ -108,7 +151,7 @@ public class AttestationClient {
     SUCCESS(1),
     // Indicates a problem with the request. Similar to HTTP 400 status code.
     BAD_REQUEST(2),
-    // Indicates violation of the response size limit specified in the security policy.
+    // Indicates violation of the request or response size limit specified in the security policy.
     POLICY_SIZE_VIOLATION(3),
     // Indicates violation of the response processing-time limit specified in the security policy.
     POLICY_TIME_VIOLATION(4),
@@ -237,14 +280,34 @@ public class AttestationClient {
    *
    * @param request contains a request to be sent via the attested gRPC channel.
    */
-  @SuppressWarnings("ProtoParseWithRegistry")
   public Response send(byte[] body)
       throws GeneralSecurityException, IOException, InterruptedException, IllegalArgumentException {
+    return sendEncoded(Request.encode(body));
+  }
+
+  /**
+   * Encrypts and sends a Request via an attested gRPC channel to the server and receives and
+   * decrypts the response. The Request is padded so that its encoded length is a multiple of
+   * {@code bucketSize}, which hides the exact length of the request body from the network.
+   *
+   * <p>This method can only be used after the {@code attest} method has been called successfully.
+   *
+   * @param request contains a request to be sent via the attested gRPC channel.
+   * @param bucketSize size that the encoded length of the request is padded to a multiple of.
+   */
+  public Response send(byte[] body, int bucketSize)
+      throws GeneralSecurityException, IOException, InterruptedException, IllegalArgumentException {
+    return sendEncoded(Request.encodePadded(body, bucketSize));
+  }
+
+  @SuppressWarnings("ProtoParseWithRegistry")
+  private Response sendEncoded(byte[] encodedRequest)
+      throws GeneralSecurityException, IOException, InterruptedException, IllegalArgumentException {
     if (channel == null || encryptor == null || sessionId == null || stub == null) {
       throw new IllegalStateException("Session is not available");
     }
 
-    byte[] encryptedData = encryptor.encrypt(body);
+    byte[] encryptedData = encryptor.encrypt(encodedRequest);
     UnaryRequest unaryRequest =
         UnaryRequest.newBuilder()
             .setBody(ByteString.copyFrom(encryptedData))
@@ -0,0 +1,72 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Verifies an evidence bundle saved by the Oak Functions client against a verification policy,
+//! e.g. for auditing which servers the client talked to.
+
+use anyhow::Context;
+use clap::Parser;
+use oak_functions_client::{
+    evidence::EvidenceBundle,
+    policy::{PolicyRejection, PolicyVerifier, VerificationPolicy},
+};
+use std::path::PathBuf;
+
+#[derive(Parser, Clone)]
+#[clap(about = "Oak Functions Evidence Verifier")]
+pub struct Opt {
+    /// Path to the JSON evidence bundle written by `oak_functions_client --evidence-bundle`.
+    #[clap(long)]
+    evidence_bundle: PathBuf,
+
+    /// Path to a TOML file containing the verification policy that the server must satisfy.
+    #[clap(long)]
+    verification_policy: PathBuf,
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let opt = Opt::parse();
+
+    let bundle = EvidenceBundle::load(&opt.evidence_bundle)?;
+    let policy = VerificationPolicy::load(&opt.verification_policy)?;
+    let verifier = PolicyVerifier::create(&policy)
+        .context("Could not create verifier for the verification policy")?;
+
+    match &bundle.verification_error {
+        None => println!("Recorded result: accepted"),
+        Some(error) => println!("Recorded result: rejected: {}", error),
+    }
+
+    match bundle.verify(&verifier) {
+        Ok(()) => {
+            println!("Verification result: accepted");
+            Ok(())
+        }
+        Err(error) => {
+            println!("Verification result: rejected");
+            match error.downcast_ref::<PolicyRejection>() {
+                Some(rejection) => {
+                    for reason in &rejection.reasons {
+                        println!("- {}", reason);
+                    }
+                }
+                None => println!("- {:#}", error),
+            }
+            Err(error.context("Evidence bundle does not satisfy the verification policy"))
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Endorsement-based verification of the Wasm module that an Oak Functions server runs.
+//!
+//! An [`EndorsementVerifier`] is an [`AttestationVerifier`] that takes the hash of the Wasm module
+//! from the attested [`ConfigurationReport`], and requires the [`AttestationEvidence`] sent by the
+//! server to contain an endorsement statement for it, together with the Rekor log entry of the
+//! endorsement. The log entry is verified offline with the public key of Rekor, so the client never
+//! contacts Rekor itself.
+//!
+//! [`ConfigurationReport`]: oak_functions_abi::proto::ConfigurationReport
+//! [`AttestationEvidence`]: oak_functions_abi::proto::AttestationEvidence
+
+use crate::{
+    policy::{
+        configuration_attested_data, decode_evidence, verify_placeholder_attestation,
+        PolicyRejection, TransparencyLogKeys,
+    },
+    rekor::unmarshal_pem_to_p256_public_key,
+};
+use anyhow::Context;
+use oak_remote_attestation::handshaker::AttestationVerifier;
+
+/// PEM-encoded public key of the Rekor instance hosted by sigstore.dev, as downloaded from
+/// <https://rekor.sigstore.dev/api/v1/log/publicKey>.
+pub const REKOR_PUBLIC_KEY: &[u8] = include_bytes!("../keys/rekor_public_key.pem");
+
+/// An [`AttestationVerifier`] that only accepts servers running an endorsed Wasm module.
+///
+/// The TEE attestation is checked with
+/// [`oak_remote_attestation_amd::PlaceholderAmdAttestationVerifier`], and must be bound to the
+/// configuration report. Use a [`crate::policy::PolicyVerifier`] to also check the TEE.
+#[derive(Clone)]
+pub struct EndorsementVerifier {
+    keys: TransparencyLogKeys,
+}
+
+impl EndorsementVerifier {
+    /// Creates a verifier accepting Wasm modules endorsed with the given PEM-encoded public key,
+    /// whose endorsement is included in the Rekor instance hosted by sigstore.dev.
+    pub fn create(endorser_public_key: &[u8]) -> anyhow::Result<Self> {
+        Self::create_with_rekor_public_key(endorser_public_key, REKOR_PUBLIC_KEY)
+    }
+
+    /// Creates a verifier accepting Wasm modules endorsed with the given PEM-encoded public key,
+    /// whose endorsement is included in the Rekor instance with the given PEM-encoded public key.
+    pub fn create_with_rekor_public_key(
+        endorser_public_key: &[u8],
+        rekor_public_key: &[u8],
+    ) -> anyhow::Result<Self> {
+        unmarshal_pem_to_p256_public_key(endorser_public_key)
+            .context("Couldn't parse endorser public key")?;
+        unmarshal_pem_to_p256_public_key(rekor_public_key)
+            .context("Couldn't parse Rekor public key")?;
+        Ok(Self {
+            keys: TransparencyLogKeys {
+                rekor_public_key: rekor_public_key.to_vec(),
+                endorser_public_key: endorser_public_key.to_vec(),
+            },
+        })
+    }
+
+    /// Checks that the serialized [`oak_functions_abi::proto::AttestationEvidence`] endorses the
+    /// attested Wasm module.
+    pub fn verify(
+        &self,
+        attestation: &[u8],
+        expected_attested_data: &[u8],
+    ) -> Result<(), PolicyRejection> {
+        let (evidence, configuration) = decode_evidence(attestation)?;
+        verify_placeholder_attestation(
+            &evidence,
+            &configuration_attested_data(expected_attested_data, &configuration),
+        )?;
+        match self
+            .keys
+            .check_endorsement(&evidence, &configuration.wasm_hash)
+        {
+            Some(reason) => Err(reason.into()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl AttestationVerifier for EndorsementVerifier {
+    fn verify_attestation(
+        &self,
+        attestation: &[u8],
+        expected_attested_data: &[u8],
+    ) -> anyhow::Result<()> {
+        self.verify(attestation, expected_attested_data)
+            .map_err(anyhow::Error::from)
+    }
+}
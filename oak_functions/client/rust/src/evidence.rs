@@ -0,0 +1,179 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Evidence bundles, which persist the attestation evidence of an Oak Functions server for offline
+//! auditing.
+//!
+//! An [`EvidenceBundle`] is written as a JSON file, with all binary values hex-encoded. The format
+//! is versioned by [`EvidenceBundle::format_version`], and bundles with an unknown format version
+//! or unknown fields are rejected.
+
+use crate::policy::{decode_evidence, PolicyVerifier};
+use anyhow::Context;
+use oak_functions_abi::proto::ConfigurationReport;
+use oak_remote_attestation::{
+    crypto::CipherSuite,
+    evidence::{HandshakeEvidence, RejectedEvidence},
+};
+use serde::{Deserialize, Serialize};
+use std::{convert::TryInto, path::Path};
+
+/// Version of the evidence bundle format written by this client.
+pub const EVIDENCE_BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// Attestation evidence of a server recorded in a remote attestation handshake, together with the
+/// result of verifying it at the time of the handshake.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct EvidenceBundle {
+    /// Version of the bundle format, see [`EVIDENCE_BUNDLE_FORMAT_VERSION`].
+    pub format_version: u32,
+    /// Remote attestation protocol version negotiated in the handshake.
+    pub protocol_version: u8,
+    /// Identifier of the cipher suite negotiated in the handshake.
+    pub cipher_suite: u8,
+    /// Attestation report sent by the server.
+    #[serde(with = "hex_bytes")]
+    pub attestation_report: Vec<u8>,
+    /// Ephemeral public key of the server.
+    #[serde(with = "hex_bytes")]
+    pub ephemeral_public_key: Vec<u8>,
+    /// Public key that the server signed the handshake transcript with.
+    #[serde(with = "hex_bytes")]
+    pub signing_public_key: Vec<u8>,
+    /// SHA-256 hash of the handshake transcript signed by the server.
+    #[serde(with = "hex_bytes")]
+    pub transcript_hash: Vec<u8>,
+    /// Signature of the transcript hash by the server.
+    #[serde(with = "hex_bytes")]
+    pub transcript_signature: Vec<u8>,
+    /// Error returned by the verification policy of the client, or [`None`] if the server was
+    /// accepted.
+    pub verification_error: Option<String>,
+}
+
+impl EvidenceBundle {
+    pub fn new(evidence: &HandshakeEvidence) -> Self {
+        Self {
+            format_version: EVIDENCE_BUNDLE_FORMAT_VERSION,
+            protocol_version: evidence.protocol_version,
+            cipher_suite: evidence.cipher_suite.id(),
+            attestation_report: evidence.attestation_report.clone(),
+            ephemeral_public_key: evidence.ephemeral_public_key.to_vec(),
+            signing_public_key: evidence.signing_public_key.to_vec(),
+            transcript_hash: evidence.transcript_hash.to_vec(),
+            transcript_signature: evidence.transcript_signature.to_vec(),
+            verification_error: evidence.verification_error.clone(),
+        }
+    }
+
+    /// Returns the bundle of the attestation evidence rejected in the handshake that failed with
+    /// the given error, if the error was caused by the rejected evidence.
+    pub fn from_error(error: &anyhow::Error) -> Option<Self> {
+        error
+            .downcast_ref::<RejectedEvidence>()
+            .map(|rejected_evidence| Self::new(&rejected_evidence.0))
+    }
+
+    /// Parses a bundle from its JSON representation.
+    pub fn from_json(input: &str) -> anyhow::Result<Self> {
+        let bundle: Self = serde_json::from_str(input).context("Couldn't parse evidence bundle")?;
+        anyhow::ensure!(
+            bundle.format_version == EVIDENCE_BUNDLE_FORMAT_VERSION,
+            "Unsupported evidence bundle format version {}, expected {}",
+            bundle.format_version,
+            EVIDENCE_BUNDLE_FORMAT_VERSION
+        );
+        Ok(bundle)
+    }
+
+    /// Returns the JSON representation of the bundle.
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        serde_json::to_string_pretty(self).context("Couldn't serialize evidence bundle")
+    }
+
+    /// Reads a bundle from the given JSON file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let input = std::fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read evidence bundle file {:?}", path))?;
+        Self::from_json(&input)
+    }
+
+    /// Writes the bundle to the given JSON file.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_json()?)
+            .with_context(|| format!("Couldn't write evidence bundle file {:?}", path))
+    }
+
+    /// Returns the [`HandshakeEvidence`] contained in the bundle.
+    pub fn evidence(&self) -> anyhow::Result<HandshakeEvidence> {
+        Ok(HandshakeEvidence {
+            protocol_version: self.protocol_version,
+            cipher_suite: CipherSuite::from_id(self.cipher_suite)?,
+            attestation_report: self.attestation_report.clone(),
+            ephemeral_public_key: fixed_size(&self.ephemeral_public_key, "ephemeral public key")?,
+            signing_public_key: fixed_size(&self.signing_public_key, "signing public key")?,
+            transcript_hash: fixed_size(&self.transcript_hash, "transcript hash")?,
+            transcript_signature: fixed_size(&self.transcript_signature, "transcript signature")?,
+            verification_error: self.verification_error.clone(),
+        })
+    }
+
+    /// Verifies the evidence again against the given verification policy, independently of the
+    /// verification result recorded in the bundle.
+    ///
+    /// If the evidence is rejected by the policy, the returned error contains a
+    /// [`crate::policy::PolicyRejection`], which can be obtained with
+    /// [`anyhow::Error::downcast_ref`].
+    pub fn verify(&self, verifier: &PolicyVerifier) -> anyhow::Result<()> {
+        self.evidence()?.verify(verifier)
+    }
+
+    /// Returns the configuration report of the server (i.e. the hash of its Wasm module and its
+    /// server policy), if its attestation report contains
+    /// [`oak_functions_abi::proto::AttestationEvidence`].
+    pub fn configuration_report(&self) -> anyhow::Result<ConfigurationReport> {
+        let (_, configuration) = decode_evidence(&self.attestation_report)
+            .map_err(|reason| anyhow::anyhow!("{}", reason))
+            .context("Couldn't get configuration report")?;
+        Ok(configuration)
+    }
+}
+
+fn fixed_size<const N: usize>(value: &[u8], description: &str) -> anyhow::Result<[u8; N]> {
+    value.try_into().map_err(|_| {
+        anyhow::anyhow!(
+            "Invalid {} length: expected {} bytes, found {}",
+            description,
+            N,
+            value.len()
+        )
+    })
+}
+
+/// Serializes byte fields as hex strings.
+mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        hex::decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
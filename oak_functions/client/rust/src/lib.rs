@@ -18,34 +18,366 @@ pub mod proto {
     tonic::include_proto!("oak.session.unary.v1");
 }
 
+pub mod endorsement;
+pub mod evidence;
+pub mod load;
+pub mod policy;
 pub mod rekor;
+pub mod replay;
+pub mod typed;
 
 use anyhow::Context;
+use endorsement::EndorsementVerifier;
+use evidence::EvidenceBundle;
 use grpc_unary_attestation::client::AttestationClient;
 use oak_functions_abi::{Request, Response};
+use oak_remote_attestation::handshaker::{
+    AttestationBehavior, AttestationVerifier, EmptyAttestationGenerator,
+};
+use policy::{PolicyVerifier, TeePolicy, VerificationPolicy};
+use std::{sync::Mutex, time::Duration};
+use tokio::sync::Semaphore;
+use typed::{Codec, TypedClient};
+
+pub use grpc_unary_attestation::client::Transport;
 
 #[cfg(test)]
 mod tests;
 
+/// Configuration of the sessions of a [`Client`], of the transport they use, and of the retries of
+/// its invocations.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClientConfig {
+    /// Maximum number of sessions, each with its own connection, used by concurrent invocations.
+    /// Sessions are created when needed, and reused by later invocations. Must be at least 1, as
+    /// checked by [`ClientConfig::validate`].
+    pub max_sessions: usize,
+    /// Maximum number of times a request sent with [`Client::invoke`] is sent again after it
+    /// failed, and its session may have been lost. Defaults to 0, since the server may have
+    /// processed a request before it failed (e.g. the server aborts the session if the Wasm module
+    /// couldn't handle the request), so retries must only be enabled for idempotent requests.
+    pub retry_budget: usize,
+    /// Delay before the first retry, which doubles with every further retry.
+    pub retry_backoff: Duration,
+    /// Transport used to send messages to the server, e.g. [`Transport::GrpcWeb`] or
+    /// [`Transport::Http`] if the server is behind a proxy that only supports HTTP/1.1.
+    pub transport: Transport,
+    /// If set, requests are padded so that the size of every encoded request is a multiple of
+    /// this size, which hides the exact size of the request body from the network. The padding is
+    /// removed by the server. Padded requests must not exceed the maximum request size of the
+    /// server policy.
+    pub request_bucket_size: Option<usize>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            max_sessions: 1,
+            retry_budget: 0,
+            retry_backoff: Duration::from_millis(100),
+            transport: Transport::Grpc,
+            request_bucket_size: None,
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Returns an error if the configuration can't be used by a [`Client`].
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.max_sessions > 0,
+            "Maximum number of sessions must be at least 1"
+        );
+        Ok(())
+    }
+}
+
+/// Verifier of the attestation evidence of the server, used in the handshake of every session.
+#[derive(Clone)]
+enum ServerVerifier {
+    Endorsement(EndorsementVerifier),
+    Policy(PolicyVerifier),
+}
+
+/// Client of an Oak Functions server.
+///
+/// Requests are sent in attested sessions taken from a pool, so that the server can be invoked
+/// concurrently.
+///
+/// If the server doesn't know a session anymore (e.g. because it restarted, or evicted the session
+/// from its cache), it asks the client to perform a new handshake, after which the request is
+/// sent again. Since the server erases the state of a session after any error, a session is also
+/// discarded after an error (e.g. if the server was unreachable), and replaced by a new session in
+/// the next invocation.
 pub struct Client {
-    inner: AttestationClient,
+    uri: String,
+    verifier: ServerVerifier,
+    config: ClientConfig,
+    /// Sessions that are not used by an invocation.
+    idle_sessions: Mutex<Vec<AttestationClient>>,
+    /// Limits the number of sessions to `config.max_sessions`.
+    session_permits: Semaphore,
+    /// Evidence bundle of the server in the last handshake.
+    evidence_bundle: Mutex<Option<EvidenceBundle>>,
 }
 
 impl Client {
-    pub async fn new(uri: &str) -> anyhow::Result<Self> {
-        let inner = AttestationClient::create(uri)
+    /// Creates a client that only sends requests to a server running a Wasm module endorsed with
+    /// the given PEM-encoded public key, as checked by an [`EndorsementVerifier`]. The Rekor log
+    /// entry of the endorsement, sent by the server, is verified with the public key of the Rekor
+    /// instance hosted by sigstore.dev.
+    ///
+    /// Requests are sent to the server at `uri`, with the transport and sessions of the given
+    /// configuration.
+    ///
+    /// Servers that don't provide an endorsement are rejected.
+    pub async fn new(
+        uri: &str,
+        endorser_public_key: &[u8],
+        config: ClientConfig,
+    ) -> anyhow::Result<Self> {
+        let verifier = EndorsementVerifier::create(endorser_public_key)
+            .context("Could not create endorsement verifier")?;
+        Self::create(uri, ServerVerifier::Endorsement(verifier), config).await
+    }
+
+    /// Creates a client like [`Client::new`], but whose endorsements must be included in the
+    /// Rekor instance with the given PEM-encoded public key.
+    pub async fn new_with_rekor_public_key(
+        uri: &str,
+        endorser_public_key: &[u8],
+        rekor_public_key: &[u8],
+        config: ClientConfig,
+    ) -> anyhow::Result<Self> {
+        let verifier = EndorsementVerifier::create_with_rekor_public_key(
+            endorser_public_key,
+            rekor_public_key,
+        )
+        .context("Could not create endorsement verifier")?;
+        Self::create(uri, ServerVerifier::Endorsement(verifier), config).await
+    }
+
+    /// Creates a client that accepts the placeholder attestation evidence of any server, without
+    /// checking which Wasm module it runs.
+    ///
+    /// Must only be used with servers that don't provide an endorsement of their Wasm module, e.g.
+    /// for testing.
+    pub async fn new_without_endorsement_verification(
+        uri: &str,
+        config: ClientConfig,
+    ) -> anyhow::Result<Self> {
+        let verifier = PolicyVerifier::create(&VerificationPolicy {
+            tee: TeePolicy::Placeholder,
+            wasm_hashes: None,
+            server_policies: None,
+            transparency_log: None,
+        })
+        .context("Could not create policy verifier")?;
+        Self::create(uri, ServerVerifier::Policy(verifier), config).await
+    }
+
+    /// Creates a client that only sends requests to a server whose attestation evidence satisfies
+    /// the verification policy of the given [`PolicyVerifier`].
+    pub async fn new_with_policy(
+        uri: &str,
+        verifier: PolicyVerifier,
+        config: ClientConfig,
+    ) -> anyhow::Result<Self> {
+        Self::create(uri, ServerVerifier::Policy(verifier), config).await
+    }
+
+    async fn create(
+        uri: &str,
+        verifier: ServerVerifier,
+        config: ClientConfig,
+    ) -> anyhow::Result<Self> {
+        config
+            .validate()
+            .context("Could not validate client configuration")?;
+        let client = Client {
+            uri: uri.to_string(),
+            verifier,
+            config,
+            idle_sessions: Mutex::new(Vec::new()),
+            session_permits: Semaphore::new(config.max_sessions),
+            evidence_bundle: Mutex::new(None),
+        };
+        // The first session is created right away, so that a server that is not reachable or not
+        // accepted by the verifier is reported when creating the client.
+        let session = client
+            .create_session()
             .await
             .context("Could not create Oak Functions client")?;
-        Ok(Client { inner })
+        client.release_session(session);
+        Ok(client)
+    }
+
+    /// Replaces the configuration of the client, or returns an error if it is invalid.
+    ///
+    /// If the transport changes, idle sessions are discarded, so that later invocations use new
+    /// sessions with the new transport. Otherwise idle sessions beyond the new
+    /// [`ClientConfig::max_sessions`] are discarded.
+    pub fn with_config(mut self, config: ClientConfig) -> anyhow::Result<Self> {
+        config
+            .validate()
+            .context("Could not validate client configuration")?;
+        let idle_sessions = self
+            .idle_sessions
+            .get_mut()
+            .expect("Couldn't lock idle sessions mutex");
+        if config.transport != self.config.transport {
+            idle_sessions.clear();
+        }
+        idle_sessions.truncate(config.max_sessions);
+        self.session_permits = Semaphore::new(config.max_sessions);
+        self.config = config;
+        Ok(self)
     }
 
-    pub async fn invoke(&mut self, request: Request) -> anyhow::Result<Response> {
-        let encoded_response = self
-            .inner
-            .send(&request.body)
+    /// Returns a client sending requests and receiving responses encoded with the given codec.
+    pub fn typed<C: Codec>(self, codec: C) -> TypedClient<C> {
+        TypedClient::new(self, codec)
+    }
+
+    /// Invokes the server with a request.
+    ///
+    /// If the request failed in a way that may have lost its session (e.g. because the server was
+    /// unreachable while restarting), the request is sent again in a new session, at most
+    /// [`ClientConfig::retry_budget`] times. The server may already have processed the request, so
+    /// the retry budget must only be set for idempotent requests.
+    pub async fn invoke(&self, request: Request) -> anyhow::Result<Response> {
+        self.invoke_with_retry_budget(&request, self.config.retry_budget)
+            .await
+    }
+
+    /// Invokes the server with a request that must not be sent more than once, e.g. because the
+    /// Wasm module accounts for every request it receives.
+    ///
+    /// A lost session is still replaced in the next invocation.
+    pub async fn invoke_at_most_once(&self, request: Request) -> anyhow::Result<Response> {
+        self.invoke_with_retry_budget(&request, 0).await
+    }
+
+    /// Returns the attestation evidence of the server, as verified in the last handshake.
+    ///
+    /// If the server was rejected in the handshake of [`Client::new`] (or of any other
+    /// constructor), the rejected evidence can instead be retrieved from the returned error with
+    /// [`EvidenceBundle::from_error`].
+    pub fn evidence_bundle(&self) -> Option<EvidenceBundle> {
+        self.evidence_bundle
+            .lock()
+            .expect("Couldn't lock evidence bundle mutex")
+            .clone()
+    }
+
+    async fn invoke_with_retry_budget(
+        &self,
+        request: &Request,
+        retry_budget: usize,
+    ) -> anyhow::Result<Response> {
+        let _permit = self
+            .session_permits
+            .acquire()
+            .await
+            .context("Could not acquire a session")?;
+        let mut backoff = self.config.retry_backoff;
+        let mut retries = 0;
+        loop {
+            match self.invoke_once(request).await {
+                Err(error) if retries < retry_budget && is_session_lost(&error) => {
+                    log::debug!("Retrying request after the session was lost: {:?}", error);
+                    retries += 1;
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Sends the request in an idle session, or in a new session if there is none.
+    async fn invoke_once(&self, request: &Request) -> anyhow::Result<Response> {
+        let idle_session = self
+            .idle_sessions
+            .lock()
+            .expect("Couldn't lock idle sessions mutex")
+            .pop();
+        let mut session = match idle_session {
+            Some(session) => session,
+            None => self.create_session().await?,
+        };
+        let encoded_request = match self.config.request_bucket_size {
+            Some(bucket_size) => request.encode_padded(bucket_size),
+            None => request.encode_to_vec(),
+        };
+        let encoded_response = session
+            .send(&encoded_request)
             .await
             .context("Error invoking Oak Functions instance")?;
+        self.release_session(session);
 
         Response::decode(encoded_response.as_ref()).context("Could not decode the response")
     }
+
+    /// Creates a new session, in which the client performs a handshake with the server.
+    ///
+    /// The evidence of the server is recorded even if the verifier rejected it.
+    async fn create_session(&self) -> anyhow::Result<AttestationClient> {
+        let result = match &self.verifier {
+            ServerVerifier::Endorsement(verifier) => {
+                self.create_session_with_verifier(verifier.clone()).await
+            }
+            ServerVerifier::Policy(verifier) => {
+                self.create_session_with_verifier(verifier.clone()).await
+            }
+        };
+        let evidence_bundle = match &result {
+            Ok(session) => session.evidence().map(EvidenceBundle::new),
+            Err(error) => EvidenceBundle::from_error(error),
+        };
+        *self
+            .evidence_bundle
+            .lock()
+            .expect("Couldn't lock evidence bundle mutex") = evidence_bundle;
+        result.context("Could not create session")
+    }
+
+    async fn create_session_with_verifier<V: 'static + AttestationVerifier>(
+        &self,
+        verifier: V,
+    ) -> anyhow::Result<AttestationClient> {
+        AttestationClient::create_with_transport(
+            &self.uri,
+            self.config.transport,
+            AttestationBehavior::create(EmptyAttestationGenerator, verifier),
+        )
+        .await
+    }
+
+    fn release_session(&self, session: AttestationClient) {
+        self.idle_sessions
+            .lock()
+            .expect("Couldn't lock idle sessions mutex")
+            .push(session);
+    }
+}
+
+/// Returns whether the error indicates that the session may have been lost, i.e. the server was
+/// not reachable, or aborted the session.
+fn is_session_lost(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause.is::<tonic::transport::Error>()
+            || matches!(
+                cause
+                    .downcast_ref::<tonic::Status>()
+                    .map(tonic::Status::code),
+                Some(tonic::Code::Aborted | tonic::Code::Unavailable)
+            )
+            || matches!(
+                cause
+                    .downcast_ref::<oak_grpc_web::Status>()
+                    .map(oak_grpc_web::Status::code),
+                Some(oak_grpc_web::code::ABORTED | oak_grpc_web::code::UNAVAILABLE)
+            )
+    })
 }
@@ -0,0 +1,223 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Load generation against an Oak Functions application, measuring throughput and latency.
+
+use crate::{typed::response_body, Client};
+use futures::stream::{FuturesUnordered, StreamExt};
+use oak_functions_abi::Request;
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// How requests are sent during a load test.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoadMode {
+    /// Each of `concurrency` workers sends a request as soon as it received the response to its
+    /// previous request.
+    ClosedLoop { concurrency: usize },
+    /// Requests are sent at a fixed `rate` per second, independently of how long responses take.
+    /// Latencies are measured from the time a request was due, so they include the time it waited
+    /// for a session of the client.
+    OpenLoop { rate: f64 },
+}
+
+/// Configuration of a load test.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoadConfig {
+    pub mode: LoadMode,
+    /// Total number of requests to send.
+    pub requests: usize,
+}
+
+/// Outcome of a single invocation in a load test.
+struct Sample {
+    latency: Duration,
+    error: Option<String>,
+}
+
+/// Results of a load test.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadReport {
+    /// Number of successful invocations.
+    pub successes: usize,
+    /// Number of failed invocations for each error.
+    pub errors: BTreeMap<String, usize>,
+    /// Duration of the whole load test.
+    pub duration: Duration,
+    /// Latencies of all invocations (including failed ones), in increasing order.
+    pub latencies: Vec<Duration>,
+}
+
+impl LoadReport {
+    fn new(samples: Vec<Sample>, duration: Duration) -> Self {
+        let mut report = LoadReport {
+            successes: 0,
+            errors: BTreeMap::new(),
+            duration,
+            latencies: samples.iter().map(|sample| sample.latency).collect(),
+        };
+        report.latencies.sort();
+        for sample in samples {
+            match sample.error {
+                None => report.successes += 1,
+                Some(error) => *report.errors.entry(error).or_default() += 1,
+            }
+        }
+        report
+    }
+
+    /// Returns the number of failed invocations.
+    pub fn failures(&self) -> usize {
+        self.errors.values().sum()
+    }
+
+    /// Returns the number of invocations per second.
+    pub fn throughput(&self) -> f64 {
+        self.latencies.len() as f64 / self.duration.as_secs_f64()
+    }
+
+    /// Returns the latency below which `percentile` percent of the invocations completed, using the
+    /// nearest-rank method, or [`None`] if there were no invocations.
+    pub fn latency_percentile(&self, percentile: f64) -> Option<Duration> {
+        let rank = (percentile / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies.get(rank.max(1) - 1).copied()
+    }
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "requests: {} ({} succeeded, {} failed)",
+            self.latencies.len(),
+            self.successes,
+            self.failures()
+        )?;
+        writeln!(
+            f,
+            "duration: {:.3}s ({:.1} requests/s)",
+            self.duration.as_secs_f64(),
+            self.throughput()
+        )?;
+        for percentile in [50.0, 90.0, 99.0, 100.0] {
+            if let Some(latency) = self.latency_percentile(percentile) {
+                writeln!(f, "latency p{}: {:.1}ms", percentile, millis(latency))?;
+            }
+        }
+        for (error, count) in &self.errors {
+            writeln!(f, "error ({} times): {}", count, error)?;
+        }
+        Ok(())
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Sends `config.requests` requests with the given body, and returns the resulting report.
+///
+/// Requests are sent with [`Client::invoke_at_most_once`], so that failures are not hidden by
+/// retries. The number of concurrent invocations is limited by the sessions of the client, see
+/// [`crate::ClientConfig::max_sessions`].
+///
+/// Returns an error without sending any request if the rate of [`LoadMode::OpenLoop`] is not a
+/// positive number, or is too low or too high to be represented as a period between requests.
+pub async fn run(client: &Client, body: &[u8], config: &LoadConfig) -> anyhow::Result<LoadReport> {
+    let start = Instant::now();
+    let samples = match config.mode {
+        LoadMode::ClosedLoop { concurrency } => {
+            let remaining = Cell::new(config.requests);
+            let workers = (0..concurrency.max(1)).map(|_| async {
+                let mut samples = Vec::new();
+                while remaining.get() > 0 {
+                    remaining.set(remaining.get() - 1);
+                    samples.push(invoke(client, body, Instant::now()).await);
+                }
+                samples
+            });
+            futures::future::join_all(workers)
+                .await
+                .into_iter()
+                .flatten()
+                .collect()
+        }
+        LoadMode::OpenLoop { rate } => {
+            let mut interval = tokio::time::interval(period(rate)?);
+            let mut in_flight = FuturesUnordered::new();
+            let mut samples = Vec::with_capacity(config.requests);
+            let mut sent = 0;
+            while sent < config.requests || !in_flight.is_empty() {
+                tokio::select! {
+                    due = interval.tick(), if sent < config.requests => {
+                        sent += 1;
+                        in_flight.push(invoke(client, body, due.into_std()));
+                    }
+                    Some(sample) = in_flight.next(), if !in_flight.is_empty() => {
+                        samples.push(sample);
+                    }
+                }
+            }
+            samples
+        }
+    };
+    Ok(LoadReport::new(samples, start.elapsed()))
+}
+
+/// Returns the period between requests sent at the given rate per second.
+fn period(rate: f64) -> anyhow::Result<Duration> {
+    anyhow::ensure!(
+        rate.is_finite() && rate > 0.0,
+        "Rate must be a positive number, got {}",
+        rate
+    );
+    let period = 1.0 / rate;
+    // `Duration::from_secs_f64` panics if the period overflows a `Duration`.
+    anyhow::ensure!(
+        period.is_finite() && period < u64::MAX as f64,
+        "Rate {} is too low",
+        rate
+    );
+    let period = Duration::from_secs_f64(period);
+    anyhow::ensure!(
+        !period.is_zero(),
+        "Rate {} is too high, its period rounds to zero",
+        rate
+    );
+    Ok(period)
+}
+
+async fn invoke(client: &Client, body: &[u8], start: Instant) -> Sample {
+    let result = client
+        .invoke_at_most_once(Request {
+            body: body.to_vec(),
+        })
+        .await
+        .map_err(|error| format!("{:#}", error))
+        .and_then(|response| {
+            response_body(&response)
+                .map(|_| ())
+                .map_err(|error| error.to_string())
+        });
+    Sample {
+        latency: start.elapsed(),
+        error: result.err(),
+    }
+}
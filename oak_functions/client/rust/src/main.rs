@@ -16,18 +16,29 @@
 
 //! Sends a gRPC request to an Oak Functions application and checks that the response has the
 //! correct format.
+//!
+//! The `inspect`, `replay` and `load` subcommands instead print the attestation evidence of the
+//! server, check the responses to a file of request cases, or send requests under load.
 
 use anyhow::Context;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use oak_functions_abi::Request;
-use oak_functions_client::Client;
+use oak_functions_client::{
+    evidence::EvidenceBundle,
+    load::{LoadConfig, LoadMode},
+    policy::{PolicyVerifier, VerificationPolicy},
+    replay::ReplayCases,
+    typed::TextCodec,
+    Client, ClientConfig, Transport,
+};
 use regex::Regex;
+use std::path::{Path, PathBuf};
 
 const TWO_MIB: usize = (2 * 1024) ^ 2;
 const LARGE_MESSAGE: [u8; TWO_MIB] = [0; TWO_MIB];
 
 #[derive(Parser, Clone)]
-#[clap(about = "Oak Functions Client")]
+#[clap(about = "Oak Functions Client", subcommand_negates_reqs = true)]
 pub struct Opt {
     #[clap(
         long,
@@ -36,6 +47,12 @@ pub struct Opt {
     )]
     uri: String,
 
+    /// Transport used to send messages to the server: `grpc`, or `grpc-web` or `http` (for the
+    /// HTTP gateway of the Oak Functions loader) for servers behind a proxy that only supports
+    /// HTTP/1.1.
+    #[clap(long, default_value = "grpc")]
+    transport: Transport,
+
     #[clap(
         long,
         help = "request payload",
@@ -51,9 +68,77 @@ pub struct Opt {
     #[clap(long, requires_all = &["request", "expected-response-pattern"])]
     iterations: Option<usize>,
 
+    /// Path to a TOML file containing the verification policy that the server must satisfy.
+    #[clap(long, conflicts_with_all = &["endorser-public-key", "skip-endorsement-verification"])]
+    verification_policy: Option<PathBuf>,
+
+    /// Path to the PEM-encoded public key that the Wasm module of the server must be endorsed
+    /// with. The endorsement must be included in Rekor.
+    #[clap(
+        long,
+        required_unless_present_any = &["verification-policy", "skip-endorsement-verification"],
+        conflicts_with = "skip-endorsement-verification"
+    )]
+    endorser_public_key: Option<PathBuf>,
+
+    /// Accept the placeholder attestation of the server without checking which Wasm module it
+    /// runs. Must only be used for testing.
+    #[clap(long)]
+    skip_endorsement_verification: bool,
+
+    /// Path of a JSON file to write the evidence bundle of the server to, which records the
+    /// attestation evidence verified in the handshake. It can be verified again later with
+    /// `oak_functions_verify_evidence`.
+    #[clap(long)]
+    evidence_bundle: Option<PathBuf>,
+
+    /// Pad requests so that the size of every encoded request is a multiple of this size, which
+    /// hides the exact size of the request from the network.
+    #[clap(long)]
+    request_bucket_size: Option<usize>,
+
     /// Test sending a large message
     #[clap(long, conflicts_with_all = &["request", "expected-response-pattern", "iterations"])]
     test_large_message: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Clone)]
+enum Command {
+    /// Print the attestation evidence of the server verified in the handshake, and its
+    /// configuration report.
+    Inspect,
+    /// Send the requests of a TOML file of cases, and check their responses.
+    Replay {
+        /// Path to the TOML file of cases.
+        #[clap(long)]
+        cases: PathBuf,
+    },
+    /// Send requests under load, and print the throughput and latency percentiles.
+    Load(LoadOpt),
+}
+
+#[derive(Parser, Clone)]
+struct LoadOpt {
+    /// Body of the requests, as UTF-8 text.
+    #[clap(long)]
+    request: String,
+
+    /// Total number of requests to send.
+    #[clap(long, default_value = "100")]
+    requests: usize,
+
+    /// Number of concurrent sessions. In closed-loop mode, also the number of workers sending
+    /// requests.
+    #[clap(long, default_value = "1")]
+    concurrency: usize,
+
+    /// Send requests at this rate per second (open loop), instead of sending the next request as
+    /// soon as a worker received its previous response (closed loop).
+    #[clap(long)]
+    rate: Option<f64>,
 }
 
 #[tokio::main]
@@ -61,9 +146,50 @@ async fn main() -> anyhow::Result<()> {
     env_logger::init();
     let opt = Opt::parse();
 
-    let mut client = Client::new(&opt.uri)
-        .await
-        .context("Could not create Oak Functions client")?;
+    let config = ClientConfig {
+        transport: opt.transport,
+        request_bucket_size: opt.request_bucket_size,
+        ..Default::default()
+    };
+    let client = match (&opt.verification_policy, &opt.endorser_public_key) {
+        (Some(path), _) => {
+            let policy = VerificationPolicy::load(path)?;
+            let verifier = PolicyVerifier::create(&policy)
+                .context("Could not create verifier for the verification policy")?;
+            Client::new_with_policy(&opt.uri, verifier, config).await
+        }
+        (None, Some(path)) => {
+            let endorser_public_key = std::fs::read(path)
+                .with_context(|| format!("Could not read endorser public key file {:?}", path))?;
+            Client::new(&opt.uri, &endorser_public_key, config).await
+        }
+        (None, None) => Client::new_without_endorsement_verification(&opt.uri, config).await,
+    };
+    let client = match client {
+        Ok(client) => client,
+        Err(error) => {
+            // The evidence of a rejected server is saved as well, with the reason for rejecting it.
+            if let (Some(path), Some(bundle)) =
+                (&opt.evidence_bundle, EvidenceBundle::from_error(&error))
+            {
+                bundle.save(path)?;
+            }
+            return Err(error.context("Could not create Oak Functions client"));
+        }
+    };
+    if let Some(path) = &opt.evidence_bundle {
+        client
+            .evidence_bundle()
+            .context("Could not get the evidence bundle of the server")?
+            .save(path)?;
+    }
+
+    match opt.command {
+        Some(Command::Inspect) => return inspect(&client),
+        Some(Command::Replay { cases }) => return replay(&client, &cases).await,
+        Some(Command::Load(load_opt)) => return load(client, config, &load_opt).await,
+        None => {}
+    }
 
     if opt.test_large_message {
         // The client should be a able to send a large message without
@@ -89,22 +215,107 @@ async fn main() -> anyhow::Result<()> {
         }
     );
 
+    let client = client.typed(TextCodec);
     for _ in 0..iterations {
-        let response = client
-            .invoke(Request {
-                body: request.as_bytes().to_vec(),
-            })
+        let response_body = client
+            .invoke(&request)
             .await
             .context("Could not invoke Oak Functions")?;
 
-        println!("Response: {:?}", response);
-        let response_body = std::str::from_utf8(response.body().unwrap()).unwrap();
         println!("Response: {:?}", response_body);
         if let Some(ref expected) = opt.expected_response_pattern {
             let re = Regex::new(expected).unwrap();
-            assert!(re.is_match(response_body));
+            assert!(re.is_match(&response_body));
         }
     }
 
     Ok(())
 }
+
+fn inspect(client: &Client) -> anyhow::Result<()> {
+    let bundle = client
+        .evidence_bundle()
+        .context("Could not get the evidence bundle of the server")?;
+    println!("{}", bundle.to_json()?);
+    match bundle.configuration_report() {
+        Ok(configuration) => {
+            println!(
+                "Wasm module hash: {}",
+                hex::encode(&configuration.wasm_hash)
+            );
+            if let Some(policy) = configuration.policy {
+                println!(
+                    "Constant response size: {} bytes",
+                    policy.constant_response_size_bytes
+                );
+                println!(
+                    "Constant processing time: {} ms",
+                    policy.constant_processing_time_ms
+                );
+                if policy.max_request_size_bytes != 0 {
+                    println!(
+                        "Maximum request size: {} bytes",
+                        policy.max_request_size_bytes
+                    );
+                }
+            }
+        }
+        Err(error) => println!("No configuration report: {:#}", error),
+    }
+    Ok(())
+}
+
+async fn replay(client: &Client, path: &Path) -> anyhow::Result<()> {
+    let cases = ReplayCases::load(path)?;
+    let mut failures = 0;
+    for case in &cases.cases {
+        let result = match client
+            .invoke(Request {
+                body: case.request.as_bytes().to_vec(),
+            })
+            .await
+        {
+            Ok(response) => case.check(&response),
+            Err(error) => Err(error),
+        };
+        match result {
+            Ok(()) => println!("PASS {}", case.name),
+            Err(error) => {
+                failures += 1;
+                println!("FAIL {}: {:#}", case.name, error);
+            }
+        }
+    }
+    anyhow::ensure!(
+        failures == 0,
+        "{} of {} replay cases failed",
+        failures,
+        cases.cases.len()
+    );
+    Ok(())
+}
+
+async fn load(client: Client, config: ClientConfig, opt: &LoadOpt) -> anyhow::Result<()> {
+    anyhow::ensure!(opt.concurrency > 0, "Concurrency must be at least 1");
+    let mode = match opt.rate {
+        Some(rate) => LoadMode::OpenLoop { rate },
+        None => LoadMode::ClosedLoop {
+            concurrency: opt.concurrency,
+        },
+    };
+    let client = client
+        .with_config(ClientConfig {
+            max_sessions: opt.concurrency,
+            ..config
+        })
+        .context("Could not configure client")?;
+    let config = LoadConfig {
+        mode,
+        requests: opt.requests,
+    };
+    let report = oak_functions_client::load::run(&client, opt.request.as_bytes(), &config)
+        .await
+        .context("Could not run load test")?;
+    print!("{}", report);
+    Ok(())
+}
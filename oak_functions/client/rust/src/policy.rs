@@ -0,0 +1,570 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Declarative verification policy describing which Oak Functions servers a client accepts.
+//!
+//! A [`VerificationPolicy`] is loaded from a TOML file, and turned into a [`PolicyVerifier`], which
+//! is an [`AttestationVerifier`] checking the [`AttestationEvidence`] sent by the server in the
+//! remote attestation handshake.
+//!
+//! If the evidence is rejected, the returned error is a [`PolicyRejection`] listing every
+//! [`RejectionReason`] that applies, which can be obtained with [`anyhow::Error::downcast_ref`].
+
+use crate::rekor::verify_rekor_log_entry;
+use anyhow::Context;
+use oak_functions_abi::proto::{AttestationEvidence, ConfigurationReport, ServerPolicy};
+use oak_remote_attestation::handshaker::{hash_concat_hash, AttestationVerifier};
+use oak_remote_attestation_amd::{
+    certificates::verify_certificate_chain,
+    report::{AttestationReport, TcbVersion, MEASUREMENT_SIZE},
+    verification::{check_report_data, verify_report_signature, SnpEvidence},
+    PlaceholderAmdAttestationVerifier,
+};
+use prost::Message;
+use serde::Deserialize;
+use std::{
+    convert::TryInto,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Size (in bytes) of a SHA-256 hash of a Wasm module.
+const WASM_HASH_SIZE: usize = 32;
+
+/// Verification policy, as read from a TOML file.
+///
+/// Lists that are not set do not constrain the server.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct VerificationPolicy {
+    /// Requirements on the TEE that the server runs in.
+    pub tee: TeePolicy,
+    /// Hex-encoded SHA-256 hashes of the accepted Wasm modules.
+    #[serde(default)]
+    pub wasm_hashes: Option<Vec<String>>,
+    /// Accepted server policies.
+    #[serde(default)]
+    pub server_policies: Option<Vec<AcceptedServerPolicy>>,
+    /// If set, the server must provide an endorsement of its Wasm module, together with a log
+    /// entry proving its inclusion in the Rekor transparency log.
+    #[serde(default)]
+    pub transparency_log: Option<TransparencyLogPolicy>,
+}
+
+/// Requirements on the TEE that the server runs in.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TeePolicy {
+    /// Accepts the placeholder attestation of
+    /// [`oak_remote_attestation_amd::PlaceholderAmdAttestationGenerator`]. Must only be used for
+    /// testing, since it provides no security guarantees.
+    Placeholder,
+    /// Requires an AMD SEV-SNP attestation report.
+    AmdSevSnp(SnpPolicy),
+}
+
+/// Requirements on an AMD SEV-SNP attestation report.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SnpPolicy {
+    /// Path to the DER-encoded ARK certificate that the VCEK must be chained to.
+    pub ark_certificate_path: PathBuf,
+    /// Hex-encoded accepted launch measurements.
+    pub measurements: Vec<String>,
+    /// Minimum accepted TCB version that the report was signed at.
+    #[serde(default)]
+    pub minimum_tcb: TcbVersion,
+    /// Minimum accepted security version number of the guest.
+    #[serde(default)]
+    pub minimum_guest_svn: u32,
+    /// Whether guests that allow debugging are accepted. Must only be used for testing.
+    #[serde(default)]
+    pub allow_debug: bool,
+    /// Whether guests that allow association with a migration agent are accepted.
+    #[serde(default)]
+    pub allow_migrate_ma: bool,
+}
+
+/// A server policy that is accepted, see [`ServerPolicy`].
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AcceptedServerPolicy {
+    pub constant_response_size_bytes: u32,
+    pub constant_processing_time_ms: u32,
+}
+
+/// Keys used to verify the endorsement of the Wasm module and its inclusion in Rekor.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TransparencyLogPolicy {
+    /// Path to the PEM-encoded public key of the Rekor instance.
+    pub rekor_public_key_path: PathBuf,
+    /// Path to the PEM-encoded public key of the party endorsing the Wasm module.
+    pub endorser_public_key_path: PathBuf,
+}
+
+impl VerificationPolicy {
+    /// Parses a policy from its TOML representation.
+    pub fn from_toml(input: &str) -> anyhow::Result<Self> {
+        toml::from_str(input).context("Couldn't parse verification policy")
+    }
+
+    /// Reads a policy from the given TOML file. Relative paths in the policy are resolved
+    /// relative to the directory containing the file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let input = std::fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read verification policy file {:?}", path))?;
+        let mut policy = Self::from_toml(&input)?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        if let TeePolicy::AmdSevSnp(snp_policy) = &mut policy.tee {
+            snp_policy.ark_certificate_path = directory.join(&snp_policy.ark_certificate_path);
+        }
+        if let Some(transparency_log) = &mut policy.transparency_log {
+            transparency_log.rekor_public_key_path =
+                directory.join(&transparency_log.rekor_public_key_path);
+            transparency_log.endorser_public_key_path =
+                directory.join(&transparency_log.endorser_public_key_path);
+        }
+        Ok(policy)
+    }
+}
+
+/// A reason for rejecting the attestation evidence of a server.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RejectionReason {
+    /// The evidence could not be parsed.
+    InvalidEvidence(String),
+    /// The TEE attestation is not authentic, or is not bound to the handshake and the
+    /// configuration report.
+    TeeVerificationFailed(String),
+    MeasurementNotAllowed {
+        measurement: Vec<u8>,
+    },
+    TcbVersionTooOld {
+        reported: TcbVersion,
+        minimum: TcbVersion,
+    },
+    GuestSvnTooOld {
+        guest_svn: u32,
+        minimum: u32,
+    },
+    DebugAllowed,
+    MigrationAgentAllowed,
+    WasmHashNotAllowed {
+        wasm_hash: Vec<u8>,
+    },
+    ServerPolicyNotAllowed(AcceptedServerPolicy),
+    MissingEndorsement,
+    TransparencyLogVerificationFailed(String),
+    /// The endorsement does not refer to the Wasm module that the server runs.
+    EndorsementSubjectMismatch {
+        wasm_hash: Vec<u8>,
+    },
+}
+
+impl fmt::Display for RejectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectionReason::InvalidEvidence(error) => {
+                write!(f, "invalid attestation evidence: {}", error)
+            }
+            RejectionReason::TeeVerificationFailed(error) => {
+                write!(f, "TEE attestation verification failed: {}", error)
+            }
+            RejectionReason::MeasurementNotAllowed { measurement } => {
+                write!(f, "measurement {} is not allowed", hex::encode(measurement))
+            }
+            RejectionReason::TcbVersionTooOld { reported, minimum } => write!(
+                f,
+                "TCB version {:?} is older than the minimum TCB version {:?}",
+                reported, minimum
+            ),
+            RejectionReason::GuestSvnTooOld { guest_svn, minimum } => write!(
+                f,
+                "guest SVN {} is older than the minimum guest SVN {}",
+                guest_svn, minimum
+            ),
+            RejectionReason::DebugAllowed => write!(f, "guest policy allows debugging"),
+            RejectionReason::MigrationAgentAllowed => {
+                write!(f, "guest policy allows a migration agent")
+            }
+            RejectionReason::WasmHashNotAllowed { wasm_hash } => {
+                write!(f, "Wasm module {} is not allowed", hex::encode(wasm_hash))
+            }
+            RejectionReason::ServerPolicyNotAllowed(policy) => {
+                write!(f, "server policy {:?} is not allowed", policy)
+            }
+            RejectionReason::MissingEndorsement => {
+                write!(f, "missing endorsement of the Wasm module")
+            }
+            RejectionReason::TransparencyLogVerificationFailed(error) => {
+                write!(f, "transparency log verification failed: {}", error)
+            }
+            RejectionReason::EndorsementSubjectMismatch { wasm_hash } => write!(
+                f,
+                "endorsement does not refer to Wasm module {}",
+                hex::encode(wasm_hash)
+            ),
+        }
+    }
+}
+
+/// Error returned when the attestation evidence of a server does not satisfy the policy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyRejection {
+    pub reasons: Vec<RejectionReason>,
+}
+
+impl fmt::Display for PolicyRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "attestation evidence rejected by verification policy")?;
+        for (index, reason) in self.reasons.iter().enumerate() {
+            write!(f, "{} {}", if index == 0 { ":" } else { ";" }, reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PolicyRejection {}
+
+impl From<RejectionReason> for PolicyRejection {
+    fn from(reason: RejectionReason) -> Self {
+        Self {
+            reasons: vec![reason],
+        }
+    }
+}
+
+/// Requirements of an [`SnpPolicy`], with all files read and all values decoded.
+#[derive(Clone)]
+pub(crate) struct SnpRequirements {
+    ark: Vec<u8>,
+    measurements: Vec<[u8; MEASUREMENT_SIZE]>,
+    minimum_tcb: TcbVersion,
+    minimum_guest_svn: u32,
+    allow_debug: bool,
+    allow_migrate_ma: bool,
+}
+
+impl SnpRequirements {
+    pub(crate) fn create(policy: &SnpPolicy) -> anyhow::Result<Self> {
+        Ok(Self {
+            ark: read_file(&policy.ark_certificate_path, "ARK certificate")?,
+            measurements: policy
+                .measurements
+                .iter()
+                .map(|measurement| decode_hex(measurement, "measurement"))
+                .collect::<anyhow::Result<_>>()?,
+            minimum_tcb: policy.minimum_tcb,
+            minimum_guest_svn: policy.minimum_guest_svn,
+            allow_debug: policy.allow_debug,
+            allow_migrate_ma: policy.allow_migrate_ma,
+        })
+    }
+
+    /// Checks an authentic attestation report against the requirements.
+    pub(crate) fn check(&self, report: &AttestationReport) -> Vec<RejectionReason> {
+        let mut reasons = Vec::new();
+        if !self.measurements.contains(&report.measurement) {
+            reasons.push(RejectionReason::MeasurementNotAllowed {
+                measurement: report.measurement.to_vec(),
+            });
+        }
+        if !report.reported_tcb.is_at_least(&self.minimum_tcb) {
+            reasons.push(RejectionReason::TcbVersionTooOld {
+                reported: report.reported_tcb,
+                minimum: self.minimum_tcb,
+            });
+        }
+        if report.guest_svn < self.minimum_guest_svn {
+            reasons.push(RejectionReason::GuestSvnTooOld {
+                guest_svn: report.guest_svn,
+                minimum: self.minimum_guest_svn,
+            });
+        }
+        if !self.allow_debug && report.policy.debug_allowed() {
+            reasons.push(RejectionReason::DebugAllowed);
+        }
+        if !self.allow_migrate_ma && report.policy.migrate_ma_allowed() {
+            reasons.push(RejectionReason::MigrationAgentAllowed);
+        }
+        reasons
+    }
+}
+
+#[derive(Clone)]
+enum TeeRequirements {
+    Placeholder,
+    AmdSevSnp(SnpRequirements),
+}
+
+/// PEM-encoded public keys used to verify the endorsement of a Wasm module and its inclusion in
+/// Rekor.
+#[derive(Clone)]
+pub(crate) struct TransparencyLogKeys {
+    pub(crate) rekor_public_key: Vec<u8>,
+    pub(crate) endorser_public_key: Vec<u8>,
+}
+
+impl TransparencyLogKeys {
+    /// Checks that the evidence contains an endorsement of the Wasm module with the given hash,
+    /// which is signed by the endorser and included in Rekor.
+    pub(crate) fn check_endorsement(
+        &self,
+        evidence: &AttestationEvidence,
+        wasm_hash: &[u8],
+    ) -> Option<RejectionReason> {
+        if evidence.endorsement.is_empty() || evidence.rekor_log_entry.is_empty() {
+            return Some(RejectionReason::MissingEndorsement);
+        }
+        if let Err(error) = verify_rekor_log_entry(
+            &evidence.rekor_log_entry,
+            &self.rekor_public_key,
+            &self.endorser_public_key,
+            &evidence.endorsement,
+        ) {
+            return Some(RejectionReason::TransparencyLogVerificationFailed(format!(
+                "{:#}",
+                error
+            )));
+        }
+        match endorsed_sha256_digests(&evidence.endorsement) {
+            Ok(digests) if digests.contains(&hex::encode(wasm_hash)) => None,
+            Ok(_) => Some(RejectionReason::EndorsementSubjectMismatch {
+                wasm_hash: wasm_hash.to_vec(),
+            }),
+            Err(error) => Some(RejectionReason::InvalidEvidence(format!("{:#}", error))),
+        }
+    }
+}
+
+/// An [`AttestationVerifier`] that checks [`AttestationEvidence`] against a
+/// [`VerificationPolicy`].
+#[derive(Clone)]
+pub struct PolicyVerifier {
+    tee: TeeRequirements,
+    wasm_hashes: Option<Vec<[u8; WASM_HASH_SIZE]>>,
+    server_policies: Option<Vec<AcceptedServerPolicy>>,
+    transparency_log: Option<TransparencyLogKeys>,
+}
+
+impl PolicyVerifier {
+    /// Creates a verifier for the given policy, reading the files it refers to.
+    pub fn create(policy: &VerificationPolicy) -> anyhow::Result<Self> {
+        let tee = match &policy.tee {
+            TeePolicy::Placeholder => TeeRequirements::Placeholder,
+            TeePolicy::AmdSevSnp(snp_policy) => {
+                TeeRequirements::AmdSevSnp(SnpRequirements::create(snp_policy)?)
+            }
+        };
+        let wasm_hashes = policy
+            .wasm_hashes
+            .as_ref()
+            .map(|wasm_hashes| {
+                wasm_hashes
+                    .iter()
+                    .map(|wasm_hash| decode_hex(wasm_hash, "Wasm hash"))
+                    .collect::<anyhow::Result<_>>()
+            })
+            .transpose()?;
+        let transparency_log = policy
+            .transparency_log
+            .as_ref()
+            .map(|transparency_log| {
+                Ok::<_, anyhow::Error>(TransparencyLogKeys {
+                    rekor_public_key: read_file(
+                        &transparency_log.rekor_public_key_path,
+                        "Rekor public key",
+                    )?,
+                    endorser_public_key: read_file(
+                        &transparency_log.endorser_public_key_path,
+                        "endorser public key",
+                    )?,
+                })
+            })
+            .transpose()?;
+        Ok(Self {
+            tee,
+            wasm_hashes,
+            server_policies: policy.server_policies.clone(),
+            transparency_log,
+        })
+    }
+
+    /// Checks the serialized [`AttestationEvidence`] against the policy.
+    ///
+    /// The configuration report is only checked if the TEE attestation is authentic and bound to
+    /// it, in which case all the applicable rejection reasons are returned.
+    pub fn verify(
+        &self,
+        attestation: &[u8],
+        expected_attested_data: &[u8],
+    ) -> Result<(), PolicyRejection> {
+        let (evidence, configuration) = decode_evidence(attestation)?;
+        let attested_data = configuration_attested_data(expected_attested_data, &configuration);
+
+        let mut reasons = match &self.tee {
+            TeeRequirements::Placeholder => {
+                verify_placeholder_attestation(&evidence, &attested_data)?;
+                Vec::new()
+            }
+            TeeRequirements::AmdSevSnp(requirements) => {
+                let report = verify_snp_attestation(
+                    &requirements.ark,
+                    &evidence.tee_attestation,
+                    &attested_data,
+                )
+                .map_err(|error| RejectionReason::TeeVerificationFailed(format!("{:#}", error)))?;
+                requirements.check(&report)
+            }
+        };
+        reasons.extend(self.check_configuration(&configuration));
+        if let Some(keys) = &self.transparency_log {
+            reasons.extend(keys.check_endorsement(&evidence, &configuration.wasm_hash));
+        }
+
+        if reasons.is_empty() {
+            Ok(())
+        } else {
+            Err(PolicyRejection { reasons })
+        }
+    }
+
+    fn check_configuration(&self, configuration: &ConfigurationReport) -> Vec<RejectionReason> {
+        let mut reasons = Vec::new();
+        if let Some(wasm_hashes) = &self.wasm_hashes {
+            if !wasm_hashes
+                .iter()
+                .any(|wasm_hash| wasm_hash[..] == configuration.wasm_hash[..])
+            {
+                reasons.push(RejectionReason::WasmHashNotAllowed {
+                    wasm_hash: configuration.wasm_hash.clone(),
+                });
+            }
+        }
+        if let Some(server_policies) = &self.server_policies {
+            match &configuration.policy {
+                Some(policy) => {
+                    let policy = accepted_server_policy(policy);
+                    if !server_policies.contains(&policy) {
+                        reasons.push(RejectionReason::ServerPolicyNotAllowed(policy));
+                    }
+                }
+                None => reasons.push(RejectionReason::InvalidEvidence(
+                    "missing server policy".to_string(),
+                )),
+            }
+        }
+        reasons
+    }
+}
+
+impl AttestationVerifier for PolicyVerifier {
+    fn verify_attestation(
+        &self,
+        attestation: &[u8],
+        expected_attested_data: &[u8],
+    ) -> anyhow::Result<()> {
+        self.verify(attestation, expected_attested_data)
+            .map_err(anyhow::Error::from)
+    }
+}
+
+/// Returns the data that the TEE attestation must be generated for, which binds the configuration
+/// report to the data attested in the handshake.
+pub fn configuration_attested_data(
+    attested_data: &[u8],
+    configuration: &ConfigurationReport,
+) -> Vec<u8> {
+    hash_concat_hash(&[attested_data, &configuration.encode_to_vec()]).to_vec()
+}
+
+/// Decodes the serialized [`AttestationEvidence`], and returns it together with its configuration
+/// report.
+pub(crate) fn decode_evidence(
+    attestation: &[u8],
+) -> Result<(AttestationEvidence, ConfigurationReport), RejectionReason> {
+    let mut evidence = AttestationEvidence::decode(attestation)
+        .map_err(|error| RejectionReason::InvalidEvidence(error.to_string()))?;
+    let configuration = evidence.configuration.take().ok_or_else(|| {
+        RejectionReason::InvalidEvidence("missing configuration report".to_string())
+    })?;
+    Ok((evidence, configuration))
+}
+
+/// Verifies that the evidence contains a placeholder TEE attestation for the given attested data.
+pub(crate) fn verify_placeholder_attestation(
+    evidence: &AttestationEvidence,
+    attested_data: &[u8],
+) -> Result<(), RejectionReason> {
+    PlaceholderAmdAttestationVerifier
+        .verify_attestation(&evidence.tee_attestation, attested_data)
+        .map_err(|error| RejectionReason::TeeVerificationFailed(format!("{:#}", error)))
+}
+
+/// Verifies that the serialized [`SnpEvidence`] contains an authentic attestation report for the
+/// given attested data, and returns the report.
+fn verify_snp_attestation(
+    ark: &[u8],
+    attestation: &[u8],
+    attested_data: &[u8],
+) -> anyhow::Result<AttestationReport> {
+    let evidence = SnpEvidence::deserialize(attestation)?;
+    let report = AttestationReport::parse(&evidence.report)?;
+    let vcek = verify_certificate_chain(ark, &evidence.ask, &evidence.vcek)?;
+    verify_report_signature(&report, &vcek)?;
+    check_report_data(&report, attested_data)?;
+    Ok(report)
+}
+
+fn accepted_server_policy(policy: &ServerPolicy) -> AcceptedServerPolicy {
+    AcceptedServerPolicy {
+        constant_response_size_bytes: policy.constant_response_size_bytes,
+        constant_processing_time_ms: policy.constant_processing_time_ms,
+    }
+}
+
+/// Returns the hex-encoded SHA-256 digests of the subjects of an in-toto endorsement statement.
+fn endorsed_sha256_digests(endorsement: &[u8]) -> anyhow::Result<Vec<String>> {
+    let statement: serde_json::Value =
+        serde_json::from_slice(endorsement).context("Couldn't parse endorsement statement")?;
+    let subjects = statement["subject"]
+        .as_array()
+        .context("Endorsement statement has no subjects")?;
+    Ok(subjects
+        .iter()
+        .filter_map(|subject| subject["digest"]["sha256"].as_str())
+        .map(|digest| digest.to_lowercase())
+        .collect())
+}
+
+fn read_file(path: &Path, description: &str) -> anyhow::Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("Couldn't read {} file {:?}", description, path))
+}
+
+fn decode_hex<const N: usize>(value: &str, description: &str) -> anyhow::Result<[u8; N]> {
+    hex::decode(value)
+        .with_context(|| format!("Couldn't decode {} {}", description, value))?
+        .try_into()
+        .map_err(|value: Vec<u8>| {
+            anyhow::anyhow!(
+                "Invalid {} length: expected {} bytes, found {}",
+                description,
+                N,
+                value.len()
+            )
+        })
+}
@@ -15,7 +15,8 @@
 //
 
 //! This module provides structs for representing a Rekor LogEntry, as well as logic for parsing and
-//! verifying signatures in a Rekor LogEntry.
+//! verifying signatures in a Rekor LogEntry, its inclusion proof, and signed checkpoints of the
+//! log.
 
 use anyhow::Context;
 use ecdsa::Signature;
@@ -102,16 +103,47 @@ pub struct PublicKey {
     pub content: String,
 }
 
-/// Struct representing a verification object in a Rekor LogEntry. The verification object in Rekor
-/// also contains an inclusion proof. Since we currently don't verify the inclusion proof in the
-/// client, it is omitted from this struct.
+/// Struct representing a verification object in a Rekor LogEntry.
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct LogEntryVerification {
+    /// Proof of inclusion of the LogEntry in the log. Log entries returned by older Rekor versions
+    /// don't contain one, and are rejected by `verify_rekor_log_entry`.
+    #[serde(rename = "inclusionProof")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inclusion_proof: Option<InclusionProof>,
+
     // Base64-encoded signature over the body, integratedTime, logID, and logIndex.
     #[serde(rename = "signedEntryTimestamp")]
     pub signed_entry_timestamp: String,
 }
 
+/// Struct representing an RFC 6962 inclusion proof of a Rekor LogEntry.
+/// Based on <https://github.com/sigstore/rekor/blob/2978cdc26fdf8f5bfede8459afd9735f0f231a2a/pkg/generated/models/inclusion_proof.go#L34.>
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct InclusionProof {
+    /// Hex-encoded hashes of the sibling nodes on the path from the leaf to the root, starting at
+    /// the leaf.
+    pub hashes: Vec<String>,
+
+    /// Index of the entry in the tree. Minimum: 0
+    #[serde(rename = "logIndex")]
+    pub log_index: u64,
+
+    /// Hex-encoded hash of the root of the tree, at the time the proof was generated.
+    /// Pattern: ^[0-9a-fA-F]{64}$
+    #[serde(rename = "rootHash")]
+    pub root_hash: String,
+
+    /// Size of the tree, at the time the proof was generated. Minimum: 1
+    #[serde(rename = "treeSize")]
+    pub tree_size: u64,
+
+    /// Signed checkpoint of the tree, committing to `tree_size` and `root_hash`. Only included by
+    /// newer Rekor versions, but required by `verify_rekor_log_entry`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checkpoint: Option<String>,
+}
+
 /// Convenient struct for verifying the `signedEntryTimestamp` in a Rekor LogEntry.
 ///
 /// This bundle can be verified using the public key from Rekor. The public key can
@@ -170,6 +202,9 @@ impl TryFrom<&LogEntry> for RekorSignatureBundle {
 /// The verification involves the following:
 ///
 /// 1. verifying the signature in `signedEntryTimestamp`, using Rekor's public key,
+/// 1. verifying the inclusion proof of the LogEntry,
+/// 1. verifying the signed checkpoint in the inclusion proof, using Rekor's public key, and that it
+///    commits to the tree size and root hash of the inclusion proof,
 /// 1. verifying the signature in `body.RekordObj.signature`, using Oak's public key,
 /// 1. verifying that the content of the body matches the input `endorsement_bytes`.
 ///
@@ -190,6 +225,22 @@ pub fn verify_rekor_log_entry(
     // Parse base64-encoded entry.body into an instance of Body.
     let body_bytes =
         base64::decode(entry.body.clone()).context("couldn't decode Base64 signature")?;
+
+    let inclusion_proof = entry
+        .verification
+        .as_ref()
+        .and_then(|verification| verification.inclusion_proof.as_ref())
+        .context("no inclusion proof in the log entry")?;
+    verify_inclusion_proof(inclusion_proof, &body_bytes)?;
+    // Without a checkpoint signed by Rekor, nothing binds the root hash of the inclusion proof to
+    // the log.
+    let checkpoint = inclusion_proof
+        .checkpoint
+        .as_ref()
+        .context("no signed checkpoint in the inclusion proof")?;
+    let checkpoint = SignedCheckpoint::parse(checkpoint)?;
+    checkpoint.verify(pem_encoded_public_key_bytes)?;
+    checkpoint.verify_inclusion_proof(inclusion_proof)?;
     let body: Body =
         serde_json::from_slice(&body_bytes).context("couldn't parse bytes into a Body object.")?;
 
@@ -322,6 +373,244 @@ fn compare_keys(public_key_bytes_a: &[u8], public_key_bytes_b: &[u8]) -> anyhow:
     Ok(key_a.cmp(&key_b))
 }
 
+/// Verifies that the inclusion proof proves the inclusion of a leaf with the given content (i.e.
+/// the decoded body of a LogEntry) in the tree with the root hash given in the proof, as described
+/// in <https://www.rfc-editor.org/rfc/rfc9162.html#section-2.1.3.2>.
+///
+/// Returns `Ok(())` if the verification succeeds, otherwise returns `Err()`.
+pub fn verify_inclusion_proof(proof: &InclusionProof, leaf_bytes: &[u8]) -> anyhow::Result<()> {
+    let hashes = proof
+        .hashes
+        .iter()
+        .map(|hash| decode_hash(hash))
+        .collect::<anyhow::Result<Vec<_>>>()
+        .context("couldn't decode the hashes of the inclusion proof")?;
+    let root_hash = decode_hash(&proof.root_hash).context("couldn't decode the root hash")?;
+    let computed_root_hash = root_hash_from_inclusion_proof(
+        proof.log_index,
+        proof.tree_size,
+        &hash_leaf(leaf_bytes),
+        &hashes,
+    )?;
+    if computed_root_hash != root_hash {
+        anyhow::bail!(
+            "the root hash computed from the inclusion proof ({}) does not match the root hash of the proof ({})",
+            hex::encode(computed_root_hash),
+            proof.root_hash
+        )
+    }
+    Ok(())
+}
+
+/// Computes the root hash of a tree of the given size from the inclusion proof of the leaf with the
+/// given index and hash, following the verification algorithm of RFC 9162.
+fn root_hash_from_inclusion_proof(
+    leaf_index: u64,
+    tree_size: u64,
+    leaf_hash: &[u8; 32],
+    hashes: &[[u8; 32]],
+) -> anyhow::Result<[u8; 32]> {
+    if leaf_index >= tree_size {
+        anyhow::bail!(
+            "leaf index {} is not in a tree of size {}",
+            leaf_index,
+            tree_size
+        )
+    }
+    let mut node_index = leaf_index;
+    let mut last_node_index = tree_size - 1;
+    let mut hash = *leaf_hash;
+    for sibling in hashes {
+        if last_node_index == 0 {
+            anyhow::bail!("the inclusion proof is too long")
+        }
+        if node_index & 1 == 1 || node_index == last_node_index {
+            hash = hash_children(sibling, &hash);
+            // The node is the last one on its level, so its ancestors are promoted until they
+            // have a left sibling.
+            while node_index & 1 == 0 && node_index != 0 {
+                node_index >>= 1;
+                last_node_index >>= 1;
+            }
+        } else {
+            hash = hash_children(&hash, sibling);
+        }
+        node_index >>= 1;
+        last_node_index >>= 1;
+    }
+    if last_node_index != 0 {
+        anyhow::bail!("the inclusion proof is too short")
+    }
+    Ok(hash)
+}
+
+/// Computes the RFC 6962 hash of a leaf of a Merkle tree.
+fn hash_leaf(leaf_bytes: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([0x00]);
+    hasher.update(leaf_bytes);
+    hasher.finalize().into()
+}
+
+/// Computes the RFC 6962 hash of an inner node of a Merkle tree from the hashes of its children.
+fn hash_children(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([0x01]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+fn decode_hash(hash: &str) -> anyhow::Result<[u8; 32]> {
+    hex::decode(hash)
+        .with_context(|| format!("couldn't decode hex-encoded hash {}", hash))?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("invalid hash length: {}", hash))
+}
+
+/// A signature of a [`SignedCheckpoint`].
+#[derive(Debug, PartialEq)]
+pub struct CheckpointSignature {
+    /// Name of the signer, usually the origin of the log.
+    pub name: String,
+    /// The first 4 bytes of the SHA-256 hash of the DER-encoded public key of the signer.
+    pub key_hint: [u8; 4],
+    /// DER-encoded ECDSA signature over the checkpoint.
+    pub signature: Vec<u8>,
+}
+
+/// A checkpoint of a transparency log (i.e. a signed tree head), in the signed note format used by
+/// Rekor, as described in <https://github.com/transparency-dev/formats/blob/main/log/README.md>.
+#[derive(Debug, PartialEq)]
+pub struct SignedCheckpoint {
+    /// Unique identifier of the log.
+    pub origin: String,
+    pub tree_size: u64,
+    pub root_hash: [u8; 32],
+    /// Optional extension lines of the checkpoint, e.g. a timestamp.
+    pub other_content: Vec<String>,
+    pub signatures: Vec<CheckpointSignature>,
+    /// The signed part of the checkpoint, including the trailing newline.
+    note: String,
+}
+
+impl SignedCheckpoint {
+    /// Parses a signed checkpoint.
+    pub fn parse(checkpoint: &str) -> anyhow::Result<Self> {
+        let (note, signatures) = checkpoint
+            .split_once("\n\n")
+            .context("no signatures in the checkpoint")?;
+        let note = format!("{}\n", note);
+        let mut lines = note.lines();
+        let origin = lines
+            .next()
+            .filter(|origin| !origin.is_empty())
+            .context("no origin in the checkpoint")?
+            .to_string();
+        let tree_size = lines
+            .next()
+            .context("no tree size in the checkpoint")?
+            .parse()
+            .context("couldn't parse the tree size of the checkpoint")?;
+        let root_hash = base64::decode(lines.next().context("no root hash in the checkpoint")?)
+            .context("couldn't decode Base64 root hash")?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("invalid root hash length in the checkpoint"))?;
+        let other_content = lines.map(str::to_string).collect();
+        let signatures = signatures
+            .lines()
+            .map(CheckpointSignature::parse)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if signatures.is_empty() {
+            anyhow::bail!("no signatures in the checkpoint")
+        }
+        Ok(Self {
+            origin,
+            tree_size,
+            root_hash,
+            other_content,
+            signatures,
+            note,
+        })
+    }
+
+    /// Verifies that the checkpoint is signed with the PEM-encoded public key in
+    /// `pem_encoded_public_key_bytes`. Signatures with a different key hint are ignored.
+    ///
+    /// Returns `Ok(())` if the verification succeeds, otherwise returns `Err()`.
+    pub fn verify(&self, pem_encoded_public_key_bytes: &[u8]) -> anyhow::Result<()> {
+        let key = unmarshal_pem_to_p256_public_key(pem_encoded_public_key_bytes)?;
+        let key_hint = pem_key_hint(pem_encoded_public_key_bytes)?;
+        let signature = self
+            .signatures
+            .iter()
+            .find(|signature| signature.key_hint == key_hint)
+            .context("no signature of the given public key in the checkpoint")?;
+        let signature =
+            Signature::from_der(&signature.signature).context("invalid ASN.1 signature")?;
+        key.verify(self.note.as_bytes(), &signature)
+            .context("failed to verify the signature of the checkpoint")
+    }
+
+    /// Verifies that the inclusion proof was generated for the tree committed to by the
+    /// checkpoint, and is valid for it.
+    ///
+    /// Returns `Ok(())` if the verification succeeds, otherwise returns `Err()`.
+    pub fn verify_inclusion_proof(&self, proof: &InclusionProof) -> anyhow::Result<()> {
+        if proof.tree_size != self.tree_size {
+            anyhow::bail!(
+                "the tree size of the inclusion proof ({}) does not match the tree size of the checkpoint ({})",
+                proof.tree_size,
+                self.tree_size
+            )
+        }
+        if decode_hash(&proof.root_hash)? != self.root_hash {
+            anyhow::bail!(
+                "the root hash of the inclusion proof ({}) does not match the root hash of the checkpoint ({})",
+                proof.root_hash,
+                hex::encode(self.root_hash)
+            )
+        }
+        Ok(())
+    }
+}
+
+impl CheckpointSignature {
+    /// Parses a signature line of the form `— <name> <base64(key hint || signature)>`.
+    fn parse(line: &str) -> anyhow::Result<Self> {
+        let (name, signature) = line
+            .strip_prefix("\u{2014} ")
+            .and_then(|line| line.rsplit_once(' '))
+            .with_context(|| format!("invalid checkpoint signature line: {:?}", line))?;
+        let signature = base64::decode(signature).context("couldn't decode Base64 signature")?;
+        if signature.len() <= 4 {
+            anyhow::bail!("checkpoint signature is too short")
+        }
+        let (key_hint, signature) = signature.split_at(4);
+        Ok(Self {
+            name: name.to_string(),
+            key_hint: key_hint.try_into().expect("key hint should have 4 bytes"),
+            signature: signature.to_vec(),
+        })
+    }
+}
+
+/// Returns the key hint of a PEM-encoded public key, i.e. the first 4 bytes of the SHA-256 hash of
+/// the DER-encoded public key.
+fn pem_key_hint(pem_bytes: &[u8]) -> anyhow::Result<[u8; 4]> {
+    let pem_str = std::str::from_utf8(pem_bytes).context("couldn't convert bytes to string")?;
+    let der = base64::decode(
+        pem_str
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect::<String>(),
+    )
+    .context("couldn't decode Base64 public key")?;
+    Ok(get_sha256(&der)[..4]
+        .try_into()
+        .expect("key hint should have 4 bytes"))
+}
+
 /// Computes a SHA-256 digest of `input` and returns it in a form of raw bytes.
 /// Returns the hash as a 32-bytes array.
 pub fn get_sha256(input: &[u8]) -> [u8; 32] {
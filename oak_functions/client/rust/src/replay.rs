@@ -0,0 +1,118 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Replay of request cases with expected responses, e.g. to check a deployed application.
+//!
+//! Cases are read from a TOML file, see `oak_functions/client/testdata/replay_cases.toml` for an
+//! example.
+
+use anyhow::Context;
+use oak_functions_abi::{Response, StatusCode};
+use regex::Regex;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Requests to send to an Oak Functions application, together with their expected responses.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ReplayCases {
+    #[serde(default, rename = "case")]
+    pub cases: Vec<ReplayCase>,
+}
+
+/// A request and its expected response.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ReplayCase {
+    /// Name of the case, used when reporting its result.
+    pub name: String,
+    /// Body of the request, as UTF-8 text.
+    pub request: String,
+    /// Expected status of the response (e.g. `BadRequest`). Defaults to `Success`.
+    pub expected_status: Option<String>,
+    /// Expected body of the response, as UTF-8 text.
+    pub expected_response: Option<String>,
+    /// Regular expression that the body of the response must match.
+    pub expected_response_pattern: Option<String>,
+}
+
+impl ReplayCases {
+    /// Parses cases from their TOML representation.
+    pub fn from_toml(input: &str) -> anyhow::Result<Self> {
+        let cases: Self = toml::from_str(input).context("Couldn't parse replay cases")?;
+        for case in &cases.cases {
+            case.expected_status()
+                .with_context(|| format!("Invalid replay case {:?}", case.name))?;
+            if let Some(pattern) = &case.expected_response_pattern {
+                Regex::new(pattern)
+                    .with_context(|| format!("Invalid replay case {:?}", case.name))?;
+            }
+        }
+        Ok(cases)
+    }
+
+    /// Reads cases from the given TOML file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let input = std::fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read replay cases file {:?}", path))?;
+        Self::from_toml(&input)
+    }
+}
+
+impl ReplayCase {
+    fn expected_status(&self) -> anyhow::Result<StatusCode> {
+        match &self.expected_status {
+            None => Ok(StatusCode::Success),
+            Some(name) => (0..)
+                .map_while(StatusCode::from_repr)
+                .find(|status| &status.to_string() == name)
+                .with_context(|| format!("Unknown status {:?}", name)),
+        }
+    }
+
+    /// Checks that the response matches the expected status and body of the case.
+    pub fn check(&self, response: &Response) -> anyhow::Result<()> {
+        let expected_status = self.expected_status()?;
+        anyhow::ensure!(
+            response.status == expected_status,
+            "Expected status {}, got {}",
+            expected_status,
+            response.status
+        );
+        let body = response
+            .body()
+            .map_err(|error| anyhow::anyhow!("Invalid response length: {}", error))?;
+        let body = std::str::from_utf8(body).context("Response body is not valid UTF-8")?;
+        if let Some(expected_response) = &self.expected_response {
+            anyhow::ensure!(
+                body == expected_response,
+                "Expected response {:?}, got {:?}",
+                expected_response,
+                body
+            );
+        }
+        if let Some(pattern) = &self.expected_response_pattern {
+            let regex = Regex::new(pattern).context("Couldn't parse expected response pattern")?;
+            anyhow::ensure!(
+                regex.is_match(body),
+                "Response {:?} doesn't match pattern {:?}",
+                body,
+                pattern
+            );
+        }
+        Ok(())
+    }
+}
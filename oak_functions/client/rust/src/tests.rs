@@ -14,8 +14,47 @@
 // limitations under the License.
 //
 
-use crate::rekor::*;
-use std::fs;
+use crate::{
+    endorsement::{EndorsementVerifier, REKOR_PUBLIC_KEY},
+    evidence::EvidenceBundle,
+    is_session_lost,
+    load::{LoadConfig, LoadMode, LoadReport},
+    policy::{
+        configuration_attested_data, AcceptedServerPolicy, PolicyRejection, PolicyVerifier,
+        RejectionReason, SnpPolicy, SnpRequirements, TeePolicy, VerificationPolicy,
+    },
+    rekor::*,
+    replay::ReplayCases,
+    typed::{response_body, InvocationError, JsonCodec, ProtobufCodec, TextCodec},
+    Client, ClientConfig, Transport,
+};
+use grpc_unary_attestation::{
+    proto::unary_session_server::UnarySessionServer, server::AttestationServer,
+};
+use oak_functions_abi::{
+    proto::{AttestationEvidence, ConfigurationReport, ServerPolicy},
+    Request, Response, StatusCode,
+};
+use oak_remote_attestation::handshaker::{
+    AttestationBehavior, AttestationGenerator, AttestationVerifier, ClientHandshaker,
+    EmptyAttestationGenerator, EmptyAttestationVerifier, ServerHandshaker,
+};
+use oak_remote_attestation_amd::{
+    report::{AttestationReport, TcbVersion},
+    PlaceholderAmdAttestationGenerator,
+};
+use oak_utils::testing::{self, TestLogger};
+use prost::Message;
+use std::{
+    fs,
+    net::SocketAddr,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 #[test]
 fn test_verify_rekor_log_entry() {
@@ -33,26 +72,49 @@ fn test_verify_rekor_log_entry() {
     // The body of LogEntry can be fetched using `rekor-cli get --log-index 1323526`.
     let log_entry_path = "../testdata/logentry.json";
 
+    // The same LogEntry, with a signed checkpoint in its inclusion proof, which Rekor didn't
+    // include at the time. The checkpoint and the `signedEntryTimestamp` are signed with a test
+    // key, standing in for the key of Rekor.
+    let log_entry_with_checkpoint_path = "../testdata/logentry_with_checkpoint.json";
+
     // Test public key, representing the product team that signed the endorsement file.
     let pubkey_path = "../testdata/ec_public.pem";
 
     // Public key of the Rekor instance hosted by sigstore.dev. It is downloaded from https://rekor.sigstore.dev/api/v1/log/publicKey.
     let rekor_pubkey_path = "../testdata/rekor_public_key.pem";
 
+    // Test public key, standing in for the public key of Rekor.
+    let test_rekor_pubkey_path = "../testdata/test_rekor_public_key.pem";
+
     let endorsement_bytes = fs::read(endorsement_path).expect("Couldn't read endorsement file.");
     let log_entry_bytes = fs::read(log_entry_path).expect("Couldn't read log entry file.");
+    let log_entry_with_checkpoint_bytes =
+        fs::read(log_entry_with_checkpoint_path).expect("Couldn't read log entry file.");
     let rekor_pem_bytes =
         fs::read(rekor_pubkey_path).expect("Couldn't read Rekor's public key file.");
+    let test_rekor_pem_bytes =
+        fs::read(test_rekor_pubkey_path).expect("Couldn't read the test Rekor public key file.");
     let pubkey_pem_bytes =
         fs::read(pubkey_path).expect("Couldn't read product team's public key file.");
 
+    let result = verify_rekor_log_entry(
+        &log_entry_with_checkpoint_bytes,
+        &test_rekor_pem_bytes,
+        &pubkey_pem_bytes,
+        &endorsement_bytes,
+    );
+    assert!(result.is_ok(), "{:?}", result);
+
+    // The LogEntry without a checkpoint is rejected, even though its `signedEntryTimestamp` and
+    // inclusion proof are valid.
+    assert!(verify_rekor_signature(&log_entry_bytes, &rekor_pem_bytes).is_ok());
     let result = verify_rekor_log_entry(
         &log_entry_bytes,
         &rekor_pem_bytes,
         &pubkey_pem_bytes,
         &endorsement_bytes,
     );
-    assert!(result.is_ok(), "{:?}", result);
+    assert!(result.is_err());
 }
 
 #[test]
@@ -76,3 +138,1131 @@ fn test_verify_rekor_signature() {
 
     assert!(result.is_ok());
 }
+
+/// Returns the LogEntry with a signed checkpoint from the test data.
+fn log_entry() -> LogEntry {
+    let entries: std::collections::HashMap<String, LogEntry> =
+        serde_json::from_slice(&fs::read("../testdata/logentry_with_checkpoint.json").unwrap())
+            .unwrap();
+    entries.into_values().next().unwrap()
+}
+
+fn inclusion_proof(entry: &mut LogEntry) -> &mut InclusionProof {
+    entry
+        .verification
+        .as_mut()
+        .unwrap()
+        .inclusion_proof
+        .as_mut()
+        .unwrap()
+}
+
+/// Returns the LogEntry from the test data, modified by `modify`, in its serialized form.
+fn modified_log_entry_bytes(modify: impl FnOnce(&mut LogEntry)) -> Vec<u8> {
+    let mut entry = log_entry();
+    modify(&mut entry);
+    serde_json::to_vec(&std::collections::HashMap::from([(
+        "bb05be1bd813f8afb7b77b2d9f7be5ae25b396d111c7a26a04b785c48c277372".to_string(),
+        entry,
+    )]))
+    .unwrap()
+}
+
+#[test]
+fn test_verify_inclusion_proof_test_vectors() {
+    // Inclusion proofs for all leaves of the trees of size 1 to 8 built from the leaves of the
+    // RFC 6962 test data, computed with the recursive definition of RFC 6962 section 2.1.1.
+    let vectors: Vec<serde_json::Value> =
+        serde_json::from_slice(&fs::read("../testdata/inclusion_proofs.json").unwrap()).unwrap();
+    assert_eq!(vectors.len(), 36);
+    for vector in vectors {
+        let leaf = hex::decode(vector["leaf"].as_str().unwrap()).unwrap();
+        let mut proof = InclusionProof {
+            hashes: serde_json::from_value(vector["hashes"].clone()).unwrap(),
+            log_index: vector["leaf_index"].as_u64().unwrap(),
+            root_hash: vector["root_hash"].as_str().unwrap().to_string(),
+            tree_size: vector["tree_size"].as_u64().unwrap(),
+            checkpoint: None,
+        };
+        let result = verify_inclusion_proof(&proof, &leaf);
+        assert!(result.is_ok(), "{:?}: {:?}", vector, result);
+
+        assert!(verify_inclusion_proof(&proof, b"other leaf").is_err());
+        if proof.tree_size > 1 {
+            proof.log_index = (proof.log_index + 1) % proof.tree_size;
+            assert!(verify_inclusion_proof(&proof, &leaf).is_err());
+        }
+        proof.log_index = proof.tree_size;
+        assert!(verify_inclusion_proof(&proof, &leaf).is_err());
+        proof.log_index = vector["leaf_index"].as_u64().unwrap();
+        proof.hashes.push(proof.root_hash.clone());
+        assert!(verify_inclusion_proof(&proof, &leaf).is_err());
+        proof.hashes.pop();
+        if let Some(hash) = proof.hashes.pop() {
+            assert!(verify_inclusion_proof(&proof, &leaf).is_err());
+            proof.hashes.push(hash.replace(&hash[..2], "ff"));
+            assert!(verify_inclusion_proof(&proof, &leaf).is_err());
+        }
+    }
+}
+
+#[test]
+fn test_verify_log_entry_inclusion_proof() {
+    let mut entry = log_entry();
+    let body = base64::decode(&entry.body).unwrap();
+    let proof = inclusion_proof(&mut entry);
+    assert!(verify_inclusion_proof(proof, &body).is_ok());
+
+    assert!(verify_inclusion_proof(proof, b"other body").is_err());
+    proof.log_index += 1;
+    assert!(verify_inclusion_proof(proof, &body).is_err());
+}
+
+#[test]
+fn test_verify_rekor_log_entry_requires_inclusion_proof() {
+    let endorsement_bytes = fs::read("../testdata/endorsement.json").unwrap();
+    let rekor_pem_bytes = fs::read("../testdata/test_rekor_public_key.pem").unwrap();
+    let pubkey_pem_bytes = fs::read("../testdata/ec_public.pem").unwrap();
+    let verify = |log_entry_bytes: &[u8]| {
+        verify_rekor_log_entry(
+            log_entry_bytes,
+            &rekor_pem_bytes,
+            &pubkey_pem_bytes,
+            &endorsement_bytes,
+        )
+    };
+
+    // The inclusion proof is not covered by the `signedEntryTimestamp`, so it is checked on its
+    // own.
+    assert!(verify(&modified_log_entry_bytes(|_| {})).is_ok());
+    assert!(verify(&modified_log_entry_bytes(|entry| {
+        entry.verification.as_mut().unwrap().inclusion_proof = None;
+    }))
+    .is_err());
+    assert!(verify(&modified_log_entry_bytes(|entry| {
+        inclusion_proof(entry).hashes.swap(0, 1);
+    }))
+    .is_err());
+
+    // The inclusion proof must contain a checkpoint signed by Rekor, for the same tree.
+    assert!(verify(&modified_log_entry_bytes(|entry| {
+        inclusion_proof(entry).checkpoint = None;
+    }))
+    .is_err());
+    assert!(verify(&modified_log_entry_bytes(|entry| {
+        let checkpoint = inclusion_proof(entry).checkpoint.as_mut().unwrap();
+        *checkpoint = checkpoint.replace("Oak test log\n", "Other log\n");
+    }))
+    .is_err());
+    assert!(verify(&modified_log_entry_bytes(|entry| {
+        let checkpoint = inclusion_proof(entry).checkpoint.as_mut().unwrap();
+        *checkpoint = checkpoint.replace("1323534", "1323535");
+    }))
+    .is_err());
+}
+
+#[test]
+fn test_signed_checkpoint() {
+    // Checkpoint of the tree that the LogEntry from the test data is included in, signed with the
+    // test Rekor key, since Rekor didn't include checkpoints in log entries at the time.
+    let checkpoint =
+        SignedCheckpoint::parse(&fs::read_to_string("../testdata/checkpoint.txt").unwrap())
+            .unwrap();
+    assert_eq!(checkpoint.origin, "Oak test log");
+    assert_eq!(checkpoint.tree_size, 1323534);
+    assert_eq!(checkpoint.signatures.len(), 1);
+    assert_eq!(checkpoint.signatures[0].name, "Oak test log");
+
+    let checkpoint_pem_bytes = fs::read("../testdata/test_rekor_public_key.pem").unwrap();
+    let rekor_pem_bytes = fs::read("../testdata/rekor_public_key.pem").unwrap();
+    assert!(checkpoint.verify(&checkpoint_pem_bytes).is_ok());
+    assert!(checkpoint.verify(&rekor_pem_bytes).is_err());
+
+    let mut entry = log_entry();
+    let proof = inclusion_proof(&mut entry);
+    assert!(checkpoint.verify_inclusion_proof(proof).is_ok());
+    proof.tree_size += 1;
+    assert!(checkpoint.verify_inclusion_proof(proof).is_err());
+    proof.tree_size -= 1;
+    proof.root_hash = hex::encode([0; 32]);
+    assert!(checkpoint.verify_inclusion_proof(proof).is_err());
+    assert_eq!(
+        Some(&fs::read_to_string("../testdata/checkpoint.txt").unwrap()),
+        log_entry()
+            .verification
+            .unwrap()
+            .inclusion_proof
+            .unwrap()
+            .checkpoint
+            .as_ref()
+    );
+
+    // The signature covers the tree size and root hash.
+    let tampered_checkpoint = SignedCheckpoint::parse(
+        &fs::read_to_string("../testdata/checkpoint.txt")
+            .unwrap()
+            .replace("1323534", "1323535"),
+    )
+    .unwrap();
+    assert!(tampered_checkpoint.verify(&checkpoint_pem_bytes).is_err());
+
+    assert!(SignedCheckpoint::parse("Oak test log\n1323534\n").is_err());
+}
+
+const WASM_HASH: &str = "15dc16c42a4ac9ed77f337a4a3065a63e444c29c18c8cf69d6a6b4ae678dca5c";
+const ATTESTED_DATA: &[u8] = b"Test attested data";
+
+fn configuration_report() -> ConfigurationReport {
+    ConfigurationReport {
+        wasm_hash: hex::decode(WASM_HASH).unwrap(),
+        policy: Some(ServerPolicy {
+            constant_response_size_bytes: 90,
+            constant_processing_time_ms: 20,
+            max_request_size_bytes: 0,
+        }),
+    }
+}
+
+/// Returns serialized attestation evidence containing a placeholder TEE attestation and the
+/// endorsement from the test data.
+fn placeholder_evidence(configuration: ConfigurationReport, attested_data: &[u8]) -> Vec<u8> {
+    AttestationEvidence {
+        tee_attestation: PlaceholderAmdAttestationGenerator
+            .generate_attestation(&configuration_attested_data(attested_data, &configuration))
+            .unwrap(),
+        configuration: Some(configuration),
+        endorsement: fs::read("../testdata/endorsement.json").unwrap(),
+        rekor_log_entry: fs::read("../testdata/logentry_with_checkpoint.json").unwrap(),
+    }
+    .encode_to_vec()
+}
+
+fn policy_verifier() -> PolicyVerifier {
+    let policy = VerificationPolicy::load(Path::new("../testdata/verification_policy.toml"))
+        .expect("Couldn't load verification policy");
+    PolicyVerifier::create(&policy).expect("Couldn't create policy verifier")
+}
+
+fn rejection_reasons(verifier: &PolicyVerifier, evidence: &[u8]) -> Vec<RejectionReason> {
+    verifier
+        .verify(evidence, ATTESTED_DATA)
+        .expect_err("evidence should have been rejected")
+        .reasons
+}
+
+#[test]
+fn test_parse_verification_policy() {
+    let policy = VerificationPolicy::from_toml(
+        r#"
+        wasm_hashes = ["15dc16c42a4ac9ed77f337a4a3065a63e444c29c18c8cf69d6a6b4ae678dca5c"]
+
+        [tee]
+        type = "amd_sev_snp"
+        ark_certificate_path = "ark.der"
+        measurements = ["00"]
+        minimum_tcb = { boot_loader = 3, tee = 0, snp = 8, microcode = 115 }
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        policy.tee,
+        TeePolicy::AmdSevSnp(SnpPolicy {
+            ark_certificate_path: "ark.der".into(),
+            measurements: vec!["00".to_string()],
+            minimum_tcb: TcbVersion {
+                boot_loader: 3,
+                tee: 0,
+                snp: 8,
+                microcode: 115,
+            },
+            minimum_guest_svn: 0,
+            allow_debug: false,
+            allow_migrate_ma: false,
+        })
+    );
+    assert_eq!(policy.server_policies, None);
+    assert_eq!(policy.transparency_log, None);
+
+    // Unknown fields are rejected, so that misspelled requirements are not silently ignored.
+    assert!(VerificationPolicy::from_toml(
+        r#"
+        wasm_hash = ["15dc16c42a4ac9ed77f337a4a3065a63e444c29c18c8cf69d6a6b4ae678dca5c"]
+
+        [tee]
+        type = "placeholder"
+        "#
+    )
+    .is_err());
+}
+
+#[test]
+fn test_policy_verifier_accepts_valid_evidence() {
+    let verifier = policy_verifier();
+    let evidence = placeholder_evidence(configuration_report(), ATTESTED_DATA);
+    assert_eq!(verifier.verify(&evidence, ATTESTED_DATA), Ok(()));
+}
+
+#[test]
+fn test_policy_verifier_rejects_unbound_evidence() {
+    let verifier = policy_verifier();
+
+    let evidence = placeholder_evidence(configuration_report(), b"Other attested data");
+    assert!(matches!(
+        rejection_reasons(&verifier, &evidence)[..],
+        [RejectionReason::TeeVerificationFailed(_)]
+    ));
+
+    // The configuration report cannot be replaced without invalidating the TEE attestation.
+    let mut evidence = AttestationEvidence::decode(
+        placeholder_evidence(configuration_report(), ATTESTED_DATA).as_ref(),
+    )
+    .unwrap();
+    evidence.configuration.as_mut().unwrap().wasm_hash[0] ^= 1;
+    assert!(matches!(
+        rejection_reasons(&verifier, &evidence.encode_to_vec())[..],
+        [RejectionReason::TeeVerificationFailed(_)]
+    ));
+
+    assert!(matches!(
+        rejection_reasons(&verifier, b"invalid evidence")[..],
+        [RejectionReason::InvalidEvidence(_)]
+    ));
+}
+
+#[test]
+fn test_policy_verifier_reports_all_violations() {
+    let verifier = policy_verifier();
+    let mut configuration = configuration_report();
+    configuration.wasm_hash = vec![0; 32];
+    configuration.policy = Some(ServerPolicy {
+        constant_response_size_bytes: 1024,
+        constant_processing_time_ms: 20,
+        max_request_size_bytes: 0,
+    });
+    let evidence = placeholder_evidence(configuration, ATTESTED_DATA);
+
+    assert_eq!(
+        rejection_reasons(&verifier, &evidence),
+        vec![
+            RejectionReason::WasmHashNotAllowed {
+                wasm_hash: vec![0; 32]
+            },
+            RejectionReason::ServerPolicyNotAllowed(AcceptedServerPolicy {
+                constant_response_size_bytes: 1024,
+                constant_processing_time_ms: 20,
+            }),
+            RejectionReason::EndorsementSubjectMismatch {
+                wasm_hash: vec![0; 32]
+            },
+        ]
+    );
+
+    // The structured reasons are preserved when used as an `AttestationVerifier`.
+    let error = verifier
+        .verify_attestation(&evidence, ATTESTED_DATA)
+        .unwrap_err();
+    assert_eq!(
+        error
+            .downcast_ref::<PolicyRejection>()
+            .unwrap()
+            .reasons
+            .len(),
+        3
+    );
+}
+
+#[test]
+fn test_policy_verifier_requires_endorsement() {
+    let verifier = policy_verifier();
+    let mut evidence = AttestationEvidence::decode(
+        placeholder_evidence(configuration_report(), ATTESTED_DATA).as_ref(),
+    )
+    .unwrap();
+    evidence.rekor_log_entry.clear();
+    assert_eq!(
+        rejection_reasons(&verifier, &evidence.encode_to_vec()),
+        vec![RejectionReason::MissingEndorsement]
+    );
+}
+
+fn endorsement_verifier() -> EndorsementVerifier {
+    EndorsementVerifier::create_with_rekor_public_key(
+        &fs::read("../testdata/ec_public.pem").unwrap(),
+        &fs::read("../testdata/test_rekor_public_key.pem").unwrap(),
+    )
+    .expect("Couldn't create endorsement verifier")
+}
+
+#[test]
+fn test_bundled_rekor_public_key() {
+    assert_eq!(
+        REKOR_PUBLIC_KEY,
+        fs::read("../testdata/rekor_public_key.pem").unwrap()
+    );
+}
+
+#[test]
+fn test_endorsement_verifier_accepts_endorsed_wasm_module() {
+    let verifier = endorsement_verifier();
+    let evidence = placeholder_evidence(configuration_report(), ATTESTED_DATA);
+    assert_eq!(verifier.verify(&evidence, ATTESTED_DATA), Ok(()));
+    assert!(verifier
+        .verify_attestation(&evidence, ATTESTED_DATA)
+        .is_ok());
+}
+
+#[test]
+fn test_endorsement_verifier_rejects_unendorsed_wasm_module() {
+    let verifier = endorsement_verifier();
+    let mut configuration = configuration_report();
+    configuration.wasm_hash = vec![0; 32];
+    let evidence = placeholder_evidence(configuration, ATTESTED_DATA);
+    assert_eq!(
+        verifier.verify(&evidence, ATTESTED_DATA),
+        Err(RejectionReason::EndorsementSubjectMismatch {
+            wasm_hash: vec![0; 32]
+        }
+        .into())
+    );
+
+    // The attested configuration report cannot be replaced by an endorsed one.
+    let mut evidence = AttestationEvidence::decode(evidence.as_ref()).unwrap();
+    evidence.configuration = Some(configuration_report());
+    assert!(matches!(
+        verifier
+            .verify(&evidence.encode_to_vec(), ATTESTED_DATA)
+            .unwrap_err()
+            .reasons[..],
+        [RejectionReason::TeeVerificationFailed(_)]
+    ));
+}
+
+#[test]
+fn test_endorsement_verifier_requires_endorsement() {
+    let verifier = endorsement_verifier();
+    let mut evidence = AttestationEvidence::decode(
+        placeholder_evidence(configuration_report(), ATTESTED_DATA).as_ref(),
+    )
+    .unwrap();
+    evidence.endorsement.clear();
+    assert_eq!(
+        verifier.verify(&evidence.encode_to_vec(), ATTESTED_DATA),
+        Err(RejectionReason::MissingEndorsement.into())
+    );
+
+    // The plain placeholder attestation, which has no configuration report, is rejected.
+    let attestation = PlaceholderAmdAttestationGenerator
+        .generate_attestation(ATTESTED_DATA)
+        .unwrap();
+    assert!(verifier
+        .verify_attestation(&attestation, ATTESTED_DATA)
+        .is_err());
+}
+
+#[test]
+fn test_endorsement_verifier_checks_keys() {
+    // The endorsement must be signed with the endorser public key.
+    let test_rekor_public_key = fs::read("../testdata/test_rekor_public_key.pem").unwrap();
+    let verifier =
+        EndorsementVerifier::create_with_rekor_public_key(REKOR_PUBLIC_KEY, &test_rekor_public_key)
+            .unwrap();
+    let evidence = placeholder_evidence(configuration_report(), ATTESTED_DATA);
+    assert!(matches!(
+        verifier
+            .verify(&evidence, ATTESTED_DATA)
+            .unwrap_err()
+            .reasons[..],
+        [RejectionReason::TransparencyLogVerificationFailed(_)]
+    ));
+
+    // The log entry must be signed by Rekor.
+    let endorser_public_key = fs::read("../testdata/ec_public.pem").unwrap();
+    let verifier = EndorsementVerifier::create_with_rekor_public_key(
+        &endorser_public_key,
+        &endorser_public_key,
+    )
+    .unwrap();
+    assert!(matches!(
+        verifier
+            .verify(&evidence, ATTESTED_DATA)
+            .unwrap_err()
+            .reasons[..],
+        [RejectionReason::TransparencyLogVerificationFailed(_)]
+    ));
+
+    assert!(EndorsementVerifier::create(b"invalid key").is_err());
+}
+
+#[test]
+fn test_snp_requirements() {
+    // The attestation report is generated by `oak_remote_attestation_amd/testdata`, and signed at
+    // TCB version 3.0.8.115 by a guest with SVN 1.
+    let report = AttestationReport::parse(
+        &fs::read("../../../oak_remote_attestation_amd/testdata/report.bin").unwrap(),
+    )
+    .unwrap();
+    let policy = SnpPolicy {
+        ark_certificate_path: "../../../oak_remote_attestation_amd/testdata/ark.der".into(),
+        measurements: vec![hex::encode(
+            fs::read("../../../oak_remote_attestation_amd/testdata/measurement.bin").unwrap(),
+        )],
+        minimum_tcb: TcbVersion {
+            boot_loader: 3,
+            tee: 0,
+            snp: 8,
+            microcode: 115,
+        },
+        minimum_guest_svn: 1,
+        allow_debug: false,
+        allow_migrate_ma: false,
+    };
+    assert_eq!(
+        SnpRequirements::create(&policy).unwrap().check(&report),
+        vec![]
+    );
+
+    let stricter_policy = SnpPolicy {
+        measurements: vec![hex::encode([0; 48])],
+        minimum_tcb: TcbVersion {
+            microcode: 116,
+            ..policy.minimum_tcb
+        },
+        minimum_guest_svn: 2,
+        ..policy
+    };
+    let mut debug_report = report.clone();
+    debug_report.policy.0 |= 1 << 19;
+    assert_eq!(
+        SnpRequirements::create(&stricter_policy)
+            .unwrap()
+            .check(&debug_report),
+        vec![
+            RejectionReason::MeasurementNotAllowed {
+                measurement: report.measurement.to_vec()
+            },
+            RejectionReason::TcbVersionTooOld {
+                reported: report.reported_tcb,
+                minimum: stricter_policy.minimum_tcb,
+            },
+            RejectionReason::GuestSvnTooOld {
+                guest_svn: 1,
+                minimum: 2
+            },
+            RejectionReason::DebugAllowed,
+        ]
+    );
+}
+
+/// An attestation generator producing placeholder attestation evidence with the configuration
+/// report and the endorsement from the test data.
+#[derive(Clone)]
+struct PlaceholderEvidenceGenerator;
+
+impl AttestationGenerator for PlaceholderEvidenceGenerator {
+    fn generate_attestation(&self, attested_data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        Ok(placeholder_evidence(configuration_report(), attested_data))
+    }
+}
+
+/// Performs a handshake with a server attesting with [`PlaceholderEvidenceGenerator`], and returns
+/// the evidence bundle recorded by the client.
+fn handshake_evidence_bundle(verifier: PolicyVerifier) -> EvidenceBundle {
+    let mut client_handshaker = ClientHandshaker::new(AttestationBehavior::create(
+        EmptyAttestationGenerator,
+        verifier,
+    ))
+    .unwrap();
+    let mut server_handshaker = ServerHandshaker::new(AttestationBehavior::create(
+        PlaceholderEvidenceGenerator,
+        EmptyAttestationVerifier,
+    ))
+    .unwrap();
+    let client_hello = client_handshaker.create_client_hello().unwrap();
+    let server_identity = server_handshaker.next_step(&client_hello).unwrap().unwrap();
+    // The evidence is recorded whether or not the server is accepted.
+    let _ = client_handshaker.next_step(&server_identity);
+    EvidenceBundle::new(&client_handshaker.get_evidence().unwrap())
+}
+
+/// Returns a verifier that only accepts a Wasm module different from the one in the test data.
+fn other_wasm_policy_verifier() -> PolicyVerifier {
+    let mut policy = VerificationPolicy::load(Path::new("../testdata/verification_policy.toml"))
+        .expect("Couldn't load verification policy");
+    policy.wasm_hashes = Some(vec!["00".repeat(32)]);
+    PolicyVerifier::create(&policy).expect("Couldn't create policy verifier")
+}
+
+#[test]
+fn test_evidence_bundle() {
+    let bundle = handshake_evidence_bundle(policy_verifier());
+    assert_eq!(bundle.verification_error, None);
+    assert_eq!(
+        EvidenceBundle::from_json(&bundle.to_json().unwrap()).unwrap(),
+        bundle
+    );
+    assert!(bundle.verify(&policy_verifier()).is_ok());
+
+    // The bundle is verified against the current policy, rather than the recorded result.
+    let error = bundle.verify(&other_wasm_policy_verifier()).unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<PolicyRejection>().unwrap().reasons[..],
+        [RejectionReason::WasmHashNotAllowed { .. }, ..]
+    ));
+
+    // The attestation report cannot be replaced.
+    let mut invalid_bundle = bundle.clone();
+    invalid_bundle.attestation_report = placeholder_evidence(configuration_report(), ATTESTED_DATA);
+    assert!(invalid_bundle.verify(&policy_verifier()).is_err());
+
+    // The transcript must be signed by the attested signing key.
+    let mut invalid_bundle = bundle;
+    invalid_bundle.transcript_hash[0] ^= 1;
+    assert!(invalid_bundle.verify(&policy_verifier()).is_err());
+}
+
+#[test]
+fn test_rejected_evidence_bundle() {
+    let bundle = handshake_evidence_bundle(other_wasm_policy_verifier());
+    assert!(bundle
+        .verification_error
+        .as_ref()
+        .unwrap()
+        .contains("is not allowed"));
+    assert!(bundle.verify(&policy_verifier()).is_ok());
+}
+
+#[test]
+fn test_parse_evidence_bundle() {
+    let bundle = handshake_evidence_bundle(policy_verifier());
+    let mut json: serde_json::Value = serde_json::from_str(&bundle.to_json().unwrap()).unwrap();
+    assert_eq!(
+        json["signing_public_key"],
+        hex::encode(&bundle.signing_public_key)
+    );
+
+    json["format_version"] = 2.into();
+    assert!(EvidenceBundle::from_json(&json.to_string()).is_err());
+
+    json["format_version"] = 1.into();
+    json["transcript_hash"] = "abcd".into();
+    let bundle = EvidenceBundle::from_json(&json.to_string()).unwrap();
+    assert!(bundle.evidence().is_err());
+
+    json["unknown_field"] = true.into();
+    assert!(EvidenceBundle::from_json(&json.to_string()).is_err());
+}
+
+/// Test server that echoes requests, and counts the requests it handled.
+struct TestServer {
+    server: testing::TestServer,
+    requests: Arc<AtomicUsize>,
+    /// Size of the last encoded request, including padding.
+    last_request_size: Arc<AtomicUsize>,
+}
+
+impl TestServer {
+    /// Starts a server on `address`, or on a free port if not provided.
+    async fn start(address: Option<SocketAddr>) -> Self {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let handled_requests = requests.clone();
+        let last_request_size = Arc::new(AtomicUsize::new(0));
+        let handled_request_size = last_request_size.clone();
+        let server = AttestationServer::create_with_attestation_behavior(
+            move |encoded_request: Vec<u8>| {
+                handled_requests.fetch_add(1, Ordering::SeqCst);
+                handled_request_size.store(encoded_request.len(), Ordering::SeqCst);
+                async move {
+                    let request = Request::decode(&encoded_request)?;
+                    Ok(Response::create(StatusCode::Success, request.body).encode_to_vec())
+                }
+            },
+            TestLogger,
+            AttestationBehavior::create(PlaceholderEvidenceGenerator, EmptyAttestationVerifier),
+            None,
+        )
+        .expect("Couldn't create attestation server");
+        let server = testing::TestServer::start(
+            tonic::transport::Server::builder().add_service(UnarySessionServer::new(server)),
+            address,
+        )
+        .await;
+        Self {
+            server,
+            requests,
+            last_request_size,
+        }
+    }
+
+    fn uri(&self) -> String {
+        self.server.uri()
+    }
+
+    /// Creates a client that verifies the endorsement sent by the server, as [`Client::new`] does,
+    /// but with the Rekor public key of the test data.
+    async fn client(&self, config: ClientConfig) -> Client {
+        Client::new_with_rekor_public_key(
+            &self.uri(),
+            &fs::read("../testdata/ec_public.pem").unwrap(),
+            &fs::read("../testdata/test_rekor_public_key.pem").unwrap(),
+            config,
+        )
+        .await
+        .expect("Couldn't create client")
+    }
+
+    fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    fn last_request_size(&self) -> usize {
+        self.last_request_size.load(Ordering::SeqCst)
+    }
+
+    /// Stops the server, which loses all its sessions, and returns its address.
+    async fn stop(self) -> SocketAddr {
+        self.server.stop().await
+    }
+}
+
+fn request(body: &[u8]) -> Request {
+    Request {
+        body: body.to_vec(),
+    }
+}
+
+#[tokio::test]
+async fn test_client_renews_lost_session() {
+    let server = TestServer::start(None).await;
+    let client = server.client(ClientConfig::default()).await;
+    let response = client.invoke(request(b"first")).await.unwrap();
+    assert_eq!(response.body().unwrap(), b"first");
+
+    // A restarted server asks the client to perform a new handshake, since it doesn't know the
+    // session. The request was not processed, so it is sent again even if it is not idempotent.
+    let server = TestServer::start(Some(server.stop().await)).await;
+    let response = client
+        .invoke_at_most_once(request(b"second"))
+        .await
+        .unwrap();
+    assert_eq!(response.body().unwrap(), b"second");
+    assert_eq!(server.requests(), 1);
+}
+
+#[tokio::test]
+async fn test_client_rejected_server_evidence_bundle() {
+    let server = TestServer::start(None).await;
+
+    // The evidence of a rejected server is carried by the error, together with the reason.
+    let error = Client::new_with_policy(
+        &server.uri(),
+        other_wasm_policy_verifier(),
+        ClientConfig::default(),
+    )
+    .await
+    .err()
+    .expect("Server running a different Wasm module was accepted");
+    let bundle = EvidenceBundle::from_error(&error).expect("Missing rejected evidence bundle");
+    assert!(bundle
+        .verification_error
+        .as_ref()
+        .unwrap()
+        .contains("is not allowed"));
+    assert!(bundle.verify(&policy_verifier()).is_ok());
+    assert_eq!(server.requests(), 0);
+}
+
+#[tokio::test]
+async fn test_client_verifies_endorsement_by_default() {
+    let server = TestServer::start(None).await;
+
+    // The endorsement sent by the test server is not included in the Rekor instance hosted by
+    // sigstore.dev.
+    let error = Client::new(
+        &server.uri(),
+        &fs::read("../testdata/ec_public.pem").unwrap(),
+        ClientConfig::default(),
+    )
+    .await
+    .err()
+    .expect("Server with an endorsement not included in Rekor was accepted");
+    let bundle = EvidenceBundle::from_error(&error).expect("Missing rejected evidence bundle");
+    assert!(bundle
+        .verification_error
+        .as_ref()
+        .unwrap()
+        .contains("transparency log verification failed"));
+
+    // Opting out of endorsement verification accepts any server.
+    let client =
+        Client::new_without_endorsement_verification(&server.uri(), ClientConfig::default())
+            .await
+            .unwrap();
+    let response = client.invoke(request(b"unverified")).await.unwrap();
+    assert_eq!(response.body().unwrap(), b"unverified");
+}
+
+#[tokio::test]
+async fn test_client_does_not_retry_by_default() {
+    let server = TestServer::start(None).await;
+    let client = server.client(ClientConfig::default()).await;
+    assert_eq!(ClientConfig::default().retry_budget, 0);
+
+    // The request fails while the server is unreachable, and is not sent again once it is back.
+    let address = server.stop().await;
+    assert!(client.invoke(request(b"first")).await.is_err());
+    let server = TestServer::start(Some(address)).await;
+    assert_eq!(server.requests(), 0);
+    let response = client.invoke(request(b"second")).await.unwrap();
+    assert_eq!(response.body().unwrap(), b"second");
+    assert_eq!(server.requests(), 1);
+}
+
+#[tokio::test]
+async fn test_client_retries_idempotent_requests() {
+    let server = TestServer::start(None).await;
+    let client = server
+        .client(ClientConfig {
+            retry_budget: 5,
+            retry_backoff: Duration::from_millis(50),
+            ..Default::default()
+        })
+        .await;
+
+    // Idempotent requests are retried until the server is reachable again.
+    let address = server.stop().await;
+    let restarted_server = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        TestServer::start(Some(address)).await
+    });
+    let response = client.invoke(request(b"first")).await.unwrap();
+    assert_eq!(response.body().unwrap(), b"first");
+
+    // Other requests are not retried, but the failed session is replaced in the next invocation.
+    let address = restarted_server.await.unwrap().stop().await;
+    assert!(client
+        .invoke_at_most_once(request(b"second"))
+        .await
+        .is_err());
+    let server = TestServer::start(Some(address)).await;
+    let response = client.invoke_at_most_once(request(b"third")).await.unwrap();
+    assert_eq!(response.body().unwrap(), b"third");
+    assert_eq!(server.requests(), 1);
+
+    // The retry budget is limited.
+    server.stop().await;
+    assert!(client.invoke(request(b"fourth")).await.is_err());
+}
+
+#[tokio::test]
+async fn test_client_concurrent_invocations() {
+    let server = TestServer::start(None).await;
+    let client = server
+        .client(ClientConfig {
+            max_sessions: 2,
+            ..Default::default()
+        })
+        .await;
+
+    let (first, second, third) = tokio::join!(
+        client.invoke(request(b"first")),
+        client.invoke(request(b"second")),
+        client.invoke(request(b"third")),
+    );
+    assert_eq!(first.unwrap().body().unwrap(), b"first");
+    assert_eq!(second.unwrap().body().unwrap(), b"second");
+    assert_eq!(third.unwrap().body().unwrap(), b"third");
+    assert_eq!(server.requests(), 3);
+    assert!(client.idle_sessions.lock().unwrap().len() <= 2);
+}
+
+#[tokio::test]
+async fn test_client_max_sessions() {
+    let server = TestServer::start(None).await;
+    let config = |max_sessions| ClientConfig {
+        max_sessions,
+        ..Default::default()
+    };
+    assert!(
+        Client::new_without_endorsement_verification(&server.uri(), config(0))
+            .await
+            .is_err()
+    );
+
+    // The idle session created with the client is used by the first invocation, while the second
+    // invocation creates another session.
+    let client = server.client(config(2)).await;
+    let (first, second) = tokio::join!(
+        client.invoke(request(b"first")),
+        client.invoke(request(b"second")),
+    );
+    assert_eq!(first.unwrap().body().unwrap(), b"first");
+    assert_eq!(second.unwrap().body().unwrap(), b"second");
+    assert_eq!(client.idle_sessions.lock().unwrap().len(), 2);
+
+    // Idle sessions beyond the new maximum are discarded.
+    let client = client.with_config(config(1)).unwrap();
+    assert_eq!(client.idle_sessions.lock().unwrap().len(), 1);
+    assert!(client.with_config(config(0)).is_err());
+}
+
+#[test]
+fn test_response_body_status() {
+    let response = Response::create(StatusCode::Success, b"body".to_vec());
+    assert_eq!(response_body(&response).unwrap(), b"body");
+
+    let error = |status| response_body(&Response::create(status, vec![])).unwrap_err();
+    assert!(matches!(
+        error(StatusCode::BadRequest),
+        InvocationError::BadRequest
+    ));
+    assert!(matches!(
+        error(StatusCode::PolicySizeViolation),
+        InvocationError::PolicySizeViolation
+    ));
+    assert!(matches!(
+        error(StatusCode::PolicyTimeViolation),
+        InvocationError::PolicyTimeViolation
+    ));
+    assert!(matches!(
+        error(StatusCode::InternalServerError),
+        InvocationError::InternalServerError
+    ));
+    assert!(matches!(
+        error(StatusCode::Unspecified),
+        InvocationError::UnspecifiedStatus
+    ));
+}
+
+#[tokio::test]
+async fn test_typed_client() {
+    let server = TestServer::start(None).await;
+    let client = || async { server.client(ClientConfig::default()).await };
+
+    let text_client = client().await.typed(TextCodec);
+    let response = text_client.invoke(&"text".to_string()).await.unwrap();
+    assert_eq!(response, "text");
+
+    let json_client = client()
+        .await
+        .typed(JsonCodec::<serde_json::Value, serde_json::Value>::default());
+    let request = serde_json::json!({"lat": 52, "lng": 0});
+    let response = json_client.invoke_at_most_once(&request).await.unwrap();
+    assert_eq!(response, request);
+
+    // The echoed request can't be decoded as a different type.
+    let mismatched_client = client()
+        .await
+        .typed(JsonCodec::<serde_json::Value, Vec<u8>>::default());
+    assert!(matches!(
+        mismatched_client.invoke(&request).await,
+        Err(InvocationError::Codec(_))
+    ));
+
+    let protobuf_client = client()
+        .await
+        .typed(ProtobufCodec::<ServerPolicy, ServerPolicy>::default());
+    let request = ServerPolicy {
+        constant_response_size_bytes: 1024,
+        constant_processing_time_ms: 200,
+        max_request_size_bytes: 0,
+    };
+    let response = protobuf_client.invoke(&request).await.unwrap();
+    assert_eq!(response, request);
+}
+
+#[test]
+fn test_replay_cases() {
+    let cases = ReplayCases::load(Path::new("../testdata/replay_cases.toml")).unwrap();
+    assert_eq!(cases.cases.len(), 2);
+    let case = &cases.cases[0];
+    assert!(case
+        .check(&Response::create(
+            StatusCode::Success,
+            br#"{"temperature_degrees_celsius":10}"#.to_vec()
+        ))
+        .is_ok());
+    assert!(case
+        .check(&Response::create(
+            StatusCode::Success,
+            b"not found".to_vec()
+        ))
+        .is_err());
+    assert!(case
+        .check(&Response::create(
+            StatusCode::PolicySizeViolation,
+            br#"{"temperature_degrees_celsius":10}"#.to_vec()
+        ))
+        .is_err());
+
+    let cases = ReplayCases::from_toml(
+        r#"
+        [[case]]
+        name = "too large"
+        request = "large"
+        expected_status = "PolicySizeViolation"
+        expected_response = ""
+        "#,
+    )
+    .unwrap();
+    let case = &cases.cases[0];
+    assert!(case
+        .check(&Response::create(StatusCode::PolicySizeViolation, vec![]))
+        .is_ok());
+    assert!(case
+        .check(&Response::create(StatusCode::Success, vec![]))
+        .is_err());
+
+    assert!(ReplayCases::from_toml(
+        r#"
+        [[case]]
+        name = "unknown status"
+        request = ""
+        expected_status = "NotFound"
+        "#
+    )
+    .is_err());
+    assert!(ReplayCases::from_toml(
+        r#"
+        [[case]]
+        name = "invalid pattern"
+        request = ""
+        expected_response_pattern = "("
+        "#
+    )
+    .is_err());
+}
+
+#[test]
+fn test_load_report_percentiles() {
+    let report = LoadReport {
+        successes: 10,
+        errors: Default::default(),
+        duration: Duration::from_secs(2),
+        latencies: (1..=10).map(Duration::from_millis).collect(),
+    };
+    assert_eq!(report.throughput(), 5.0);
+    assert_eq!(
+        report.latency_percentile(0.0),
+        Some(Duration::from_millis(1))
+    );
+    assert_eq!(
+        report.latency_percentile(50.0),
+        Some(Duration::from_millis(5))
+    );
+    assert_eq!(
+        report.latency_percentile(90.0),
+        Some(Duration::from_millis(9))
+    );
+    assert_eq!(
+        report.latency_percentile(99.0),
+        Some(Duration::from_millis(10))
+    );
+    assert_eq!(
+        report.latency_percentile(100.0),
+        Some(Duration::from_millis(10))
+    );
+
+    let empty_report = LoadReport {
+        latencies: vec![],
+        ..report
+    };
+    assert_eq!(empty_report.latency_percentile(50.0), None);
+}
+
+#[tokio::test]
+async fn test_load() {
+    let server = TestServer::start(None).await;
+    let client = server
+        .client(ClientConfig {
+            max_sessions: 2,
+            ..Default::default()
+        })
+        .await;
+
+    let closed_loop = LoadConfig {
+        mode: LoadMode::ClosedLoop { concurrency: 2 },
+        requests: 5,
+    };
+    let report = crate::load::run(&client, b"load", &closed_loop)
+        .await
+        .unwrap();
+    assert_eq!(report.successes, 5);
+    assert_eq!(report.failures(), 0);
+    assert_eq!(report.latencies.len(), 5);
+
+    let open_loop = LoadConfig {
+        mode: LoadMode::OpenLoop { rate: 100.0 },
+        requests: 5,
+    };
+    let report = crate::load::run(&client, b"load", &open_loop)
+        .await
+        .unwrap();
+    assert_eq!(report.successes, 5);
+    // The last request is sent 40ms after the first one.
+    assert!(report.duration >= Duration::from_millis(40));
+    assert_eq!(server.requests(), 10);
+
+    // Rates that are not positive numbers, or whose period between requests doesn't fit in a
+    // `Duration`, are rejected without sending any request.
+    for rate in [0.0, -1.0, f64::NAN, f64::INFINITY, 1e-300, 1e300] {
+        let invalid_open_loop = LoadConfig {
+            mode: LoadMode::OpenLoop { rate },
+            requests: 5,
+        };
+        assert!(crate::load::run(&client, b"load", &invalid_open_loop)
+            .await
+            .is_err());
+    }
+    assert_eq!(server.requests(), 10);
+
+    server.stop().await;
+    let report = crate::load::run(&client, b"load", &closed_loop)
+        .await
+        .unwrap();
+    assert_eq!(report.successes, 0);
+    assert_eq!(report.failures(), 5);
+}
+
+#[tokio::test]
+async fn test_client_request_padding() {
+    let server = TestServer::start(None).await;
+    let client = server.client(ClientConfig::default()).await;
+    let response = client.invoke(request(b"short")).await.unwrap();
+    assert_eq!(response.body().unwrap(), b"short");
+    // Requests are not padded by default.
+    assert_eq!(server.last_request_size(), 5);
+
+    let client = client
+        .with_config(ClientConfig {
+            request_bucket_size: Some(64),
+            ..Default::default()
+        })
+        .unwrap();
+    // Padded requests start with the 8 bytes of the padding marker and the 8 bytes of the length.
+    for body in [&b"short"[..], &[1; 48], &[2; 49]] {
+        let response = client.invoke(request(body)).await.unwrap();
+        assert_eq!(response.body().unwrap(), body);
+        let expected_size = if body.len() <= 48 { 64 } else { 128 };
+        assert_eq!(server.last_request_size(), expected_size);
+    }
+}
+
+#[test]
+fn test_grpc_web_session_lost() {
+    let session_lost = |code| {
+        let error = anyhow::Error::from(oak_grpc_web::Status::new(code, ""))
+            .context("Error invoking Oak Functions instance");
+        is_session_lost(&error)
+    };
+    assert!(session_lost(oak_grpc_web::code::UNAVAILABLE));
+    assert!(session_lost(oak_grpc_web::code::ABORTED));
+    assert!(!session_lost(oak_grpc_web::code::INTERNAL));
+}
+
+#[test]
+fn test_parse_transport() {
+    assert_eq!("grpc".parse::<Transport>().unwrap(), Transport::Grpc);
+    assert_eq!("grpc-web".parse::<Transport>().unwrap(), Transport::GrpcWeb);
+    assert_eq!("http".parse::<Transport>().unwrap(), Transport::Http);
+    assert!("h2".parse::<Transport>().is_err());
+}
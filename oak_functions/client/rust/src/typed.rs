@@ -0,0 +1,222 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Typed requests and responses for Oak Functions applications.
+//!
+//! A [`TypedClient`] encodes requests and decodes responses with a [`Codec`] matching the encoding
+//! used by the Wasm module, and turns the [`StatusCode`] of unsuccessful responses into an
+//! [`InvocationError`].
+
+use crate::Client;
+use oak_functions_abi::{Request, Response, StatusCode};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fmt, marker::PhantomData};
+
+/// Encoding of the requests and responses of an Oak Functions application.
+pub trait Codec {
+    type Request;
+    type Response;
+
+    fn encode_request(&self, request: &Self::Request) -> anyhow::Result<Vec<u8>>;
+    fn decode_response(&self, body: &[u8]) -> anyhow::Result<Self::Response>;
+}
+
+/// Encodes requests and responses as JSON, e.g. for the `weather_lookup` example.
+pub struct JsonCodec<Req, Res> {
+    types: PhantomData<fn(&Req) -> Res>,
+}
+
+impl<Req, Res> Default for JsonCodec<Req, Res> {
+    fn default() -> Self {
+        Self { types: PhantomData }
+    }
+}
+
+impl<Req: Serialize, Res: DeserializeOwned> Codec for JsonCodec<Req, Res> {
+    type Request = Req;
+    type Response = Res;
+
+    fn encode_request(&self, request: &Req) -> anyhow::Result<Vec<u8>> {
+        Ok(serde_json::to_vec(request)?)
+    }
+
+    fn decode_response(&self, body: &[u8]) -> anyhow::Result<Res> {
+        Ok(serde_json::from_slice(body)?)
+    }
+}
+
+/// Encodes requests and responses as protocol buffers.
+pub struct ProtobufCodec<Req, Res> {
+    types: PhantomData<fn(&Req) -> Res>,
+}
+
+impl<Req, Res> Default for ProtobufCodec<Req, Res> {
+    fn default() -> Self {
+        Self { types: PhantomData }
+    }
+}
+
+impl<Req: prost::Message, Res: prost::Message + Default> Codec for ProtobufCodec<Req, Res> {
+    type Request = Req;
+    type Response = Res;
+
+    fn encode_request(&self, request: &Req) -> anyhow::Result<Vec<u8>> {
+        Ok(request.encode_to_vec())
+    }
+
+    fn decode_response(&self, body: &[u8]) -> anyhow::Result<Res> {
+        Ok(Res::decode(body)?)
+    }
+}
+
+/// Encodes requests and responses as UTF-8 text.
+#[derive(Default)]
+pub struct TextCodec;
+
+impl Codec for TextCodec {
+    type Request = String;
+    type Response = String;
+
+    fn encode_request(&self, request: &String) -> anyhow::Result<Vec<u8>> {
+        Ok(request.as_bytes().to_vec())
+    }
+
+    fn decode_response(&self, body: &[u8]) -> anyhow::Result<String> {
+        Ok(std::str::from_utf8(body)?.to_string())
+    }
+}
+
+/// Error returned when invoking an Oak Functions application with a [`TypedClient`].
+#[derive(Debug)]
+pub enum InvocationError {
+    /// The Wasm module could not handle the request, e.g. because it is malformed.
+    BadRequest,
+    /// The response of the Wasm module is larger than the constant response size of the server
+    /// policy.
+    PolicySizeViolation,
+    /// The Wasm module took longer than the constant processing time of the server policy.
+    PolicyTimeViolation,
+    InternalServerError,
+    /// The response has an unspecified status.
+    UnspecifiedStatus,
+    /// The request could not be encoded, or the response could not be decoded.
+    Codec(anyhow::Error),
+    /// The application could not be invoked, e.g. because the server is not reachable.
+    Invocation(anyhow::Error),
+}
+
+impl InvocationError {
+    /// Returns the error corresponding to the status of an unsuccessful response, or [`None`] for
+    /// [`StatusCode::Success`].
+    pub fn from_status(status: StatusCode) -> Option<Self> {
+        match status {
+            StatusCode::Success => None,
+            StatusCode::Unspecified => Some(InvocationError::UnspecifiedStatus),
+            StatusCode::BadRequest => Some(InvocationError::BadRequest),
+            StatusCode::PolicySizeViolation => Some(InvocationError::PolicySizeViolation),
+            StatusCode::PolicyTimeViolation => Some(InvocationError::PolicyTimeViolation),
+            StatusCode::InternalServerError => Some(InvocationError::InternalServerError),
+        }
+    }
+}
+
+impl fmt::Display for InvocationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvocationError::BadRequest => write!(f, "bad request"),
+            InvocationError::PolicySizeViolation => {
+                write!(f, "response size exceeds the server policy")
+            }
+            InvocationError::PolicyTimeViolation => {
+                write!(f, "processing time exceeds the server policy")
+            }
+            InvocationError::InternalServerError => write!(f, "internal server error"),
+            InvocationError::UnspecifiedStatus => write!(f, "unspecified response status"),
+            InvocationError::Codec(error) => write!(f, "couldn't encode or decode: {:#}", error),
+            InvocationError::Invocation(error) => write!(f, "couldn't invoke: {:#}", error),
+        }
+    }
+}
+
+impl std::error::Error for InvocationError {}
+
+/// Returns the body of a successful response without padding, or the error corresponding to its
+/// status.
+pub fn response_body(response: &Response) -> Result<&[u8], InvocationError> {
+    if let Some(error) = InvocationError::from_status(response.status) {
+        return Err(error);
+    }
+    response
+        .body()
+        .map_err(|error| InvocationError::Codec(anyhow::anyhow!("invalid body length: {}", error)))
+}
+
+/// A [`Client`] sending requests and receiving responses encoded with a [`Codec`].
+pub struct TypedClient<C: Codec> {
+    client: Client,
+    codec: C,
+}
+
+impl<C: Codec> TypedClient<C> {
+    pub fn new(client: Client, codec: C) -> Self {
+        Self { client, codec }
+    }
+
+    /// Returns the underlying client, e.g. to get the evidence bundle of the server.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Invokes the application with an idempotent request, see [`Client::invoke`].
+    pub async fn invoke(&self, request: &C::Request) -> Result<C::Response, InvocationError> {
+        let request = self.encode_request(request)?;
+        let response = self
+            .client
+            .invoke(request)
+            .await
+            .map_err(InvocationError::Invocation)?;
+        self.decode_response(&response)
+    }
+
+    /// Invokes the application with a request that must not be sent more than once, see
+    /// [`Client::invoke_at_most_once`].
+    pub async fn invoke_at_most_once(
+        &self,
+        request: &C::Request,
+    ) -> Result<C::Response, InvocationError> {
+        let request = self.encode_request(request)?;
+        let response = self
+            .client
+            .invoke_at_most_once(request)
+            .await
+            .map_err(InvocationError::Invocation)?;
+        self.decode_response(&response)
+    }
+
+    fn encode_request(&self, request: &C::Request) -> Result<Request, InvocationError> {
+        let body = self
+            .codec
+            .encode_request(request)
+            .map_err(InvocationError::Codec)?;
+        Ok(Request { body })
+    }
+
+    fn decode_response(&self, response: &Response) -> Result<C::Response, InvocationError> {
+        self.codec
+            .decode_response(response_body(response)?)
+            .map_err(InvocationError::Codec)
+    }
+}
@@ -19,7 +19,10 @@ use hashbrown::HashMap;
 use maplit::hashmap;
 use oak_functions_abi::{proto::ServerPolicy, Request, StatusCode};
 use oak_functions_loader::{
+    attestation::{ClientAttestationVerifier, EvidenceGenerator},
     grpc::{create_and_start_grpc_server, create_wasm_handler},
+    health::HealthState,
+    listener::ListenAddress,
     logger::Logger,
     lookup_data::{LookupDataAuth, LookupDataRefresher, LookupDataSource},
     server::WasmHandler,
@@ -65,6 +68,7 @@ async fn test_server() {
     let policy = ServerPolicy {
         constant_response_size_bytes: 100,
         constant_processing_time_ms: 200,
+        max_request_size_bytes: 0,
     };
     let logger = Logger::for_test();
     let lookup_data_manager = Arc::new(LookupDataManager::new_empty(logger.clone()));
@@ -74,6 +78,7 @@ async fn test_server() {
             auth: LookupDataAuth::default(),
         }),
         lookup_data_manager.clone(),
+        Arc::new(HealthState::default()),
         logger.clone(),
     );
     lookup_data_refresher.refresh().await.unwrap();
@@ -85,8 +90,20 @@ async fn test_server() {
         create_wasm_handler(&wasm_module_bytes, vec![lookup_factory], logger.clone())
             .expect("could not create wasm_handler");
 
+    let attestation_generator = EvidenceGenerator::create(&wasm_module_bytes, &policy);
     let server_background = test_utils::background(|term| async move {
-        create_and_start_grpc_server(&address, wasm_handler, policy.clone(), term, logger).await
+        create_and_start_grpc_server(
+            &ListenAddress::Tcp(address),
+            wasm_handler,
+            policy.clone(),
+            attestation_generator,
+            ClientAttestationVerifier::default(),
+            None,
+            Arc::new(HealthState::default()),
+            term,
+            logger,
+        )
+        .await
     });
 
     {
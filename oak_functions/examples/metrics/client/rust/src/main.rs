@@ -18,8 +18,7 @@
 
 use anyhow::Context;
 use clap::Parser;
-use oak_functions_abi::Request;
-use oak_functions_client::Client;
+use oak_functions_client::{typed::TextCodec, Client, ClientConfig};
 
 #[derive(Parser, Clone)]
 #[clap(about = "Oak Functions Client")]
@@ -36,18 +35,17 @@ pub struct Opt {
 async fn main() -> anyhow::Result<()> {
     let opt = Opt::parse();
 
-    let mut client = Client::new(&opt.uri)
+    let client = Client::new_without_endorsement_verification(&opt.uri, ClientConfig::default())
         .await
-        .context("Could not create Oak Functions client")?;
+        .context("Could not create Oak Functions client")?
+        .typed(TextCodec);
 
     for i in 0..200 {
-        let body = if i % 2 == 0 { b"a" } else { b"b" };
-        let request = Request {
-            body: body.to_vec(),
-        };
+        let request = if i % 2 == 0 { "a" } else { "b" }.to_string();
 
+        // Every request is counted in the metrics, so requests must not be sent again.
         client
-            .invoke(request)
+            .invoke_at_most_once(&request)
             .await
             .context("Could not invoke Oak Functions")?;
     }
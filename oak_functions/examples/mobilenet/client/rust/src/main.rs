@@ -18,7 +18,7 @@
 
 use anyhow::Context;
 use oak_functions_abi::Request;
-use oak_functions_client::Client;
+use oak_functions_client::{typed::response_body, Client, ClientConfig};
 use regex::Regex;
 use tract_tensorflow::prelude::*;
 
@@ -31,9 +31,12 @@ const CHANNELS: usize = 3;
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     env_logger::init();
-    let mut client = Client::new("http://localhost:8080")
-        .await
-        .context("Could not create Oak Functions client")?;
+    let client = Client::new_without_endorsement_verification(
+        "http://localhost:8080",
+        ClientConfig::default(),
+    )
+    .await
+    .context("Could not create Oak Functions client")?;
 
     let image_buffer = image::open("oak_functions/examples/mobilenet/files/oak.jpg")
         .unwrap()
@@ -66,7 +69,7 @@ async fn main() -> anyhow::Result<()> {
 
     // Allow some small variance in the result, as the number can change slightly between different
     // versions of libraries. We still want the test to fail for any significant changes, though.
-    let response_body = std::str::from_utf8(response.body().unwrap()).unwrap();
+    let response_body = std::str::from_utf8(response_body(&response)?)?;
     let regex = Regex::new(r"^Best result: Some\(\(0.175232\d+, 789\)\)$").unwrap();
     assert!(
         regex.is_match(response_body),
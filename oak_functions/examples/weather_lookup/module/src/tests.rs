@@ -23,7 +23,10 @@ use lookup_data_generator::data::generate_and_serialize_sparse_weather_entries;
 use maplit::hashmap;
 use oak_functions_abi::{proto::ServerPolicy, Request, StatusCode};
 use oak_functions_loader::{
+    attestation::{ClientAttestationVerifier, EvidenceGenerator},
     grpc::{create_and_start_grpc_server, create_wasm_handler},
+    health::HealthState,
+    listener::ListenAddress,
     logger::Logger,
     lookup_data::{parse_lookup_entries, LookupDataAuth, LookupDataRefresher, LookupDataSource},
     server::WasmHandler,
@@ -87,6 +90,7 @@ async fn test_server() {
         // the WASM module itself may also not be optimized. Creating lookup
         // tables for the weather example is computationally expensive.
         constant_processing_time_ms: 200 * 10,
+        max_request_size_bytes: 0,
     };
     let logger = Logger::for_test();
     let workload_logging_factory =
@@ -99,6 +103,7 @@ async fn test_server() {
             auth: LookupDataAuth::default(),
         }),
         lookup_data_manager.clone(),
+        Arc::new(HealthState::default()),
         logger.clone(),
     );
     lookup_data_refresher.refresh().await.unwrap();
@@ -113,8 +118,20 @@ async fn test_server() {
     )
     .expect("could not create wasm_handler");
 
+    let attestation_generator = EvidenceGenerator::create(&wasm_module_bytes, &policy);
     let server_background = test_utils::background(|term| async move {
-        create_and_start_grpc_server(&address, wasm_handler, policy.clone(), term, logger).await
+        create_and_start_grpc_server(
+            &ListenAddress::Tcp(address),
+            wasm_handler,
+            policy.clone(),
+            attestation_generator,
+            ClientAttestationVerifier::default(),
+            None,
+            Arc::new(HealthState::default()),
+            term,
+            logger,
+        )
+        .await
     });
 
     // Test request coordinates are defined in `oak_functions/lookup_data_generator/src/data.rs`.
@@ -106,8 +106,11 @@ pub struct PrivateMetricsConfig {
 }
 
 impl PrivateMetricsConfig {
+    /// Checks that the config is valid. Called both when creating the metrics extension and by the
+    /// `check-config` mode of the loader, so that both accept the same configs.
     pub fn validate(&self) -> anyhow::Result<()> {
         anyhow::ensure!(self.epsilon > 0.0, "Epsilon must be positive",);
+        anyhow::ensure!(self.batch_size > 0, "Batch size must be positive");
         for (label, bucket_config) in &self.buckets {
             if let BucketConfig::Sum { min, max } = bucket_config {
                 anyhow::ensure!(
@@ -522,6 +525,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_validate_batch_size() {
+        let config = PrivateMetricsConfig {
+            epsilon: 1.0,
+            batch_size: 0,
+            buckets: btreemap! {
+                "a".to_string() => BucketConfig::Count,
+            },
+        };
+        assert!(config.validate().is_err());
+    }
+
     #[test]
     fn test_laplace_noise() {
         // Run many times and make sure the shape of the histogram looks roughly right.
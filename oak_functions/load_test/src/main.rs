@@ -17,7 +17,7 @@
 use anyhow::Context;
 use bencher::stats::Stats;
 use oak_functions_abi::Request;
-use oak_functions_client::Client;
+use oak_functions_client::{Client, ClientConfig};
 use std::time::Instant;
 
 // From https://pantheon.corp.google.com/api-gateway/gateway/weather-lookup-grpc/location/europe-west2?project=oak-ci.
@@ -28,7 +28,9 @@ const TOTAL_REQUESTS: usize = 50;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let mut client = Client::new(URL).await.context("Could not create client")?;
+    let client = Client::new_without_endorsement_verification(URL, ClientConfig::default())
+        .await
+        .context("Could not create client")?;
 
     let mut latencies_millis = Vec::<f64>::with_capacity(TOTAL_REQUESTS);
 
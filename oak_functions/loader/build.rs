@@ -19,7 +19,10 @@ use oak_utils::{generate_grpc_code, CodegenOptions};
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     generate_grpc_code(
         "../../",
-        &["oak_functions/proto/benchmark.proto"],
+        &[
+            "oak_functions/proto/benchmark.proto",
+            "oak_functions/proto/health.proto",
+        ],
         CodegenOptions {
             build_client: false,
             build_server: true,
@@ -49,6 +49,7 @@ impl Arbitrary<'_> for ResponseAndValidPolicy {
         let policy = ServerPolicy {
             constant_response_size_bytes: raw.int_in_range(50..=5000)?,
             constant_processing_time_ms: 10,
+            max_request_size_bytes: 0,
         };
 
         Ok(ResponseAndValidPolicy { response, policy })
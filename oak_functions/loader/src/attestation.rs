@@ -0,0 +1,325 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Attestation of the gRPC server of the Oak Functions loader to its clients, and of clients to the
+//! server.
+//!
+//! The server attests itself with an [`EvidenceGenerator`], which sends the
+//! [`AttestationEvidence`] checked by the verification policies of Oak Functions clients.
+//!
+//! By default clients are not attested. Clients running in a TEE (e.g. other enclaves calling the
+//! Oak Functions server) can be required to attest themselves during the handshake, in which case
+//! the handshake fails unless their attestation is verified.
+//!
+//! If the loader is configured with an endorsement of its Wasm module (see [`EndorsementConfig`]),
+//! the endorsement and its Rekor log entry are sent in the attestation evidence as well, so that
+//! clients verifying endorsements accept the server.
+//!
+//! Sessions established in a handshake can be resumed with session tickets, which are sealed with
+//! a key shared by all the servers of a deployment (see [`SessionTicketsConfig`]).
+
+use anyhow::Context;
+use oak_functions_abi::proto::{AttestationEvidence, ConfigurationReport, ServerPolicy};
+use oak_remote_attestation::{
+    crypto::get_sha256,
+    handshaker::{
+        hash_concat_hash, AttestationGenerator, AttestationVerifier, EmptyAttestationVerifier,
+    },
+    resumption::{SessionTicketKey, SESSION_TICKET_KEY_LENGTH},
+};
+use oak_remote_attestation_amd::{
+    report::{TcbVersion, MEASUREMENT_SIZE},
+    verification::{SnpAttestationVerifier, SnpReferenceValues},
+    PlaceholderAmdAttestationGenerator, PlaceholderAmdAttestationVerifier,
+};
+use prost::Message;
+use serde_derive::Deserialize;
+use std::{convert::TryInto, fs};
+
+/// Configuration of the attestation of clients.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields, tag = "type")]
+pub enum ClientAttestationConfig {
+    /// Clients are not attested.
+    #[default]
+    None,
+    /// Clients must attest themselves with a placeholder AMD attestation report.
+    PlaceholderAmd,
+    /// Clients must attest themselves with AMD SEV-SNP attestation evidence.
+    SevSnp {
+        /// Path of the DER-encoded ARK certificate, which is the root of trust.
+        ark_path: String,
+        /// Accepted hex-encoded launch measurements.
+        measurements: Vec<String>,
+        /// Minimum accepted TCB version that reports were signed at.
+        #[serde(default)]
+        minimum_tcb: TcbVersion,
+        /// Minimum accepted security version number of the guest.
+        #[serde(default)]
+        minimum_guest_svn: u32,
+    },
+}
+
+impl ClientAttestationConfig {
+    /// Creates the verifier for the attestation of clients, reading the files referenced by the
+    /// config.
+    pub fn create_verifier(&self) -> anyhow::Result<ClientAttestationVerifier> {
+        match self {
+            ClientAttestationConfig::None => Ok(ClientAttestationVerifier::default()),
+            ClientAttestationConfig::PlaceholderAmd => Ok(
+                ClientAttestationVerifier::PlaceholderAmd(PlaceholderAmdAttestationVerifier),
+            ),
+            ClientAttestationConfig::SevSnp {
+                ark_path,
+                measurements,
+                minimum_tcb,
+                minimum_guest_svn,
+            } => {
+                let ark = fs::read(ark_path)
+                    .with_context(|| format!("Couldn't read ARK certificate {}", ark_path))?;
+                let measurements = measurements
+                    .iter()
+                    .map(|measurement| parse_measurement(measurement))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                anyhow::ensure!(
+                    !measurements.is_empty(),
+                    "at least one measurement must be accepted"
+                );
+                Ok(ClientAttestationVerifier::SevSnp(
+                    SnpAttestationVerifier::new(
+                        ark,
+                        SnpReferenceValues {
+                            measurements,
+                            minimum_tcb: *minimum_tcb,
+                            minimum_guest_svn: *minimum_guest_svn,
+                            ..Default::default()
+                        },
+                    ),
+                ))
+            }
+        }
+    }
+}
+
+fn parse_measurement(measurement: &str) -> anyhow::Result<[u8; MEASUREMENT_SIZE]> {
+    hex::decode(measurement)
+        .with_context(|| format!("Couldn't decode measurement {}", measurement))?
+        .try_into()
+        .map_err(|measurement: Vec<u8>| {
+            anyhow::anyhow!(
+                "invalid measurement length: expected {} bytes, found {}",
+                MEASUREMENT_SIZE,
+                measurement.len()
+            )
+        })
+}
+
+/// Configuration of the session tickets issued to clients after the handshake.
+///
+/// Servers configured with the same ticket key (e.g. replicas behind a load balancer) resume each
+/// other's sessions, so that clients don't have to perform a new handshake when their requests
+/// are routed to a different server.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SessionTicketsConfig {
+    /// Path of the file containing the hex-encoded 32-byte key used for sealing session tickets.
+    pub key_path: String,
+}
+
+impl SessionTicketsConfig {
+    /// Reads the session ticket key from the file referenced by the config.
+    pub fn read_key(&self) -> anyhow::Result<SessionTicketKey> {
+        let key = fs::read_to_string(&self.key_path)
+            .with_context(|| format!("Couldn't read session ticket key {}", self.key_path))?;
+        let key: [u8; SESSION_TICKET_KEY_LENGTH] = hex::decode(key.trim())
+            .context("Couldn't decode session ticket key")?
+            .try_into()
+            .map_err(|key: Vec<u8>| {
+                anyhow::anyhow!(
+                    "invalid session ticket key length: expected {} bytes, found {}",
+                    SESSION_TICKET_KEY_LENGTH,
+                    key.len()
+                )
+            })?;
+        Ok(SessionTicketKey::new(key))
+    }
+}
+
+/// Configuration of the endorsement of the Wasm module, which is sent to clients in the
+/// attestation evidence of the server.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct EndorsementConfig {
+    /// Path of the endorsement statement of the Wasm module, in the in-toto format.
+    pub statement_path: String,
+    /// Path of the JSON-encoded Rekor log entry of the signed endorsement statement, which must
+    /// contain an inclusion proof with a signed checkpoint.
+    pub rekor_log_entry_path: String,
+}
+
+impl EndorsementConfig {
+    /// Reads the endorsement statement and its Rekor log entry from the files referenced by the
+    /// config.
+    pub fn read(&self) -> anyhow::Result<Endorsement> {
+        let statement = fs::read(&self.statement_path).with_context(|| {
+            format!(
+                "Couldn't read endorsement statement {}",
+                self.statement_path
+            )
+        })?;
+        anyhow::ensure!(!statement.is_empty(), "endorsement statement is empty");
+        let rekor_log_entry = fs::read(&self.rekor_log_entry_path).with_context(|| {
+            format!(
+                "Couldn't read Rekor log entry {}",
+                self.rekor_log_entry_path
+            )
+        })?;
+        anyhow::ensure!(!rekor_log_entry.is_empty(), "Rekor log entry is empty");
+        Ok(Endorsement {
+            statement,
+            rekor_log_entry,
+        })
+    }
+}
+
+/// Endorsement of the Wasm module of the server, together with the Rekor log entry proving its
+/// inclusion in the transparency log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endorsement {
+    pub statement: Vec<u8>,
+    pub rekor_log_entry: Vec<u8>,
+}
+
+/// [`AttestationVerifier`] for the attestation of clients, as selected by the
+/// [`ClientAttestationConfig`].
+#[derive(Clone)]
+pub enum ClientAttestationVerifier {
+    None(EmptyAttestationVerifier),
+    PlaceholderAmd(PlaceholderAmdAttestationVerifier),
+    SevSnp(SnpAttestationVerifier),
+}
+
+impl Default for ClientAttestationVerifier {
+    fn default() -> Self {
+        ClientAttestationVerifier::None(EmptyAttestationVerifier)
+    }
+}
+
+impl AttestationVerifier for ClientAttestationVerifier {
+    fn verify_attestation(
+        &self,
+        attestation: &[u8],
+        expected_attested_data: &[u8],
+    ) -> anyhow::Result<()> {
+        match self {
+            ClientAttestationVerifier::None(verifier) => {
+                verifier.verify_attestation(attestation, expected_attested_data)
+            }
+            ClientAttestationVerifier::PlaceholderAmd(verifier) => {
+                verifier.verify_attestation(attestation, expected_attested_data)
+            }
+            ClientAttestationVerifier::SevSnp(verifier) => {
+                verifier.verify_attestation(attestation, expected_attested_data)
+            }
+        }
+    }
+}
+
+/// [`AttestationGenerator`] of the gRPC server, which sends [`AttestationEvidence`] containing the
+/// configuration report of the server (i.e. the hash of its Wasm module and its server policy).
+///
+/// The TEE attestation is generated by `G` for the data attested in the handshake and the
+/// configuration report, as described in [`AttestationEvidence`], which binds the configuration
+/// report to the TEE.
+///
+/// If the generator has an [`Endorsement`], it is sent in the evidence as well.
+#[derive(Clone)]
+pub struct EvidenceGenerator<G: AttestationGenerator = PlaceholderAmdAttestationGenerator> {
+    tee_generator: G,
+    configuration: ConfigurationReport,
+    endorsement: Option<Endorsement>,
+}
+
+impl EvidenceGenerator {
+    /// Creates a generator of evidence for the given Wasm module and server policy, with a
+    /// placeholder TEE attestation.
+    pub fn create(wasm_module_bytes: &[u8], policy: &ServerPolicy) -> Self {
+        Self::create_with_tee_generator(
+            PlaceholderAmdAttestationGenerator,
+            configuration_report(wasm_module_bytes, policy),
+        )
+    }
+}
+
+impl<G: AttestationGenerator> EvidenceGenerator<G> {
+    /// Creates a generator of evidence for the given configuration report, whose TEE attestation
+    /// is generated by `tee_generator`.
+    pub fn create_with_tee_generator(tee_generator: G, configuration: ConfigurationReport) -> Self {
+        Self {
+            tee_generator,
+            configuration,
+            endorsement: None,
+        }
+    }
+
+    /// Sends the given endorsement of the Wasm module in the evidence.
+    ///
+    /// The endorsement is not checked against the hash of the Wasm module, which is done by
+    /// clients.
+    pub fn with_endorsement(self, endorsement: Endorsement) -> Self {
+        Self {
+            endorsement: Some(endorsement),
+            ..self
+        }
+    }
+}
+
+impl<G: AttestationGenerator> AttestationGenerator for EvidenceGenerator<G> {
+    fn generate_attestation(&self, attested_data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let tee_attestation = self
+            .tee_generator
+            .generate_attestation(&hash_concat_hash(&[
+                attested_data,
+                &self.configuration.encode_to_vec(),
+            ]))?;
+        let (endorsement, rekor_log_entry) = match &self.endorsement {
+            Some(endorsement) => (
+                endorsement.statement.clone(),
+                endorsement.rekor_log_entry.clone(),
+            ),
+            None => (Vec::new(), Vec::new()),
+        };
+        Ok(AttestationEvidence {
+            tee_attestation,
+            configuration: Some(self.configuration.clone()),
+            endorsement,
+            rekor_log_entry,
+        }
+        .encode_to_vec())
+    }
+}
+
+/// Returns the configuration report of a server running the given Wasm module with the given
+/// server policy.
+pub fn configuration_report(
+    wasm_module_bytes: &[u8],
+    policy: &ServerPolicy,
+) -> ConfigurationReport {
+    ConfigurationReport {
+        wasm_hash: get_sha256(wasm_module_bytes).to_vec(),
+        policy: Some(policy.clone()),
+    }
+}
@@ -0,0 +1,409 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Runtime configuration of the Oak Functions loader, shared by all loader binaries, and the
+//! `check-config` mode that validates it.
+
+use crate::{
+    attestation::{ClientAttestationConfig, EndorsementConfig, SessionTicketsConfig},
+    health::HealthConfig,
+    listener::ListenerConfig,
+    logger::{Logger, LoggerConfig},
+    lookup_data::{parse_lookup_entries, LookupDataSource},
+    server::{Policy, WasmHandler},
+    LoadLookupDataConfig, Opt,
+};
+use anyhow::Context;
+use log::LevelFilter;
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fmt::Debug,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Names of the top-level sections that are common to all loader binaries. All other top-level
+/// sections are parsed as the binary-specific extensions config.
+const COMMON_SECTIONS: &[&str] = &[
+    "load_lookup_data",
+    "policy",
+    "listener",
+    "health",
+    "logging",
+    "client_attestation",
+    "session_tickets",
+    "endorsement",
+];
+
+/// Runtime configuration of a loader binary.
+///
+/// This struct serves as a schema for a static TOML config file provided by application
+/// developers. In deployment, this static config file is typically bundled with the Oak Runtime
+/// binary. Config values captured in it serve as a type safe version of regular command line
+/// flags.
+///
+/// The sections that are specific to a loader binary (e.g. for extensions that are only available
+/// in some binaries) are parsed into `E`.
+#[derive(Debug)]
+pub struct Config<E> {
+    /// Configuration to load the LookupData.
+    pub load_lookup_data: LoadLookupDataConfig,
+    /// Security policy guaranteed by the server.
+    pub policy: Option<Policy>,
+    /// Configuration of the listener for the gRPC server. Defaults to TCP.
+    pub listener: ListenerConfig,
+    /// Configuration of the health, readiness and public metrics endpoint.
+    ///
+    /// If not provided, the endpoint is not started.
+    pub health: Option<HealthConfig>,
+    /// Configuration of the logger.
+    pub logging: LoggerConfig,
+    /// Configuration of the attestation of clients by the gRPC server. Defaults to not attesting
+    /// clients.
+    pub client_attestation: ClientAttestationConfig,
+    /// Configuration of the session tickets issued to clients.
+    ///
+    /// If not provided, sessions cannot be resumed, and clients have to perform a new handshake
+    /// once their session expired or is not known to the server.
+    pub session_tickets: Option<SessionTicketsConfig>,
+    /// Configuration of the endorsement of the Wasm module sent to clients.
+    ///
+    /// If not provided, no endorsement is sent, and clients that verify endorsements reject the
+    /// server.
+    pub endorsement: Option<EndorsementConfig>,
+    /// Binary-specific configuration sections.
+    pub extensions: E,
+}
+
+/// The sections of [`Config`] that are common to all loader binaries.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct CommonConfig {
+    #[serde(default)]
+    load_lookup_data: LoadLookupDataConfig,
+    policy: Option<Policy>,
+    #[serde(default)]
+    listener: ListenerConfig,
+    #[serde(default)]
+    health: Option<HealthConfig>,
+    #[serde(default)]
+    logging: LoggerConfig,
+    #[serde(default)]
+    client_attestation: ClientAttestationConfig,
+    #[serde(default)]
+    session_tickets: Option<SessionTicketsConfig>,
+    #[serde(default)]
+    endorsement: Option<EndorsementConfig>,
+}
+
+/// Binary-specific sections of a [`Config`].
+pub trait ExtensionsConfig: DeserializeOwned + Debug {
+    /// Checks the sections, and records the results in the given report.
+    fn check(&self, report: &mut ConfigReport);
+}
+
+/// Extensions config for loader binaries that do not support any additional sections.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct NoExtensions {}
+
+impl ExtensionsConfig for NoExtensions {
+    fn check(&self, _report: &mut ConfigReport) {}
+}
+
+impl<E: ExtensionsConfig> Config<E> {
+    /// Parses a config from the given TOML bytes.
+    ///
+    /// Unknown sections and fields are rejected.
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut extensions: toml::value::Table =
+            toml::from_slice(bytes).context("Couldn't parse config file as TOML")?;
+        let mut common = toml::value::Table::new();
+        for section in COMMON_SECTIONS {
+            if let Some(value) = extensions.remove(*section) {
+                common.insert(section.to_string(), value);
+            }
+        }
+        let common: CommonConfig = toml::Value::Table(common)
+            .try_into()
+            .context("Couldn't parse config file")?;
+        let extensions: E = toml::Value::Table(extensions)
+            .try_into()
+            .context("Couldn't parse config file")?;
+        Ok(Self {
+            load_lookup_data: common.load_lookup_data,
+            policy: common.policy,
+            listener: common.listener,
+            health: common.health,
+            logging: common.logging,
+            client_attestation: common.client_attestation,
+            session_tickets: common.session_tickets,
+            endorsement: common.endorsement,
+            extensions,
+        })
+    }
+
+    /// Reads and parses the config file at the given path.
+    pub fn read(config_path: &str) -> anyhow::Result<Self> {
+        let config_file_bytes = fs::read(config_path)
+            .with_context(|| format!("Couldn't read config file {}", config_path))?;
+        Self::parse(&config_file_bytes)
+    }
+}
+
+/// Outcome of checking a single config section.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SectionStatus {
+    /// The section is valid.
+    Ok,
+    /// The section is invalid.
+    Error,
+    /// The section is optional and not provided.
+    Absent,
+}
+
+/// Result of checking a single config section.
+#[derive(Serialize, Debug)]
+pub struct SectionReport {
+    pub section: String,
+    pub status: SectionStatus,
+    /// Description of the error, if the section is invalid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Absolute paths of the files referenced by the section.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub resolved_paths: Vec<PathBuf>,
+}
+
+/// Machine-readable report of checking a config file, as printed by the `check-config` mode.
+#[derive(Serialize, Debug)]
+pub struct ConfigReport {
+    /// Whether all the sections are valid.
+    pub valid: bool,
+    pub sections: Vec<SectionReport>,
+}
+
+impl Default for ConfigReport {
+    fn default() -> Self {
+        Self {
+            valid: true,
+            sections: Vec::new(),
+        }
+    }
+}
+
+impl ConfigReport {
+    /// Records the result of checking a section. If successful, the result contains the paths of
+    /// the files referenced by the section.
+    pub fn record(&mut self, section: &str, result: anyhow::Result<Vec<PathBuf>>) {
+        let report = match result {
+            Ok(resolved_paths) => SectionReport {
+                section: section.to_string(),
+                status: SectionStatus::Ok,
+                error: None,
+                resolved_paths,
+            },
+            Err(err) => {
+                self.valid = false;
+                SectionReport {
+                    section: section.to_string(),
+                    status: SectionStatus::Error,
+                    error: Some(format!("{:#}", err)),
+                    resolved_paths: Vec::new(),
+                }
+            }
+        };
+        self.sections.push(report);
+    }
+
+    /// Records the result of checking an optional section, which is only checked if it is
+    /// provided.
+    pub fn record_optional<T, F>(&mut self, section: &str, value: Option<&T>, check: F)
+    where
+        F: FnOnce(&T) -> anyhow::Result<Vec<PathBuf>>,
+    {
+        match value {
+            Some(value) => self.record(section, check(value)),
+            None => self.sections.push(SectionReport {
+                section: section.to_string(),
+                status: SectionStatus::Absent,
+                error: None,
+                resolved_paths: Vec::new(),
+            }),
+        }
+    }
+}
+
+/// Returns the absolute path of the given file, failing if it does not exist.
+pub fn resolve_path<P: AsRef<Path>>(path: P) -> anyhow::Result<PathBuf> {
+    fs::canonicalize(&path)
+        .with_context(|| format!("Couldn't resolve path {}", path.as_ref().display()))
+}
+
+/// Runs the `check-config` mode: checks the config and the Wasm module given in the command line
+/// options, and prints the report as JSON to stdout.
+///
+/// Returns an error if any of the checks failed.
+pub fn run_check_config<E: ExtensionsConfig>(opt: &Opt) -> anyhow::Result<()> {
+    let report = check_config::<E>(opt);
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).context("Couldn't serialize config report")?
+    );
+    anyhow::ensure!(report.valid, "config file {} is invalid", opt.config_path);
+    Ok(())
+}
+
+/// Checks the config and the Wasm module given in the command line options, and returns a report
+/// with the result of checking each section.
+pub fn check_config<E: ExtensionsConfig>(opt: &Opt) -> ConfigReport {
+    let mut report = ConfigReport::default();
+
+    report.record(
+        "wasm_module",
+        resolve_path(&opt.wasm_path).and_then(|path| {
+            let wasm_module_bytes = fs::read(&path)
+                .with_context(|| format!("Couldn't read Wasm file {}", opt.wasm_path))?;
+            WasmHandler::create(&wasm_module_bytes, vec![], Logger::new(LevelFilter::Off))?;
+            Ok(vec![path])
+        }),
+    );
+
+    let config = match Config::<E>::read(&opt.config_path) {
+        Ok(config) => {
+            report.record(
+                "config",
+                resolve_path(&opt.config_path).map(|path| vec![path]),
+            );
+            config
+        }
+        Err(err) => {
+            report.record("config", Err(err));
+            return report;
+        }
+    };
+
+    report.record(
+        "policy",
+        config
+            .policy
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("a valid policy must be provided"))
+            .and_then(|policy| policy.validate())
+            .map(|_| vec![]),
+    );
+    report.record(
+        "load_lookup_data",
+        check_load_lookup_data(&config.load_lookup_data),
+    );
+    report.record("listener", check_listener(&config.listener));
+    report.record_optional("health", config.health.as_ref(), |health| {
+        // The health endpoint always listens on TCP, so it only conflicts with TCP listeners.
+        if config.listener == ListenerConfig::Tcp {
+            anyhow::ensure!(
+                health.listen_port != opt.http_listen_port,
+                "health listen port must differ from the HTTP listen port"
+            );
+        }
+        if let (Some(max_staleness), Some(period)) = (
+            health.max_lookup_data_staleness,
+            config.load_lookup_data.lookup_data_download_period,
+        ) {
+            anyhow::ensure!(
+                max_staleness > period,
+                "max_lookup_data_staleness must be larger than lookup_data_download_period"
+            );
+        }
+        Ok(vec![])
+    });
+    // The logging section can't be invalid once parsed.
+    report.record("logging", Ok(vec![]));
+    report.record(
+        "client_attestation",
+        check_client_attestation(&config.client_attestation),
+    );
+    report.record_optional(
+        "session_tickets",
+        config.session_tickets.as_ref(),
+        |session_tickets| {
+            session_tickets.read_key()?;
+            Ok(vec![resolve_path(&session_tickets.key_path)?])
+        },
+    );
+    report.record_optional("endorsement", config.endorsement.as_ref(), |endorsement| {
+        endorsement.read()?;
+        Ok(vec![
+            resolve_path(&endorsement.statement_path)?,
+            resolve_path(&endorsement.rekor_log_entry_path)?,
+        ])
+    });
+
+    config.extensions.check(&mut report);
+
+    report
+}
+
+fn check_listener(config: &ListenerConfig) -> anyhow::Result<Vec<PathBuf>> {
+    match config {
+        ListenerConfig::Tcp => Ok(vec![]),
+        ListenerConfig::Unix { path } => {
+            let path = Path::new(path);
+            let parent = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new("."));
+            let parent = resolve_path(parent)?;
+            let file_name = path
+                .file_name()
+                .ok_or_else(|| anyhow::anyhow!("invalid socket path {}", path.display()))?;
+            Ok(vec![parent.join(file_name)])
+        }
+        ListenerConfig::Vsock { port } => {
+            anyhow::ensure!(*port != 0, "vsock port must not be zero");
+            Ok(vec![])
+        }
+    }
+}
+
+fn check_client_attestation(config: &ClientAttestationConfig) -> anyhow::Result<Vec<PathBuf>> {
+    config.create_verifier()?;
+    match config {
+        ClientAttestationConfig::None | ClientAttestationConfig::PlaceholderAmd => Ok(vec![]),
+        ClientAttestationConfig::SevSnp { ark_path, .. } => Ok(vec![resolve_path(ark_path)?]),
+    }
+}
+
+fn check_load_lookup_data(config: &LoadLookupDataConfig) -> anyhow::Result<Vec<PathBuf>> {
+    if let Some(period) = config.lookup_data_download_period {
+        anyhow::ensure!(
+            !period.is_zero(),
+            "lookup_data_download_period must not be zero"
+        );
+    }
+    match config.lookup_data_source()? {
+        Some(LookupDataSource::File(path)) => {
+            let path = resolve_path(path)?;
+            let lookup_data =
+                fs::read(&path).with_context(|| format!("Couldn't read {}", path.display()))?;
+            parse_lookup_entries(lookup_data.as_ref()).context("could not parse lookup data")?;
+            Ok(vec![path])
+        }
+        // Lookup data downloaded via HTTP is not fetched while checking the config.
+        Some(LookupDataSource::Http { .. }) | None => Ok(vec![]),
+    }
+}
@@ -15,32 +15,284 @@
 //
 
 //! gRPC server for Oak Functions.
+//!
+//! The server exposes the gRPC `UnarySession` service (and gRPC-web, with the `oak-web` feature),
+//! and the gRPC `StreamingSession` service, which carries each session in a single bidirectional
+//! stream. Besides these, the server exposes an HTTP gateway for clients that can only issue plain
+//! HTTP POST requests. The gateway carries exactly the same session id and attestation messages as
+//! `UnaryRequest` and `UnaryResponse`, so the payloads remain end-to-end encrypted between the
+//! client and the runtime; the gateway only changes the framing.
+//!
+//! Gateway requests are sent as `POST` requests to [`HTTP_GATEWAY_PATH`], and use one of the
+//! following envelopes, selected by the `Content-Type` header of the request:
+//!
+//! - `application/json`: the request is a [`JsonUnaryRequest`] and the response is a
+//!   [`JsonUnaryResponse`], with all byte fields encoded as standard base64 (with padding).
+//! - `application/x-protobuf`: the request and response are a serialized `UnaryRequest` and
+//!   `UnaryResponse` protobuf message respectively.
+//!
+//! Errors are reported via the HTTP status code: `400` for malformed requests, `409` if the
+//! attestation message could not be processed (e.g. a handshake or decryption failure, after which
+//! the client has to start a new session), `413` if the request is too large, `415` for an
+//! unsupported content type and `500` for internal errors. JSON error responses additionally
+//! contain an `error` field with a generic description; no details are ever included.
 
 use crate::{
+    attestation::{ClientAttestationVerifier, EvidenceGenerator},
+    health::HealthState,
+    listener::{bind, ListenAddress},
     logger::Logger,
-    server::{apply_policy, WasmHandler},
+    server::{apply_policy, decode_request, WasmHandler},
     OakFunctionsBoxedExtensionFactory,
 };
 use anyhow::Context;
+use grpc_streaming_attestation::proto::streaming_session_server::StreamingSessionServer;
+use grpc_unary_attestation::{
+    proto::{
+        unary_session_server::{UnarySession, UnarySessionServer},
+        UnaryRequest, UnaryResponse,
+    },
+    server::AttestationServer,
+};
+use hyper::{
+    body::HttpBody,
+    server::accept,
+    service::{make_service_fn, service_fn},
+    Body, Method, Response, Server,
+};
 use log::Level;
-use oak_functions_abi::{proto::ServerPolicy, Request};
+use oak_functions_abi::proto::ServerPolicy;
 use oak_logger::OakLogger;
+use oak_remote_attestation::{handshaker::AttestationBehavior, resumption::SessionTicketKey};
 use oak_utils::LogError;
-use std::{future::Future, net::SocketAddr};
+use prost::Message;
+use serde_derive::{Deserialize, Serialize};
+use std::{convert::Infallible, future::Future, sync::Arc};
+use tonic::{body::BoxBody, codegen::Service};
+
+/// Path of the HTTP gateway endpoint. This is the same path as the gRPC `Message` method, gRPC
+/// requests are distinguished by their content type.
+pub const HTTP_GATEWAY_PATH: &str = "/oak.session.unary.v1.UnarySession/Message";
+
+/// Path prefix of the methods of the gRPC `StreamingSession` service.
+pub const STREAMING_SESSION_PATH_PREFIX: &str = "/oak.session.stream.v1.StreamingSession/";
+
+/// Maximum size of the body of an HTTP gateway request.
+pub const MAX_HTTP_GATEWAY_REQUEST_SIZE: usize = 16 * 1024 * 1024;
+
+const JSON_CONTENT_TYPE: &str = "application/json";
+const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+
+/// JSON envelope of an HTTP gateway request, equivalent to a `UnaryRequest`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct JsonUnaryRequest {
+    /// Base64-encoded session id.
+    #[serde(with = "base64_bytes")]
+    pub session_id: Vec<u8>,
+    /// Base64-encoded serialized attestation message.
+    #[serde(with = "base64_bytes")]
+    pub body: Vec<u8>,
+}
+
+/// JSON envelope of an HTTP gateway response, equivalent to a `UnaryResponse`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct JsonUnaryResponse {
+    /// Base64-encoded serialized attestation message.
+    #[serde(with = "base64_bytes")]
+    pub body: Vec<u8>,
+}
+
+/// Serializes byte fields as base64 strings.
+mod base64_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        base64::decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Envelope format of an HTTP gateway request and its response.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EnvelopeFormat {
+    Json,
+    Protobuf,
+}
+
+impl EnvelopeFormat {
+    fn from_request(request: &hyper::Request<Body>) -> Option<Self> {
+        let content_type = request
+            .headers()
+            .get(http::header::CONTENT_TYPE)?
+            .to_str()
+            .ok()?;
+        // Ignore parameters such as `charset`.
+        match content_type.split(';').next()?.trim() {
+            JSON_CONTENT_TYPE => Some(Self::Json),
+            PROTOBUF_CONTENT_TYPE => Some(Self::Protobuf),
+            _ => None,
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Self::Json => JSON_CONTENT_TYPE,
+            Self::Protobuf => PROTOBUF_CONTENT_TYPE,
+        }
+    }
+
+    fn decode_request(&self, bytes: &[u8]) -> Option<UnaryRequest> {
+        match self {
+            Self::Json => serde_json::from_slice::<JsonUnaryRequest>(bytes)
+                .ok()
+                .map(|request| UnaryRequest {
+                    body: request.body,
+                    session_id: request.session_id,
+                }),
+            Self::Protobuf => UnaryRequest::decode(bytes).ok(),
+        }
+    }
+
+    fn encode_response(&self, response: UnaryResponse) -> Vec<u8> {
+        match self {
+            Self::Json => serde_json::to_vec(&JsonUnaryResponse {
+                body: response.body,
+            })
+            .expect("couldn't serialize JSON response"),
+            Self::Protobuf => response.encode_to_vec(),
+        }
+    }
+
+    fn response(&self, status: http::StatusCode, body: Vec<u8>) -> Response<Body> {
+        let mut response = Response::new(Body::from(body));
+        *response.status_mut() = status;
+        response.headers_mut().insert(
+            http::header::CONTENT_TYPE,
+            http::HeaderValue::from_static(self.content_type()),
+        );
+        response
+    }
+
+    fn error_response(&self, status: http::StatusCode) -> Response<Body> {
+        let body = match self {
+            Self::Json => serde_json::json!({
+                "error": status.canonical_reason().unwrap_or_default(),
+            })
+            .to_string()
+            .into_bytes(),
+            Self::Protobuf => vec![],
+        };
+        self.response(status, body)
+    }
+}
+
+fn empty_response(status: http::StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+/// Maps the status returned by the `UnarySession` service to an HTTP status code.
+fn http_status(code: tonic::Code) -> http::StatusCode {
+    match code {
+        tonic::Code::InvalidArgument => http::StatusCode::BAD_REQUEST,
+        tonic::Code::Aborted => http::StatusCode::CONFLICT,
+        _ => http::StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Reads the body of a request, failing if it is larger than `max_size` bytes.
+async fn read_body(mut body: Body, max_size: usize) -> Result<Vec<u8>, http::StatusCode> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| http::StatusCode::BAD_REQUEST)?;
+        if bytes.len() + chunk.len() > max_size {
+            return Err(http::StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// Handles an HTTP gateway request by passing the attestation message it carries to the given
+/// `UnarySession` service.
+async fn handle_http_gateway_request<S: UnarySession>(
+    session_service: &S,
+    request: hyper::Request<Body>,
+) -> Response<Body> {
+    if request.uri().path() != HTTP_GATEWAY_PATH {
+        return empty_response(http::StatusCode::NOT_FOUND);
+    }
+    if request.method() != Method::POST {
+        return empty_response(http::StatusCode::METHOD_NOT_ALLOWED);
+    }
+    let format = match EnvelopeFormat::from_request(&request) {
+        Some(format) => format,
+        None => return empty_response(http::StatusCode::UNSUPPORTED_MEDIA_TYPE),
+    };
+    let bytes = match read_body(request.into_body(), MAX_HTTP_GATEWAY_REQUEST_SIZE).await {
+        Ok(bytes) => bytes,
+        Err(status) => return format.error_response(status),
+    };
+    let unary_request = match format.decode_request(&bytes) {
+        Some(unary_request) => unary_request,
+        None => return format.error_response(http::StatusCode::BAD_REQUEST),
+    };
+    match session_service
+        .message(tonic::Request::new(unary_request))
+        .await
+    {
+        Ok(response) => format.response(
+            http::StatusCode::OK,
+            format.encode_response(response.into_inner()),
+        ),
+        Err(status) => format.error_response(http_status(status.code())),
+    }
+}
+
+/// Returns whether the given gRPC request is addressed to the `StreamingSession` service.
+fn is_streaming_session_request(request: &hyper::Request<Body>) -> bool {
+    request
+        .uri()
+        .path()
+        .starts_with(STREAMING_SESSION_PATH_PREFIX)
+}
+
+/// Returns whether the given request is a gRPC or gRPC-web request, based on its content type.
+pub(crate) fn is_grpc_request(request: &hyper::Request<Body>) -> bool {
+    request
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map(|content_type| content_type.starts_with("application/grpc"))
+        .unwrap_or(false)
+}
 
 async fn handle_request(
     wasm_handler: WasmHandler,
     policy: ServerPolicy,
+    health_state: Arc<HealthState>,
     decrypted_request: Vec<u8>,
 ) -> anyhow::Result<Vec<u8>> {
-    let request = Request {
-        body: decrypted_request,
+    let _in_flight_request = health_state.metrics.start_request();
+    // Requests that are too large or malformed are answered like any other request, so that they
+    // can't be distinguished by the size or timing of the response.
+    let request_policy = policy.clone();
+    let function = move || match decode_request(&request_policy, &decrypted_request) {
+        Ok(request) => wasm_handler.clone().handle_invoke(request),
+        Err(response) => Ok(response),
     };
-    let function = move || wasm_handler.clone().handle_invoke(request);
-    let policy = policy.clone();
     let response = apply_policy(policy, function)
         .await
         .context("internal error")?;
+    // Only aggregate counts of the status codes are exported, which are considered public.
+    health_state.metrics.record_response(response.status);
     Ok(response.encode_to_vec())
 }
 
@@ -56,55 +308,106 @@ pub fn create_wasm_handler(
     Ok(wasm_handler)
 }
 
-/// Starts a gRPC server on the given address, serving the `main` function from the given
-/// [`WasmHandler`].
+/// Starts a gRPC server on the given address, which may be a TCP address, a Unix domain socket or
+/// an AF_VSOCK port, serving the `main` function from the given [`WasmHandler`].
+///
+/// The server attests itself to clients with the given [`EvidenceGenerator`], and clients are
+/// attested during the handshake with the given [`ClientAttestationVerifier`]. If a
+/// [`SessionTicketKey`] is given, clients receive session tickets sealed with it, which allow them
+/// to resume their sessions on any server sharing the key.
+///
+/// Public metrics about the handled requests are recorded in the given [`HealthState`].
 #[allow(clippy::too_many_arguments)]
 pub async fn create_and_start_grpc_server<F: Future<Output = ()>>(
-    address: &SocketAddr,
+    listen_address: &ListenAddress,
     wasm_handler: WasmHandler,
     policy: ServerPolicy,
+    attestation_generator: EvidenceGenerator,
+    client_attestation_verifier: ClientAttestationVerifier,
+    session_ticket_key: Option<SessionTicketKey>,
+    health_state: Arc<HealthState>,
     terminate: F,
     logger: Logger,
 ) -> anyhow::Result<()> {
     logger.log_public(
         Level::Info,
         &format!(
-            "{:?}: Starting gRPC server on {:?}",
+            "{:?}: Starting gRPC server on {}",
             std::thread::current().id(),
-            &address
+            listen_address
         ),
     );
 
-    let request_handler =
-        async move |request| handle_request(wasm_handler, policy.clone(), request).await;
-
-    let grpc_unary_attestation_service =
-        grpc_unary_attestation::proto::unary_session_server::UnarySessionServer::new(
-            grpc_unary_attestation::server::AttestationServer::create(
-                request_handler,
-                ErrorLogger { logger },
-            )
-            .context("Couldn't create remote attestation server")?,
-        );
+    let request_handler = async move |request| {
+        handle_request(wasm_handler, policy.clone(), health_state.clone(), request).await
+    };
 
-    // Create a server and add the relevant service definitions. Server creation
-    // and start is handled entirely within each respective conditional arm, as
-    // the added service alters the type signature of the created server.
-    if cfg!(feature = "oak-web") {
-        #[cfg(feature = "oak-web")]
-        tonic::transport::Server::builder()
-            .accept_http1(true)
-            .add_service(tonic_web::enable(grpc_unary_attestation_service))
-            .serve_with_shutdown(*address, terminate)
-            .await
-            .context("Couldn't start server")?;
-    } else {
-        tonic::transport::Server::builder()
-            .add_service(grpc_unary_attestation_service)
-            .serve_with_shutdown(*address, terminate)
-            .await
-            .context("Couldn't start server")?;
+    let error_logger = ErrorLogger {
+        logger: logger.clone(),
     };
+    let attestation_behavior =
+        AttestationBehavior::create(attestation_generator, client_attestation_verifier);
+    let streaming_attestation_server =
+        grpc_streaming_attestation::server::AttestationServer::create_with_attestation_behavior(
+            request_handler.clone(),
+            error_logger.clone(),
+            attestation_behavior.clone(),
+            session_ticket_key.clone(),
+        )
+        .context("Couldn't create streaming remote attestation server")?;
+    let grpc_streaming_attestation_service =
+        StreamingSessionServer::new(streaming_attestation_server);
+    let attestation_server = Arc::new(
+        AttestationServer::create_with_attestation_behavior(
+            request_handler,
+            error_logger,
+            attestation_behavior,
+            session_ticket_key,
+        )
+        .context("Couldn't create remote attestation server")?,
+    );
+    let grpc_unary_attestation_service = UnarySessionServer::from_arc(attestation_server.clone());
+    #[cfg(feature = "oak-web")]
+    let grpc_unary_attestation_service = tonic_web::enable(grpc_unary_attestation_service);
+
+    // gRPC (and gRPC-web) requests are identified by their content type and dispatched to the gRPC
+    // service for their path, all other requests are handled by the HTTP gateway.
+    let make_service = make_service_fn(move |_connection| {
+        let attestation_server = attestation_server.clone();
+        let grpc_unary_attestation_service = grpc_unary_attestation_service.clone();
+        let grpc_streaming_attestation_service = grpc_streaming_attestation_service.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                let attestation_server = attestation_server.clone();
+                let mut grpc_unary_attestation_service = grpc_unary_attestation_service.clone();
+                let mut grpc_streaming_attestation_service =
+                    grpc_streaming_attestation_service.clone();
+                async move {
+                    if is_grpc_request(&request) && is_streaming_session_request(&request) {
+                        grpc_streaming_attestation_service.call(request).await
+                    } else if is_grpc_request(&request) {
+                        grpc_unary_attestation_service.call(request).await
+                    } else {
+                        let response: Response<BoxBody> =
+                            handle_http_gateway_request(&*attestation_server, request)
+                                .await
+                                .map(|body| {
+                                    body.map_err(|err| tonic::Status::internal(err.to_string()))
+                                        .boxed_unsync()
+                                });
+                        Ok(response)
+                    }
+                }
+            }))
+        }
+    });
+
+    let incoming = bind(listen_address, logger).await?;
+    Server::builder(accept::from_stream(incoming))
+        .serve(make_service)
+        .with_graceful_shutdown(terminate)
+        .await
+        .context("Couldn't start server")?;
 
     Ok(())
 }
@@ -0,0 +1,314 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Health checking, readiness and public metrics endpoint for the Oak Functions loader.
+//!
+//! The endpoint listens on its own port, separate from the port serving user requests, and
+//! exposes:
+//! - the standard gRPC health checking service (`grpc.health.v1.Health`);
+//! - `GET /healthz`: liveness, succeeds as long as the loader process is running;
+//! - `GET /readyz`: readiness, succeeds only if the Wasm module is valid, the lookup data has been
+//!   loaded and is fresh enough, and the loader is not overloaded;
+//! - `GET /metrics`: the [`PublicMetrics`] in the Prometheus text format.
+//!
+//! Everything returned by this endpoint is public, in the same sense as
+//! [`oak_logger::OakLogger::log_public`].
+
+use crate::{grpc::is_grpc_request, logger::Logger, metrics::PublicMetrics};
+use anyhow::Context;
+use futures::Stream;
+use hyper::{
+    body::HttpBody,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server,
+};
+use log::Level;
+use oak_logger::OakLogger;
+use serde_derive::Deserialize;
+use std::{
+    convert::Infallible,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tonic::{body::BoxBody, codegen::Service};
+
+pub mod proto {
+    #![allow(clippy::return_self_not_must_use)]
+    tonic::include_proto!("grpc.health.v1");
+}
+
+use proto::{
+    health_check_response::ServingStatus,
+    health_server::{Health, HealthServer},
+    HealthCheckRequest, HealthCheckResponse,
+};
+
+/// Name of the gRPC service serving user requests, as used in health checking requests.
+const UNARY_SESSION_SERVICE_NAME: &str = "oak.session.unary.v1.UnarySession";
+
+/// How often the readiness is re-evaluated for `Watch` health checking requests.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Configuration of the health, readiness and metrics endpoint.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HealthConfig {
+    /// Port number that the health, readiness and metrics endpoint listens on.
+    pub listen_port: u16,
+    /// Maximum time since the last successful refresh of the lookup data, after which the loader
+    /// is reported as not ready.
+    ///
+    /// If empty or not provided, the freshness of the lookup data is not checked.
+    #[serde(default, with = "humantime_serde")]
+    pub max_lookup_data_staleness: Option<Duration>,
+    /// Maximum number of concurrently handled requests, above which the loader is reported as not
+    /// ready.
+    ///
+    /// If empty or not provided, the number of concurrent requests is not checked.
+    #[serde(default)]
+    pub max_requests_in_flight: Option<u64>,
+}
+
+/// Shared state of the loader that drives readiness reporting and holds the public metrics.
+#[derive(Default)]
+pub struct HealthState {
+    wasm_module_valid: AtomicBool,
+    lookup_data_configured: AtomicBool,
+    last_lookup_data_refresh: Mutex<Option<Instant>>,
+    max_lookup_data_staleness: Option<Duration>,
+    max_requests_in_flight: Option<u64>,
+    pub metrics: PublicMetrics,
+}
+
+impl HealthState {
+    /// Creates a new [`HealthState`] using the thresholds from the given config, if any.
+    pub fn new(config: Option<&HealthConfig>) -> Self {
+        Self {
+            max_lookup_data_staleness: config.and_then(|config| config.max_lookup_data_staleness),
+            max_requests_in_flight: config.and_then(|config| config.max_requests_in_flight),
+            ..Default::default()
+        }
+    }
+
+    /// Marks the Wasm module as successfully loaded and validated.
+    pub fn set_wasm_module_valid(&self) {
+        self.wasm_module_valid.store(true, Ordering::Relaxed);
+    }
+
+    /// Marks the lookup data as configured, so that readiness requires it to be loaded.
+    pub fn set_lookup_data_configured(&self) {
+        self.lookup_data_configured.store(true, Ordering::Relaxed);
+    }
+
+    /// Records a successful refresh of the lookup data.
+    pub fn set_lookup_data_refreshed(&self) {
+        *self
+            .last_lookup_data_refresh
+            .lock()
+            .expect("couldn't lock mutex") = Some(Instant::now());
+    }
+
+    /// Checks whether the loader is ready to serve requests.
+    ///
+    /// If it is not, returns the list of reasons. The reasons are static strings, so they cannot
+    /// accidentally contain any sensitive information.
+    pub fn readiness(&self) -> Result<(), Vec<&'static str>> {
+        let mut reasons = Vec::new();
+        if !self.wasm_module_valid.load(Ordering::Relaxed) {
+            reasons.push("Wasm module not loaded");
+        }
+        if self.lookup_data_configured.load(Ordering::Relaxed) {
+            match *self
+                .last_lookup_data_refresh
+                .lock()
+                .expect("couldn't lock mutex")
+            {
+                None => reasons.push("lookup data not loaded"),
+                Some(last_refresh) => {
+                    if let Some(max_staleness) = self.max_lookup_data_staleness {
+                        if last_refresh.elapsed() > max_staleness {
+                            reasons.push("lookup data is stale");
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(max_requests_in_flight) = self.max_requests_in_flight {
+            if self.metrics.requests_in_flight() > max_requests_in_flight {
+                reasons.push("too many requests in flight");
+            }
+        }
+        if reasons.is_empty() {
+            Ok(())
+        } else {
+            Err(reasons)
+        }
+    }
+
+    fn serving_status(&self) -> ServingStatus {
+        match self.readiness() {
+            Ok(()) => ServingStatus::Serving,
+            Err(_) => ServingStatus::NotServing,
+        }
+    }
+}
+
+/// Implementation of the gRPC health checking service, backed by a [`HealthState`].
+#[derive(Clone)]
+struct HealthService {
+    state: Arc<HealthState>,
+}
+
+impl HealthService {
+    /// Returns the serving status of the given service, or `None` if the service is unknown.
+    ///
+    /// The empty service name refers to the loader as a whole.
+    fn serving_status(&self, service: &str) -> Option<ServingStatus> {
+        match service {
+            "" | UNARY_SESSION_SERVICE_NAME => Some(self.state.serving_status()),
+            _ => None,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Health for HealthService {
+    async fn check(
+        &self,
+        request: tonic::Request<HealthCheckRequest>,
+    ) -> Result<tonic::Response<HealthCheckResponse>, tonic::Status> {
+        let status = self
+            .serving_status(&request.get_ref().service)
+            .ok_or_else(|| tonic::Status::not_found("unknown service"))?;
+        Ok(tonic::Response::new(HealthCheckResponse {
+            status: status as i32,
+        }))
+    }
+
+    type WatchStream =
+        Pin<Box<dyn Stream<Item = Result<HealthCheckResponse, tonic::Status>> + Send + 'static>>;
+
+    async fn watch(
+        &self,
+        request: tonic::Request<HealthCheckRequest>,
+    ) -> Result<tonic::Response<Self::WatchStream>, tonic::Status> {
+        let service = request.into_inner().service;
+        let health_service = self.clone();
+        let stream = async_stream::stream! {
+            let mut interval = tokio::time::interval(WATCH_INTERVAL);
+            let mut previous_status = None;
+            loop {
+                interval.tick().await;
+                let status = health_service
+                    .serving_status(&service)
+                    .unwrap_or(ServingStatus::ServiceUnknown);
+                // Only send updates when the status changes, as required by the protocol.
+                if previous_status != Some(status) {
+                    previous_status = Some(status);
+                    yield Ok(HealthCheckResponse {
+                        status: status as i32,
+                    });
+                }
+            }
+        };
+        Ok(tonic::Response::new(Box::pin(stream)))
+    }
+}
+
+/// Handles a plain HTTP request to the endpoint.
+fn handle_http_request(state: &HealthState, request: &Request<Body>) -> Response<Body> {
+    let (status, body) = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/healthz") => (http::StatusCode::OK, "ok\n".to_string()),
+        (&Method::GET, "/readyz") => match state.readiness() {
+            Ok(()) => (http::StatusCode::OK, "ok\n".to_string()),
+            Err(reasons) => (
+                http::StatusCode::SERVICE_UNAVAILABLE,
+                reasons
+                    .iter()
+                    .map(|reason| format!("{}\n", reason))
+                    .collect(),
+            ),
+        },
+        (&Method::GET, "/metrics") => (http::StatusCode::OK, state.metrics.render_prometheus()),
+        _ => (http::StatusCode::NOT_FOUND, "not found\n".to_string()),
+    };
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        http::header::CONTENT_TYPE,
+        http::HeaderValue::from_static("text/plain; version=0.0.4"),
+    );
+    response
+}
+
+/// Starts the health, readiness and metrics endpoint on the given address.
+///
+/// gRPC requests (identified by their content type) are dispatched to the gRPC health checking
+/// service, all other requests are handled as plain HTTP requests.
+pub async fn create_and_start_health_server<F: Future<Output = ()>>(
+    address: &SocketAddr,
+    state: Arc<HealthState>,
+    terminate: F,
+    logger: Logger,
+) -> anyhow::Result<()> {
+    logger.log_public(
+        Level::Info,
+        &format!(
+            "{:?}: Starting health server on {:?}",
+            std::thread::current().id(),
+            address
+        ),
+    );
+
+    let grpc_health_service = HealthServer::new(HealthService {
+        state: state.clone(),
+    });
+
+    let make_service = make_service_fn(move |_connection| {
+        let state = state.clone();
+        let grpc_health_service = grpc_health_service.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let state = state.clone();
+                let mut grpc_health_service = grpc_health_service.clone();
+                async move {
+                    if is_grpc_request(&request) {
+                        grpc_health_service.call(request).await
+                    } else {
+                        let response: Response<BoxBody> = handle_http_request(&state, &request)
+                            .map(|body| {
+                                body.map_err(|err| tonic::Status::internal(err.to_string()))
+                                    .boxed_unsync()
+                            });
+                        Ok(response)
+                    }
+                }
+            }))
+        }
+    });
+
+    Server::bind(address)
+        .serve(make_service)
+        .with_graceful_shutdown(terminate)
+        .await
+        .context("Couldn't start health server")
+}
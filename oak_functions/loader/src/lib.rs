@@ -18,19 +18,29 @@
 
 extern crate alloc;
 
+pub mod attestation;
+pub mod config;
 pub mod grpc;
+pub mod health;
+pub mod listener;
 pub mod logger;
 pub mod lookup_data;
+pub mod metrics;
 pub mod server;
 
 use crate::{
+    attestation::{
+        ClientAttestationConfig, EndorsementConfig, EvidenceGenerator, SessionTicketsConfig,
+    },
     grpc::{create_and_start_grpc_server, create_wasm_handler},
+    health::{create_and_start_health_server, HealthConfig, HealthState},
+    listener::ListenerConfig,
     logger::Logger,
     lookup_data::{LookupDataAuth, LookupDataRefresher, LookupDataSource},
     server::Policy,
 };
 use anyhow::Context;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use log::Level;
 use oak_functions_extension::ExtensionFactory;
 use oak_functions_lookup::{LookupDataManager, LookupFactory};
@@ -55,8 +65,8 @@ pub type OakFunctionsBoxedExtensionFactory = Box<dyn ExtensionFactory<Logger>>;
 
 /// Command line options for the Oak loader.
 ///
-/// In general, when adding new configuration parameters, they should go in the `Config` struct
-/// instead of here.
+/// In general, when adding new configuration parameters, they should go in the [`config::Config`]
+/// struct instead of here.
 #[derive(Parser, Clone, Debug)]
 #[clap(about = "Oak Functions Loader")]
 pub struct Opt {
@@ -76,6 +86,17 @@ pub struct Opt {
         help = "Path to a file containing configuration parameters in TOML format."
     )]
     pub config_path: String,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Alternative modes of the loader binaries, instead of starting the server.
+#[derive(Subcommand, Clone, Debug)]
+pub enum Command {
+    /// Checks the config file and the Wasm module without starting the server, prints a JSON
+    /// report of the result of checking each config section to stdout, and exits with a non-zero
+    /// status if any check failed.
+    CheckConfig,
 }
 
 async fn background_refresh_lookup_data(
@@ -100,11 +121,17 @@ async fn background_refresh_lookup_data(
 
 /// This crate is just a library so this function does not get executed directly by anything, it
 /// needs to be wrapped in the "actual" `main` from a bin crate.
+#[allow(clippy::too_many_arguments)]
 pub fn lib_main(
     opt: Opt,
     logger: Logger,
     load_lookup_data_config: LoadLookupDataConfig,
     policy: Option<Policy>,
+    listener_config: ListenerConfig,
+    health_config: Option<HealthConfig>,
+    client_attestation_config: ClientAttestationConfig,
+    session_tickets_config: Option<SessionTicketsConfig>,
+    endorsement_config: Option<EndorsementConfig>,
     extension_factories: Vec<Box<dyn ExtensionFactory<Logger>>>,
 ) -> anyhow::Result<()> {
     tokio::runtime::Builder::new_multi_thread()
@@ -116,30 +143,67 @@ pub fn lib_main(
             logger,
             load_lookup_data_config,
             policy,
+            listener_config,
+            health_config,
+            client_attestation_config,
+            session_tickets_config,
+            endorsement_config,
             extension_factories,
         ))
 }
 
 /// Main execution point for the Oak Functions Loader.
+#[allow(clippy::too_many_arguments)]
 async fn async_main(
     opt: Opt,
     logger: Logger,
     load_lookup_data_config: LoadLookupDataConfig,
     policy: Option<Policy>,
+    listener_config: ListenerConfig,
+    health_config: Option<HealthConfig>,
+    client_attestation_config: ClientAttestationConfig,
+    session_tickets_config: Option<SessionTicketsConfig>,
+    endorsement_config: Option<EndorsementConfig>,
     extension_factories: Vec<Box<dyn ExtensionFactory<Logger>>>,
 ) -> anyhow::Result<()> {
     let (notify_sender, notify_receiver) = tokio::sync::oneshot::channel::<()>();
+    let (health_notify_sender, health_notify_receiver) = tokio::sync::oneshot::channel::<()>();
+
+    let health_state = Arc::new(HealthState::new(health_config.as_ref()));
+
+    // Start the health server first, so that the loader is reported as alive (but not ready) while
+    // the lookup data and the Wasm module are being loaded.
+    let health_server_handle = health_config.map(|health_config| {
+        let address = SocketAddr::from((Ipv6Addr::UNSPECIFIED, health_config.listen_port));
+        let health_state = health_state.clone();
+        let logger = logger.clone();
+        tokio::spawn(async move {
+            create_and_start_health_server(
+                &address,
+                health_state,
+                async { health_notify_receiver.await.unwrap() },
+                logger.with_component("health"),
+            )
+            .await
+            .context("error while waiting for the health server to terminate")
+        })
+    });
 
     let wasm_module_bytes = fs::read(&opt.wasm_path)
         .with_context(|| format!("Couldn't read Wasm file {}", &opt.wasm_path))?;
-    let mut extensions =
-        create_base_extension_factories(load_lookup_data_config, logger.clone()).await?;
+    let mut extensions = create_base_extension_factories(
+        load_lookup_data_config,
+        health_state.clone(),
+        logger.clone(),
+    )
+    .await?;
 
     for extension_factory in extension_factories {
         extensions.push(extension_factory);
     }
 
     let wasm_handler = create_wasm_handler(&wasm_module_bytes, extensions, logger.clone())?;
+    health_state.set_wasm_module_valid();
 
     // Make sure that a policy is specified and is valid.
     let policy = policy
@@ -147,16 +211,35 @@ async fn async_main(
         .ok_or_else(|| anyhow::anyhow!("a valid policy must be provided"))
         .and_then(|policy| policy.validate())?;
 
-    let address = SocketAddr::from((Ipv6Addr::UNSPECIFIED, opt.http_listen_port));
+    let mut attestation_generator = EvidenceGenerator::create(&wasm_module_bytes, &policy);
+    if let Some(endorsement_config) = endorsement_config {
+        let endorsement = endorsement_config
+            .read()
+            .context("Couldn't read endorsement of the Wasm module")?;
+        attestation_generator = attestation_generator.with_endorsement(endorsement);
+    }
+    let client_attestation_verifier = client_attestation_config
+        .create_verifier()
+        .context("Couldn't create client attestation verifier")?;
+    let session_ticket_key = session_tickets_config
+        .map(|session_tickets_config| session_tickets_config.read_key())
+        .transpose()
+        .context("Couldn't read session ticket key")?;
+
+    let listen_address = listener_config.listen_address(opt.http_listen_port);
 
     // Start server.
     let server_handle = tokio::spawn(async move {
         create_and_start_grpc_server(
-            &address,
+            &listen_address,
             wasm_handler,
             policy.clone(),
+            attestation_generator,
+            client_attestation_verifier,
+            session_ticket_key,
+            health_state,
             async { notify_receiver.await.unwrap() },
-            logger,
+            logger.with_component("grpc"),
         )
         .await
         .context("error while waiting for the server to terminate")
@@ -181,6 +264,15 @@ async fn async_main(
         .send(())
         .expect("Couldn't send completion signal.");
 
+    if let Some(health_server_handle) = health_server_handle {
+        health_notify_sender
+            .send(())
+            .expect("Couldn't send completion signal.");
+        health_server_handle
+            .await
+            .context("error while waiting for the health server to terminate")??;
+    }
+
     server_handle
         .await
         .context("error while waiting for the server to terminate")?
@@ -217,37 +309,50 @@ pub struct LoadLookupDataConfig {
     lookup_data_auth: LookupDataAuth,
 }
 
+impl LoadLookupDataConfig {
+    /// Returns the source of the lookup data, if any is configured.
+    pub fn lookup_data_source(&self) -> anyhow::Result<Option<LookupDataSource>> {
+        Ok(match &self.lookup_data {
+            Some(lookup_data) => match &lookup_data {
+                Data::Url(url_string) => {
+                    let url =
+                        url::Url::parse(url_string).context("Couldn't parse lookup data URL")?;
+                    match url.scheme() {
+                        "http" | "https" => Some(LookupDataSource::Http {
+                            url: url_string.clone(),
+                            auth: self.lookup_data_auth,
+                        }),
+                        scheme => anyhow::bail!(
+                            "Unknown URL scheme in lookup data: expected 'http' or 'https', found {}",
+                            scheme
+                        ),
+                    }
+                }
+                Data::File(path) => Some(LookupDataSource::File(path.clone().into())),
+            },
+            None => None,
+        })
+    }
+}
+
 /// Creates LookupDataManager and sets up LookupDataRefresher.
+///
+/// The state of the lookup data is reported in the given [`HealthState`].
 pub async fn load_lookup_data(
     config: LoadLookupDataConfig,
+    health_state: Arc<HealthState>,
     logger: Logger,
 ) -> anyhow::Result<Arc<LookupDataManager<Logger>>> {
     // Allow lookup data to be loaded by an untrusted launcher.
-    let lookup_data_source = match &config.lookup_data {
-        Some(lookup_data) => match &lookup_data {
-            Data::Url(url_string) => {
-                let url = url::Url::parse(url_string).context("Couldn't parse lookup data URL")?;
-                match url.scheme() {
-                    "http" | "https" => Some(LookupDataSource::Http {
-                        url: url_string.clone(),
-                        auth: config.lookup_data_auth,
-                    }),
-                    scheme => anyhow::bail!(
-                        "Unknown URL scheme in lookup data: expected 'http' or 'https', found {}",
-                        scheme
-                    ),
-                }
-            }
-            Data::File(path) => Some(LookupDataSource::File(path.clone().into())),
-        },
-        None => None,
-    };
+    let lookup_data_source = config.lookup_data_source()?;
     let lookup_data_manager = Arc::new(LookupDataManager::new_empty(logger.clone()));
     if lookup_data_source.is_some() {
+        health_state.set_lookup_data_configured();
         let lookup_data_refresher = LookupDataRefresher::new(
             lookup_data_source,
             lookup_data_manager.clone(),
-            logger.clone(),
+            health_state,
+            logger.with_component("lookup_data"),
         );
         // First load the lookup data upfront in a blocking fashion.
         // TODO(#1930): Retry the initial lookup a few times if it fails.
@@ -261,7 +366,7 @@ pub async fn load_lookup_data(
                 background_refresh_lookup_data(
                     &lookup_data_refresher,
                     lookup_data_download_period,
-                    &logger,
+                    &logger.with_component("lookup_data"),
                 )
                 .await
             });
@@ -272,6 +377,7 @@ pub async fn load_lookup_data(
 
 pub async fn create_base_extension_factories(
     load_lookup_data_config: LoadLookupDataConfig,
+    health_state: Arc<HealthState>,
     logger: Logger,
 ) -> anyhow::Result<Vec<Box<dyn ExtensionFactory<Logger>>>> {
     let mut extensions = Vec::new();
@@ -282,7 +388,8 @@ pub async fn create_base_extension_factories(
     extensions.push(workload_logging_factory);
 
     // For Base we add the Lookup extension factory
-    let lookup_data_manager = load_lookup_data(load_lookup_data_config, logger.clone()).await?;
+    let lookup_data_manager =
+        load_lookup_data(load_lookup_data_config, health_state, logger.clone()).await?;
     let lookup_factory = LookupFactory::new_boxed_extension_factory(lookup_data_manager)?;
     extensions.push(lookup_factory);
 
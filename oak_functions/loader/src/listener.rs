@@ -0,0 +1,255 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Listeners for incoming connections to the gRPC server of the Oak Functions loader.
+//!
+//! Besides TCP, the loader can listen on a Unix domain socket (e.g. when running behind a local
+//! proxy) or on an AF_VSOCK port (e.g. when running inside a VM).
+
+use crate::logger::Logger;
+use anyhow::Context;
+use async_trait::async_trait;
+use futures::Stream;
+use log::Level;
+use oak_logger::OakLogger;
+use serde_derive::Deserialize;
+use std::{
+    fmt,
+    io::ErrorKind,
+    net::{Ipv6Addr, SocketAddr},
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Delay before accepting connections again after an error that is not specific to a single
+/// connection, e.g. because the process ran out of file descriptors, so that the listener doesn't
+/// spin until the condition clears.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Configuration of the listener for the gRPC server.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields, tag = "type")]
+pub enum ListenerConfig {
+    /// Listen on TCP on all interfaces, on the port given by the `--http-listen-port` flag.
+    #[default]
+    Tcp,
+    /// Listen on a Unix domain socket at the given path.
+    ///
+    /// A stale socket file left at the path by a previous run is removed before binding.
+    Unix { path: String },
+    /// Listen on the given AF_VSOCK port, accepting connections to any CID.
+    Vsock { port: u32 },
+}
+
+impl ListenerConfig {
+    /// Returns the address to listen on, using `http_listen_port` for TCP listeners.
+    pub fn listen_address(&self, http_listen_port: u16) -> ListenAddress {
+        match self {
+            ListenerConfig::Tcp => {
+                ListenAddress::Tcp(SocketAddr::from((Ipv6Addr::UNSPECIFIED, http_listen_port)))
+            }
+            ListenerConfig::Unix { path } => ListenAddress::Unix(path.into()),
+            ListenerConfig::Vsock { port } => ListenAddress::Vsock { port: *port },
+        }
+    }
+}
+
+/// Address that the gRPC server listens on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListenAddress {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+    Vsock { port: u32 },
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddress::Tcp(address) => write!(f, "tcp://{}", address),
+            ListenAddress::Unix(path) => write!(f, "unix://{}", path.display()),
+            ListenAddress::Vsock { port } => write!(f, "vsock://any:{}", port),
+        }
+    }
+}
+
+/// Any bidirectional stream that can carry a connection to the gRPC server.
+trait Io: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+impl<T> Io for T where T: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+/// A connection accepted by a listener, regardless of the underlying transport.
+pub struct Connection(Box<dyn Io>);
+
+impl Connection {
+    pub(crate) fn new(io: impl AsyncRead + AsyncWrite + Send + Unpin + 'static) -> Self {
+        Self(Box::new(io))
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut *self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.0).poll_shutdown(cx)
+    }
+}
+
+/// Stream of connections accepted by a listener.
+pub type Incoming = Pin<Box<dyn Stream<Item = std::io::Result<Connection>> + Send>>;
+
+/// A listener accepting connections of any transport.
+#[async_trait]
+pub(crate) trait Accept: Send + Sync + 'static {
+    async fn accept(&self, logger: &Logger) -> std::io::Result<Connection>;
+}
+
+#[async_trait]
+impl Accept for tokio::net::TcpListener {
+    async fn accept(&self, logger: &Logger) -> std::io::Result<Connection> {
+        let (stream, _) = tokio::net::TcpListener::accept(self).await?;
+        // The connection still works without TCP_NODELAY, only with higher latency.
+        if let Err(err) = stream.set_nodelay(true) {
+            logger.log_public(
+                Level::Warn,
+                &format!("Couldn't set TCP_NODELAY on connection: {:?}", err),
+            );
+        }
+        Ok(Connection::new(stream))
+    }
+}
+
+#[async_trait]
+impl Accept for tokio::net::UnixListener {
+    async fn accept(&self, _logger: &Logger) -> std::io::Result<Connection> {
+        let (stream, _) = tokio::net::UnixListener::accept(self).await?;
+        Ok(Connection::new(stream))
+    }
+}
+
+#[async_trait]
+impl Accept for tokio_vsock::VsockListener {
+    async fn accept(&self, _logger: &Logger) -> std::io::Result<Connection> {
+        let (stream, _) = tokio_vsock::VsockListener::accept(self).await?;
+        Ok(Connection::new(stream))
+    }
+}
+
+/// Binds a listener to the given address, and returns the stream of accepted connections.
+pub async fn bind(listen_address: &ListenAddress, logger: Logger) -> anyhow::Result<Incoming> {
+    match listen_address {
+        ListenAddress::Tcp(address) => {
+            let listener = tokio::net::TcpListener::bind(address)
+                .await
+                .with_context(|| format!("Couldn't bind TCP listener to {}", address))?;
+            Ok(incoming(listener, logger))
+        }
+        ListenAddress::Unix(path) => {
+            remove_stale_socket(path)?;
+            let listener = tokio::net::UnixListener::bind(path).with_context(|| {
+                format!("Couldn't bind Unix domain socket to {}", path.display())
+            })?;
+            Ok(incoming(listener, logger))
+        }
+        ListenAddress::Vsock { port } => {
+            let listener = tokio_vsock::VsockListener::bind(tokio_vsock::VsockAddr::new(
+                tokio_vsock::VMADDR_CID_ANY,
+                *port,
+            ))
+            .with_context(|| format!("Couldn't bind vsock listener to port {}", port))?;
+            Ok(incoming(listener, logger))
+        }
+    }
+}
+
+/// Returns the stream of connections accepted by the listener.
+///
+/// Errors accepting a connection either only affect that connection (e.g. it was aborted by the
+/// peer before it was accepted) or are transient (e.g. the process ran out of file descriptors),
+/// so they are logged and the listener keeps accepting connections, rather than ending the stream
+/// and with it the server.
+pub(crate) fn incoming<A: Accept>(listener: A, logger: Logger) -> Incoming {
+    Box::pin(async_stream::stream! {
+        loop {
+            match listener.accept(&logger).await {
+                Ok(connection) => yield Ok(connection),
+                Err(err) => {
+                    logger.log_public(
+                        Level::Warn,
+                        &format!("Couldn't accept connection: {:?}", err),
+                    );
+                    if !is_connection_error(&err) {
+                        tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Returns whether the error only affects the connection that was being accepted.
+fn is_connection_error(err: &std::io::Error) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::ConnectionRefused | ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset
+    )
+}
+
+/// Removes a socket file left at the given path, e.g. by a previous run of the loader that was not
+/// shut down cleanly. Fails if there is a file at the path that is not a socket.
+fn remove_stale_socket(path: &Path) -> anyhow::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) => {
+            anyhow::ensure!(
+                metadata.file_type().is_socket(),
+                "{} exists and is not a socket",
+                path.display()
+            );
+            std::fs::remove_file(path)
+                .with_context(|| format!("Couldn't remove stale socket {}", path.display()))
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => {
+            Err(err).with_context(|| format!("Couldn't read metadata of {}", path.display()))
+        }
+    }
+}
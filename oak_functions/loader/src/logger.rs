@@ -13,25 +13,139 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use anyhow::Context;
 use chrono::{SecondsFormat, Utc};
 use log::{Level, LevelFilter};
 use oak_logger::OakLogger;
-use std::default::Default;
+use serde_derive::Deserialize;
+use std::{
+    collections::BTreeMap,
+    default::Default,
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+/// The component name used by loggers that were not explicitly assigned a component.
+const DEFAULT_COMPONENT: &str = "oak_functions_loader";
+
+/// Output format of log records.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable single-line records.
+    #[default]
+    Text,
+    /// One JSON object per line, with `timestamp`, `level`, `component`, `visibility`, `message`
+    /// and `context` fields.
+    Json,
+}
+
+/// Configuration of the loader [`Logger`].
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LoggerConfig {
+    /// Maximum level of log records that are written, e.g. `"info"` or `"debug"`.
+    ///
+    /// If empty or not provided, defaults to `"debug"`.
+    #[serde(default)]
+    pub max_level: Option<LevelFilter>,
+    /// Output format of log records.
+    #[serde(default)]
+    pub format: LogFormat,
+    /// Request-independent context that is attached to every log record, e.g. the name of the
+    /// deployment. Must not contain any sensitive information.
+    #[serde(default)]
+    pub context: BTreeMap<String, String>,
+}
+
+/// Whether a log record may contain sensitive information.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Visibility {
+    Public,
+    Sensitive,
+}
+
+impl Visibility {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Sensitive => "sensitive",
+        }
+    }
+}
+
+/// Destination of potentially sensitive log records, which is never the stream to which public
+/// log records are written.
+#[derive(Clone)]
+pub enum SensitiveSink {
+    Stdout,
+    File(Arc<Mutex<std::fs::File>>),
+}
+
+impl SensitiveSink {
+    /// Returns a sink appending to the file at the given path, or writing to stdout if no path is
+    /// given.
+    pub fn create(path: Option<&str>) -> anyhow::Result<Self> {
+        match path {
+            Some(path) => Ok(SensitiveSink::File(Arc::new(Mutex::new(
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Couldn't open sensitive log file {}", path))?,
+            )))),
+            None => Ok(SensitiveSink::Stdout),
+        }
+    }
+}
 
 /// A simple logger that splits logging between writing logs that contain only public, non-sensitive
 /// content and writing logs that could potentially contain sensitive content.
 ///
-/// Writing of potentially sensitive content will be ignored unless the "oak-unsafe" feature is
-/// enabled.
+/// Public logs are written to stderr. Writing of potentially sensitive content will be ignored
+/// unless a sink for it is set with [`Logger::with_sensitive_sink`], which only the unsafe loader
+/// binary does.
 #[derive(Clone)]
 pub struct Logger {
     max_level: LevelFilter,
+    format: LogFormat,
+    component: Arc<str>,
+    context: Arc<BTreeMap<String, String>>,
+    sensitive_sink: Option<SensitiveSink>,
 }
 
 impl Logger {
-    /// Creates a new logger with the specified maximum `LevelFilter`.
+    /// Creates a new logger with the specified maximum `LevelFilter`, writing text records.
     pub fn new(max_level: LevelFilter) -> Self {
-        Self { max_level }
+        Self {
+            max_level,
+            format: LogFormat::Text,
+            component: DEFAULT_COMPONENT.into(),
+            context: Arc::new(BTreeMap::new()),
+            sensitive_sink: None,
+        }
+    }
+
+    /// Creates a new logger from the given config.
+    pub fn create(config: &LoggerConfig) -> Self {
+        Self {
+            max_level: config.max_level.unwrap_or(LevelFilter::Debug),
+            format: config.format,
+            component: DEFAULT_COMPONENT.into(),
+            context: Arc::new(config.context.clone()),
+            sensitive_sink: None,
+        }
+    }
+
+    /// Returns a copy of this logger that writes potentially sensitive log records to the given
+    /// sink.
+    ///
+    /// Must only be used by the unsafe loader binary, which is not meant for production use.
+    pub fn with_sensitive_sink(self, sensitive_sink: SensitiveSink) -> Self {
+        Self {
+            sensitive_sink: Some(sensitive_sink),
+            ..self
+        }
     }
 
     /// Creates a new logger for testing using the debug `LevelFilter`.
@@ -39,14 +153,54 @@ impl Logger {
         Self::new(LevelFilter::Debug)
     }
 
-    fn log(&self, level: Level, message: &str) {
-        if level <= self.max_level {
-            eprintln!(
-                "{} {} - {}",
-                Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
-                level,
-                message,
-            );
+    /// Returns a copy of this logger that attributes its records to the given component.
+    pub fn with_component(&self, component: &str) -> Self {
+        Self {
+            component: component.into(),
+            ..self.clone()
+        }
+    }
+
+    /// Formats a single log record, without a trailing newline.
+    pub(crate) fn format_record(
+        &self,
+        level: Level,
+        visibility: Visibility,
+        message: &str,
+    ) -> String {
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        match self.format {
+            LogFormat::Text => {
+                format!("{} {} [{}] - {}", timestamp, level, self.component, message)
+            }
+            LogFormat::Json => serde_json::json!({
+                "timestamp": timestamp,
+                "level": level.as_str(),
+                "component": &*self.component,
+                "visibility": visibility.as_str(),
+                "message": message,
+                "context": &*self.context,
+            })
+            .to_string(),
+        }
+    }
+
+    fn log(&self, level: Level, visibility: Visibility, message: &str) {
+        if level > self.max_level {
+            return;
+        }
+        let record = self.format_record(level, visibility, message);
+        match visibility {
+            Visibility::Public => eprintln!("{}", record),
+            Visibility::Sensitive => match &self.sensitive_sink {
+                Some(SensitiveSink::Stdout) => println!("{}", record),
+                Some(SensitiveSink::File(file)) => {
+                    let mut file = file.lock().expect("couldn't lock sensitive log file");
+                    // There is nowhere to report a failure to write a log record.
+                    let _ = writeln!(file, "{}", record);
+                }
+                None => {}
+            },
         }
     }
 }
@@ -58,15 +212,11 @@ impl Default for Logger {
 }
 
 impl OakLogger for Logger {
-    #[cfg(feature = "oak-unsafe")]
     fn log_sensitive(&self, level: Level, message: &str) {
-        self.log(level, message);
+        self.log(level, Visibility::Sensitive, message);
     }
 
-    #[cfg(not(feature = "oak-unsafe"))]
-    fn log_sensitive(&self, _level: Level, _message: &str) {}
-
     fn log_public(&self, level: Level, message: &str) {
-        self.log(level, message);
+        self.log(level, Visibility::Public, message);
     }
 }
@@ -14,7 +14,7 @@
 // limitations under the License.
 //
 
-use crate::logger::Logger;
+use crate::{health::HealthState, logger::Logger, metrics::LookupDataRefreshTimings};
 use anyhow::{anyhow, Context};
 use hashbrown::HashMap;
 use hyper::{body::Bytes, client::connect::Connect, Body, Client, Request};
@@ -52,6 +52,7 @@ pub enum LookupDataSource {
 pub struct LookupDataRefresher {
     lookup_data_source: Option<LookupDataSource>,
     manager: Arc<LookupDataManager<Logger>>,
+    health_state: Arc<HealthState>,
     logger: Logger,
 }
 
@@ -61,14 +62,18 @@ impl LookupDataRefresher {
     ///
     /// [`LookupDataRefresher::refresh`] must be called at least once for for lookup data for be
     /// populated.
+    ///
+    /// The outcome and the timings of each refresh are recorded in the given [`HealthState`].
     pub fn new(
         lookup_data_source: Option<LookupDataSource>,
         manager: Arc<LookupDataManager<Logger>>,
+        health_state: Arc<HealthState>,
         logger: Logger,
     ) -> Self {
         Self {
             lookup_data_source,
             manager,
+            health_state,
             logger,
         }
     }
@@ -88,42 +93,70 @@ impl LookupDataRefresher {
     pub async fn refresh(&self) -> anyhow::Result<()> {
         match &self.lookup_data_source {
             Some(lookup_data_source) => {
-                let start = Instant::now();
-                let lookup_data_buf = fetch_lookup_data(&self.logger, lookup_data_source).await?;
-                self.logger.log_public(
-                    Level::Info,
-                    &format!(
-                        "fetched {} bytes of lookup data in {:.0?}",
-                        lookup_data_buf.len(),
-                        start.elapsed()
-                    ),
-                );
-
-                let start = Instant::now();
-                let entries = parse_lookup_entries(&mut lookup_data_buf.as_ref())
-                    .context("could not parse lookup data")?;
-
-                self.logger.log_public(
-                    Level::Info,
-                    &format!(
-                        "parsed {} entries of lookup data in {:.0?}",
-                        entries.len(),
-                        start.elapsed()
-                    ),
-                );
-
-                let start = Instant::now();
-                self.manager.update_data(entries);
-                self.logger.log_public(
-                    Level::Debug,
-                    &format!("updated entries in manager in: {:.0?}", start.elapsed()),
-                );
-
-                Ok(())
+                let result = self.refresh_from(lookup_data_source).await;
+                if result.is_err() {
+                    self.health_state
+                        .metrics
+                        .record_lookup_data_refresh_failure();
+                }
+                result
             }
             None => Ok(()),
         }
     }
+
+    async fn refresh_from(&self, lookup_data_source: &LookupDataSource) -> anyhow::Result<()> {
+        let start = Instant::now();
+        let lookup_data_buf = fetch_lookup_data(&self.logger, lookup_data_source).await?;
+        let fetch_duration = start.elapsed();
+        self.logger.log_public(
+            Level::Info,
+            &format!(
+                "fetched {} bytes of lookup data in {:.0?}",
+                lookup_data_buf.len(),
+                fetch_duration
+            ),
+        );
+
+        let start = Instant::now();
+        let entries = parse_lookup_entries(&mut lookup_data_buf.as_ref())
+            .context("could not parse lookup data")?;
+        let parse_duration = start.elapsed();
+
+        self.logger.log_public(
+            Level::Info,
+            &format!(
+                "parsed {} entries of lookup data in {:.0?}",
+                entries.len(),
+                parse_duration
+            ),
+        );
+
+        let entries_count = entries.len();
+        let start = Instant::now();
+        self.manager.update_data(entries);
+        let update_duration = start.elapsed();
+        self.logger.log_public(
+            Level::Debug,
+            &format!("updated entries in manager in: {:.0?}", update_duration),
+        );
+
+        // The same values are already logged publicly above.
+        self.health_state
+            .metrics
+            .record_lookup_data_refresh_success(
+                entries_count,
+                lookup_data_buf.len(),
+                LookupDataRefreshTimings {
+                    fetch: fetch_duration,
+                    parse: parse_duration,
+                    update: update_duration,
+                },
+            );
+        self.health_state.set_lookup_data_refreshed();
+
+        Ok(())
+    }
 }
 
 async fn fetch_lookup_data(
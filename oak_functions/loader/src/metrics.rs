@@ -0,0 +1,305 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Public operational metrics for the Oak Functions loader, exported in the Prometheus text format.
+//!
+//! Similar to [`oak_logger::OakLogger::log_public`], everything recorded here is exposed outside
+//! of the trusted runtime without any further processing, so it must only ever contain public,
+//! non-sensitive information. To make this hard to get wrong, the recording methods only accept
+//! values from closed sets (e.g. [`StatusCode`]) or values that are already logged publicly (e.g.
+//! lookup data refresh timings), and never anything derived from the content of user requests or
+//! responses. There is intentionally no equivalent of `log_sensitive` for metrics.
+
+use oak_functions_abi::StatusCode;
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+
+/// All the response status codes, in the order in which they are exported.
+const STATUS_CODES: [StatusCode; 6] = [
+    StatusCode::Unspecified,
+    StatusCode::Success,
+    StatusCode::BadRequest,
+    StatusCode::PolicySizeViolation,
+    StatusCode::PolicyTimeViolation,
+    StatusCode::InternalServerError,
+];
+
+/// Timings of the individual phases of a successful lookup data refresh.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LookupDataRefreshTimings {
+    pub fetch: Duration,
+    pub parse: Duration,
+    pub update: Duration,
+}
+
+/// Counters and gauges that only contain public information about the loader.
+#[derive(Default)]
+pub struct PublicMetrics {
+    requests_total: AtomicU64,
+    requests_in_flight: AtomicU64,
+    /// Indexed by the position of the status code in [`STATUS_CODES`].
+    responses_total: [AtomicU64; STATUS_CODES.len()],
+    lookup_data_refresh_success_total: AtomicU64,
+    lookup_data_refresh_failure_total: AtomicU64,
+    lookup_data_entries: AtomicU64,
+    lookup_data_bytes: AtomicU64,
+    lookup_data_fetch_micros: AtomicU64,
+    lookup_data_parse_micros: AtomicU64,
+    lookup_data_update_micros: AtomicU64,
+    /// Seconds since the Unix epoch of the last successful lookup data refresh, or 0 if there was
+    /// none yet.
+    lookup_data_last_refresh_timestamp_seconds: AtomicU64,
+}
+
+/// Tracks a single request that is being handled, and records its completion when dropped.
+pub struct InFlightRequest<'a> {
+    metrics: &'a PublicMetrics,
+}
+
+impl<'a> Drop for InFlightRequest<'a> {
+    fn drop(&mut self) {
+        self.metrics
+            .requests_in_flight
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl PublicMetrics {
+    /// Records the start of handling a request. The request is counted as in flight until the
+    /// returned value is dropped.
+    pub fn start_request(&self) -> InFlightRequest {
+        self.requests_total.fetch_add(1, Ordering::Relaxed);
+        self.requests_in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlightRequest { metrics: self }
+    }
+
+    /// Returns the number of requests that are currently being handled.
+    pub fn requests_in_flight(&self) -> u64 {
+        self.requests_in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Records the status code of a response after the policy was applied to it.
+    pub fn record_response(&self, status: StatusCode) {
+        let index = STATUS_CODES
+            .iter()
+            .position(|status_code| *status_code == status)
+            .expect("unknown status code");
+        self.responses_total[index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of responses that were returned with the given status code.
+    pub fn responses(&self, status: StatusCode) -> u64 {
+        STATUS_CODES
+            .iter()
+            .position(|status_code| *status_code == status)
+            .map(|index| self.responses_total[index].load(Ordering::Relaxed))
+            .unwrap_or_default()
+    }
+
+    /// Records a successful refresh of the lookup data.
+    pub fn record_lookup_data_refresh_success(
+        &self,
+        entries: usize,
+        bytes: usize,
+        timings: LookupDataRefreshTimings,
+    ) {
+        self.lookup_data_refresh_success_total
+            .fetch_add(1, Ordering::Relaxed);
+        self.lookup_data_entries
+            .store(entries as u64, Ordering::Relaxed);
+        self.lookup_data_bytes
+            .store(bytes as u64, Ordering::Relaxed);
+        self.lookup_data_fetch_micros
+            .store(timings.fetch.as_micros() as u64, Ordering::Relaxed);
+        self.lookup_data_parse_micros
+            .store(timings.parse.as_micros() as u64, Ordering::Relaxed);
+        self.lookup_data_update_micros
+            .store(timings.update.as_micros() as u64, Ordering::Relaxed);
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        self.lookup_data_last_refresh_timestamp_seconds
+            .store(now.as_secs(), Ordering::Relaxed);
+    }
+
+    /// Records a failed refresh of the lookup data.
+    pub fn record_lookup_data_refresh_failure(&self) {
+        self.lookup_data_refresh_failure_total
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Renders all the metrics in the Prometheus text exposition format.
+    ///
+    /// See <https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format>.
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        write_header(
+            &mut out,
+            "oak_functions_requests_total",
+            "counter",
+            "Total number of decrypted requests handled by the Wasm module.",
+        );
+        write_value(
+            &mut out,
+            "oak_functions_requests_total",
+            "",
+            self.requests_total.load(Ordering::Relaxed),
+        );
+
+        write_header(
+            &mut out,
+            "oak_functions_requests_in_flight",
+            "gauge",
+            "Number of requests currently being handled.",
+        );
+        write_value(
+            &mut out,
+            "oak_functions_requests_in_flight",
+            "",
+            self.requests_in_flight(),
+        );
+
+        write_header(
+            &mut out,
+            "oak_functions_responses_total",
+            "counter",
+            "Total number of responses, by status code.",
+        );
+        for (status, count) in STATUS_CODES.iter().zip(self.responses_total.iter()) {
+            write_value(
+                &mut out,
+                "oak_functions_responses_total",
+                &format!("{{status=\"{}\"}}", status),
+                count.load(Ordering::Relaxed),
+            );
+        }
+
+        write_header(
+            &mut out,
+            "oak_functions_policy_violations_total",
+            "counter",
+            "Total number of responses replaced because of a server policy violation.",
+        );
+        write_value(
+            &mut out,
+            "oak_functions_policy_violations_total",
+            "{policy=\"size\"}",
+            self.responses(StatusCode::PolicySizeViolation),
+        );
+        write_value(
+            &mut out,
+            "oak_functions_policy_violations_total",
+            "{policy=\"time\"}",
+            self.responses(StatusCode::PolicyTimeViolation),
+        );
+
+        write_header(
+            &mut out,
+            "oak_functions_lookup_data_refresh_total",
+            "counter",
+            "Total number of lookup data refreshes, by result.",
+        );
+        write_value(
+            &mut out,
+            "oak_functions_lookup_data_refresh_total",
+            "{result=\"success\"}",
+            self.lookup_data_refresh_success_total
+                .load(Ordering::Relaxed),
+        );
+        write_value(
+            &mut out,
+            "oak_functions_lookup_data_refresh_total",
+            "{result=\"failure\"}",
+            self.lookup_data_refresh_failure_total
+                .load(Ordering::Relaxed),
+        );
+
+        write_header(
+            &mut out,
+            "oak_functions_lookup_data_entries",
+            "gauge",
+            "Number of entries in the lookup data after the last successful refresh.",
+        );
+        write_value(
+            &mut out,
+            "oak_functions_lookup_data_entries",
+            "",
+            self.lookup_data_entries.load(Ordering::Relaxed),
+        );
+
+        write_header(
+            &mut out,
+            "oak_functions_lookup_data_bytes",
+            "gauge",
+            "Size of the lookup data fetched by the last successful refresh.",
+        );
+        write_value(
+            &mut out,
+            "oak_functions_lookup_data_bytes",
+            "",
+            self.lookup_data_bytes.load(Ordering::Relaxed),
+        );
+
+        write_header(
+            &mut out,
+            "oak_functions_lookup_data_refresh_duration_seconds",
+            "gauge",
+            "Duration of the phases of the last successful lookup data refresh.",
+        );
+        for (phase, micros) in [
+            ("fetch", &self.lookup_data_fetch_micros),
+            ("parse", &self.lookup_data_parse_micros),
+            ("update", &self.lookup_data_update_micros),
+        ] {
+            let _ = writeln!(
+                out,
+                "oak_functions_lookup_data_refresh_duration_seconds{{phase=\"{}\"}} {}",
+                phase,
+                Duration::from_micros(micros.load(Ordering::Relaxed)).as_secs_f64()
+            );
+        }
+
+        write_header(
+            &mut out,
+            "oak_functions_lookup_data_last_refresh_timestamp_seconds",
+            "gauge",
+            "Unix time of the last successful lookup data refresh.",
+        );
+        write_value(
+            &mut out,
+            "oak_functions_lookup_data_last_refresh_timestamp_seconds",
+            "",
+            self.lookup_data_last_refresh_timestamp_seconds
+                .load(Ordering::Relaxed),
+        );
+
+        out
+    }
+}
+
+fn write_header(out: &mut String, name: &str, metric_type: &str, help: &str) {
+    // Writing to a `String` cannot fail.
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, metric_type);
+}
+
+fn write_value(out: &mut String, name: &str, labels: &str, value: u64) {
+    let _ = writeln!(out, "{}{} {}", name, labels, value);
+}
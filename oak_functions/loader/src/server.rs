@@ -16,7 +16,7 @@
 use crate::logger::Logger;
 use anyhow::Context;
 use futures::future::FutureExt;
-use oak_functions_abi::{proto::ServerPolicy, Response, StatusCode};
+use oak_functions_abi::{proto::ServerPolicy, Request, Response, StatusCode};
 use serde::Deserialize;
 use std::{convert::TryInto, str, time::Duration};
 
@@ -37,6 +37,9 @@ pub struct Policy {
     /// A fixed response time. See [`ServerPolicy::constant_processing_time_ms`].
     #[serde(with = "humantime_serde")]
     pub constant_processing_time: Duration,
+    /// A maximum request size. See [`ServerPolicy::max_request_size_bytes`]. Requests are not
+    /// limited if not provided.
+    pub max_request_size_bytes: Option<u32>,
 }
 
 impl Policy {
@@ -45,6 +48,10 @@ impl Policy {
             self.constant_response_size_bytes >= MIN_RESPONSE_SIZE,
             "Response size is too small",
         );
+        anyhow::ensure!(
+            self.max_request_size_bytes != Some(0),
+            "Request size limit must be positive",
+        );
 
         Ok(ServerPolicy {
             constant_response_size_bytes: self.constant_response_size_bytes,
@@ -53,6 +60,7 @@ impl Policy {
                 .as_millis()
                 .try_into()
                 .context("could not convert milliseconds to u32")?,
+            max_request_size_bytes: self.max_request_size_bytes.unwrap_or(0),
         })
     }
 }
@@ -89,6 +97,29 @@ impl FixedSizeBodyPadder for Response {
     }
 }
 
+/// Checks the size of an encoded request against the given security policy, and decodes it.
+///
+/// If the request is larger than allowed by the security policy, a response with status
+/// `PolicySizeViolation` is returned instead. If the request cannot be decoded, a response with
+/// status `BadRequest` is returned instead. Like any other response, these responses are subject to
+/// [`apply_policy`].
+pub fn decode_request(policy: &ServerPolicy, encoded_request: &[u8]) -> Result<Request, Response> {
+    if policy.max_request_size_bytes != 0
+        && encoded_request.len() > policy.max_request_size_bytes as usize
+    {
+        return Err(Response::create(
+            StatusCode::PolicySizeViolation,
+            "Reason: the request is too large.".as_bytes().to_vec(),
+        ));
+    }
+    Request::decode(encoded_request).map_err(|err| {
+        Response::create(
+            StatusCode::BadRequest,
+            format!("Reason: {}", err).as_bytes().to_vec(),
+        )
+    })
+}
+
 /// Runs the given function and applies the given security policy to the execution of the function
 /// and the response returned from it. Serializes and returns the response as a binary
 /// protobuf-encoded byte array of a constant size.
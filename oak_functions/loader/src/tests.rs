@@ -15,23 +15,62 @@
 //
 
 use crate::{
-    grpc::{create_and_start_grpc_server, create_wasm_handler},
-    logger::Logger,
+    attestation::{
+        ClientAttestationConfig, ClientAttestationVerifier, EndorsementConfig, EvidenceGenerator,
+        SessionTicketsConfig,
+    },
+    config::{check_config, Config, NoExtensions, SectionStatus},
+    grpc::{
+        create_and_start_grpc_server, create_wasm_handler, JsonUnaryRequest, JsonUnaryResponse,
+        HTTP_GATEWAY_PATH,
+    },
+    health::{HealthConfig, HealthState},
+    listener::{bind, incoming, Accept, Connection, ListenAddress, ListenerConfig},
+    logger::{Logger, LoggerConfig, SensitiveSink, Visibility},
     lookup_data::{parse_lookup_entries, LookupDataAuth, LookupDataRefresher, LookupDataSource},
-    server::apply_policy,
+    metrics::PublicMetrics,
+    server::{apply_policy, decode_request},
+    Command, Opt,
 };
+use async_trait::async_trait;
+use futures::StreamExt;
+use log::Level;
 use maplit::hashmap;
-use oak_functions_abi::{proto::ServerPolicy, Response, StatusCode};
+use oak_functions_abi::{
+    proto::{ConfigurationReport, ServerPolicy},
+    Request, Response, StatusCode,
+};
+use oak_functions_client::{
+    endorsement::EndorsementVerifier,
+    policy::{AcceptedServerPolicy, PolicyVerifier, TeePolicy, VerificationPolicy},
+    Client, ClientConfig,
+};
 use oak_functions_lookup::{LookupDataManager, LookupFactory};
 use oak_functions_workload_logging::WorkloadLoggingFactory;
+use oak_logger::OakLogger;
+use oak_remote_attestation::{
+    crypto::get_sha256,
+    handshaker::{
+        AttestationBehavior, AttestationGenerator, AttestationVerifier, EmptyAttestationGenerator,
+    },
+    message::{deserialize_message, MessageWrapper},
+    resumption::SessionTicketKey,
+};
+use oak_remote_attestation_amd::PlaceholderAmdAttestationGenerator;
+use oak_remote_attestation_sessions::SessionId;
+use oak_remote_attestation_sessions_client::{GenericAttestationClient, UnaryClient};
 use prost::Message;
 use std::{
     io::{Seek, Write},
     net::{Ipv6Addr, SocketAddr},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 use test_utils::make_request;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 const MANIFEST_PATH: &str = "examples/key_value_lookup/module/Cargo.toml";
 
@@ -43,6 +82,7 @@ async fn test_valid_policy() {
     let policy = ServerPolicy {
         constant_response_size_bytes: 100,
         constant_processing_time_ms: constant_processing_time.as_millis().try_into().unwrap(),
+        max_request_size_bytes: 0,
     };
 
     let scenario = |server_port: u16| async move {
@@ -75,6 +115,7 @@ async fn test_long_response_time() {
     let policy = ServerPolicy {
         constant_response_size_bytes: 100,
         constant_processing_time_ms: constant_processing_time.as_millis().try_into().unwrap(),
+        max_request_size_bytes: 0,
     };
 
     // So we expect the request to fail, with `response not available error`.
@@ -101,6 +142,400 @@ async fn test_long_response_time() {
     run_scenario_with_policy(scenario, policy).await;
 }
 
+async fn send_encoded_request(
+    attestation_client: &mut grpc_streaming_attestation::client::AttestationClient,
+    encoded_request: Vec<u8>,
+) -> Response {
+    let encoded_response = attestation_client.send(&encoded_request).await.unwrap();
+    Response::decode(encoded_response.as_ref()).unwrap()
+}
+
+fn request(body: &[u8]) -> Request {
+    Request {
+        body: body.to_vec(),
+    }
+}
+
+#[tokio::test]
+async fn test_request_size_policy() {
+    let policy = ServerPolicy {
+        constant_response_size_bytes: 100,
+        constant_processing_time_ms: 200,
+        max_request_size_bytes: 64,
+    };
+
+    let scenario = |server_port: u16| async move {
+        let mut attestation_client =
+            grpc_streaming_attestation::client::AttestationClient::create_with_attestation_verifier(
+                &format!("http://localhost:{}", server_port),
+                placeholder_policy_verifier(),
+            )
+            .await
+        .expect("couldn't complete handshake via the streaming session");
+        // The padding is removed before the request is passed to the Wasm module.
+        let response =
+            send_encoded_request(&mut attestation_client, request(b"key_1").encode_padded(64))
+                .await;
+        assert_eq!(StatusCode::Success, response.status);
+        assert_eq!(response.body().unwrap(), b"value_1");
+
+        // Requests larger than allowed by the policy are rejected, even if only due to padding.
+        let response = send_encoded_request(
+            &mut attestation_client,
+            request(b"key_1").encode_padded(128),
+        )
+        .await;
+        assert_eq!(StatusCode::PolicySizeViolation, response.status);
+        assert_eq!(
+            std::str::from_utf8(response.body().unwrap()).unwrap(),
+            "Reason: the request is too large."
+        );
+
+        // Padding is opt-in, so raw request bodies are accepted as well.
+        let response = send_encoded_request(&mut attestation_client, b"key_1".to_vec()).await;
+        assert_eq!(StatusCode::Success, response.status);
+        assert_eq!(response.body().unwrap(), b"value_1");
+
+        // Padded requests with an invalid padding are rejected.
+        let mut encoded_request = request(b"key_1").encode_padded(32);
+        encoded_request[31] = 1;
+        let response = send_encoded_request(&mut attestation_client, encoded_request).await;
+        assert_eq!(StatusCode::BadRequest, response.status);
+
+        // Every response has the constant size of the policy.
+        assert_eq!(response.body.len(), 100);
+    };
+
+    run_scenario_with_policy(scenario, policy).await;
+}
+
+/// [`UnaryClient`] that sends attestation messages via the HTTP gateway, using the JSON envelope.
+struct HttpGatewayClient {
+    uri: String,
+    client: hyper::Client<hyper::client::HttpConnector>,
+}
+
+#[async_trait(?Send)]
+impl UnaryClient for HttpGatewayClient {
+    async fn message(&mut self, session_id: SessionId, body: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        let request = JsonUnaryRequest {
+            session_id: session_id.to_vec(),
+            body,
+        };
+        let response = self
+            .client
+            .request(
+                hyper::Request::post(&self.uri)
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(hyper::Body::from(serde_json::to_vec(&request)?))?,
+            )
+            .await?;
+        anyhow::ensure!(
+            response.status() == http::StatusCode::OK,
+            "unexpected status: {}",
+            response.status()
+        );
+        let response_bytes = hyper::body::to_bytes(response.into_body()).await?;
+        let response: JsonUnaryResponse = serde_json::from_slice(&response_bytes)?;
+        Ok(response.body)
+    }
+}
+
+#[tokio::test]
+async fn test_http_gateway() {
+    let policy = ServerPolicy {
+        constant_response_size_bytes: 100,
+        constant_processing_time_ms: 200,
+        max_request_size_bytes: 0,
+    };
+
+    let scenario = |server_port: u16| async move {
+        let uri = format!("http://localhost:{}{}", server_port, HTTP_GATEWAY_PATH);
+        let client = hyper::Client::new();
+
+        let mut attestation_client = GenericAttestationClient::create(
+            HttpGatewayClient {
+                uri: uri.clone(),
+                client: client.clone(),
+            },
+            AttestationBehavior::create(EmptyAttestationGenerator, placeholder_policy_verifier()),
+        )
+        .await
+        .expect("couldn't complete handshake via the HTTP gateway");
+        let encoded_response = attestation_client
+            .message(&request(b"key_1").encode_to_vec())
+            .await
+            .unwrap();
+        let response = Response::decode(encoded_response.as_ref()).unwrap();
+        assert_eq!(StatusCode::Success, response.status);
+        assert_eq!(
+            std::str::from_utf8(response.body().unwrap()).unwrap(),
+            r#"value_1"#
+        );
+
+        let post = |content_type: &str, body: &'static str| {
+            hyper::Request::post(&uri)
+                .header(http::header::CONTENT_TYPE, content_type)
+                .body(hyper::Body::from(body))
+                .unwrap()
+        };
+
+        // Session ids with an invalid length are rejected.
+        let response = client
+            .request(post(
+                "application/json",
+                r#"{"session_id": "AAAA", "body": ""}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+
+        // Malformed envelopes are rejected.
+        let response = client
+            .request(post("application/json", r#"{"body": "not base64"}"#))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+
+        // Only the JSON and protobuf envelopes are supported.
+        let response = client.request(post("text/plain", "")).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    };
+
+    run_scenario_with_policy(scenario, policy).await;
+}
+
+#[tokio::test]
+async fn test_verification_policy() {
+    let policy = ServerPolicy {
+        constant_response_size_bytes: 100,
+        constant_processing_time_ms: 200,
+        max_request_size_bytes: 0,
+    };
+    let expected_policy = policy.clone();
+    let wasm_hash = hex::encode(get_sha256(&compile_wasm_module()));
+    let accepted_server_policy = AcceptedServerPolicy {
+        constant_response_size_bytes: 100,
+        constant_processing_time_ms: 200,
+    };
+
+    let scenario = |server_port: u16| async move {
+        let uri = format!("http://localhost:{}", server_port);
+
+        // The server sends attestation evidence with the hash of its Wasm module and its policy.
+        let client = Client::new_with_policy(
+            &uri,
+            verification_policy_verifier(
+                Some(vec![wasm_hash.clone()]),
+                Some(vec![accepted_server_policy]),
+            ),
+            ClientConfig::default(),
+        )
+        .await
+        .expect("Couldn't create client");
+        let response = client.invoke(request(b"key_1")).await.unwrap();
+        assert_eq!(StatusCode::Success, response.status);
+        assert_eq!(response.body().unwrap(), b"value_1");
+        let configuration = client
+            .evidence_bundle()
+            .unwrap()
+            .configuration_report()
+            .unwrap();
+        assert_eq!(hex::encode(configuration.wasm_hash), wasm_hash);
+        assert_eq!(configuration.policy, Some(expected_policy));
+
+        // Servers running a different Wasm module are rejected.
+        let other_wasm_hash = "00".repeat(32);
+        let error = Client::new_with_policy(
+            &uri,
+            verification_policy_verifier(Some(vec![other_wasm_hash]), None),
+            ClientConfig::default(),
+        )
+        .await
+        .err()
+        .expect("Server running a different Wasm module was accepted");
+        assert!(
+            format!("{:#}", error).contains(&format!("Wasm module {} is not allowed", wasm_hash)),
+            "unexpected error: {:#}",
+            error
+        );
+
+        // Servers with a different policy are rejected.
+        let error = Client::new_with_policy(
+            &uri,
+            verification_policy_verifier(
+                None,
+                Some(vec![AcceptedServerPolicy {
+                    constant_response_size_bytes: 200,
+                    ..accepted_server_policy
+                }]),
+            ),
+            ClientConfig::default(),
+        )
+        .await
+        .err()
+        .expect("Server with a different policy was accepted");
+        assert!(
+            format!("{:#}", error).contains("server policy"),
+            "unexpected error: {:#}",
+            error
+        );
+    };
+
+    run_scenario_with_policy(scenario, policy).await;
+}
+
+#[tokio::test]
+async fn test_streaming_session() {
+    let policy = ServerPolicy {
+        constant_response_size_bytes: 100,
+        constant_processing_time_ms: 200,
+        max_request_size_bytes: 0,
+    };
+
+    let scenario = |server_port: u16| async move {
+        let mut attestation_client =
+            grpc_streaming_attestation::client::AttestationClient::create_with_attestation_verifier(
+                &format!("http://localhost:{}", server_port),
+                placeholder_policy_verifier(),
+            )
+            .await
+        .expect("couldn't complete handshake via the streaming session");
+        for _ in 0..2 {
+            let encoded_response = attestation_client
+                .send(&request(b"key_1").encode_to_vec())
+                .await
+                .unwrap();
+            let response = Response::decode(encoded_response.as_ref()).unwrap();
+            assert_eq!(StatusCode::Success, response.status);
+            assert_eq!(
+                std::str::from_utf8(response.body().unwrap()).unwrap(),
+                r#"value_1"#
+            );
+        }
+    };
+
+    run_scenario_with_policy(scenario, policy).await;
+}
+
+/// [`UnaryClient`] that sends attestation messages to one of several servers via their HTTP
+/// gateways, and counts the handshakes started by the client.
+struct ReplicatedClient {
+    replicas: Vec<HttpGatewayClient>,
+    /// Index of the replica that receives the following messages.
+    current_replica: Arc<AtomicUsize>,
+    /// Number of handshakes started by the client.
+    handshakes: Arc<AtomicUsize>,
+}
+
+#[async_trait(?Send)]
+impl UnaryClient for ReplicatedClient {
+    async fn message(&mut self, session_id: SessionId, body: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        if let Ok(MessageWrapper::ClientHello(_)) = deserialize_message(&body) {
+            self.handshakes.fetch_add(1, Ordering::SeqCst);
+        }
+        self.replicas[self.current_replica.load(Ordering::SeqCst)]
+            .message(session_id, body)
+            .await
+    }
+}
+
+#[tokio::test]
+async fn test_session_resumption() {
+    let policy = ServerPolicy {
+        constant_response_size_bytes: 100,
+        constant_processing_time_ms: 200,
+        max_request_size_bytes: 0,
+    };
+    let wasm_module_bytes = compile_wasm_module();
+    let logger = Logger::for_test();
+    let session_ticket_key =
+        SessionTicketKey::generate().expect("Couldn't generate session ticket key");
+
+    // Two server instances sharing the session ticket key, e.g. replicas behind a load balancer.
+    let server_ports = [test_utils::free_port(), test_utils::free_port()];
+    let server_backgrounds: Vec<_> = server_ports
+        .iter()
+        .map(|server_port| {
+            start_server(
+                *server_port,
+                &wasm_module_bytes,
+                Arc::new(LookupDataManager::new_empty(logger.clone())),
+                policy.clone(),
+                Some(session_ticket_key.clone()),
+                logger.clone(),
+            )
+        })
+        .collect();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let current_replica = Arc::new(AtomicUsize::new(0));
+    let handshakes = Arc::new(AtomicUsize::new(0));
+    let client = hyper::Client::new();
+    let mut attestation_client = GenericAttestationClient::create(
+        ReplicatedClient {
+            replicas: server_ports
+                .iter()
+                .map(|server_port| HttpGatewayClient {
+                    uri: format!("http://localhost:{}{}", server_port, HTTP_GATEWAY_PATH),
+                    client: client.clone(),
+                })
+                .collect(),
+            current_replica: current_replica.clone(),
+            handshakes: handshakes.clone(),
+        },
+        AttestationBehavior::create(EmptyAttestationGenerator, placeholder_policy_verifier()),
+    )
+    .await
+    .expect("Couldn't complete handshake");
+
+    for replica in [0, 1] {
+        // The second server doesn't know the session, and resumes it with the session ticket
+        // issued by the first server instead of performing a new handshake.
+        current_replica.store(replica, Ordering::SeqCst);
+        let encoded_response = attestation_client
+            .message(&request(b"key_1").encode_to_vec())
+            .await
+            .unwrap();
+        let response = Response::decode(encoded_response.as_ref()).unwrap();
+        assert_eq!(StatusCode::Success, response.status);
+        assert_eq!(handshakes.load(Ordering::SeqCst), 1);
+    }
+
+    for server_background in server_backgrounds {
+        assert!(server_background.terminate_and_join().await.is_ok());
+    }
+}
+
+/// Compiles the Wasm module served in the test scenarios.
+fn compile_wasm_module() -> Vec<u8> {
+    let mut manifest_path = std::env::current_dir().unwrap();
+    manifest_path.pop();
+    manifest_path.push(MANIFEST_PATH);
+    test_utils::compile_rust_wasm(manifest_path.to_str().expect("Invalid target dir"), false)
+        .expect("Couldn't read Wasm module")
+}
+
+/// Returns a verifier that accepts the placeholder attestation evidence of any server.
+fn placeholder_policy_verifier() -> PolicyVerifier {
+    verification_policy_verifier(None, None)
+}
+
+/// Returns a verifier that accepts the placeholder attestation evidence of servers running one of
+/// the given Wasm modules with one of the given server policies, if set.
+fn verification_policy_verifier(
+    wasm_hashes: Option<Vec<String>>,
+    server_policies: Option<Vec<AcceptedServerPolicy>>,
+) -> PolicyVerifier {
+    PolicyVerifier::create(&VerificationPolicy {
+        tee: TeePolicy::Placeholder,
+        wasm_hashes,
+        server_policies,
+        transparency_log: None,
+    })
+    .expect("Couldn't create policy verifier")
+}
+
 /// Starts the server with the given policy, and runs the given test scenario.
 ///
 /// A normal test scenario makes any number of requests and checks the responses. It has to be an
@@ -112,14 +547,8 @@ where
     S: std::future::Future<Output = ()>,
 {
     let server_port = test_utils::free_port();
-    let address = SocketAddr::from((Ipv6Addr::UNSPECIFIED, server_port));
 
-    let mut manifest_path = std::env::current_dir().unwrap();
-    manifest_path.pop();
-    manifest_path.push(MANIFEST_PATH);
-    let wasm_module_bytes =
-        test_utils::compile_rust_wasm(manifest_path.to_str().expect("Invalid target dir"), false)
-            .expect("Couldn't read Wasm module");
+    let wasm_module_bytes = compile_wasm_module();
 
     let logger = Logger::for_test();
 
@@ -146,24 +575,18 @@ where
             auth: LookupDataAuth::default(),
         }),
         lookup_data_manager.clone(),
+        Arc::new(HealthState::default()),
         logger.clone(),
     );
     lookup_data_refresher.refresh().await.unwrap();
-    let workload_logging_factory =
-        WorkloadLoggingFactory::new_boxed_extension_factory(logger.clone())
-            .expect("could not create WorkloadLoggingFactory");
-    let lookup_factory = LookupFactory::new_boxed_extension_factory(lookup_data_manager.clone())
-        .expect("could not create LookupFactory");
-    let wasm_handler = create_wasm_handler(
+    let server_background = start_server(
+        server_port,
         &wasm_module_bytes,
-        vec![lookup_factory, workload_logging_factory],
-        logger.clone(),
-    )
-    .expect("could not create wasm_handler");
-
-    let server_background = test_utils::background(|term| async move {
-        create_and_start_grpc_server(&address, wasm_handler, policy.clone(), term, logger).await
-    });
+        lookup_data_manager,
+        policy,
+        None,
+        logger,
+    );
 
     // Wait for the server thread to make progress before starting the client. This is needed for a
     // more accurate measurement of the processing time, and to avoid `connection refused` from the
@@ -178,6 +601,47 @@ where
     mock_static_server_background.terminate_and_join().await;
 }
 
+/// Starts a server on the given port, serving the given Wasm module with the lookup data of the
+/// given [`LookupDataManager`]. If a [`SessionTicketKey`] is given, the server issues session
+/// tickets sealed with it.
+fn start_server(
+    server_port: u16,
+    wasm_module_bytes: &[u8],
+    lookup_data_manager: Arc<LookupDataManager<Logger>>,
+    policy: ServerPolicy,
+    session_ticket_key: Option<SessionTicketKey>,
+    logger: Logger,
+) -> test_utils::Background<anyhow::Result<()>> {
+    let address = SocketAddr::from((Ipv6Addr::UNSPECIFIED, server_port));
+    let workload_logging_factory =
+        WorkloadLoggingFactory::new_boxed_extension_factory(logger.clone())
+            .expect("could not create WorkloadLoggingFactory");
+    let lookup_factory = LookupFactory::new_boxed_extension_factory(lookup_data_manager)
+        .expect("could not create LookupFactory");
+    let wasm_handler = create_wasm_handler(
+        wasm_module_bytes,
+        vec![lookup_factory, workload_logging_factory],
+        logger.clone(),
+    )
+    .expect("could not create wasm_handler");
+
+    let attestation_generator = EvidenceGenerator::create(wasm_module_bytes, &policy);
+    test_utils::background(|term| async move {
+        create_and_start_grpc_server(
+            &ListenAddress::Tcp(address),
+            wasm_handler,
+            policy.clone(),
+            attestation_generator,
+            ClientAttestationVerifier::default(),
+            session_ticket_key,
+            Arc::new(HealthState::default()),
+            term,
+            logger,
+        )
+        .await
+    })
+}
+
 #[test]
 fn parse_lookup_entries_empty() {
     let empty = vec![];
@@ -280,6 +744,7 @@ async fn lookup_data_refresh_http() {
             auth: LookupDataAuth::default(),
         }),
         lookup_data_manager.clone(),
+        Arc::new(HealthState::default()),
         Logger::for_test(),
     );
     let lookup_data = lookup_data_manager.create_lookup_data();
@@ -333,6 +798,7 @@ async fn lookup_data_refresh_file() {
     let lookup_data_refresher = LookupDataRefresher::new(
         Some(LookupDataSource::File(temp_file.path().to_path_buf())),
         lookup_data_manager.clone(),
+        Arc::new(HealthState::default()),
         Logger::for_test(),
     );
     let lookup_data = lookup_data_manager.create_lookup_data();
@@ -397,8 +863,12 @@ async fn lookup_data_refresh_file() {
 #[tokio::test]
 async fn lookup_data_refresh_no_lookup_source() {
     let lookup_data_manager = Arc::new(LookupDataManager::new_empty(Logger::for_test()));
-    let lookup_data_refresher =
-        LookupDataRefresher::new(None, lookup_data_manager.clone(), Logger::for_test());
+    let lookup_data_refresher = LookupDataRefresher::new(
+        None,
+        lookup_data_manager.clone(),
+        Arc::new(HealthState::default()),
+        Logger::for_test(),
+    );
     let lookup_data = lookup_data_manager.create_lookup_data();
     assert!(lookup_data.is_empty());
 
@@ -408,6 +878,606 @@ async fn lookup_data_refresh_no_lookup_source() {
     assert!(lookup_data.is_empty());
 }
 
+#[tokio::test]
+async fn lookup_data_refresh_updates_health_state() {
+    let temp_file = tempfile::NamedTempFile::new().unwrap();
+    let health_state = Arc::new(HealthState::new(Some(&HealthConfig {
+        listen_port: 0,
+        max_lookup_data_staleness: None,
+        max_requests_in_flight: None,
+    })));
+    health_state.set_wasm_module_valid();
+    health_state.set_lookup_data_configured();
+
+    let lookup_data_manager = Arc::new(LookupDataManager::new_empty(Logger::for_test()));
+    let lookup_data_refresher = LookupDataRefresher::new(
+        Some(LookupDataSource::File(temp_file.path().to_path_buf())),
+        lookup_data_manager,
+        health_state.clone(),
+        Logger::for_test(),
+    );
+    assert_eq!(
+        health_state.readiness(),
+        Err(vec!["lookup data not loaded"])
+    );
+
+    temp_file
+        .as_file()
+        .write_all(ENTRY_0_LENGTH_DELIMITED)
+        .unwrap();
+    lookup_data_refresher.refresh().await.unwrap();
+    assert_eq!(health_state.readiness(), Ok(()));
+
+    let metrics = health_state.metrics.render_prometheus();
+    assert!(metrics.contains("oak_functions_lookup_data_refresh_total{result=\"success\"} 1\n"));
+    assert!(metrics.contains("oak_functions_lookup_data_refresh_total{result=\"failure\"} 0\n"));
+    assert!(metrics.contains("oak_functions_lookup_data_entries 1\n"));
+
+    // Invalid lookup data is reported as a failed refresh, but the previous data stays available.
+    temp_file.as_file().write_all(&[1, 2, 3]).unwrap();
+    assert!(lookup_data_refresher.refresh().await.is_err());
+    assert_eq!(health_state.readiness(), Ok(()));
+    let metrics = health_state.metrics.render_prometheus();
+    assert!(metrics.contains("oak_functions_lookup_data_refresh_total{result=\"failure\"} 1\n"));
+}
+
+#[test]
+fn health_state_readiness() {
+    let health_state = HealthState::new(Some(&HealthConfig {
+        listen_port: 0,
+        max_lookup_data_staleness: Some(Duration::from_millis(10)),
+        max_requests_in_flight: Some(1),
+    }));
+    assert_eq!(
+        health_state.readiness(),
+        Err(vec!["Wasm module not loaded"])
+    );
+
+    health_state.set_wasm_module_valid();
+    assert_eq!(health_state.readiness(), Ok(()));
+
+    health_state.set_lookup_data_configured();
+    health_state.set_lookup_data_refreshed();
+    assert_eq!(health_state.readiness(), Ok(()));
+
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(health_state.readiness(), Err(vec!["lookup data is stale"]));
+
+    health_state.set_lookup_data_refreshed();
+    let _first_request = health_state.metrics.start_request();
+    assert_eq!(health_state.readiness(), Ok(()));
+    {
+        let _second_request = health_state.metrics.start_request();
+        assert_eq!(
+            health_state.readiness(),
+            Err(vec!["too many requests in flight"])
+        );
+    }
+    assert_eq!(health_state.readiness(), Ok(()));
+}
+
+#[test]
+fn public_metrics_render_prometheus() {
+    let metrics = PublicMetrics::default();
+    {
+        let _request = metrics.start_request();
+        metrics.record_response(StatusCode::Success);
+    }
+    {
+        let _request = metrics.start_request();
+        metrics.record_response(StatusCode::PolicyTimeViolation);
+    }
+
+    let rendered = metrics.render_prometheus();
+    assert!(rendered.contains("# TYPE oak_functions_requests_total counter\n"));
+    assert!(rendered.contains("oak_functions_requests_total 2\n"));
+    assert!(rendered.contains("oak_functions_requests_in_flight 0\n"));
+    assert!(rendered.contains("oak_functions_responses_total{status=\"Success\"} 1\n"));
+    assert!(rendered.contains("oak_functions_responses_total{status=\"BadRequest\"} 0\n"));
+    assert!(rendered.contains("oak_functions_policy_violations_total{policy=\"time\"} 1\n"));
+    assert!(rendered.contains("oak_functions_policy_violations_total{policy=\"size\"} 0\n"));
+}
+
+#[test]
+fn logger_json_format() {
+    let config: LoggerConfig = serde_json::from_str(
+        r#"{"max_level": "info", "format": "json", "context": {"deployment": "test"}}"#,
+    )
+    .unwrap();
+    let logger = Logger::create(&config).with_component("lookup_data");
+
+    let record: serde_json::Value = serde_json::from_str(&logger.format_record(
+        Level::Warn,
+        Visibility::Public,
+        "some message",
+    ))
+    .unwrap();
+    assert_eq!(record["level"], "WARN");
+    assert_eq!(record["component"], "lookup_data");
+    assert_eq!(record["visibility"], "public");
+    assert_eq!(record["message"], "some message");
+    assert_eq!(record["context"]["deployment"], "test");
+    assert!(record["timestamp"].is_string());
+}
+
+#[test]
+fn logger_text_format() {
+    let logger = Logger::create(&LoggerConfig::default());
+    let record = logger.format_record(Level::Info, Visibility::Public, "some message");
+    assert!(
+        record.ends_with("INFO [oak_functions_loader] - some message"),
+        "record: {}",
+        record
+    );
+}
+
+#[test]
+fn logger_config_rejects_sensitive_log_path() {
+    // Sensitive logging is only configured by the unsafe loader binary.
+    assert!(
+        serde_json::from_str::<LoggerConfig>(r#"{"sensitive_log_path": "sensitive.log"}"#).is_err()
+    );
+}
+
+#[test]
+fn logger_sensitive_sink() {
+    let sensitive_log = tempfile::NamedTempFile::new().unwrap();
+    let sensitive_log_path = sensitive_log.path().to_str().unwrap();
+
+    let logger = Logger::for_test()
+        .with_sensitive_sink(SensitiveSink::create(Some(sensitive_log_path)).unwrap());
+    logger.log_sensitive(Level::Info, "sensitive message");
+    logger.log_public(Level::Info, "public message");
+
+    let contents = std::fs::read_to_string(sensitive_log_path).unwrap();
+    assert!(contents.contains("sensitive message"), "{}", contents);
+    assert!(!contents.contains("public message"), "{}", contents);
+}
+
+#[test]
+fn parse_config() {
+    let config = Config::<NoExtensions>::parse(
+        br#"
+        [load_lookup_data]
+        lookup_data = { File = "lookup_data.bin" }
+        lookup_data_download_period = "1m"
+
+        [policy]
+        constant_response_size_bytes = 100
+        constant_processing_time = "200ms"
+
+        [logging]
+        max_level = "warn"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.policy.unwrap().constant_processing_time,
+        Duration::from_millis(200)
+    );
+    assert!(config.health.is_none());
+
+    // Unknown sections are rejected.
+    assert!(Config::<NoExtensions>::parse(b"[tf_model]\npath = \"model.pb\"").is_err());
+    // Unknown fields in known sections are rejected.
+    assert!(Config::<NoExtensions>::parse(b"[policy]\nconstant_response_size = 100").is_err());
+}
+
+#[test]
+fn check_config_reports_invalid_sections() {
+    let mut wasm_file = tempfile::NamedTempFile::new().unwrap();
+    wasm_file.write_all(b"not a wasm module").unwrap();
+    let mut lookup_data_file = tempfile::NamedTempFile::new().unwrap();
+    lookup_data_file
+        .write_all(ENTRY_0_LENGTH_DELIMITED)
+        .unwrap();
+    let mut config_file = tempfile::NamedTempFile::new().unwrap();
+    write!(
+        config_file,
+        r#"
+        [load_lookup_data]
+        lookup_data = {{ File = "{}" }}
+
+        [policy]
+        constant_response_size_bytes = 10
+        constant_processing_time = "200ms"
+
+        [health]
+        listen_port = 8080
+        "#,
+        lookup_data_file.path().display()
+    )
+    .unwrap();
+
+    let opt = Opt {
+        http_listen_port: 8080,
+        wasm_path: wasm_file.path().to_str().unwrap().to_string(),
+        config_path: config_file.path().to_str().unwrap().to_string(),
+        command: Some(Command::CheckConfig),
+    };
+    let report = check_config::<NoExtensions>(&opt);
+    assert!(!report.valid);
+
+    let status = |section: &str| {
+        report
+            .sections
+            .iter()
+            .find(|report| report.section == section)
+            .map(|report| report.status)
+    };
+    assert_eq!(status("wasm_module"), Some(SectionStatus::Error));
+    assert_eq!(status("config"), Some(SectionStatus::Ok));
+    assert_eq!(status("policy"), Some(SectionStatus::Error));
+    assert_eq!(status("load_lookup_data"), Some(SectionStatus::Ok));
+    assert_eq!(status("health"), Some(SectionStatus::Error));
+    assert_eq!(status("logging"), Some(SectionStatus::Ok));
+
+    let lookup_data_report = report
+        .sections
+        .iter()
+        .find(|report| report.section == "load_lookup_data")
+        .unwrap();
+    assert_eq!(
+        lookup_data_report.resolved_paths,
+        vec![std::fs::canonicalize(lookup_data_file.path()).unwrap()]
+    );
+}
+
+#[test]
+fn check_config_allows_health_port_of_non_tcp_listener() {
+    let wasm_file = tempfile::NamedTempFile::new().unwrap();
+    let socket_dir = tempfile::tempdir().unwrap();
+    for listener in [
+        format!(
+            "type = \"Unix\"\npath = \"{}\"",
+            socket_dir.path().join("loader.sock").display()
+        ),
+        "type = \"Vsock\"\nport = 8080".to_string(),
+    ] {
+        let mut config_file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            config_file,
+            "[listener]\n{}\n[health]\nlisten_port = 8080\n",
+            listener
+        )
+        .unwrap();
+        // The HTTP listen port is not used by Unix and vsock listeners, so the health endpoint may
+        // listen on the same port.
+        let opt = Opt {
+            http_listen_port: 8080,
+            wasm_path: wasm_file.path().to_str().unwrap().to_string(),
+            config_path: config_file.path().to_str().unwrap().to_string(),
+            command: Some(Command::CheckConfig),
+        };
+        let report = check_config::<NoExtensions>(&opt);
+        let status = |section: &str| {
+            report
+                .sections
+                .iter()
+                .find(|report| report.section == section)
+                .map(|report| report.status)
+        };
+        assert_eq!(status("listener"), Some(SectionStatus::Ok));
+        assert_eq!(status("health"), Some(SectionStatus::Ok));
+    }
+}
+
+#[test]
+fn parse_listener_config() {
+    let config = Config::<NoExtensions>::parse(b"").unwrap();
+    assert_eq!(config.listener, ListenerConfig::Tcp);
+    assert_eq!(
+        config.listener.listen_address(8080),
+        ListenAddress::Tcp(SocketAddr::from((Ipv6Addr::UNSPECIFIED, 8080)))
+    );
+
+    let config = Config::<NoExtensions>::parse(
+        br#"
+        [listener]
+        type = "Unix"
+        path = "/run/oak/loader.sock"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.listener.listen_address(8080),
+        ListenAddress::Unix("/run/oak/loader.sock".into())
+    );
+
+    let config =
+        Config::<NoExtensions>::parse(b"[listener]\ntype = \"Vsock\"\nport = 8080").unwrap();
+    assert_eq!(
+        config.listener.listen_address(0),
+        ListenAddress::Vsock { port: 8080 }
+    );
+
+    // Unix listeners require a path.
+    assert!(Config::<NoExtensions>::parse(b"[listener]\ntype = \"Unix\"").is_err());
+}
+
+#[test]
+fn parse_client_attestation_config() {
+    let config = Config::<NoExtensions>::parse(b"").unwrap();
+    assert_eq!(config.client_attestation, ClientAttestationConfig::None);
+
+    let config =
+        Config::<NoExtensions>::parse(b"[client_attestation]\ntype = \"PlaceholderAmd\"").unwrap();
+    assert_eq!(
+        config.client_attestation,
+        ClientAttestationConfig::PlaceholderAmd
+    );
+    assert!(matches!(
+        config.client_attestation.create_verifier(),
+        Ok(ClientAttestationVerifier::PlaceholderAmd(_))
+    ));
+
+    let mut ark = tempfile::NamedTempFile::new().unwrap();
+    ark.write_all(b"ark").unwrap();
+    let measurement = "ab".repeat(48);
+    let config = Config::<NoExtensions>::parse(
+        format!(
+            r#"
+            [client_attestation]
+            type = "SevSnp"
+            ark_path = "{}"
+            measurements = ["{}"]
+            minimum_guest_svn = 2
+            [client_attestation.minimum_tcb]
+            boot_loader = 1
+            tee = 0
+            snp = 6
+            microcode = 115
+            "#,
+            ark.path().display(),
+            measurement
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    assert!(matches!(
+        config.client_attestation.create_verifier(),
+        Ok(ClientAttestationVerifier::SevSnp(_))
+    ));
+
+    // Measurements must be 48 bytes long.
+    let config = Config::<NoExtensions>::parse(
+        format!(
+            "[client_attestation]\ntype = \"SevSnp\"\nark_path = \"{}\"\nmeasurements = [\"abcd\"]",
+            ark.path().display()
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    assert!(config.client_attestation.create_verifier().is_err());
+
+    // SEV-SNP attestation requires an ARK certificate.
+    assert!(Config::<NoExtensions>::parse(
+        b"[client_attestation]\ntype = \"SevSnp\"\nmeasurements = []"
+    )
+    .is_err());
+}
+
+#[test]
+fn parse_session_tickets_config() {
+    let config = Config::<NoExtensions>::parse(b"").unwrap();
+    assert_eq!(config.session_tickets, None);
+
+    let mut key_file = tempfile::NamedTempFile::new().unwrap();
+    writeln!(key_file, "{}", "ab".repeat(32)).unwrap();
+    let config = Config::<NoExtensions>::parse(
+        format!(
+            "[session_tickets]\nkey_path = \"{}\"",
+            key_file.path().display()
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    let session_tickets = config
+        .session_tickets
+        .expect("Missing session tickets config");
+    assert!(session_tickets.read_key().is_ok());
+
+    // Keys must be 32 bytes long.
+    let mut short_key_file = tempfile::NamedTempFile::new().unwrap();
+    write!(short_key_file, "abcd").unwrap();
+    let session_tickets = SessionTicketsConfig {
+        key_path: short_key_file.path().to_str().unwrap().to_string(),
+    };
+    assert!(session_tickets.read_key().is_err());
+}
+
+#[test]
+fn parse_endorsement_config() {
+    let config = Config::<NoExtensions>::parse(b"").unwrap();
+    assert_eq!(config.endorsement, None);
+
+    let config = Config::<NoExtensions>::parse(
+        br#"
+        [endorsement]
+        statement_path = "../client/testdata/endorsement.json"
+        rekor_log_entry_path = "../client/testdata/logentry_with_checkpoint.json"
+        "#,
+    )
+    .unwrap();
+    let endorsement = config
+        .endorsement
+        .expect("Missing endorsement config")
+        .read()
+        .unwrap();
+    assert_eq!(
+        endorsement.statement,
+        std::fs::read("../client/testdata/endorsement.json").unwrap()
+    );
+
+    // The Rekor log entry is required.
+    assert!(Config::<NoExtensions>::parse(
+        b"[endorsement]\nstatement_path = \"../client/testdata/endorsement.json\""
+    )
+    .is_err());
+    let endorsement = EndorsementConfig {
+        statement_path: "../client/testdata/endorsement.json".to_string(),
+        rekor_log_entry_path: "missing_logentry.json".to_string(),
+    };
+    assert!(endorsement.read().is_err());
+}
+
+#[test]
+fn evidence_generator_sends_endorsement() {
+    const ATTESTED_DATA: &[u8] = b"Test attested data";
+    // Hash of the Wasm module endorsed in the client test data.
+    let configuration = ConfigurationReport {
+        wasm_hash: hex::decode("15dc16c42a4ac9ed77f337a4a3065a63e444c29c18c8cf69d6a6b4ae678dca5c")
+            .unwrap(),
+        policy: Some(ServerPolicy::default()),
+    };
+    let generator = EvidenceGenerator::create_with_tee_generator(
+        PlaceholderAmdAttestationGenerator,
+        configuration,
+    );
+    // The verifier used by `oak_functions_client::Client::new`, with the Rekor public key of the
+    // test data.
+    let verifier = EndorsementVerifier::create_with_rekor_public_key(
+        &std::fs::read("../client/testdata/ec_public.pem").unwrap(),
+        &std::fs::read("../client/testdata/test_rekor_public_key.pem").unwrap(),
+    )
+    .unwrap();
+
+    // Without an endorsement, the server is rejected.
+    let evidence = generator.generate_attestation(ATTESTED_DATA).unwrap();
+    assert!(verifier
+        .verify_attestation(&evidence, ATTESTED_DATA)
+        .is_err());
+
+    let endorsement = EndorsementConfig {
+        statement_path: "../client/testdata/endorsement.json".to_string(),
+        rekor_log_entry_path: "../client/testdata/logentry_with_checkpoint.json".to_string(),
+    }
+    .read()
+    .unwrap();
+    let evidence = generator
+        .with_endorsement(endorsement)
+        .generate_attestation(ATTESTED_DATA)
+        .unwrap();
+    assert!(verifier
+        .verify_attestation(&evidence, ATTESTED_DATA)
+        .is_ok());
+}
+
+#[tokio::test]
+async fn unix_listener_accepts_connections() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("loader.sock");
+    let listen_address = ListenAddress::Unix(path.clone());
+
+    // Binding twice succeeds, since the socket left by the first listener is removed.
+    drop(bind(&listen_address, Logger::for_test()).await.unwrap());
+    let mut incoming = bind(&listen_address, Logger::for_test()).await.unwrap();
+
+    let mut client = tokio::net::UnixStream::connect(&path).await.unwrap();
+    let mut connection = incoming.next().await.unwrap().unwrap();
+    client.write_all(b"ping").await.unwrap();
+    let mut buf = [0; 4];
+    connection.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+}
+
+/// Listener that fails with the given errors, before accepting a single connection.
+struct FailingListener {
+    errors: std::sync::Mutex<Vec<std::io::Error>>,
+    connection: std::sync::Mutex<Option<tokio::io::DuplexStream>>,
+}
+
+#[async_trait]
+impl Accept for FailingListener {
+    async fn accept(&self, _logger: &Logger) -> std::io::Result<Connection> {
+        if let Some(err) = self.errors.lock().unwrap().pop() {
+            return Err(err);
+        }
+        let connection = self.connection.lock().unwrap().take();
+        match connection {
+            Some(connection) => Ok(Connection::new(connection)),
+            None => futures::future::pending().await,
+        }
+    }
+}
+
+#[tokio::test]
+async fn listener_keeps_accepting_after_errors() {
+    let (mut client, server) = tokio::io::duplex(16);
+    let listener = FailingListener {
+        errors: std::sync::Mutex::new(vec![
+            std::io::Error::from(std::io::ErrorKind::ConnectionAborted),
+            // EMFILE: the process ran out of file descriptors.
+            std::io::Error::from_raw_os_error(24),
+        ]),
+        connection: std::sync::Mutex::new(Some(server)),
+    };
+    let mut incoming = incoming(listener, Logger::for_test());
+
+    let mut connection = incoming.next().await.unwrap().unwrap();
+    client.write_all(b"ping").await.unwrap();
+    let mut buf = [0; 4];
+    connection.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+}
+
+#[tokio::test]
+async fn unix_listener_does_not_remove_regular_file() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let listen_address = ListenAddress::Unix(file.path().to_path_buf());
+
+    assert!(bind(&listen_address, Logger::for_test()).await.is_err());
+    assert!(file.path().exists());
+}
+
+#[test]
+fn test_decode_request() {
+    let policy = ServerPolicy {
+        constant_response_size_bytes: 50,
+        constant_processing_time_ms: 10,
+        max_request_size_bytes: 32,
+    };
+
+    // The padding is removed.
+    let encoded_request = request(b"key_1").encode_padded(32);
+    assert_eq!(encoded_request.len(), 32);
+    assert_eq!(
+        decode_request(&policy, &encoded_request).unwrap(),
+        request(b"key_1")
+    );
+
+    // Requests larger than allowed by the policy are rejected, even if only due to padding.
+    let response = decode_request(&policy, &request(b"key_1").encode_padded(64)).unwrap_err();
+    assert_eq!(response.status, StatusCode::PolicySizeViolation);
+    let unlimited_policy = ServerPolicy {
+        max_request_size_bytes: 0,
+        ..policy.clone()
+    };
+    assert!(decode_request(&unlimited_policy, &request(b"key_1").encode_padded(64)).is_ok());
+
+    // Requests without the padding marker are decoded as their raw body.
+    assert_eq!(
+        decode_request(&policy, b"key_1").unwrap(),
+        request(b"key_1")
+    );
+    assert_eq!(request(b"key_1").encode_to_vec(), b"key_1");
+    let marker_request = request(b"\0OAKPAD\0key_1");
+    assert_eq!(
+        decode_request(&policy, &marker_request.encode_to_vec()).unwrap(),
+        marker_request
+    );
+
+    // Padded requests with an invalid length or padding are rejected.
+    let mut encoded_request = request(b"key_1").encode_padded(1);
+    encoded_request[8] = 6;
+    let response = decode_request(&policy, &encoded_request).unwrap_err();
+    assert_eq!(response.status, StatusCode::BadRequest);
+    let mut encoded_request = request(b"key_1").encode_padded(32);
+    encoded_request[31] = 1;
+    let response = decode_request(&policy, &encoded_request).unwrap_err();
+    assert_eq!(response.status, StatusCode::BadRequest);
+}
+
 #[tokio::test]
 async fn test_apply_policy() {
     // A valid constant response body size
@@ -417,6 +1487,7 @@ async fn test_apply_policy() {
     let policy = ServerPolicy {
         constant_response_size_bytes: size as u32,
         constant_processing_time_ms: 10,
+        max_request_size_bytes: 0,
     };
 
     {
@@ -20,49 +20,87 @@
 
 use anyhow::Context;
 use clap::Parser;
-use log::Level;
-use oak_functions_loader::{logger::Logger, server::Policy, LoadLookupDataConfig, Opt};
+use log::{Level, LevelFilter};
+use oak_functions_loader::{
+    config::{resolve_path, run_check_config, Config, ConfigReport, ExtensionsConfig},
+    logger::{Logger, SensitiveSink},
+    Command, Opt,
+};
 use oak_functions_metrics::{PrivateMetricsConfig, PrivateMetricsProxyFactory};
 use oak_functions_tf_inference::{read_model_from_path, TensorFlowFactory, TensorFlowModelConfig};
 use oak_logger::OakLogger;
 use serde_derive::Deserialize;
-use std::fs;
 
-/// Runtime Configuration of Unsafe Runtime.
-///
-/// This struct serves as a schema for a static TOML config file provided by
-/// application developers. In deployment, this static config file is typically
-/// bundled with the Oak Runtime binary. Config values captured in it serve
-/// as a type safe version of regular command line flags.
+/// Configuration sections that are only supported by the Unsafe Runtime.
 #[derive(Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
-pub struct Config {
-    /// Configuration to load the LookupData.
-    #[serde(default)]
-    load_lookup_data: LoadLookupDataConfig,
-    /// Security policy guaranteed by the server.
-    policy: Option<Policy>,
+pub struct UnsafeExtensionsConfig {
     /// Configuration for TensorFlow model.
     #[serde(default)]
     tf_model: Option<TensorFlowModelConfig>,
     /// Differentially private metrics configuration.
     #[serde(default)]
     metrics: Option<PrivateMetricsConfig>,
+    /// Configuration of the potentially sensitive log records.
+    #[serde(default)]
+    sensitive_logging: SensitiveLoggingConfig,
+}
+
+/// Configuration of the potentially sensitive log records, which are only written by the Unsafe
+/// Runtime.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SensitiveLoggingConfig {
+    /// Path to a file to which potentially sensitive log records are appended.
+    ///
+    /// If not provided, sensitive log records are written to stdout, while public log records are
+    /// always written to stderr, so that the two are never mixed in the same stream.
+    #[serde(default)]
+    path: Option<String>,
+}
+
+impl ExtensionsConfig for UnsafeExtensionsConfig {
+    fn check(&self, report: &mut ConfigReport) {
+        report.record_optional("tf_model", self.tf_model.as_ref(), |tf_model_config| {
+            let path = resolve_path(&tf_model_config.path)?;
+            anyhow::ensure!(
+                !tf_model_config.shape.is_empty(),
+                "TensorFlow model shape must not be empty"
+            );
+            anyhow::ensure!(
+                !tf_model_config.shape.contains(&0),
+                "TensorFlow model shape must not contain zero dimensions"
+            );
+            // Parsing the model also checks that it accepts inputs of the given shape.
+            let model = read_model_from_path(&tf_model_config.path)?;
+            TensorFlowFactory::new_boxed_extension_factory(
+                model,
+                tf_model_config.shape.clone(),
+                Logger::new(LevelFilter::Off),
+            )?;
+            Ok(vec![path])
+        });
+        report.record_optional("metrics", self.metrics.as_ref(), |metrics_config| {
+            metrics_config.validate()?;
+            Ok(vec![])
+        });
+    }
 }
 
 pub fn main() -> anyhow::Result<()> {
     let opt = Opt::parse();
-    let config_file_bytes = fs::read(&opt.config_path)
-        .with_context(|| format!("Couldn't read config file {}", &opt.config_path))?;
-    let config: Config =
-        toml::from_slice(&config_file_bytes).context("Couldn't parse config file")?;
-    // TODO(#1971): Make maximum log level configurable.
-    let logger = Logger::default();
+    if let Some(Command::CheckConfig) = opt.command {
+        return run_check_config::<UnsafeExtensionsConfig>(&opt);
+    }
+    let config: Config<UnsafeExtensionsConfig> = Config::read(&opt.config_path)?;
+    let sensitive_sink = SensitiveSink::create(config.extensions.sensitive_logging.path.as_deref())
+        .context("Couldn't create sensitive log sink")?;
+    let logger = Logger::create(&config.logging).with_sensitive_sink(sensitive_sink);
     logger.log_public(Level::Info, &format!("parsed config file:\n{:#?}", config));
 
     let mut extension_factories = vec![];
 
-    if let Some(tf_model_config) = &config.tf_model {
+    if let Some(tf_model_config) = &config.extensions.tf_model {
         // Load the TensorFlow model from the given path in the config
         let model = read_model_from_path(&tf_model_config.path)?;
         let tf_model_factory = TensorFlowFactory::new_boxed_extension_factory(
@@ -74,7 +112,7 @@ pub fn main() -> anyhow::Result<()> {
         logger.log_public(Level::Info, "Added TensorFlow extension.");
     }
 
-    if let Some(metrics_config) = &config.metrics {
+    if let Some(metrics_config) = &config.extensions.metrics {
         let metrics_factory = PrivateMetricsProxyFactory::new_boxed_extension_factory(
             metrics_config,
             logger.clone(),
@@ -88,6 +126,11 @@ pub fn main() -> anyhow::Result<()> {
         logger,
         config.load_lookup_data,
         config.policy,
+        config.listener,
+        config.health,
+        config.client_attestation,
+        config.session_tickets,
+        config.endorsement,
         extension_factories,
     )
 }
@@ -24,7 +24,7 @@ use hyper::{
 use log::info;
 use oak_functions_abi::{Request, Response};
 
-use oak_functions_client::Client;
+use oak_functions_client::{Client, ClientConfig};
 use prost::Message;
 use std::{
     collections::HashMap,
@@ -212,7 +212,9 @@ pub async fn make_request(port: u16, request_body: &[u8]) -> TestResult {
     let uri = format!("http://localhost:{}/", port);
 
     // Create client
-    let mut client = Client::new(&uri).await.expect("Could not create client");
+    let client = Client::new_without_endorsement_verification(&uri, ClientConfig::default())
+        .await
+        .expect("Could not create client");
 
     let request = Request {
         body: request_body.to_vec(),
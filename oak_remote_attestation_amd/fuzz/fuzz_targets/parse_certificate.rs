@@ -0,0 +1,32 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use oak_remote_attestation_amd::certificates::verify_certificate_chain;
+
+const ARK: &[u8] = include_bytes!("../../testdata/ark.der");
+const ASK: &[u8] = include_bytes!("../../testdata/ask.der");
+const VCEK: &[u8] = include_bytes!("../../testdata/vcek.der");
+
+fuzz_target!(|data: &[u8]| {
+    // All certificates are parsed before any signature is checked, so arbitrary input reaches the
+    // certificate parser in each position of the chain. It must never panic, and can never be
+    // accepted, since it is not signed by the test keys.
+    let _ = verify_certificate_chain(data, ASK, VCEK);
+    let _ = verify_certificate_chain(ARK, data, VCEK);
+    let _ = verify_certificate_chain(ARK, ASK, data);
+});
@@ -0,0 +1,29 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use oak_remote_attestation_amd::{report::AttestationReport, verification::SnpEvidence};
+
+fuzz_target!(|data: &[u8]| {
+    // Arbitrary input must never make the parsers panic. Evidence that is accepted must have a
+    // unique encoding, so serializing it again must produce the same bytes.
+    let _ = AttestationReport::parse(data);
+    if let Ok(evidence) = SnpEvidence::deserialize(data) {
+        let _ = AttestationReport::parse(&evidence.report);
+        assert_eq!(evidence.serialize(), data);
+    }
+});
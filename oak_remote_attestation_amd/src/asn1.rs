@@ -0,0 +1,257 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Minimal parser for the subset of DER and X.509 needed to verify the AMD certificate chain.
+//!
+//! The X.509 crates available for `no_std` environments depend on a newer version of `zeroize`
+//! than the one that `x25519-dalek` is pinned to, so only the few structures that are needed are
+//! parsed here. Values are borrowed from the input, and never re-encoded, so that signatures are
+//! verified over the exact bytes that were signed.
+
+use alloc::vec::Vec;
+
+pub(crate) const TAG_BOOLEAN: u8 = 0x01;
+pub(crate) const TAG_INTEGER: u8 = 0x02;
+pub(crate) const TAG_BIT_STRING: u8 = 0x03;
+pub(crate) const TAG_OCTET_STRING: u8 = 0x04;
+pub(crate) const TAG_OBJECT_IDENTIFIER: u8 = 0x06;
+pub(crate) const TAG_SEQUENCE: u8 = 0x30;
+const TAG_VERSION: u8 = 0xA0;
+const TAG_ISSUER_UNIQUE_ID: u8 = 0x81;
+const TAG_SUBJECT_UNIQUE_ID: u8 = 0x82;
+const TAG_EXTENSIONS: u8 = 0xA3;
+
+/// Maximum number of bytes used to encode a length in the long form.
+const MAX_LENGTH_BYTES: usize = 4;
+
+/// Reads consecutive DER-encoded values from a byte slice.
+pub(crate) struct DerReader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> DerReader<'a> {
+    pub(crate) fn new(input: &'a [u8]) -> Self {
+        Self { input }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    fn peek_tag(&self) -> Option<u8> {
+        self.input.first().copied()
+    }
+
+    /// Reads the next value, and returns its tag, its content and its complete encoding.
+    fn read_any(&mut self) -> anyhow::Result<(u8, &'a [u8], &'a [u8])> {
+        let input = self.input;
+        anyhow::ensure!(input.len() >= 2, "truncated DER value");
+        let tag = input[0];
+        anyhow::ensure!(tag & 0x1F != 0x1F, "unsupported DER tag");
+        let (length, header_length) = match input[1] {
+            length if length < 0x80 => (length as usize, 2),
+            length_bytes => {
+                let length_bytes = (length_bytes & 0x7F) as usize;
+                anyhow::ensure!(
+                    (1..=MAX_LENGTH_BYTES).contains(&length_bytes),
+                    "unsupported DER length"
+                );
+                anyhow::ensure!(input.len() >= 2 + length_bytes, "truncated DER length");
+                let length = input[2..2 + length_bytes]
+                    .iter()
+                    .fold(0usize, |length, byte| (length << 8) | *byte as usize);
+                // DER requires the shortest possible length encoding.
+                anyhow::ensure!(length >= 0x80 && input[2] != 0, "non-canonical DER length");
+                (length, 2 + length_bytes)
+            }
+        };
+        let total_length = header_length
+            .checked_add(length)
+            .ok_or_else(|| anyhow::anyhow!("invalid DER length"))?;
+        anyhow::ensure!(input.len() >= total_length, "truncated DER value");
+        self.input = &input[total_length..];
+        Ok((
+            tag,
+            &input[header_length..total_length],
+            &input[..total_length],
+        ))
+    }
+
+    /// Reads the next value, which must have the given tag, and returns its content and its
+    /// complete encoding.
+    fn read_tagged(&mut self, expected_tag: u8) -> anyhow::Result<(&'a [u8], &'a [u8])> {
+        let (tag, content, encoded) = self.read_any()?;
+        anyhow::ensure!(
+            tag == expected_tag,
+            "unexpected DER tag: expected {:#04x}, found {:#04x}",
+            expected_tag,
+            tag
+        );
+        Ok((content, encoded))
+    }
+
+    /// Reads the next value, which must have the given tag, and returns its content.
+    pub(crate) fn read(&mut self, expected_tag: u8) -> anyhow::Result<&'a [u8]> {
+        self.read_tagged(expected_tag).map(|(content, _)| content)
+    }
+
+    /// Reads the next value, which must have the given tag, and returns its complete encoding.
+    pub(crate) fn read_encoded(&mut self, expected_tag: u8) -> anyhow::Result<&'a [u8]> {
+        self.read_tagged(expected_tag).map(|(_, encoded)| encoded)
+    }
+
+    /// Reads the next value if it has the given tag, and returns its content.
+    pub(crate) fn read_optional(&mut self, tag: u8) -> anyhow::Result<Option<&'a [u8]>> {
+        if self.peek_tag() == Some(tag) {
+            self.read(tag).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Reads a sequence, and returns a reader for its elements.
+    pub(crate) fn read_sequence(&mut self) -> anyhow::Result<DerReader<'a>> {
+        self.read(TAG_SEQUENCE).map(DerReader::new)
+    }
+
+    /// Reads a bit string without unused bits, and returns its bytes.
+    pub(crate) fn read_bit_string(&mut self) -> anyhow::Result<&'a [u8]> {
+        match self.read(TAG_BIT_STRING)?.split_first() {
+            Some((0, bytes)) => Ok(bytes),
+            _ => anyhow::bail!("unsupported DER bit string"),
+        }
+    }
+
+    /// Fails if there are any values left.
+    pub(crate) fn finish(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(self.is_empty(), "unexpected trailing DER data");
+        Ok(())
+    }
+}
+
+/// Parses a DER-encoded non-negative integer that must fit into a `u8`.
+pub(crate) fn parse_u8(encoded: &[u8]) -> anyhow::Result<u8> {
+    let mut reader = DerReader::new(encoded);
+    let content = reader.read(TAG_INTEGER)?;
+    reader.finish()?;
+    match content {
+        [value] if *value < 0x80 => Ok(*value),
+        [0, value] if *value >= 0x80 => Ok(*value),
+        _ => anyhow::bail!("integer out of range"),
+    }
+}
+
+/// An algorithm identifier, with the object identifiers as their DER-encoded content.
+pub(crate) struct AlgorithmIdentifier<'a> {
+    pub algorithm: &'a [u8],
+    /// The parameters, if they are an object identifier (e.g. the curve of an elliptic curve key).
+    pub parameters_oid: Option<&'a [u8]>,
+}
+
+impl<'a> AlgorithmIdentifier<'a> {
+    fn parse(reader: &mut DerReader<'a>) -> anyhow::Result<Self> {
+        let mut sequence = reader.read_sequence()?;
+        let algorithm = sequence.read(TAG_OBJECT_IDENTIFIER)?;
+        // Other parameters (e.g. the RSASSA-PSS parameters) are ignored, since the verification
+        // algorithm is fixed by the caller.
+        let parameters_oid = sequence.read_optional(TAG_OBJECT_IDENTIFIER)?;
+        Ok(Self {
+            algorithm,
+            parameters_oid,
+        })
+    }
+}
+
+/// A certificate extension.
+pub(crate) struct Extension<'a> {
+    /// DER-encoded content of the object identifier.
+    pub id: &'a [u8],
+    pub value: &'a [u8],
+}
+
+/// The parts of an X.509 certificate that are needed to verify the AMD certificate chain.
+pub(crate) struct Certificate<'a> {
+    /// Complete encoding of the `TBSCertificate`, which is covered by the signature.
+    pub tbs_certificate: &'a [u8],
+    /// Complete encoding of the issuer name.
+    pub issuer: &'a [u8],
+    /// Complete encoding of the subject name.
+    pub subject: &'a [u8],
+    pub public_key_algorithm: AlgorithmIdentifier<'a>,
+    pub public_key: &'a [u8],
+    pub extensions: Vec<Extension<'a>>,
+    pub signature_algorithm: AlgorithmIdentifier<'a>,
+    pub signature: &'a [u8],
+}
+
+impl<'a> Certificate<'a> {
+    pub(crate) fn parse(input: &'a [u8]) -> anyhow::Result<Self> {
+        let mut reader = DerReader::new(input);
+        let mut certificate = reader.read_sequence()?;
+        reader.finish()?;
+
+        let tbs_certificate = certificate.read_encoded(TAG_SEQUENCE)?;
+        let signature_algorithm = AlgorithmIdentifier::parse(&mut certificate)?;
+        let signature = certificate.read_bit_string()?;
+        certificate.finish()?;
+
+        let mut tbs = DerReader::new(tbs_certificate).read_sequence()?;
+        tbs.read_optional(TAG_VERSION)?;
+        let _serial_number = tbs.read(TAG_INTEGER)?;
+        let _signature = tbs.read(TAG_SEQUENCE)?;
+        let issuer = tbs.read_encoded(TAG_SEQUENCE)?;
+        let _validity = tbs.read(TAG_SEQUENCE)?;
+        let subject = tbs.read_encoded(TAG_SEQUENCE)?;
+        let mut public_key_info = tbs.read_sequence()?;
+        let public_key_algorithm = AlgorithmIdentifier::parse(&mut public_key_info)?;
+        let public_key = public_key_info.read_bit_string()?;
+        public_key_info.finish()?;
+        tbs.read_optional(TAG_ISSUER_UNIQUE_ID)?;
+        tbs.read_optional(TAG_SUBJECT_UNIQUE_ID)?;
+        let mut extensions = Vec::new();
+        if let Some(encoded_extensions) = tbs.read_optional(TAG_EXTENSIONS)? {
+            let mut extensions_reader = DerReader::new(encoded_extensions).read_sequence()?;
+            while !extensions_reader.is_empty() {
+                let mut extension = extensions_reader.read_sequence()?;
+                let id = extension.read(TAG_OBJECT_IDENTIFIER)?;
+                extension.read_optional(TAG_BOOLEAN)?;
+                let value = extension.read(TAG_OCTET_STRING)?;
+                extension.finish()?;
+                extensions.push(Extension { id, value });
+            }
+        }
+        tbs.finish()?;
+
+        Ok(Self {
+            tbs_certificate,
+            issuer,
+            subject,
+            public_key_algorithm,
+            public_key,
+            extensions,
+            signature_algorithm,
+            signature,
+        })
+    }
+
+    /// Returns the value of the extension with the given object identifier, if present.
+    pub(crate) fn extension(&self, id: &[u8]) -> Option<&'a [u8]> {
+        self.extensions
+            .iter()
+            .find(|extension| extension.id == id)
+            .map(|extension| extension.value)
+    }
+}
@@ -0,0 +1,158 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Verification of the AMD certificate chain that certifies the key signing attestation reports.
+//!
+//! The chain consists of:
+//! - the AMD Root Key (ARK), a self-signed RSA certificate for a product line, which is the root
+//!   of trust and must be obtained out of band;
+//! - the AMD SEV Key (ASK), an RSA certificate signed by the ARK;
+//! - the Versioned Chip Endorsement Key (VCEK), an ECDSA P-384 certificate signed by the ASK, which
+//!   is unique to a chip and a TCB version, and signs the attestation reports.
+//!
+//! All RSA signatures use RSASSA-PSS with SHA-384. See
+//! <https://www.amd.com/system/files/TechDocs/57230.pdf> for details.
+//!
+//! Validity periods are not checked, since there is no trusted source of time in `no_std`
+//! environments.
+
+use crate::{
+    asn1::{parse_u8, Certificate, DerReader, TAG_OCTET_STRING},
+    report::{TcbVersion, CHIP_ID_SIZE},
+};
+use alloc::vec::Vec;
+use anyhow::Context;
+use core::convert::TryInto;
+use ring::signature::{UnparsedPublicKey, RSA_PSS_2048_8192_SHA384};
+
+// Object identifiers are stored as the content of their DER encoding.
+
+/// Object identifier of the RSASSA-PSS signature algorithm (1.2.840.113549.1.1.10).
+const RSASSA_PSS_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x0A];
+
+/// Object identifier of RSA public keys (1.2.840.113549.1.1.1).
+const RSA_ENCRYPTION_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x01];
+
+/// Object identifier of elliptic curve public keys (1.2.840.10045.2.1).
+const EC_PUBLIC_KEY_OID: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01];
+
+/// Object identifier of the P-384 curve (1.3.132.0.34).
+const SECP384R1_OID: &[u8] = &[0x2B, 0x81, 0x04, 0x00, 0x22];
+
+/// Object identifiers of the AMD-specific VCEK certificate extensions (1.3.6.1.4.1.3704.1.3.1,
+/// 1.3.6.1.4.1.3704.1.3.2, 1.3.6.1.4.1.3704.1.3.3, 1.3.6.1.4.1.3704.1.3.8 and
+/// 1.3.6.1.4.1.3704.1.4).
+const BOOT_LOADER_SPL_OID: &[u8] = &[0x2B, 0x06, 0x01, 0x04, 0x01, 0x9C, 0x78, 0x01, 0x03, 0x01];
+const TEE_SPL_OID: &[u8] = &[0x2B, 0x06, 0x01, 0x04, 0x01, 0x9C, 0x78, 0x01, 0x03, 0x02];
+const SNP_SPL_OID: &[u8] = &[0x2B, 0x06, 0x01, 0x04, 0x01, 0x9C, 0x78, 0x01, 0x03, 0x03];
+const MICROCODE_SPL_OID: &[u8] = &[0x2B, 0x06, 0x01, 0x04, 0x01, 0x9C, 0x78, 0x01, 0x03, 0x08];
+const HW_ID_OID: &[u8] = &[0x2B, 0x06, 0x01, 0x04, 0x01, 0x9C, 0x78, 0x01, 0x04];
+
+/// A VCEK certificate whose chain to a trusted ARK has been verified.
+#[derive(Clone, Debug)]
+pub struct Vcek {
+    /// The uncompressed SEC1-encoded P-384 public key that signs attestation reports.
+    pub public_key: Vec<u8>,
+    /// The TCB version that the key was derived for.
+    pub tcb: TcbVersion,
+    /// The unique identifier of the chip that the key belongs to.
+    pub chip_id: [u8; CHIP_ID_SIZE],
+}
+
+/// Verifies that the given VCEK certificate is signed by the given ASK certificate, which in turn
+/// is signed by the given trusted ARK certificate. All certificates are DER-encoded.
+///
+/// Returns the verified VCEK.
+pub fn verify_certificate_chain(ark: &[u8], ask: &[u8], vcek: &[u8]) -> anyhow::Result<Vcek> {
+    let ark = Certificate::parse(ark).context("couldn't parse ARK certificate")?;
+    let ask = Certificate::parse(ask).context("couldn't parse ASK certificate")?;
+    let vcek = Certificate::parse(vcek).context("couldn't parse VCEK certificate")?;
+
+    verify_signed_by(&ark, &ark).context("invalid ARK certificate")?;
+    verify_signed_by(&ask, &ark).context("ASK certificate is not signed by the ARK")?;
+    verify_signed_by(&vcek, &ask).context("VCEK certificate is not signed by the ASK")?;
+
+    anyhow::ensure!(
+        vcek.public_key_algorithm.algorithm == EC_PUBLIC_KEY_OID
+            && vcek.public_key_algorithm.parameters_oid == Some(SECP384R1_OID),
+        "VCEK certificate does not contain a P-384 public key"
+    );
+
+    Ok(Vcek {
+        public_key: vcek.public_key.to_vec(),
+        tcb: TcbVersion {
+            boot_loader: read_spl_extension(&vcek, BOOT_LOADER_SPL_OID, "bootloader SPL")?,
+            tee: read_spl_extension(&vcek, TEE_SPL_OID, "TEE SPL")?,
+            snp: read_spl_extension(&vcek, SNP_SPL_OID, "SNP SPL")?,
+            microcode: read_spl_extension(&vcek, MICROCODE_SPL_OID, "microcode SPL")?,
+        },
+        chip_id: read_hw_id_extension(&vcek)?,
+    })
+}
+
+/// Verifies that `certificate` is issued and signed by `issuer`, using RSASSA-PSS with SHA-384.
+fn verify_signed_by(certificate: &Certificate, issuer: &Certificate) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        certificate.issuer == issuer.subject,
+        "issuer does not match"
+    );
+    anyhow::ensure!(
+        certificate.signature_algorithm.algorithm == RSASSA_PSS_OID,
+        "unsupported signature algorithm"
+    );
+    anyhow::ensure!(
+        issuer.public_key_algorithm.algorithm == RSA_ENCRYPTION_OID,
+        "issuer certificate does not contain an RSA public key"
+    );
+    // The subject public key of an RSA certificate is a DER-encoded `RSAPublicKey`, as expected by
+    // `ring`.
+    UnparsedPublicKey::new(&RSA_PSS_2048_8192_SHA384, issuer.public_key)
+        .verify(certificate.tbs_certificate, certificate.signature)
+        .map_err(|_err| anyhow::anyhow!("signature verification failed"))
+}
+
+fn find_extension<'a>(
+    certificate: &Certificate<'a>,
+    id: &[u8],
+    name: &str,
+) -> anyhow::Result<&'a [u8]> {
+    certificate
+        .extension(id)
+        .ok_or_else(|| anyhow::anyhow!("VCEK certificate is missing the {} extension", name))
+}
+
+/// Reads a security patch level extension, which contains a DER-encoded integer.
+fn read_spl_extension(certificate: &Certificate, id: &[u8], name: &str) -> anyhow::Result<u8> {
+    parse_u8(find_extension(certificate, id, name)?)
+        .with_context(|| alloc::format!("invalid {} extension in VCEK certificate", name))
+}
+
+/// Reads the hardware id extension, which contains the chip id either as raw bytes or as a
+/// DER-encoded octet string.
+fn read_hw_id_extension(certificate: &Certificate) -> anyhow::Result<[u8; CHIP_ID_SIZE]> {
+    let value = find_extension(certificate, HW_ID_OID, "hardware id")?;
+    let chip_id = if value.len() == CHIP_ID_SIZE {
+        value
+    } else {
+        let mut reader = DerReader::new(value);
+        let chip_id = reader.read(TAG_OCTET_STRING)?;
+        reader.finish()?;
+        chip_id
+    };
+    chip_id
+        .try_into()
+        .map_err(|_err| anyhow::anyhow!("invalid hardware id extension in VCEK certificate"))
+}
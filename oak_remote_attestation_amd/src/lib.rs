@@ -17,13 +17,27 @@
 //! This crate implements remote attestation primitives from `oak_remote_attestation` based on
 //! AMD-SEV-SNP.
 //!
-//! Currently only contains some placeholder structs, which will be replaced with actual
-//! functionality as part of #2842.
+//! [`verification::SnpAttestationVerifier`] verifies attestation reports signed by a VCEK that is
+//! chained to a trusted ARK, and checks them against reference values. It is currently only
+//! available with the `ring-crypto` feature. The placeholder structs are still used where no
+//! SEV-SNP hardware is available.
 
 #![no_std]
 
 extern crate alloc;
 
+// TODO(#2842): Support verification with the `rust-crypto` backend.
+#[cfg(feature = "ring-crypto")]
+mod asn1;
+#[cfg(feature = "ring-crypto")]
+pub mod certificates;
+pub mod report;
+#[cfg(feature = "ring-crypto")]
+pub mod verification;
+
+#[cfg(all(test, feature = "ring-crypto"))]
+mod tests;
+
 use alloc::{
     string::{String, ToString},
     vec::Vec,
@@ -0,0 +1,275 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Parsing of the binary AMD SEV-SNP attestation report.
+//!
+//! See the `ATTESTATION_REPORT` structure in Table 21 of
+//! <https://www.amd.com/system/files/TechDocs/56860.pdf>.
+
+use core::convert::TryInto;
+use serde::Deserialize;
+
+/// Size (in bytes) of an attestation report.
+pub const REPORT_SIZE: usize = 0x4A0;
+
+/// Size (in bytes) of the part of the attestation report that is covered by the signature.
+pub const SIGNED_REPORT_SIZE: usize = 0x2A0;
+
+/// Size (in bytes) of the guest-provided data that is included in the attestation report.
+pub const REPORT_DATA_SIZE: usize = 64;
+
+/// Size (in bytes) of the launch measurement of the guest.
+pub const MEASUREMENT_SIZE: usize = 48;
+
+/// Size (in bytes) of the unique identifier of the chip that generated the report.
+pub const CHIP_ID_SIZE: usize = 64;
+
+/// Size (in bytes) of each of the little-endian `R` and `S` components of the signature.
+const SIGNATURE_COMPONENT_SIZE: usize = 72;
+
+/// Size (in bytes) of a P-384 scalar.
+const P384_SCALAR_SIZE: usize = 48;
+
+/// Oldest supported version of the attestation report.
+const MIN_REPORT_VERSION: u32 = 2;
+
+/// Value of the `SIGNATURE_ALGO` field for ECDSA P-384 with SHA-384.
+const SIGNATURE_ALGO_ECDSA_P384_SHA384: u32 = 1;
+
+/// Security patch levels of the firmware components that make up the trusted computing base.
+///
+/// Each component is compared separately, a TCB version is only at least as recent as another one
+/// if all of its components are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TcbVersion {
+    /// Security patch level of the PSP bootloader.
+    pub boot_loader: u8,
+    /// Security patch level of the PSP operating system.
+    pub tee: u8,
+    /// Security patch level of the SNP firmware.
+    pub snp: u8,
+    /// Security patch level of the CPU microcode.
+    pub microcode: u8,
+}
+
+impl TcbVersion {
+    fn from_u64(value: u64) -> Self {
+        let bytes = value.to_le_bytes();
+        Self {
+            boot_loader: bytes[0],
+            tee: bytes[1],
+            snp: bytes[6],
+            microcode: bytes[7],
+        }
+    }
+
+    /// Returns whether all the components of this TCB version are at least as recent as the ones
+    /// of `minimum`.
+    pub fn is_at_least(&self, minimum: &TcbVersion) -> bool {
+        self.boot_loader >= minimum.boot_loader
+            && self.tee >= minimum.tee
+            && self.snp >= minimum.snp
+            && self.microcode >= minimum.microcode
+    }
+}
+
+/// Policy that the guest owner required when launching the guest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GuestPolicy(pub u64);
+
+impl GuestPolicy {
+    /// Minimum ABI minor version required for the guest to run.
+    pub fn abi_minor(&self) -> u8 {
+        self.0 as u8
+    }
+
+    /// Minimum ABI major version required for the guest to run.
+    pub fn abi_major(&self) -> u8 {
+        (self.0 >> 8) as u8
+    }
+
+    /// Whether simultaneous multi-threading is allowed.
+    pub fn smt_allowed(&self) -> bool {
+        self.0 & (1 << 16) != 0
+    }
+
+    /// Whether association with a migration agent is allowed.
+    pub fn migrate_ma_allowed(&self) -> bool {
+        self.0 & (1 << 18) != 0
+    }
+
+    /// Whether debugging is allowed, which gives the hypervisor access to the guest memory.
+    pub fn debug_allowed(&self) -> bool {
+        self.0 & (1 << 19) != 0
+    }
+
+    /// Whether the guest can only be activated on a single socket.
+    pub fn single_socket_required(&self) -> bool {
+        self.0 & (1 << 20) != 0
+    }
+}
+
+/// A parsed AMD SEV-SNP attestation report.
+///
+/// Parsing does not verify the signature, see [`crate::verification`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttestationReport {
+    /// Version number of the report.
+    pub version: u32,
+    /// Security version number of the guest.
+    pub guest_svn: u32,
+    /// Policy of the guest.
+    pub policy: GuestPolicy,
+    /// Family id provided at launch.
+    pub family_id: [u8; 16],
+    /// Image id provided at launch.
+    pub image_id: [u8; 16],
+    /// Virtual machine privilege level of the guest that requested the report.
+    pub vmpl: u32,
+    /// Algorithm used to sign the report.
+    pub signature_algo: u32,
+    /// TCB version that the platform is currently running.
+    pub current_tcb: TcbVersion,
+    /// Information about the platform.
+    pub platform_info: u64,
+    /// Whether the digest of the author key is included in the report.
+    pub author_key_en: bool,
+    /// Whether the chip id is masked (i.e. set to all zeros) in the report.
+    pub mask_chip_id: bool,
+    /// Key used to sign the report: 0 for the VCEK, 1 for the VLEK.
+    pub signing_key: u8,
+    /// Guest-provided data, used to bind the report to other data (e.g. a public key).
+    pub report_data: [u8; REPORT_DATA_SIZE],
+    /// Measurement of the initial guest memory, calculated at launch.
+    pub measurement: [u8; MEASUREMENT_SIZE],
+    /// Data provided by the hypervisor at launch.
+    pub host_data: [u8; 32],
+    /// SHA-384 digest of the id public key that signed the id block provided at launch.
+    pub id_key_digest: [u8; 48],
+    /// SHA-384 digest of the author public key that certified the id key, if enabled.
+    pub author_key_digest: [u8; 48],
+    /// Report id of the guest.
+    pub report_id: [u8; 32],
+    /// Report id of the migration agent of the guest.
+    pub report_id_ma: [u8; 32],
+    /// TCB version that was used to derive the VCEK that signed the report.
+    pub reported_tcb: TcbVersion,
+    /// Unique identifier of the chip, unless masked.
+    pub chip_id: [u8; CHIP_ID_SIZE],
+    /// TCB version that the platform committed to, i.e. the minimum version it can roll back to.
+    pub committed_tcb: TcbVersion,
+    /// TCB version that the guest was launched or migrated at.
+    pub launch_tcb: TcbVersion,
+    /// Big-endian `R` component of the ECDSA P-384 signature.
+    pub signature_r: [u8; P384_SCALAR_SIZE],
+    /// Big-endian `S` component of the ECDSA P-384 signature.
+    pub signature_s: [u8; P384_SCALAR_SIZE],
+    signed_bytes: [u8; SIGNED_REPORT_SIZE],
+}
+
+impl AttestationReport {
+    /// Parses an attestation report from its binary representation.
+    ///
+    /// Only reports signed with ECDSA P-384 with SHA-384 are supported.
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            bytes.len() == REPORT_SIZE,
+            "invalid attestation report size: expected {} bytes, found {}",
+            REPORT_SIZE,
+            bytes.len()
+        );
+        let version = read_u32(bytes, 0x00);
+        anyhow::ensure!(
+            version >= MIN_REPORT_VERSION,
+            "unsupported attestation report version {}",
+            version
+        );
+        let signature_algo = read_u32(bytes, 0x34);
+        anyhow::ensure!(
+            signature_algo == SIGNATURE_ALGO_ECDSA_P384_SHA384,
+            "unsupported attestation report signature algorithm {}",
+            signature_algo
+        );
+        let key_info = read_u32(bytes, 0x48);
+
+        Ok(Self {
+            version,
+            guest_svn: read_u32(bytes, 0x04),
+            policy: GuestPolicy(read_u64(bytes, 0x08)),
+            family_id: read_array(bytes, 0x10),
+            image_id: read_array(bytes, 0x20),
+            vmpl: read_u32(bytes, 0x30),
+            signature_algo,
+            current_tcb: TcbVersion::from_u64(read_u64(bytes, 0x38)),
+            platform_info: read_u64(bytes, 0x40),
+            author_key_en: key_info & 1 != 0,
+            mask_chip_id: key_info & (1 << 1) != 0,
+            signing_key: ((key_info >> 2) & 0b111) as u8,
+            report_data: read_array(bytes, 0x50),
+            measurement: read_array(bytes, 0x90),
+            host_data: read_array(bytes, 0xC0),
+            id_key_digest: read_array(bytes, 0xE0),
+            author_key_digest: read_array(bytes, 0x110),
+            report_id: read_array(bytes, 0x140),
+            report_id_ma: read_array(bytes, 0x160),
+            reported_tcb: TcbVersion::from_u64(read_u64(bytes, 0x180)),
+            chip_id: read_array(bytes, 0x1A0),
+            committed_tcb: TcbVersion::from_u64(read_u64(bytes, 0x1E0)),
+            launch_tcb: TcbVersion::from_u64(read_u64(bytes, 0x1F0)),
+            signature_r: read_signature_component(bytes, SIGNED_REPORT_SIZE)?,
+            signature_s: read_signature_component(
+                bytes,
+                SIGNED_REPORT_SIZE + SIGNATURE_COMPONENT_SIZE,
+            )?,
+            signed_bytes: read_array(bytes, 0),
+        })
+    }
+
+    /// Returns the part of the report that is covered by the signature.
+    pub fn signed_bytes(&self) -> &[u8] {
+        &self.signed_bytes
+    }
+}
+
+fn read_array<const N: usize>(bytes: &[u8], offset: usize) -> [u8; N] {
+    bytes[offset..offset + N]
+        .try_into()
+        .expect("invalid report field offset")
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(read_array(bytes, offset))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(read_array(bytes, offset))
+}
+
+/// Reads a little-endian signature component, and returns it as a big-endian P-384 scalar.
+fn read_signature_component(bytes: &[u8], offset: usize) -> anyhow::Result<[u8; P384_SCALAR_SIZE]> {
+    let component: [u8; SIGNATURE_COMPONENT_SIZE] = read_array(bytes, offset);
+    // Only the first 48 bytes are used for P-384, the remaining ones must be zero.
+    anyhow::ensure!(
+        component[P384_SCALAR_SIZE..].iter().all(|byte| *byte == 0),
+        "invalid attestation report signature"
+    );
+    let mut scalar: [u8; P384_SCALAR_SIZE] = component[..P384_SCALAR_SIZE]
+        .try_into()
+        .map_err(|_err| anyhow::anyhow!("invalid attestation report signature"))?;
+    scalar.reverse();
+    Ok(scalar)
+}
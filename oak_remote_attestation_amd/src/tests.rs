@@ -0,0 +1,275 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+// The test data is generated by `testdata/generate_test_data`, using a test certificate chain that
+// is not rooted in a real AMD key.
+
+use crate::{
+    certificates::verify_certificate_chain,
+    report::{AttestationReport, TcbVersion, MEASUREMENT_SIZE, REPORT_DATA_SIZE},
+    verification::{
+        report_data, verify_report_signature, AttestationReportProvider, SnpAttestationGenerator,
+        SnpAttestationVerifier, SnpEvidence, SnpReferenceValues,
+    },
+};
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use oak_remote_attestation::handshaker::{AttestationGenerator, AttestationVerifier};
+
+const ARK: &[u8] = include_bytes!("../testdata/ark.der");
+const ASK: &[u8] = include_bytes!("../testdata/ask.der");
+const VCEK: &[u8] = include_bytes!("../testdata/vcek.der");
+const REPORT: &[u8] = include_bytes!("../testdata/report.bin");
+const ATTESTED_DATA: &[u8] = include_bytes!("../testdata/attested_data.bin");
+const MEASUREMENT: &[u8] = include_bytes!("../testdata/measurement.bin");
+
+const REPORTED_TCB: TcbVersion = TcbVersion {
+    boot_loader: 3,
+    tee: 0,
+    snp: 8,
+    microcode: 115,
+};
+
+fn measurement() -> [u8; MEASUREMENT_SIZE] {
+    MEASUREMENT.try_into().unwrap()
+}
+
+fn reference_values() -> SnpReferenceValues {
+    SnpReferenceValues {
+        measurements: alloc::vec![measurement()],
+        minimum_tcb: REPORTED_TCB,
+        minimum_guest_svn: 1,
+        allow_debug: false,
+        allow_migrate_ma: false,
+    }
+}
+
+fn evidence() -> SnpEvidence {
+    SnpEvidence {
+        report: REPORT.to_vec(),
+        vcek: VCEK.to_vec(),
+        ask: ASK.to_vec(),
+    }
+}
+
+#[test]
+fn test_parse_report() {
+    let report = AttestationReport::parse(REPORT).unwrap();
+    assert_eq!(report.version, 2);
+    assert_eq!(report.guest_svn, 1);
+    assert!(report.policy.smt_allowed());
+    assert!(!report.policy.debug_allowed());
+    assert!(!report.policy.migrate_ma_allowed());
+    assert_eq!(report.vmpl, 0);
+    assert_eq!(report.signing_key, 0);
+    assert!(!report.mask_chip_id);
+    assert_eq!(report.current_tcb, REPORTED_TCB);
+    assert_eq!(report.reported_tcb, REPORTED_TCB);
+    assert_eq!(report.measurement, measurement());
+    assert_eq!(report.report_data, report_data(ATTESTED_DATA).unwrap());
+}
+
+#[test]
+fn test_parse_invalid_report() {
+    assert!(AttestationReport::parse(&REPORT[1..]).is_err());
+
+    // Unsupported version.
+    let mut report = REPORT.to_vec();
+    report[0] = 1;
+    assert!(AttestationReport::parse(&report).is_err());
+
+    // Unsupported signature algorithm.
+    let mut report = REPORT.to_vec();
+    report[0x34] = 2;
+    assert!(AttestationReport::parse(&report).is_err());
+}
+
+#[test]
+fn test_verify_certificate_chain() {
+    let vcek = verify_certificate_chain(ARK, ASK, VCEK).unwrap();
+    assert_eq!(vcek.tcb, REPORTED_TCB);
+    assert_eq!(
+        vcek.chip_id,
+        AttestationReport::parse(REPORT).unwrap().chip_id
+    );
+
+    // The chain must be rooted in the trusted ARK.
+    assert!(verify_certificate_chain(ASK, ASK, VCEK).is_err());
+    // Certificates must be in the right order.
+    assert!(verify_certificate_chain(ARK, VCEK, ASK).is_err());
+    // Tampered certificates are rejected.
+    let mut vcek = VCEK.to_vec();
+    let last = vcek.len() - 1;
+    vcek[last] ^= 1;
+    assert!(verify_certificate_chain(ARK, ASK, &vcek).is_err());
+}
+
+#[test]
+fn test_evidence_serialization() {
+    let evidence = evidence();
+    assert_eq!(
+        SnpEvidence::deserialize(&evidence.serialize()).unwrap(),
+        evidence
+    );
+
+    let serialized = evidence.serialize();
+    assert!(SnpEvidence::deserialize(&serialized[..serialized.len() - 1]).is_err());
+    let mut serialized = serialized;
+    serialized.push(0);
+    assert!(SnpEvidence::deserialize(&serialized).is_err());
+}
+
+#[test]
+fn test_verify_attestation() {
+    let verifier = SnpAttestationVerifier::new(ARK.to_vec(), reference_values());
+    let attestation = evidence().serialize();
+    assert!(verifier
+        .verify_attestation(&attestation, ATTESTED_DATA)
+        .is_ok());
+
+    // The report must be bound to the attested data of the handshake.
+    let mut other_attested_data = ATTESTED_DATA.to_vec();
+    other_attested_data[0] ^= 1;
+    assert!(verifier
+        .verify_attestation(&attestation, &other_attested_data)
+        .is_err());
+}
+
+#[test]
+fn test_verify_tampered_report() {
+    let verifier = SnpAttestationVerifier::new(ARK.to_vec(), reference_values());
+    // Changing the measurement invalidates the signature.
+    let mut evidence = evidence();
+    evidence.report[0x90] ^= 1;
+    let mut reference_values = reference_values();
+    reference_values.measurements.push(
+        evidence.report[0x90..0x90 + MEASUREMENT_SIZE]
+            .try_into()
+            .unwrap(),
+    );
+    let verifier_accepting_tampered_measurement =
+        SnpAttestationVerifier::new(ARK.to_vec(), reference_values);
+    for verifier in [verifier, verifier_accepting_tampered_measurement] {
+        assert!(verifier
+            .verify_attestation(&evidence.serialize(), ATTESTED_DATA)
+            .is_err());
+    }
+}
+
+#[test]
+fn test_verify_untrusted_root() {
+    let verifier = SnpAttestationVerifier::new(ASK.to_vec(), reference_values());
+    assert!(verifier
+        .verify_attestation(&evidence().serialize(), ATTESTED_DATA)
+        .is_err());
+}
+
+#[test]
+fn test_reference_values() {
+    let report = AttestationReport::parse(REPORT).unwrap();
+    assert!(reference_values().check(&report).is_ok());
+
+    let reference_values_with = |update: fn(&mut SnpReferenceValues)| {
+        let mut reference_values = reference_values();
+        update(&mut reference_values);
+        reference_values
+    };
+
+    // Unknown measurement.
+    assert!(reference_values_with(
+        |values| values.measurements = alloc::vec![[0; MEASUREMENT_SIZE]]
+    )
+    .check(&report)
+    .is_err());
+    // Outdated TCB.
+    assert!(
+        reference_values_with(|values| values.minimum_tcb.microcode = 116)
+            .check(&report)
+            .is_err()
+    );
+    // Outdated guest.
+    assert!(reference_values_with(|values| values.minimum_guest_svn = 2)
+        .check(&report)
+        .is_err());
+
+    // Debugging is only accepted if explicitly allowed.
+    let mut debug_report = report.clone();
+    debug_report.policy.0 |= 1 << 19;
+    assert!(reference_values().check(&debug_report).is_err());
+    assert!(reference_values_with(|values| values.allow_debug = true)
+        .check(&debug_report)
+        .is_ok());
+
+    // Migration agents are only accepted if explicitly allowed.
+    let mut migrate_ma_report = report;
+    migrate_ma_report.policy.0 |= 1 << 18;
+    assert!(reference_values().check(&migrate_ma_report).is_err());
+    assert!(
+        reference_values_with(|values| values.allow_migrate_ma = true)
+            .check(&migrate_ma_report)
+            .is_ok()
+    );
+}
+
+/// Returns the recorded report, checking that it is requested with the expected report data.
+#[derive(Clone)]
+struct RecordedReportProvider;
+
+impl AttestationReportProvider for RecordedReportProvider {
+    fn get_report(&self, report_data: &[u8; REPORT_DATA_SIZE]) -> anyhow::Result<Vec<u8>> {
+        anyhow::ensure!(report_data[..ATTESTED_DATA.len()] == *ATTESTED_DATA);
+        anyhow::ensure!(report_data[ATTESTED_DATA.len()..].iter().all(|b| *b == 0));
+        Ok(REPORT.to_vec())
+    }
+}
+
+#[test]
+fn test_generate_attestation() {
+    let generator =
+        SnpAttestationGenerator::new(RecordedReportProvider, VCEK.to_vec(), ASK.to_vec());
+    let attestation = generator.generate_attestation(ATTESTED_DATA).unwrap();
+    assert_eq!(attestation, evidence().serialize());
+
+    let verifier = SnpAttestationVerifier::new(ARK.to_vec(), reference_values());
+    assert!(verifier
+        .verify_attestation(&attestation, ATTESTED_DATA)
+        .is_ok());
+
+    // Attested data that does not fit into the report data is rejected.
+    assert!(generator
+        .generate_attestation(&[0; REPORT_DATA_SIZE + 1])
+        .is_err());
+}
+
+/// Reads a file of the real Milan test data fetched by `testdata/fetch_milan_test_data`.
+fn read_milan_test_data(name: &str) -> Vec<u8> {
+    extern crate std;
+    let path = alloc::format!("{}/testdata/milan/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read(&path).unwrap_or_else(|err| panic!("couldn't read {}: {}", path, err))
+}
+
+#[test]
+#[ignore = "requires the real Milan test data, see testdata/fetch_milan_test_data"]
+fn test_verify_real_milan_report() {
+    let ark = read_milan_test_data("ark.der");
+    let ask = read_milan_test_data("ask.der");
+    let vcek = read_milan_test_data("vcek.der");
+    let report = read_milan_test_data("report.bin");
+
+    let vcek = verify_certificate_chain(&ark, &ask, &vcek).unwrap();
+    let report = AttestationReport::parse(&report).unwrap();
+    verify_report_signature(&report, &vcek).unwrap();
+}
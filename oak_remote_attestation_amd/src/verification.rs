@@ -0,0 +1,271 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Generation and verification of AMD SEV-SNP attestation evidence in the remote attestation
+//! handshake.
+
+use crate::{
+    certificates::{verify_certificate_chain, Vcek},
+    report::{AttestationReport, TcbVersion, MEASUREMENT_SIZE, REPORT_DATA_SIZE, REPORT_SIZE},
+};
+use alloc::vec::Vec;
+use anyhow::Context;
+use core::convert::TryInto;
+use oak_remote_attestation::handshaker::{AttestationGenerator, AttestationVerifier};
+use ring::signature::{UnparsedPublicKey, ECDSA_P384_SHA384_FIXED};
+
+/// Length (in bytes) of the little-endian prefix encoding the length of a certificate in
+/// [`SnpEvidence`].
+const LENGTH_PREFIX_SIZE: usize = 8;
+
+/// Attestation evidence sent in the remote attestation handshake: the attestation report, together
+/// with the certificates needed to verify it, except for the ARK which must be trusted by the
+/// verifier.
+///
+/// It is serialized as the raw attestation report, followed by the DER-encoded VCEK and ASK
+/// certificates, each prefixed by its length as a 64-bit little-endian integer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SnpEvidence {
+    pub report: Vec<u8>,
+    pub vcek: Vec<u8>,
+    pub ask: Vec<u8>,
+}
+
+impl SnpEvidence {
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(
+            REPORT_SIZE + 2 * LENGTH_PREFIX_SIZE + self.vcek.len() + self.ask.len(),
+        );
+        result.extend_from_slice(&self.report);
+        for certificate in [&self.vcek, &self.ask] {
+            result.extend_from_slice(&(certificate.len() as u64).to_le_bytes());
+            result.extend_from_slice(certificate);
+        }
+        result
+    }
+
+    pub fn deserialize(input: &[u8]) -> anyhow::Result<Self> {
+        anyhow::ensure!(input.len() >= REPORT_SIZE, "attestation evidence too short");
+        let (report, mut input) = input.split_at(REPORT_SIZE);
+        let vcek = read_certificate(&mut input).context("couldn't read VCEK certificate")?;
+        let ask = read_certificate(&mut input).context("couldn't read ASK certificate")?;
+        anyhow::ensure!(
+            input.is_empty(),
+            "invalid attestation evidence: {} unused bytes detected",
+            input.len()
+        );
+        Ok(Self {
+            report: report.to_vec(),
+            vcek,
+            ask,
+        })
+    }
+}
+
+fn read_certificate(input: &mut &[u8]) -> anyhow::Result<Vec<u8>> {
+    anyhow::ensure!(input.len() >= LENGTH_PREFIX_SIZE, "missing length prefix");
+    let (length, rest) = input.split_at(LENGTH_PREFIX_SIZE);
+    let length = u64::from_le_bytes(
+        length
+            .try_into()
+            .map_err(|_err| anyhow::anyhow!("invalid length prefix"))?,
+    );
+    let length: usize = length
+        .try_into()
+        .map_err(|_err| anyhow::anyhow!("invalid length prefix"))?;
+    anyhow::ensure!(rest.len() >= length, "certificate too short");
+    let (certificate, rest) = rest.split_at(length);
+    *input = rest;
+    Ok(certificate.to_vec())
+}
+
+/// Values that an attestation report must match to be accepted.
+#[derive(Clone, Debug, Default)]
+pub struct SnpReferenceValues {
+    /// Accepted launch measurements. A report is only accepted if its measurement is one of these.
+    pub measurements: Vec<[u8; MEASUREMENT_SIZE]>,
+    /// Minimum accepted TCB version that the report was signed at.
+    pub minimum_tcb: TcbVersion,
+    /// Minimum accepted security version number of the guest.
+    pub minimum_guest_svn: u32,
+    /// Whether guests that allow debugging are accepted. Must only be used for testing, since
+    /// debugging gives the hypervisor access to the guest memory.
+    pub allow_debug: bool,
+    /// Whether guests that allow association with a migration agent are accepted.
+    pub allow_migrate_ma: bool,
+}
+
+impl SnpReferenceValues {
+    /// Checks that the given report matches the reference values.
+    pub fn check(&self, report: &AttestationReport) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.measurements.contains(&report.measurement),
+            "measurement {:x?} is not accepted",
+            report.measurement
+        );
+        anyhow::ensure!(
+            report.reported_tcb.is_at_least(&self.minimum_tcb),
+            "TCB version {:?} is older than the minimum TCB version {:?}",
+            report.reported_tcb,
+            self.minimum_tcb
+        );
+        anyhow::ensure!(
+            report.guest_svn >= self.minimum_guest_svn,
+            "guest SVN {} is older than the minimum guest SVN {}",
+            report.guest_svn,
+            self.minimum_guest_svn
+        );
+        anyhow::ensure!(
+            self.allow_debug || !report.policy.debug_allowed(),
+            "guest policy allows debugging"
+        );
+        anyhow::ensure!(
+            self.allow_migrate_ma || !report.policy.migrate_ma_allowed(),
+            "guest policy allows a migration agent"
+        );
+        Ok(())
+    }
+}
+
+/// Checks that the report data of the given report contains the expected attested data, padded
+/// with zeros.
+pub fn check_report_data(
+    report: &AttestationReport,
+    expected_attested_data: &[u8],
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        report.report_data == report_data(expected_attested_data)?,
+        "invalid attested data; got: {:?}, expected: {:?}",
+        &report.report_data[..],
+        expected_attested_data
+    );
+    Ok(())
+}
+
+/// Returns the report data binding the given attested data to an attestation report.
+pub fn report_data(attested_data: &[u8]) -> anyhow::Result<[u8; REPORT_DATA_SIZE]> {
+    anyhow::ensure!(
+        attested_data.len() <= REPORT_DATA_SIZE,
+        "attested data too long: expected at most {} bytes, found {}",
+        REPORT_DATA_SIZE,
+        attested_data.len()
+    );
+    let mut report_data = [0; REPORT_DATA_SIZE];
+    report_data[..attested_data.len()].copy_from_slice(attested_data);
+    Ok(report_data)
+}
+
+/// Verifies that the report is signed by the given VCEK, and that it was generated by the chip and
+/// at the TCB version that the VCEK was issued for.
+pub fn verify_report_signature(report: &AttestationReport, vcek: &Vcek) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        report.signing_key == 0,
+        "attestation report is not signed by a VCEK"
+    );
+    anyhow::ensure!(
+        report.reported_tcb == vcek.tcb,
+        "reported TCB version {:?} does not match VCEK TCB version {:?}",
+        report.reported_tcb,
+        vcek.tcb
+    );
+    anyhow::ensure!(
+        !report.mask_chip_id && report.chip_id == vcek.chip_id,
+        "chip id does not match VCEK"
+    );
+    let signature = [&report.signature_r[..], &report.signature_s[..]].concat();
+    UnparsedPublicKey::new(&ECDSA_P384_SHA384_FIXED, &vcek.public_key)
+        .verify(report.signed_bytes(), &signature)
+        .map_err(|_err| anyhow::anyhow!("attestation report signature verification failed"))
+}
+
+/// An [`AttestationVerifier`] for AMD SEV-SNP attestation evidence.
+#[derive(Clone)]
+pub struct SnpAttestationVerifier {
+    /// DER-encoded ARK certificate, which is the root of trust.
+    ark: Vec<u8>,
+    reference_values: SnpReferenceValues,
+}
+
+impl SnpAttestationVerifier {
+    /// Creates a verifier that trusts the given DER-encoded ARK certificate, and accepts reports
+    /// matching the given reference values.
+    ///
+    /// The ARK for each product line is published by AMD at
+    /// `https://kdsintf.amd.com/vcek/v1/{product_name}/cert_chain`.
+    pub fn new(ark: Vec<u8>, reference_values: SnpReferenceValues) -> Self {
+        Self {
+            ark,
+            reference_values,
+        }
+    }
+}
+
+impl AttestationVerifier for SnpAttestationVerifier {
+    fn verify_attestation(
+        &self,
+        attestation: &[u8],
+        expected_attested_data: &[u8],
+    ) -> anyhow::Result<()> {
+        let evidence = SnpEvidence::deserialize(attestation)?;
+        let report = AttestationReport::parse(&evidence.report)?;
+        let vcek = verify_certificate_chain(&self.ark, &evidence.ask, &evidence.vcek)?;
+        verify_report_signature(&report, &vcek)?;
+        check_report_data(&report, expected_attested_data)?;
+        self.reference_values.check(&report)
+    }
+}
+
+/// Source of attestation reports, e.g. the `/dev/sev-guest` device in a Linux guest, or the guest
+/// message protocol of the AMD Secure Processor in a bare-metal guest.
+pub trait AttestationReportProvider: Clone + Send + Sync {
+    /// Returns a binary attestation report that includes the given report data.
+    fn get_report(&self, report_data: &[u8; REPORT_DATA_SIZE]) -> anyhow::Result<Vec<u8>>;
+}
+
+/// An [`AttestationGenerator`] producing AMD SEV-SNP attestation evidence.
+#[derive(Clone)]
+pub struct SnpAttestationGenerator<P: AttestationReportProvider> {
+    provider: P,
+    /// DER-encoded VCEK certificate of the chip and TCB version that the guest runs on.
+    vcek: Vec<u8>,
+    /// DER-encoded ASK certificate of the product line.
+    ask: Vec<u8>,
+}
+
+impl<P: AttestationReportProvider> SnpAttestationGenerator<P> {
+    pub fn new(provider: P, vcek: Vec<u8>, ask: Vec<u8>) -> Self {
+        Self {
+            provider,
+            vcek,
+            ask,
+        }
+    }
+}
+
+impl<P: AttestationReportProvider> AttestationGenerator for SnpAttestationGenerator<P> {
+    fn generate_attestation(&self, attested_data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let report = self
+            .provider
+            .get_report(&report_data(attested_data)?)
+            .context("couldn't get attestation report")?;
+        Ok(SnpEvidence {
+            report,
+            vcek: self.vcek.clone(),
+            ask: self.ask.clone(),
+        }
+        .serialize())
+    }
+}
@@ -15,6 +15,10 @@
 //
 
 use proc_macro2::{Ident, TokenStream};
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
 use quote::{format_ident, quote};
 
 /// Returns a [`TokenStream`] representing the specified Rust type.
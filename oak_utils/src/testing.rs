@@ -0,0 +1,88 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Utilities for tests that serve gRPC services.
+
+use crate::LogError;
+use std::net::{Ipv4Addr, SocketAddr};
+use tokio::{net::TcpListener, sync::oneshot, task::JoinHandle};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::server::Router;
+
+/// [`LogError`] implementation that forwards errors to the `log` crate.
+#[derive(Clone)]
+pub struct TestLogger;
+
+impl LogError for TestLogger {
+    fn log_error(&self, error: &str) {
+        log::error!("{}", error);
+    }
+}
+
+/// gRPC server that serves on a local TCP port in the background, until it is stopped.
+pub struct TestServer {
+    address: SocketAddr,
+    terminate_sender: oneshot::Sender<()>,
+    handle: JoinHandle<Result<(), tonic::transport::Error>>,
+}
+
+impl TestServer {
+    /// Starts serving `router` on `address`, or on a free port of localhost if not provided.
+    ///
+    /// The listener is bound before this returns, so clients can connect to the server right away.
+    pub async fn start(router: Router, address: Option<SocketAddr>) -> Self {
+        let listener =
+            TcpListener::bind(address.unwrap_or_else(|| (Ipv4Addr::LOCALHOST, 0).into()))
+                .await
+                .expect("Couldn't bind listener");
+        let address = listener
+            .local_addr()
+            .expect("Couldn't get listener address");
+        let (terminate_sender, terminate_receiver) = oneshot::channel::<()>();
+        let handle = tokio::spawn(router.serve_with_incoming_shutdown(
+            TcpListenerStream::new(listener),
+            async {
+                terminate_receiver.await.ok();
+            },
+        ));
+        Self {
+            address,
+            terminate_sender,
+            handle,
+        }
+    }
+
+    /// Returns the URI of the server.
+    pub fn uri(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    /// Stops the server and waits until it closed its listener. Returns the address of the server,
+    /// so that it can be restarted on the same address.
+    ///
+    /// The server completes the requests in flight before it stops, so clients with open streams
+    /// must be dropped first.
+    pub async fn stop(self) -> SocketAddr {
+        self.terminate_sender
+            .send(())
+            .expect("Couldn't stop server");
+        self.handle
+            .await
+            .expect("Couldn't join server")
+            .expect("Server failed");
+        self.address
+    }
+}
@@ -0,0 +1,30 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use oak_remote_attestation::message::{deserialize_message, Serializable};
+
+fuzz_target!(|data: &[u8]| {
+    // Arbitrary input must never make the deserializer panic. Messages that are accepted must have
+    // a unique encoding, so serializing them again must produce the same bytes.
+    if let Ok(message) = deserialize_message(data) {
+        let serialized = message
+            .serialize()
+            .expect("Couldn't serialize deserialized message");
+        assert_eq!(serialized, data);
+    }
+});
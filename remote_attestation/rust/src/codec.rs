@@ -0,0 +1,170 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Length-checked encoding of the remote attestation messages.
+//!
+//! Messages are encoded as a header byte followed by a sequence of fields, without any padding:
+//!
+//! - Single bytes (e.g. protocol versions) are encoded as is.
+//! - Fixed-size byte arrays (e.g. public keys) are encoded as is.
+//! - Variable-size byte vectors are encoded as their length, as a little-endian 64-bit integer,
+//!   followed by their contents.
+//!
+//! Each variable-size field is described by a [`VecField`], which limits its length. Limits are
+//! checked both when encoding and decoding messages, so that a peer cannot make the other side
+//! allocate more memory than the field needs.
+//!
+//! [`Reader`] borrows all the fields from the input, so that the whole message can be validated
+//! before any of its fields is copied.
+
+use crate::message::VEC_SIZE_PREFIX_LENGTH;
+use alloc::vec::Vec;
+use anyhow::{anyhow, bail};
+use core::convert::TryInto;
+
+/// Schema of a variable-size field of a message.
+pub(crate) struct VecField {
+    /// Name of the field, used in error messages.
+    pub(crate) name: &'static str,
+    /// Maximum length (in bytes) of the field contents.
+    pub(crate) maximum_length: usize,
+}
+
+/// Decodes the fields of a message, borrowing them from the input.
+pub(crate) struct Reader<'a> {
+    input: &'a [u8],
+    /// Name of the message, used in error messages.
+    message: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    /// Creates a reader for a message, checking that the input starts with the given header.
+    pub(crate) fn new(input: &'a [u8], message: &'static str, header: u8) -> anyhow::Result<Self> {
+        let mut reader = Self { input, message };
+        if reader.read_u8("header")? != header {
+            bail!("Invalid {} message header", message);
+        }
+        Ok(reader)
+    }
+
+    /// Returns whether all the input was read.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    pub(crate) fn read_u8(&mut self, field: &'static str) -> anyhow::Result<u8> {
+        let [value] = *self.read_array::<1>(field)?;
+        Ok(value)
+    }
+
+    pub(crate) fn read_array<const N: usize>(
+        &mut self,
+        field: &'static str,
+    ) -> anyhow::Result<&'a [u8; N]> {
+        let value = self.read_slice(N, field)?;
+        value
+            .try_into()
+            .map_err(|_| anyhow!("Invalid {} length", field))
+    }
+
+    /// Reads a length-prefixed byte vector, checking its length against the field schema.
+    pub(crate) fn read_vec(&mut self, field: &VecField) -> anyhow::Result<&'a [u8]> {
+        let length = u64::from_le_bytes(*self.read_array::<VEC_SIZE_PREFIX_LENGTH>(field.name)?);
+        if length > field.maximum_length as u64 {
+            bail!(
+                "Invalid {} message: maximum {} length of {} exceeded, found {}",
+                self.message,
+                field.name,
+                field.maximum_length,
+                length
+            );
+        }
+        self.read_slice(length as usize, field.name)
+    }
+
+    /// Checks that all the input was read.
+    pub(crate) fn finish(self) -> anyhow::Result<()> {
+        if !self.input.is_empty() {
+            bail!(
+                "Invalid {} message: {} unused bytes detected",
+                self.message,
+                self.input.len()
+            );
+        }
+        Ok(())
+    }
+
+    fn read_slice(&mut self, length: usize, field: &'static str) -> anyhow::Result<&'a [u8]> {
+        if self.input.len() < length {
+            bail!(
+                "Invalid {} message: expected {} bytes for {}, found {}",
+                self.message,
+                length,
+                field,
+                self.input.len()
+            );
+        }
+        let (value, rest) = self.input.split_at(length);
+        self.input = rest;
+        Ok(value)
+    }
+}
+
+/// Encodes the fields of a message.
+pub(crate) struct Writer {
+    output: Vec<u8>,
+    /// Name of the message, used in error messages.
+    message: &'static str,
+}
+
+impl Writer {
+    /// Creates a writer for a message starting with the given header, reserving `capacity` bytes
+    /// for the whole message.
+    pub(crate) fn new(message: &'static str, header: u8, capacity: usize) -> Self {
+        let mut output = Vec::with_capacity(capacity);
+        output.push(header);
+        Self { output, message }
+    }
+
+    pub(crate) fn write_u8(&mut self, value: u8) {
+        self.output.push(value);
+    }
+
+    pub(crate) fn write_array(&mut self, value: &[u8]) {
+        self.output.extend_from_slice(value);
+    }
+
+    /// Writes a length-prefixed byte vector, checking its length against the field schema.
+    pub(crate) fn write_vec(&mut self, field: &VecField, value: &[u8]) -> anyhow::Result<()> {
+        if value.len() > field.maximum_length {
+            bail!(
+                "Invalid {} message: maximum {} length of {} exceeded, found {}",
+                self.message,
+                field.name,
+                field.maximum_length,
+                value.len()
+            );
+        }
+        self.output
+            .extend_from_slice(&(value.len() as u64).to_le_bytes());
+        self.output.extend_from_slice(value);
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.output
+    }
+}
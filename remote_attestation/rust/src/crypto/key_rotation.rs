@@ -0,0 +1,220 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Rotation of the session keys, shared by all crypto implementations.
+//!
+//! Each side replaces the key used for encrypting its messages with the next generation of the key
+//! once it was used for [`KeyRotation::max_messages`] messages or [`KeyRotation::max_bytes`]
+//! bytes. The next generation of a key is derived from the current one with HKDF (see
+//! [`crate::crypto::KEY_UPDATE_PURPOSE`]), and the generation is sent in the nonce of each message
+//! (see [`crate::crypto::NonceMode::Sequential`]), so that the peer can derive the same key for
+//! decryption.
+
+use crate::crypto::{
+    derive_next_session_key, DecryptionKey, EncryptionKey, AEAD_ALGORITHM_KEY_LENGTH,
+};
+use anyhow::anyhow;
+
+/// Default number of messages encrypted with the same session key.
+pub const DEFAULT_KEY_ROTATION_MESSAGES: u64 = 1 << 24;
+
+/// Default number of bytes encrypted with the same session key.
+pub const DEFAULT_KEY_ROTATION_BYTES: u64 = 1 << 36;
+
+/// Maximum number of key generations that a received message can skip, e.g. because the
+/// preceding messages were dropped.
+pub const MAXIMUM_KEY_GENERATION_GAP: u32 = 64;
+
+/// Defines when the session key used for encrypting messages is replaced by the next generation of
+/// the key. The key is replaced once either limit is reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyRotation {
+    /// Maximum number of messages encrypted with the same key.
+    pub max_messages: u64,
+    /// Maximum number of bytes encrypted with the same key.
+    pub max_bytes: u64,
+}
+
+impl Default for KeyRotation {
+    fn default() -> Self {
+        Self {
+            max_messages: DEFAULT_KEY_ROTATION_MESSAGES,
+            max_bytes: DEFAULT_KEY_ROTATION_BYTES,
+        }
+    }
+}
+
+impl KeyRotation {
+    /// Checks that both limits are positive.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.max_messages == 0 || self.max_bytes == 0 {
+            return Err(anyhow!(
+                "Invalid key rotation: limits must be positive, found {:?}",
+                self
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Session key used for decrypting a received message.
+///
+/// The key only replaces the current decryption key once the message is authenticated (see
+/// [`SessionKeys::accept_decryption_key`]).
+pub(crate) struct PeerKey {
+    generation: u32,
+    key: [u8; AEAD_ALGORITHM_KEY_LENGTH],
+    /// Key of the preceding generation, if the key is newer than the current decryption key.
+    previous_key: Option<[u8; AEAD_ALGORITHM_KEY_LENGTH]>,
+}
+
+impl PeerKey {
+    pub(crate) fn key(&self) -> &[u8; AEAD_ALGORITHM_KEY_LENGTH] {
+        &self.key
+    }
+}
+
+/// Session keys of both directions, together with their generations.
+pub(crate) struct SessionKeys {
+    /// Key rotation applied to the encryption key, or [`None`] if keys are never rotated.
+    key_rotation: Option<KeyRotation>,
+    encryption_key: EncryptionKey,
+    encryption_key_generation: u32,
+    /// Number of messages encrypted with the current encryption key.
+    encrypted_messages: u64,
+    /// Number of bytes encrypted with the current encryption key.
+    encrypted_bytes: u64,
+    decryption_key: DecryptionKey,
+    decryption_key_generation: u32,
+    /// Decryption key of the preceding generation, which is kept for messages that were reordered
+    /// around a key rotation.
+    previous_decryption_key: Option<DecryptionKey>,
+}
+
+impl SessionKeys {
+    /// Creates session keys with the initial generation of the keys, which are rotated according to
+    /// `key_rotation`.
+    pub(crate) fn new(
+        key_rotation: Option<KeyRotation>,
+        encryption_key: EncryptionKey,
+        decryption_key: DecryptionKey,
+    ) -> anyhow::Result<Self> {
+        if let Some(key_rotation) = key_rotation {
+            key_rotation.validate()?;
+        }
+        Ok(Self {
+            key_rotation,
+            encryption_key,
+            encryption_key_generation: 0,
+            encrypted_messages: 0,
+            encrypted_bytes: 0,
+            decryption_key,
+            decryption_key_generation: 0,
+            previous_decryption_key: None,
+        })
+    }
+
+    /// Changes the key rotation applied to the following encrypted messages.
+    pub(crate) fn set_key_rotation(&mut self, key_rotation: KeyRotation) -> anyhow::Result<()> {
+        key_rotation.validate()?;
+        self.key_rotation = Some(key_rotation);
+        Ok(())
+    }
+
+    /// Returns the generation and the value of the key for encrypting a message of `length` bytes,
+    /// rotating the key if it reached the limits of [`KeyRotation`].
+    pub(crate) fn next_encryption_key(
+        &mut self,
+        length: usize,
+    ) -> anyhow::Result<(u32, [u8; AEAD_ALGORITHM_KEY_LENGTH])> {
+        if let Some(key_rotation) = self.key_rotation {
+            if self.encrypted_messages >= key_rotation.max_messages
+                || self.encrypted_bytes >= key_rotation.max_bytes
+            {
+                self.encryption_key_generation = self
+                    .encryption_key_generation
+                    .checked_add(1)
+                    .ok_or_else(|| anyhow!("Key generations exhausted"))?;
+                self.encryption_key =
+                    EncryptionKey(derive_next_session_key(&self.encryption_key.0)?);
+                self.encrypted_messages = 0;
+                self.encrypted_bytes = 0;
+            }
+        }
+        self.encrypted_messages = self.encrypted_messages.saturating_add(1);
+        self.encrypted_bytes = self.encrypted_bytes.saturating_add(length as u64);
+        Ok((self.encryption_key_generation, self.encryption_key.0))
+    }
+
+    /// Returns the key for decrypting a message that was encrypted with the key of the given
+    /// generation.
+    ///
+    /// Only the current and the preceding generation are kept, and newer generations are derived
+    /// from the current key, as long as they don't skip more than [`MAXIMUM_KEY_GENERATION_GAP`]
+    /// generations.
+    pub(crate) fn decryption_key(&self, generation: u32) -> anyhow::Result<PeerKey> {
+        let current_generation = self.decryption_key_generation;
+        if generation == current_generation {
+            return Ok(PeerKey {
+                generation,
+                key: self.decryption_key.0,
+                previous_key: None,
+            });
+        }
+        if generation < current_generation {
+            return match &self.previous_decryption_key {
+                Some(previous_key) if generation + 1 == current_generation => Ok(PeerKey {
+                    generation,
+                    key: previous_key.0,
+                    previous_key: None,
+                }),
+                _ => Err(anyhow!(
+                    "Key generation {} is no longer accepted, current generation is {}",
+                    generation,
+                    current_generation
+                )),
+            };
+        }
+        if generation - current_generation > MAXIMUM_KEY_GENERATION_GAP {
+            return Err(anyhow!(
+                "Key generation {} is too far ahead of the current generation {}",
+                generation,
+                current_generation
+            ));
+        }
+        let mut previous_key = self.decryption_key.0;
+        let mut key = derive_next_session_key(&previous_key)?;
+        for _ in current_generation + 1..generation {
+            previous_key = key;
+            key = derive_next_session_key(&key)?;
+        }
+        Ok(PeerKey {
+            generation,
+            key,
+            previous_key: Some(previous_key),
+        })
+    }
+
+    /// Records a key returned by [`SessionKeys::decryption_key`] that authenticated a message,
+    /// which replaces the current decryption key if it is newer.
+    pub(crate) fn accept_decryption_key(&mut self, peer_key: PeerKey) {
+        if let Some(previous_key) = peer_key.previous_key {
+            self.decryption_key_generation = peer_key.generation;
+            self.decryption_key = DecryptionKey(peer_key.key);
+            self.previous_decryption_key = Some(DecryptionKey(previous_key));
+        }
+    }
+}
@@ -34,6 +34,9 @@ pub use rust_crypto::{
     get_random, get_sha256, AeadEncryptor, KeyNegotiator, SignatureVerifier, Signer,
 };
 
+#[cfg(all(feature = "rust-crypto", not(feature = "ring-crypto")))]
+pub(crate) use rust_crypto::derive_next_session_key;
+
 #[cfg(feature = "ring-crypto")]
 mod ring_crypto;
 
@@ -42,8 +45,25 @@ pub use ring_crypto::{
     get_random, get_sha256, AeadEncryptor, KeyNegotiator, SignatureVerifier, Signer,
 };
 
+#[cfg(feature = "ring-crypto")]
+pub(crate) use ring_crypto::derive_next_session_key;
+
+mod key_rotation;
+mod nonce;
+
+pub(crate) use key_rotation::SessionKeys;
+pub use key_rotation::{
+    KeyRotation, DEFAULT_KEY_ROTATION_BYTES, DEFAULT_KEY_ROTATION_MESSAGES,
+    MAXIMUM_KEY_GENERATION_GAP,
+};
+
+#[cfg(test)]
+pub(crate) use nonce::sequential_nonce;
+pub(crate) use nonce::MessageNonces;
+pub use nonce::{NonceMode, ReplayProtection, MAXIMUM_REPLAY_WINDOW_SIZE};
+
 /// Length of the encryption nonce.
-/// `ring::aead` uses 96-bit (12-byte) nonces.
+/// `ring::aead` uses 96-bit (12-byte) nonces for all supported AEAD algorithms.
 /// <https://briansmith.org/rustdoc/ring/aead/constant.NONCE_LEN.html>
 pub const NONCE_LENGTH: usize = 12;
 pub const SHA256_HASH_LENGTH: usize = 32;
@@ -56,6 +76,10 @@ pub const KEY_DERIVATION_SALT: &str = "Remote Attestation Protocol v1";
 pub const SERVER_KEY_PURPOSE: &str = "Remote Attestation Protocol Server Session Key";
 /// Purpose string used for deriving client session keys with HKDF.
 pub const CLIENT_KEY_PURPOSE: &str = "Remote Attestation Protocol Client Session Key";
+/// Purpose string used for deriving the next generation of a session key with HKDF.
+pub const KEY_UPDATE_PURPOSE: &str = "Remote Attestation Protocol Key Update";
+/// Purpose string used for deriving the secret for resuming a session with HKDF.
+pub const RESUMPTION_SECRET_PURPOSE: &str = "Remote Attestation Protocol Resumption Secret";
 /// OpenSSL ECDSA-P256 key public key length, which is represented as
 /// `0x04 | X: 32-byte | Y: 32-byte`.
 /// Where X and Y are big-endian coordinates of an Elliptic Curve point.
@@ -67,6 +91,49 @@ pub const SIGNING_ALGORITHM_KEY_LENGTH: usize = 65;
 /// <https://standards.ieee.org/standard/1363-2000.html>
 pub const SIGNATURE_LENGTH: usize = 64;
 
+/// Cipher suites that can be negotiated in the remote attestation handshake.
+///
+/// All cipher suites use X25519 for key agreement, HKDF-SHA256 for deriving session keys and
+/// ECDSA-P256 for signing transcripts, and only differ in the AEAD algorithm used for encrypting
+/// messages with the session keys. All AEAD algorithms use keys of [`AEAD_ALGORITHM_KEY_LENGTH`]
+/// and nonces of [`NONCE_LENGTH`] bytes.
+///
+/// Cipher suites are identified by a single byte in handshake messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CipherSuite {
+    /// X25519, HKDF-SHA256, AES-256-GCM and ECDSA-P256.
+    ///
+    /// <https://datatracker.ietf.org/doc/html/rfc5288>
+    Aes256Gcm = 1,
+    /// X25519, HKDF-SHA256, ChaCha20-Poly1305 and ECDSA-P256.
+    ///
+    /// <https://datatracker.ietf.org/doc/html/rfc8439>
+    ChaCha20Poly1305 = 2,
+}
+
+impl CipherSuite {
+    /// Returns the identifier of the cipher suite used in handshake messages.
+    pub const fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_id(id: u8) -> anyhow::Result<Self> {
+        match id {
+            1 => Ok(Self::Aes256Gcm),
+            2 => Ok(Self::ChaCha20Poly1305),
+            id => Err(anyhow::anyhow!("Unsupported cipher suite: {:#04x}", id)),
+        }
+    }
+}
+
+/// Cipher suite used by the legacy version of the protocol, which does not support negotiation.
+pub const LEGACY_CIPHER_SUITE: CipherSuite = CipherSuite::Aes256Gcm;
+
+/// Cipher suites supported by this implementation, in the order of preference.
+pub const SUPPORTED_CIPHER_SUITES: &[CipherSuite] =
+    &[CipherSuite::Aes256Gcm, CipherSuite::ChaCha20Poly1305];
+
 /// Defines the type of key negotiator and the set of session keys created by it.
 #[derive(Clone)]
 pub enum KeyNegotiatorType {
@@ -85,3 +152,8 @@ pub(crate) struct EncryptionKey(pub(crate) [u8; KEY_AGREEMENT_ALGORITHM_KEY_LENG
 /// Convenience struct for passing a decryption key as an argument.
 #[derive(PartialEq)]
 pub(crate) struct DecryptionKey(pub(crate) [u8; KEY_AGREEMENT_ALGORITHM_KEY_LENGTH]);
+
+/// Secret shared by both sides of a session, which is used for deriving the session keys of
+/// resumed sessions.
+#[derive(Clone, PartialEq)]
+pub(crate) struct ResumptionSecret(pub(crate) [u8; KEY_AGREEMENT_ALGORITHM_KEY_LENGTH]);
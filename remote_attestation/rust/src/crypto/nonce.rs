@@ -0,0 +1,267 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Generation and checking of the nonces of encrypted messages, shared by all crypto
+//! implementations.
+
+use crate::crypto::{get_random, NONCE_LENGTH};
+use anyhow::anyhow;
+
+/// Length (in bytes) of the key generation at the start of a sequential nonce.
+const KEY_GENERATION_LENGTH: usize = 4;
+
+/// Length (in bytes) of the sequence number at the end of a sequential nonce.
+const SEQUENCE_NUMBER_LENGTH: usize = 8;
+
+/// Maximum size of [`ReplayProtection::SlidingWindow`].
+pub const MAXIMUM_REPLAY_WINDOW_SIZE: u8 = 64;
+
+/// Defines how nonces of encrypted messages are generated and checked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NonceMode {
+    /// Random nonces, which are not checked on decryption.
+    ///
+    /// Only used by the legacy protocol version, since it does not protect against replayed
+    /// messages.
+    Random,
+    /// Nonces derived from the sequence number of each message, which is counted separately in
+    /// each direction, and checked on decryption.
+    ///
+    /// The nonce consists of the big-endian 32-bit generation of the session key (see
+    /// [`crate::crypto::KeyRotation`]) followed by the big-endian 64-bit sequence number. Since
+    /// each direction uses a different session key, a nonce is never used twice with the same key.
+    Sequential(ReplayProtection),
+}
+
+/// Defines which sequence numbers are accepted for received messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayProtection {
+    /// Messages must be received in the order they were sent, without gaps. Replayed, reordered
+    /// and dropped messages are rejected.
+    Strict,
+    /// Messages may be received out of order and with gaps, as long as they are not older than the
+    /// given number of messages relative to the newest received message. Each sequence number is
+    /// only accepted once, so replayed messages are rejected.
+    ///
+    /// The size must be between 1 and [`MAXIMUM_REPLAY_WINDOW_SIZE`].
+    SlidingWindow(u8),
+}
+
+impl ReplayProtection {
+    /// Checks that the replay protection parameters are within the supported range.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match self {
+            Self::Strict => Ok(()),
+            Self::SlidingWindow(size) if (1..=MAXIMUM_REPLAY_WINDOW_SIZE).contains(size) => Ok(()),
+            Self::SlidingWindow(size) => Err(anyhow!(
+                "Invalid replay window size: expected between 1 and {}, found {}",
+                MAXIMUM_REPLAY_WINDOW_SIZE,
+                size
+            )),
+        }
+    }
+}
+
+/// Returns the nonce for the given key generation and sequence number.
+pub(crate) fn sequential_nonce(key_generation: u32, sequence_number: u64) -> [u8; NONCE_LENGTH] {
+    let mut nonce = [0; NONCE_LENGTH];
+    nonce[..KEY_GENERATION_LENGTH].copy_from_slice(&key_generation.to_be_bytes());
+    nonce[KEY_GENERATION_LENGTH..].copy_from_slice(&sequence_number.to_be_bytes());
+    nonce
+}
+
+/// Returns the key generation encoded in the given sequential nonce.
+fn key_generation(nonce: &[u8; NONCE_LENGTH]) -> u32 {
+    let mut bytes = [0; KEY_GENERATION_LENGTH];
+    bytes.copy_from_slice(&nonce[..KEY_GENERATION_LENGTH]);
+    u32::from_be_bytes(bytes)
+}
+
+/// Returns the sequence number encoded in the given sequential nonce.
+fn sequence_number(nonce: &[u8; NONCE_LENGTH]) -> u64 {
+    let mut bytes = [0; SEQUENCE_NUMBER_LENGTH];
+    bytes.copy_from_slice(&nonce[KEY_GENERATION_LENGTH..]);
+    u64::from_be_bytes(bytes)
+}
+
+/// Keeps track of the sequence numbers of received messages.
+struct ReplayWindow {
+    replay_protection: ReplayProtection,
+    /// Highest sequence number accepted so far, if any.
+    highest: Option<u64>,
+    /// Bitmap of the accepted sequence numbers below `highest`, where bit `i` corresponds to
+    /// sequence number `highest - i`.
+    accepted: u64,
+}
+
+impl ReplayWindow {
+    fn new(replay_protection: ReplayProtection) -> Self {
+        Self {
+            replay_protection,
+            highest: None,
+            accepted: 0,
+        }
+    }
+
+    fn check(&self, sequence_number: u64) -> anyhow::Result<()> {
+        let next = match self.highest {
+            Some(highest) => highest
+                .checked_add(1)
+                .ok_or_else(|| anyhow!("Sequence numbers exhausted"))?,
+            None => 0,
+        };
+        match self.replay_protection {
+            ReplayProtection::Strict if sequence_number == next => Ok(()),
+            ReplayProtection::Strict => Err(anyhow!(
+                "Unexpected sequence number: expected {}, found {}",
+                next,
+                sequence_number
+            )),
+            ReplayProtection::SlidingWindow(_) if sequence_number >= next => Ok(()),
+            ReplayProtection::SlidingWindow(size) => {
+                // `next > sequence_number` here, so `highest` is set.
+                let offset = next - 1 - sequence_number;
+                if offset >= size as u64 {
+                    Err(anyhow!(
+                        "Sequence number {} is outside of the replay window",
+                        sequence_number
+                    ))
+                } else if self.accepted & (1 << offset) != 0 {
+                    Err(anyhow!(
+                        "Sequence number {} was already received",
+                        sequence_number
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Records a sequence number that was checked with [`ReplayWindow::check`], and whose message
+    /// was successfully authenticated.
+    fn accept(&mut self, sequence_number: u64) {
+        match self.highest {
+            Some(highest) if sequence_number <= highest => {
+                self.accepted |= 1 << (highest - sequence_number);
+            }
+            Some(highest) => {
+                let shift = sequence_number - highest;
+                self.accepted = if shift >= 64 {
+                    0
+                } else {
+                    self.accepted << shift
+                } | 1;
+                self.highest = Some(sequence_number);
+            }
+            None => {
+                self.accepted = 1;
+                self.highest = Some(sequence_number);
+            }
+        }
+    }
+}
+
+/// Generates the nonces of sent messages and checks the nonces of received messages.
+pub(crate) struct MessageNonces {
+    mode: NonceMode,
+    /// Sequence number of the next sent message.
+    next_sent: u64,
+    received: ReplayWindow,
+}
+
+impl MessageNonces {
+    pub(crate) fn new(mode: NonceMode) -> anyhow::Result<Self> {
+        let replay_protection = match mode {
+            NonceMode::Random => ReplayProtection::Strict,
+            NonceMode::Sequential(replay_protection) => {
+                replay_protection.validate()?;
+                replay_protection
+            }
+        };
+        Ok(Self {
+            mode,
+            next_sent: 0,
+            received: ReplayWindow::new(replay_protection),
+        })
+    }
+
+    /// Changes the replay protection applied to the following received messages.
+    ///
+    /// Has no effect in [`NonceMode::Random`].
+    pub(crate) fn set_replay_protection(
+        &mut self,
+        replay_protection: ReplayProtection,
+    ) -> anyhow::Result<()> {
+        replay_protection.validate()?;
+        if let NonceMode::Sequential(_) = self.mode {
+            self.mode = NonceMode::Sequential(replay_protection);
+            self.received.replay_protection = replay_protection;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the nonces allow the session keys to be rotated, which is only the case
+    /// for [`NonceMode::Sequential`].
+    pub(crate) fn supports_key_rotation(&self) -> bool {
+        matches!(self.mode, NonceMode::Sequential(_))
+    }
+
+    /// Returns the nonce for the next sent message, which is encrypted with the session key of the
+    /// given generation.
+    ///
+    /// The key generation is ignored in [`NonceMode::Random`].
+    pub(crate) fn next_nonce(&mut self, key_generation: u32) -> anyhow::Result<[u8; NONCE_LENGTH]> {
+        match self.mode {
+            NonceMode::Random => get_random(),
+            NonceMode::Sequential(_) => {
+                let nonce = sequential_nonce(key_generation, self.next_sent);
+                self.next_sent = self
+                    .next_sent
+                    .checked_add(1)
+                    .ok_or_else(|| anyhow!("Sequence numbers exhausted"))?;
+                Ok(nonce)
+            }
+        }
+    }
+
+    /// Checks whether a received message with the given nonce can be accepted.
+    ///
+    /// The nonce must only be recorded with [`MessageNonces::accept`] after the message is
+    /// authenticated, so that forged messages cannot move the replay window.
+    pub(crate) fn check(&self, nonce: &[u8; NONCE_LENGTH]) -> anyhow::Result<()> {
+        match self.mode {
+            NonceMode::Random => Ok(()),
+            NonceMode::Sequential(_) => self.received.check(sequence_number(nonce)),
+        }
+    }
+
+    /// Returns the generation of the session key that was used to encrypt a received message with
+    /// the given nonce, which is always 0 in [`NonceMode::Random`].
+    pub(crate) fn key_generation(&self, nonce: &[u8; NONCE_LENGTH]) -> u32 {
+        match self.mode {
+            NonceMode::Random => 0,
+            NonceMode::Sequential(_) => key_generation(nonce),
+        }
+    }
+
+    /// Records the nonce of an authenticated received message.
+    pub(crate) fn accept(&mut self, nonce: &[u8; NONCE_LENGTH]) {
+        if let NonceMode::Sequential(_) = self.mode {
+            self.received.accept(sequence_number(nonce));
+        }
+    }
+}
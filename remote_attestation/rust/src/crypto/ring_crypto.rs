@@ -18,9 +18,11 @@
 
 use crate::{
     crypto::{
-        DecryptionKey, EncryptionKey, KeyNegotiatorType, AEAD_ALGORITHM_KEY_LENGTH,
-        CLIENT_KEY_PURPOSE, KEY_AGREEMENT_ALGORITHM_KEY_LENGTH, KEY_DERIVATION_SALT, NONCE_LENGTH,
-        SERVER_KEY_PURPOSE, SHA256_HASH_LENGTH, SIGNATURE_LENGTH, SIGNING_ALGORITHM_KEY_LENGTH,
+        CipherSuite, DecryptionKey, EncryptionKey, KeyNegotiatorType, KeyRotation, MessageNonces,
+        NonceMode, ReplayProtection, ResumptionSecret, SessionKeys, AEAD_ALGORITHM_KEY_LENGTH,
+        CLIENT_KEY_PURPOSE, KEY_AGREEMENT_ALGORITHM_KEY_LENGTH, KEY_DERIVATION_SALT,
+        KEY_UPDATE_PURPOSE, NONCE_LENGTH, RESUMPTION_SECRET_PURPOSE, SERVER_KEY_PURPOSE,
+        SHA256_HASH_LENGTH, SIGNATURE_LENGTH, SIGNING_ALGORITHM_KEY_LENGTH,
     },
     message::EncryptedData,
 };
@@ -36,9 +38,24 @@ use ring::{
     signature::{EcdsaKeyPair, EcdsaSigningAlgorithm, EcdsaVerificationAlgorithm, KeyPair},
 };
 
-/// Algorithm used for encrypting/decrypting messages.
-/// <https://datatracker.ietf.org/doc/html/rfc5288>
-static AEAD_ALGORITHM: &aead::Algorithm = &aead::AES_256_GCM;
+/// Returns the algorithm used for encrypting/decrypting messages with the given cipher suite.
+fn aead_algorithm(cipher_suite: CipherSuite) -> &'static aead::Algorithm {
+    match cipher_suite {
+        // <https://datatracker.ietf.org/doc/html/rfc5288>
+        CipherSuite::Aes256Gcm => &aead::AES_256_GCM,
+        // <https://datatracker.ietf.org/doc/html/rfc8439>
+        CipherSuite::ChaCha20Poly1305 => &aead::CHACHA20_POLY1305,
+    }
+}
+
+/// Length of the keys derived with HKDF, which are used as session keys by all cipher suites.
+struct SessionKeyLength;
+
+impl ring::hkdf::KeyType for SessionKeyLength {
+    fn len(&self) -> usize {
+        AEAD_ALGORITHM_KEY_LENGTH
+    }
+}
 
 /// Algorithm used for negotiating a session key.
 /// <https://datatracker.ietf.org/doc/html/rfc7748>
@@ -63,28 +80,68 @@ static VERIFICATION_ALGORITHM: &EcdsaVerificationAlgorithm =
 /// It is necessary to prevent the Loopback Attack, where malicious network takes an outgoing packet
 /// and feeds it back as an incoming packet.
 pub struct AeadEncryptor {
-    /// Key used for encrypting data.
-    encryption_key: EncryptionKey,
-    /// Key used for decrypting peer encrypted data.
-    decryption_key: DecryptionKey,
+    /// Cipher suite defining the AEAD algorithm.
+    cipher_suite: CipherSuite,
+    /// Nonces of sent and received messages.
+    nonces: MessageNonces,
+    /// Keys used for encrypting data and decrypting peer encrypted data.
+    keys: SessionKeys,
 }
 
 impl AeadEncryptor {
-    pub(crate) fn new(encryption_key: EncryptionKey, decryption_key: DecryptionKey) -> Self {
-        Self {
-            encryption_key,
-            decryption_key,
+    pub(crate) fn new(
+        cipher_suite: CipherSuite,
+        nonce_mode: NonceMode,
+        encryption_key: EncryptionKey,
+        decryption_key: DecryptionKey,
+    ) -> anyhow::Result<Self> {
+        let nonces = MessageNonces::new(nonce_mode)?;
+        // Key generations are sent in the nonces, so keys can only be rotated with sequential
+        // nonces.
+        let key_rotation = nonces.supports_key_rotation().then(KeyRotation::default);
+        Ok(Self {
+            cipher_suite,
+            nonces,
+            keys: SessionKeys::new(key_rotation, encryption_key, decryption_key)?,
+        })
+    }
+
+    /// Changes the replay protection applied to the following decrypted messages.
+    ///
+    /// Has no effect if the encryptor uses [`NonceMode::Random`].
+    pub fn set_replay_protection(
+        &mut self,
+        replay_protection: ReplayProtection,
+    ) -> anyhow::Result<()> {
+        self.nonces.set_replay_protection(replay_protection)
+    }
+
+    /// Changes when the key used for encrypting the following messages is rotated.
+    ///
+    /// Has no effect if the encryptor uses [`NonceMode::Random`], which never rotates keys.
+    pub fn set_key_rotation(&mut self, key_rotation: KeyRotation) -> anyhow::Result<()> {
+        if !self.nonces.supports_key_rotation() {
+            return Ok(());
         }
+        self.keys.set_key_rotation(key_rotation)
     }
 
-    /// Encrypts `data` using `AeadEncryptor::encryption_key`.
+    /// Encrypts `data` using the current encryption key, which is rotated according to
+    /// [`KeyRotation`].
     pub fn encrypt(&mut self, data: &[u8]) -> anyhow::Result<EncryptedData> {
-        // Generate a random nonce.
-        let nonce = Self::generate_nonce().context("Couldn't generate nonce")?;
-
-        // Bind [`AeadEncryptor::key`] to a `nonce`.
-        let unbound_sealing_key = aead::UnboundKey::new(AEAD_ALGORITHM, &self.encryption_key.0)
-            .map_err(|error| anyhow!("Couldn't create sealing key: {:?}", error))?;
+        let (key_generation, encryption_key) = self
+            .keys
+            .next_encryption_key(data.len())
+            .context("Couldn't get encryption key")?;
+        let nonce = self
+            .nonces
+            .next_nonce(key_generation)
+            .context("Couldn't generate nonce")?;
+
+        // Bind the encryption key to a `nonce`.
+        let unbound_sealing_key =
+            aead::UnboundKey::new(aead_algorithm(self.cipher_suite), &encryption_key)
+                .map_err(|error| anyhow!("Couldn't create sealing key: {:?}", error))?;
         let mut sealing_key =
             ring::aead::SealingKey::new(unbound_sealing_key, OneNonceSequence::new(nonce));
 
@@ -101,13 +158,22 @@ impl AeadEncryptor {
         Ok(EncryptedData::new(nonce, encrypted_data))
     }
 
-    /// Decrypts and authenticates `data` using `AeadEncryptor::decryption_key`.
-    /// `data` must contain an encrypted message prefixed with a random nonce of [`NONCE_LENGTH`]
-    /// length.
+    /// Decrypts and authenticates `data` using the decryption key of the generation specified by
+    /// the nonce.
+    /// `data` must contain an encrypted message prefixed with a nonce of [`NONCE_LENGTH`] length,
+    /// which is rejected if it was already received or is out of order (see [`NonceMode`]).
     pub fn decrypt(&mut self, data: &EncryptedData) -> anyhow::Result<Vec<u8>> {
-        // Bind `AeadEncryptor::key` to the extracted `nonce`.
+        self.nonces
+            .check(&data.nonce)
+            .context("Couldn't check nonce")?;
+        let decryption_key = self
+            .keys
+            .decryption_key(self.nonces.key_generation(&data.nonce))
+            .context("Couldn't get decryption key")?;
+        // Bind the decryption key to the extracted `nonce`.
         let unbound_opening_key =
-            aead::UnboundKey::new(AEAD_ALGORITHM, &self.decryption_key.0).unwrap();
+            aead::UnboundKey::new(aead_algorithm(self.cipher_suite), decryption_key.key())
+                .map_err(|error| anyhow!("Couldn't create opening key: {:?}", error))?;
         let mut opening_key =
             ring::aead::OpeningKey::new(unbound_opening_key, OneNonceSequence::new(data.nonce));
 
@@ -120,13 +186,35 @@ impl AeadEncryptor {
             // https://datatracker.ietf.org/doc/html/rfc5116#section-2.1
             .open_in_place(aead::Aad::empty(), &mut decrypted_data)
             .map_err(|error| anyhow!("Couldn't decrypt data: {:?}", error))?;
+        // Only record the nonce and the key after the message is authenticated.
+        self.nonces.accept(&data.nonce);
+        self.keys.accept_decryption_key(decryption_key);
         Ok(decrypted_data.to_vec())
     }
+}
 
-    /// Generate a random nonce.
-    fn generate_nonce() -> anyhow::Result<[u8; NONCE_LENGTH]> {
-        get_random()
-    }
+/// Derives the next generation of a session key using HKDF.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc5869>
+///
+/// The current key is used as the input key material, and [`KEY_UPDATE_PURPOSE`] as the
+/// information string.
+pub(crate) fn derive_next_session_key(
+    key: &[u8; AEAD_ALGORITHM_KEY_LENGTH],
+) -> anyhow::Result<[u8; AEAD_ALGORITHM_KEY_LENGTH]> {
+    let salt = Salt::new(HKDF_SHA256, KEY_DERIVATION_SALT.as_bytes());
+    let kdf = salt.extract(key);
+    let mut next_key: [u8; AEAD_ALGORITHM_KEY_LENGTH] = Default::default();
+    kdf.expand(&[KEY_UPDATE_PURPOSE.as_bytes()], SessionKeyLength)
+        .map_err(|error| anyhow!("Couldn't run HKDF-Expand operation : {:?}", error))?
+        .fill(&mut next_key)
+        .map_err(|error| {
+            anyhow!(
+                "Couldn't get the output of the HKDF-Expand operation: {:?}",
+                error
+            )
+        })?;
+    Ok(next_key)
 }
 
 /// Nonce implementation used by [`AeadEncryptor`].
@@ -180,7 +268,8 @@ impl KeyNegotiator {
             ))
     }
 
-    /// Derives session keys from self and peer public keys and creates an [`AeadEncryptor`].
+    /// Derives session keys from self and peer public keys and creates an [`AeadEncryptor`] for
+    /// the negotiated `cipher_suite`, which uses the given `nonce_mode`.
     ///
     /// HKDF is used to derive both server and client session keys. The information string provided
     /// to HKDF consists of a purpose string, a server public key and a client public key (in that
@@ -193,27 +282,49 @@ impl KeyNegotiator {
     pub fn create_encryptor(
         self,
         peer_public_key: &[u8; KEY_AGREEMENT_ALGORITHM_KEY_LENGTH],
+        cipher_suite: CipherSuite,
+        nonce_mode: NonceMode,
     ) -> anyhow::Result<AeadEncryptor> {
-        let (encryption_key, decryption_key) = self
+        let (encryptor, _) = self.create_encryptor_with_resumption_secret(
+            peer_public_key,
+            cipher_suite,
+            nonce_mode,
+        )?;
+        Ok(encryptor)
+    }
+
+    /// Creates an [`AeadEncryptor`] like [`KeyNegotiator::create_encryptor`], and also returns
+    /// the secret used for resuming the session with a session ticket.
+    ///
+    /// The resumption secret is derived with HKDF in the same way as session keys, using the
+    /// [`RESUMPTION_SECRET_PURPOSE`] purpose string.
+    pub(crate) fn create_encryptor_with_resumption_secret(
+        self,
+        peer_public_key: &[u8; KEY_AGREEMENT_ALGORITHM_KEY_LENGTH],
+        cipher_suite: CipherSuite,
+        nonce_mode: NonceMode,
+    ) -> anyhow::Result<(AeadEncryptor, ResumptionSecret)> {
+        let (encryption_key, decryption_key, resumption_secret) = self
             .derive_session_keys(peer_public_key)
             .context("Couldn't derive session keys")?;
-        let encryptor = AeadEncryptor::new(encryption_key, decryption_key);
-        Ok(encryptor)
+        let encryptor =
+            AeadEncryptor::new(cipher_suite, nonce_mode, encryption_key, decryption_key)?;
+        Ok((encryptor, resumption_secret))
     }
 
     /// Implementation of the session keys derivation.
-    /// Returns a tuple with an encryption key and a decryption key.
+    /// Returns a tuple with an encryption key, a decryption key and a resumption secret.
     pub(crate) fn derive_session_keys(
         self,
         peer_public_key: &[u8; KEY_AGREEMENT_ALGORITHM_KEY_LENGTH],
-    ) -> anyhow::Result<(EncryptionKey, DecryptionKey)> {
+    ) -> anyhow::Result<(EncryptionKey, DecryptionKey, ResumptionSecret)> {
         let type_ = self.type_.clone();
         let self_public_key = self.public_key().context("Couldn't get self public key")?;
         agreement::agree_ephemeral(
             self.private_key,
             &agreement::UnparsedPublicKey::new(KEY_AGREEMENT_ALGORITHM, peer_public_key),
             anyhow!("Couldn't derive session keys"),
-            |key_material| -> anyhow::Result<(EncryptionKey, DecryptionKey)> {
+            |key_material| -> anyhow::Result<(EncryptionKey, DecryptionKey, ResumptionSecret)> {
                 let key_material = key_material
                     .try_into()
                     .map_err(anyhow::Error::msg)
@@ -244,7 +355,16 @@ impl KeyNegotiator {
                             )
                             .context("Couldn't derive encryption key")?,
                         );
-                        Ok((encryption_key, decryption_key))
+                        let resumption_secret = ResumptionSecret(
+                            Self::key_derivation_function(
+                                key_material,
+                                RESUMPTION_SECRET_PURPOSE,
+                                &self_public_key,
+                                &peer_public_key,
+                            )
+                            .context("Couldn't derive resumption secret")?,
+                        );
+                        Ok((encryption_key, decryption_key, resumption_secret))
                     }
                     // On the client side `peer_public_key` is the server key.
                     KeyNegotiatorType::Client => {
@@ -266,7 +386,16 @@ impl KeyNegotiator {
                             )
                             .context("Couldn't derive encryption key")?,
                         );
-                        Ok((encryption_key, decryption_key))
+                        let resumption_secret = ResumptionSecret(
+                            Self::key_derivation_function(
+                                key_material,
+                                RESUMPTION_SECRET_PURPOSE,
+                                &peer_public_key,
+                                &self_public_key,
+                            )
+                            .context("Couldn't derive resumption secret")?,
+                        );
+                        Ok((encryption_key, decryption_key, resumption_secret))
                     }
                 }
             },
@@ -299,7 +428,7 @@ impl KeyNegotiator {
         // Derive session key.
         let mut session_key: [u8; AEAD_ALGORITHM_KEY_LENGTH] = Default::default();
         let output_key_material = kdf
-            .expand(&info, AEAD_ALGORITHM)
+            .expand(&info, SessionKeyLength)
             .map_err(|error| anyhow!("Couldn't run HKDF-Expand operation : {:?}", error))?;
         output_key_material
             .fill(&mut session_key)
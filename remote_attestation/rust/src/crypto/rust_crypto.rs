@@ -19,18 +19,21 @@
 
 use crate::{
     crypto::{
-        DecryptionKey, EncryptionKey, KeyNegotiatorType, AEAD_ALGORITHM_KEY_LENGTH,
-        CLIENT_KEY_PURPOSE, KEY_AGREEMENT_ALGORITHM_KEY_LENGTH, KEY_DERIVATION_SALT, NONCE_LENGTH,
-        SERVER_KEY_PURPOSE, SHA256_HASH_LENGTH, SIGNATURE_LENGTH, SIGNING_ALGORITHM_KEY_LENGTH,
+        CipherSuite, DecryptionKey, EncryptionKey, KeyNegotiatorType, KeyRotation, MessageNonces,
+        NonceMode, ReplayProtection, ResumptionSecret, SessionKeys, AEAD_ALGORITHM_KEY_LENGTH,
+        CLIENT_KEY_PURPOSE, KEY_AGREEMENT_ALGORITHM_KEY_LENGTH, KEY_DERIVATION_SALT,
+        KEY_UPDATE_PURPOSE, NONCE_LENGTH, RESUMPTION_SECRET_PURPOSE, SERVER_KEY_PURPOSE,
+        SHA256_HASH_LENGTH, SIGNATURE_LENGTH, SIGNING_ALGORITHM_KEY_LENGTH,
     },
     message::EncryptedData,
 };
 use aes_gcm::{
-    aead::{AeadInPlace, NewAead},
-    Aes256Gcm, Key, Nonce,
+    aead::{AeadInPlace, NewAead, Nonce},
+    Aes256Gcm,
 };
 use alloc::vec::Vec;
 use anyhow::{anyhow, Context};
+use chacha20poly1305::ChaCha20Poly1305;
 use core::convert::TryInto;
 use hkdf::Hkdf;
 use p256::{
@@ -57,68 +60,158 @@ const EMPTY_ADITIONAL_DATA: &[u8] = b"";
 /// It is necessary to prevent the Loopback Attack, where malicious network takes an outgoing packet
 /// and feeds it back as an incoming packet.
 pub struct AeadEncryptor {
-    /// Key used for encrypting data.
-    encryption_key: EncryptionKey,
-    /// Key used for decrypting peer encrypted data.
-    decryption_key: DecryptionKey,
+    /// Cipher suite defining the AEAD algorithm.
+    cipher_suite: CipherSuite,
+    /// Nonces of sent and received messages.
+    nonces: MessageNonces,
+    /// Keys used for encrypting data and decrypting peer encrypted data.
+    keys: SessionKeys,
 }
 
 impl AeadEncryptor {
-    pub(crate) fn new(encryption_key: EncryptionKey, decryption_key: DecryptionKey) -> Self {
-        Self {
-            encryption_key,
-            decryption_key,
+    pub(crate) fn new(
+        cipher_suite: CipherSuite,
+        nonce_mode: NonceMode,
+        encryption_key: EncryptionKey,
+        decryption_key: DecryptionKey,
+    ) -> anyhow::Result<Self> {
+        let nonces = MessageNonces::new(nonce_mode)?;
+        // Key generations are sent in the nonces, so keys can only be rotated with sequential
+        // nonces.
+        let key_rotation = nonces.supports_key_rotation().then(KeyRotation::default);
+        Ok(Self {
+            cipher_suite,
+            nonces,
+            keys: SessionKeys::new(key_rotation, encryption_key, decryption_key)?,
+        })
+    }
+
+    /// Changes the replay protection applied to the following decrypted messages.
+    ///
+    /// Has no effect if the encryptor uses [`NonceMode::Random`].
+    pub fn set_replay_protection(
+        &mut self,
+        replay_protection: ReplayProtection,
+    ) -> anyhow::Result<()> {
+        self.nonces.set_replay_protection(replay_protection)
+    }
+
+    /// Changes when the key used for encrypting the following messages is rotated.
+    ///
+    /// Has no effect if the encryptor uses [`NonceMode::Random`], which never rotates keys.
+    pub fn set_key_rotation(&mut self, key_rotation: KeyRotation) -> anyhow::Result<()> {
+        if !self.nonces.supports_key_rotation() {
+            return Ok(());
         }
+        self.keys.set_key_rotation(key_rotation)
     }
 
-    /// Encrypts `data` using `AeadEncryptor::encryption_key`.
+    /// Encrypts `data` using the current encryption key, which is rotated according to
+    /// [`KeyRotation`].
     pub fn encrypt(&mut self, data: &[u8]) -> anyhow::Result<EncryptedData> {
-        // Generate a random nonce.
-        let nonce = Self::generate_nonce().context("Couldn't generate nonce")?;
-        let cipher = Aes256Gcm::new(Key::from_slice(&self.encryption_key.0));
+        let (key_generation, encryption_key) = self
+            .keys
+            .next_encryption_key(data.len())
+            .context("Couldn't get encryption key")?;
+        let nonce = self
+            .nonces
+            .next_nonce(key_generation)
+            .context("Couldn't generate nonce")?;
 
         let mut encrypted_data = data.to_vec();
-        // Additional authenticated data is not required for the remotely attested channel,
-        // since after session key is established client and server exchange messages with a
-        // single encrypted field.
-        // And the nonce is authenticated by the AEAD algorithm itself.
-        // https://datatracker.ietf.org/doc/html/rfc5116#section-2.1
-        cipher
-            .encrypt_in_place(
-                Nonce::from_slice(&nonce),
-                EMPTY_ADITIONAL_DATA,
-                &mut encrypted_data,
-            )
-            .map_err(|error| anyhow!("Couldn't encrypt data: {:?}", error))?;
+        match self.cipher_suite {
+            CipherSuite::Aes256Gcm => {
+                seal::<Aes256Gcm>(&encryption_key, &nonce, &mut encrypted_data)
+            }
+            CipherSuite::ChaCha20Poly1305 => {
+                seal::<ChaCha20Poly1305>(&encryption_key, &nonce, &mut encrypted_data)
+            }
+        }?;
 
         Ok(EncryptedData::new(nonce, encrypted_data))
     }
 
-    /// Decrypts and authenticates `data` using `AeadEncryptor::decryption_key`.
-    /// `data` must contain an encrypted message prefixed with a random nonce of [`NONCE_LENGTH`]
-    /// length.
+    /// Decrypts and authenticates `data` using the decryption key of the generation specified by
+    /// the nonce.
+    /// `data` must contain an encrypted message prefixed with a nonce of [`NONCE_LENGTH`] length,
+    /// which is rejected if it was already received or is out of order (see [`NonceMode`]).
     pub fn decrypt(&mut self, data: &EncryptedData) -> anyhow::Result<Vec<u8>> {
-        let cipher = Aes256Gcm::new(Key::from_slice(&self.decryption_key.0));
+        self.nonces
+            .check(&data.nonce)
+            .context("Couldn't check nonce")?;
+        let decryption_key = self
+            .keys
+            .decryption_key(self.nonces.key_generation(&data.nonce))
+            .context("Couldn't get decryption key")?;
         let mut decrypted_data = data.data.to_vec();
-        // Additional authenticated data is not required for the remotely attested channel,
-        // since after session key is established client and server exchange messages with a
-        // single encrypted field.
-        // And the nonce is authenticated by the AEAD algorithm itself.
-        // https://datatracker.ietf.org/doc/html/rfc5116#section-2.1
-        cipher
-            .decrypt_in_place(
-                Nonce::from_slice(&data.nonce),
-                EMPTY_ADITIONAL_DATA,
-                &mut decrypted_data,
-            )
-            .map_err(|error| anyhow!("Couldn't decrypt data: {:?}", error))?;
+        match self.cipher_suite {
+            CipherSuite::Aes256Gcm => {
+                open::<Aes256Gcm>(decryption_key.key(), &data.nonce, &mut decrypted_data)
+            }
+            CipherSuite::ChaCha20Poly1305 => {
+                open::<ChaCha20Poly1305>(decryption_key.key(), &data.nonce, &mut decrypted_data)
+            }
+        }?;
+        // Only record the nonce and the key after the message is authenticated.
+        self.nonces.accept(&data.nonce);
+        self.keys.accept_decryption_key(decryption_key);
         Ok(decrypted_data.to_vec())
     }
+}
 
-    /// Generate a random nonce.
-    fn generate_nonce() -> anyhow::Result<[u8; NONCE_LENGTH]> {
-        get_random()
-    }
+/// Derives the next generation of a session key using HKDF.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc5869>
+///
+/// The current key is used as the input key material, and [`KEY_UPDATE_PURPOSE`] as the
+/// information string.
+pub(crate) fn derive_next_session_key(
+    key: &[u8; AEAD_ALGORITHM_KEY_LENGTH],
+) -> anyhow::Result<[u8; AEAD_ALGORITHM_KEY_LENGTH]> {
+    let salt = KEY_DERIVATION_SALT.as_bytes();
+    let kdf = Hkdf::<Sha256>::new(Some(salt), key);
+    let mut next_key: [u8; AEAD_ALGORITHM_KEY_LENGTH] = Default::default();
+    kdf.expand(KEY_UPDATE_PURPOSE.as_bytes(), &mut next_key)
+        .map_err(|error| {
+            anyhow!(
+                "Couldn't get the output of the HKDF-Expand operation: {:?}",
+                error
+            )
+        })?;
+    Ok(next_key)
+}
+
+/// Encrypts `data` in place with the AEAD algorithm `A`, and appends the authentication tag.
+fn seal<A: NewAead + AeadInPlace>(
+    key: &[u8; AEAD_ALGORITHM_KEY_LENGTH],
+    nonce: &[u8; NONCE_LENGTH],
+    data: &mut Vec<u8>,
+) -> anyhow::Result<()> {
+    let cipher =
+        A::new_from_slice(key).map_err(|error| anyhow!("Couldn't create cipher: {:?}", error))?;
+    // Additional authenticated data is not required for the remotely attested channel,
+    // since after session key is established client and server exchange messages with a
+    // single encrypted field.
+    // And the nonce is authenticated by the AEAD algorithm itself.
+    // https://datatracker.ietf.org/doc/html/rfc5116#section-2.1
+    cipher
+        .encrypt_in_place(Nonce::<A>::from_slice(nonce), EMPTY_ADITIONAL_DATA, data)
+        .map_err(|error| anyhow!("Couldn't encrypt data: {:?}", error))
+}
+
+/// Decrypts and authenticates `data` in place with the AEAD algorithm `A`, and removes the
+/// authentication tag.
+fn open<A: NewAead + AeadInPlace>(
+    key: &[u8; AEAD_ALGORITHM_KEY_LENGTH],
+    nonce: &[u8; NONCE_LENGTH],
+    data: &mut Vec<u8>,
+) -> anyhow::Result<()> {
+    let cipher =
+        A::new_from_slice(key).map_err(|error| anyhow!("Couldn't create cipher: {:?}", error))?;
+    // See the comment in `seal` for why no additional authenticated data is used.
+    cipher
+        .decrypt_in_place(Nonce::<A>::from_slice(nonce), EMPTY_ADITIONAL_DATA, data)
+        .map_err(|error| anyhow!("Couldn't decrypt data: {:?}", error))
 }
 
 /// Implementation of the X25519 Elliptic Curve Diffie-Hellman (ECDH) key negotiation.
@@ -140,7 +233,8 @@ impl KeyNegotiator {
         Ok(public_key.to_bytes())
     }
 
-    /// Derives session keys from self and peer public keys and creates an [`AeadEncryptor`].
+    /// Derives session keys from self and peer public keys and creates an [`AeadEncryptor`] for
+    /// the negotiated `cipher_suite`, which uses the given `nonce_mode`.
     ///
     /// HKDF is used to derive both server and client session keys. The information string provided
     /// to HKDF consists of a purpose string, a server public key and a client public key (in that
@@ -153,20 +247,42 @@ impl KeyNegotiator {
     pub fn create_encryptor(
         self,
         peer_public_key: &[u8; KEY_AGREEMENT_ALGORITHM_KEY_LENGTH],
+        cipher_suite: CipherSuite,
+        nonce_mode: NonceMode,
     ) -> anyhow::Result<AeadEncryptor> {
-        let (encryption_key, decryption_key) = self
+        let (encryptor, _) = self.create_encryptor_with_resumption_secret(
+            peer_public_key,
+            cipher_suite,
+            nonce_mode,
+        )?;
+        Ok(encryptor)
+    }
+
+    /// Creates an [`AeadEncryptor`] like [`KeyNegotiator::create_encryptor`], and also returns
+    /// the secret used for resuming the session with a session ticket.
+    ///
+    /// The resumption secret is derived with HKDF in the same way as session keys, using the
+    /// [`RESUMPTION_SECRET_PURPOSE`] purpose string.
+    pub(crate) fn create_encryptor_with_resumption_secret(
+        self,
+        peer_public_key: &[u8; KEY_AGREEMENT_ALGORITHM_KEY_LENGTH],
+        cipher_suite: CipherSuite,
+        nonce_mode: NonceMode,
+    ) -> anyhow::Result<(AeadEncryptor, ResumptionSecret)> {
+        let (encryption_key, decryption_key, resumption_secret) = self
             .derive_session_keys(peer_public_key)
             .context("Couldn't derive session keys")?;
-        let encryptor = AeadEncryptor::new(encryption_key, decryption_key);
-        Ok(encryptor)
+        let encryptor =
+            AeadEncryptor::new(cipher_suite, nonce_mode, encryption_key, decryption_key)?;
+        Ok((encryptor, resumption_secret))
     }
 
     /// Implementation of the session keys derivation.
-    /// Returns a tuple with an encryption key and a decryption key.
+    /// Returns a tuple with an encryption key, a decryption key and a resumption secret.
     pub(crate) fn derive_session_keys(
         self,
         peer_public_key: &[u8; KEY_AGREEMENT_ALGORITHM_KEY_LENGTH],
-    ) -> anyhow::Result<(EncryptionKey, DecryptionKey)> {
+    ) -> anyhow::Result<(EncryptionKey, DecryptionKey, ResumptionSecret)> {
         let self_public_key = self.public_key().context("Couldn't get self public key")?;
         let parsed_public_key = PublicKey::from(*peer_public_key);
         let key_material = self.private_key.diffie_hellman(&parsed_public_key);
@@ -192,7 +308,16 @@ impl KeyNegotiator {
                     )
                     .context("Couldn't derive encryption key")?,
                 );
-                Ok((encryption_key, decryption_key))
+                let resumption_secret = ResumptionSecret(
+                    Self::key_derivation_function(
+                        key_material.as_bytes(),
+                        RESUMPTION_SECRET_PURPOSE,
+                        &self_public_key,
+                        peer_public_key,
+                    )
+                    .context("Couldn't derive resumption secret")?,
+                );
+                Ok((encryption_key, decryption_key, resumption_secret))
             }
             // On the client side `peer_public_key` is the server key.
             KeyNegotiatorType::Client => {
@@ -214,7 +339,16 @@ impl KeyNegotiator {
                     )
                     .context("Couldn't derive encryption key")?,
                 );
-                Ok((encryption_key, decryption_key))
+                let resumption_secret = ResumptionSecret(
+                    Self::key_derivation_function(
+                        key_material.as_bytes(),
+                        RESUMPTION_SECRET_PURPOSE,
+                        peer_public_key,
+                        &self_public_key,
+                    )
+                    .context("Couldn't derive resumption secret")?,
+                );
+                Ok((encryption_key, decryption_key, resumption_secret))
             }
         }
     }
@@ -0,0 +1,99 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Evidence presented by the server in the remote attestation handshake.
+//!
+//! The [`crate::handshaker::ClientHandshaker`] records the [`HandshakeEvidence`] once the server
+//! proved possession of its signing key, together with the result of verifying the attestation.
+//! The evidence can be persisted and verified again later, e.g. for auditing which server a client
+//! talked to.
+
+use crate::{
+    crypto::{
+        CipherSuite, SignatureVerifier, KEY_AGREEMENT_ALGORITHM_KEY_LENGTH, SHA256_HASH_LENGTH,
+        SIGNATURE_LENGTH, SIGNING_ALGORITHM_KEY_LENGTH,
+    },
+    handshaker::{attestation_data, AttestationVerifier},
+};
+use alloc::{string::String, vec::Vec};
+use anyhow::Context;
+use core::fmt;
+
+/// Attestation evidence of the server, and the handshake parameters it is bound to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HandshakeEvidence {
+    /// Remote attestation protocol version negotiated in the handshake.
+    pub protocol_version: u8,
+    /// Cipher suite negotiated in the handshake.
+    pub cipher_suite: CipherSuite,
+    /// Attestation report sent by the server.
+    pub attestation_report: Vec<u8>,
+    /// Ephemeral public key of the server, which is bound to the attestation report.
+    pub ephemeral_public_key: [u8; KEY_AGREEMENT_ALGORITHM_KEY_LENGTH],
+    /// Public key that the server signed the transcript with, which is bound to the attestation
+    /// report.
+    pub signing_public_key: [u8; SIGNING_ALGORITHM_KEY_LENGTH],
+    /// SHA-256 hash of the transcript signed by the server, i.e. of the `ClientHello` message and
+    /// the `ServerIdentity` message without its transcript signature.
+    pub transcript_hash: [u8; SHA256_HASH_LENGTH],
+    /// Signature of [`HandshakeEvidence::transcript_hash`] by the server.
+    pub transcript_signature: [u8; SIGNATURE_LENGTH],
+    /// Error returned by the attestation verifier of the client, or [`None`] if the attestation
+    /// was accepted.
+    pub verification_error: Option<String>,
+}
+
+impl HandshakeEvidence {
+    /// Returns whether the attestation verifier of the client accepted the attestation report.
+    pub fn is_accepted(&self) -> bool {
+        self.verification_error.is_none()
+    }
+
+    /// Returns the data that the attestation report must attest to.
+    pub fn attested_data(&self) -> Vec<u8> {
+        attestation_data(&self.ephemeral_public_key, &self.signing_public_key)
+    }
+
+    /// Checks that the transcript hash is signed by the signing public key.
+    pub fn verify_transcript_signature(&self) -> anyhow::Result<()> {
+        SignatureVerifier::new(&self.signing_public_key)?
+            .verify(&self.transcript_hash, &self.transcript_signature)
+            .context("Couldn't verify server transcript")
+    }
+
+    /// Verifies the evidence again with the given [`AttestationVerifier`], independently of the
+    /// verification result recorded in the handshake.
+    pub fn verify<V: AttestationVerifier>(&self, verifier: &V) -> anyhow::Result<()> {
+        self.verify_transcript_signature()?;
+        verifier
+            .verify_attestation(&self.attestation_report, &self.attested_data())
+            .context("Couldn't verify attestation report")
+    }
+}
+
+/// Context of the error returned by the [`crate::handshaker::ClientHandshaker`] when its
+/// attestation verifier rejected the evidence of the server.
+///
+/// The rejected evidence can be retrieved from the error with [`anyhow::Error::downcast_ref`], so
+/// that it can still be persisted, e.g. for investigating why the server was rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RejectedEvidence(pub HandshakeEvidence);
+
+impl fmt::Display for RejectedEvidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Attestation evidence of the server was rejected")
+    }
+}
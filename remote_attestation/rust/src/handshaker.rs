@@ -14,30 +14,47 @@
 // limitations under the License.
 //
 
-///! Remote attestation protocol handshake implementation.
-///!
-///! During the attestation protocol handshake participants send the following messages:
-///!
-///! - [`Client`] -> [`Server`]: [`ClientHello`]
-///! - [`Server`] -> [`Client`]: [`ServerIdentity`]
-///! - [`Client`] -> [`Server`]: [`ClientIdentity`]
-///!
-///! After the protocol handshake both sides create [`Encryptor`] for exchanging encrypted
-///! messages.
+//! Remote attestation protocol handshake implementation.
+//!
+//! During the attestation protocol handshake participants send the following messages:
+//!
+//! - [`ClientHandshaker`] -> [`ServerHandshaker`]: [`ClientHello`]
+//! - [`ServerHandshaker`] -> [`ClientHandshaker`]: [`ServerIdentity`]
+//! - [`ClientHandshaker`] -> [`ServerHandshaker`]: [`ClientIdentity`]
+//!
+//! The client offers the protocol versions and cipher suites it supports in [`ClientHello`], and
+//! the server selects one of each in [`ServerIdentity`]. Both messages are covered by the
+//! transcript signatures, so that the selection cannot be downgraded by the network.
+//!
+//! After the protocol handshake both sides create [`Encryptor`] for exchanging encrypted
+//! messages. If the server receives encrypted messages before a handshake, e.g. because the
+//! session expired, it responds with [`SessionExpired`] so that the client performs a new
+//! handshake.
+//!
+//! If the server is configured with [`SessionTickets`], it also sends a [`SessionTicket`] after the
+//! handshake, which allows the client to resume the session (see [`crate::resumption`]).
+//!
+//! The client records the attestation evidence of the server as [`HandshakeEvidence`], which can
+//! be persisted for auditing the handshake later (see [`crate::evidence`]).
+
 use crate::{
     crypto::{
-        get_random, get_sha256, AeadEncryptor, KeyNegotiator, KeyNegotiatorType, SignatureVerifier,
-        Signer, KEY_AGREEMENT_ALGORITHM_KEY_LENGTH, SHA256_HASH_LENGTH, SIGNATURE_LENGTH,
-        SIGNING_ALGORITHM_KEY_LENGTH,
+        get_random, get_sha256, AeadEncryptor, CipherSuite, KeyNegotiator, KeyNegotiatorType,
+        KeyRotation, NonceMode, ReplayProtection, ResumptionSecret, SignatureVerifier, Signer,
+        KEY_AGREEMENT_ALGORITHM_KEY_LENGTH, LEGACY_CIPHER_SUITE, SHA256_HASH_LENGTH,
+        SIGNATURE_LENGTH, SIGNING_ALGORITHM_KEY_LENGTH, SUPPORTED_CIPHER_SUITES,
     },
+    evidence::{HandshakeEvidence, RejectedEvidence},
     message::{
         deserialize_message, ClientHello, ClientIdentity, MessageWrapper, Serializable,
-        ServerIdentity,
+        ServerIdentity, SessionExpired, SessionTicket, LEGACY_PROTOCOL_VERSION,
+        SUPPORTED_PROTOCOL_VERSIONS,
     },
+    resumption::{ResumptionTicket, SessionTickets},
 };
-use alloc::{vec, vec::Vec};
+use alloc::{format, vec, vec::Vec};
 use anyhow::{anyhow, Context};
-use core::fmt::Debug;
+use core::{fmt::Debug, time::Duration};
 
 enum ClientHandshakerState {
     Initializing,
@@ -68,7 +85,8 @@ impl core::fmt::Debug for ClientHandshakerState {
 
 enum ServerHandshakerState {
     ExpectingClientHello,
-    ExpectingClientIdentity(KeyNegotiator),
+    /// Contains the negotiated protocol version and cipher suite.
+    ExpectingClientIdentity(KeyNegotiator, u8, CipherSuite),
     Completed(AeadEncryptor),
     Aborted,
     /// Additional state that represents ongoing message processing.
@@ -85,7 +103,7 @@ impl core::fmt::Debug for ServerHandshakerState {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         match self {
             Self::ExpectingClientHello => write!(f, "ExpectingClientHello"),
-            Self::ExpectingClientIdentity(_) => write!(f, "ExpectingClientIdentity"),
+            Self::ExpectingClientIdentity(..) => write!(f, "ExpectingClientIdentity"),
             Self::Completed(_) => write!(f, "Completed"),
             Self::Aborted => write!(f, "Aborted"),
             Self::MessageProcessing => write!(f, "MessageProcessing"),
@@ -105,16 +123,43 @@ pub struct ClientHandshaker<G: AttestationGenerator, V: AttestationVerifier> {
     /// Signer containing a key which public part is signed by the TEE firmware key.
     /// Used for signing protocol transcripts and preventing replay attacks.
     transcript_signer: Signer,
+    /// Cipher suites offered to the server, in the order of preference.
+    cipher_suites: Vec<CipherSuite>,
+    /// Negotiated cipher suite and resumption secret of the session, which are only kept for
+    /// protocol versions that support session tickets.
+    resumption_secret: Option<(CipherSuite, ResumptionSecret)>,
+    /// Session ticket received from the server after the handshake.
+    session_ticket: Option<ResumptionTicket>,
+    /// Attestation evidence of the server, recorded once the server transcript signature is
+    /// verified.
+    evidence: Option<HandshakeEvidence>,
 }
 
 impl<G: AttestationGenerator, V: AttestationVerifier> ClientHandshaker<G, V> {
-    /// Creates [`ClientHandshaker`] with `Initializing` state.
+    /// Creates [`ClientHandshaker`] with `Initializing` state, which offers all the
+    /// [`SUPPORTED_CIPHER_SUITES`].
     pub fn new(behavior: AttestationBehavior<G, V>) -> anyhow::Result<Self> {
+        Self::new_with_cipher_suites(behavior, SUPPORTED_CIPHER_SUITES)
+    }
+
+    /// Creates [`ClientHandshaker`] with `Initializing` state, which only offers the given cipher
+    /// suites, in the order of preference.
+    pub fn new_with_cipher_suites(
+        behavior: AttestationBehavior<G, V>,
+        cipher_suites: &[CipherSuite],
+    ) -> anyhow::Result<Self> {
+        if cipher_suites.is_empty() {
+            return Err(anyhow!("At least one cipher suite must be offered"));
+        }
         Ok(Self {
             behavior,
             state: ClientHandshakerState::Initializing,
             transcript: Transcript::new(),
             transcript_signer: Signer::create().context("Couldn't create signer")?,
+            cipher_suites: cipher_suites.to_vec(),
+            resumption_secret: None,
+            session_ticket: None,
+            evidence: None,
         })
     }
 
@@ -154,6 +199,28 @@ impl<G: AttestationGenerator, V: AttestationVerifier> ClientHandshaker<G, V> {
                     )),
                 }
             }
+            MessageWrapper::SessionTicket(session_ticket) => {
+                match (&self.state, &self.resumption_secret) {
+                    (
+                        ClientHandshakerState::Completed(_),
+                        Some((cipher_suite, resumption_secret)),
+                    ) => {
+                        self.session_ticket = Some(ResumptionTicket::new(
+                            session_ticket.ticket,
+                            *cipher_suite,
+                            resumption_secret.clone(),
+                        ));
+                        Ok(None)
+                    }
+                    (ClientHandshakerState::Completed(_), None) => Err(anyhow!(
+                        "Session tickets are not supported by the legacy protocol version"
+                    )),
+                    _ => Err(anyhow!(
+                        "Incorrect handshake message received, in state {:?}, found SessionTicket",
+                        self.state
+                    )),
+                }
+            }
             unsupported_message => Err(anyhow!(
                 "Receiving {:?} is not supported by the client handshaker",
                 unsupported_message
@@ -176,6 +243,20 @@ impl<G: AttestationGenerator, V: AttestationVerifier> ClientHandshaker<G, V> {
         }
     }
 
+    /// Returns the session ticket sent by the server after the handshake, if any, which can be
+    /// used for resuming the session with [`crate::resumption::SessionResumer`].
+    pub fn get_session_ticket(&self) -> Option<ResumptionTicket> {
+        self.session_ticket.clone()
+    }
+
+    /// Returns the attestation evidence of the server, together with the result of verifying it.
+    ///
+    /// The evidence is available as soon as the server transcript signature is verified, so that
+    /// it is also available if the handshake was aborted because the attestation was rejected.
+    pub fn get_evidence(&self) -> Option<HandshakeEvidence> {
+        self.evidence.clone()
+    }
+
     /// Initializes the remote attestation handshake by creating a serialized [`ClientHello`]
     /// message.
     ///
@@ -195,8 +276,14 @@ impl<G: AttestationGenerator, V: AttestationVerifier> ClientHandshaker<G, V> {
                     .context("Couldn't create key negotiator")?;
 
                 // Create client hello message.
-                let client_hello =
-                    ClientHello::new(get_random().context("Couldn't generate random array")?);
+                let client_hello = ClientHello::new(
+                    get_random().context("Couldn't generate random array")?,
+                    SUPPORTED_PROTOCOL_VERSIONS.to_vec(),
+                    self.cipher_suites
+                        .iter()
+                        .map(|cipher_suite| cipher_suite.id())
+                        .collect(),
+                );
 
                 // Update current transcript.
                 self.transcript
@@ -232,6 +319,21 @@ impl<G: AttestationGenerator, V: AttestationVerifier> ClientHandshaker<G, V> {
         server_identity: ServerIdentity,
         key_negotiator: KeyNegotiator,
     ) -> anyhow::Result<ClientIdentity> {
+        // Check that the server selected one of the offered protocol versions and cipher suites.
+        // The transcript signature verified below guarantees that the offer was not modified.
+        if !SUPPORTED_PROTOCOL_VERSIONS.contains(&server_identity.version) {
+            return Err(anyhow!(
+                "Server selected protocol version {} which was not offered",
+                server_identity.version
+            ));
+        }
+        if !self.cipher_suites.contains(&server_identity.cipher_suite) {
+            return Err(anyhow!(
+                "Server selected cipher suite {:?} which was not offered",
+                server_identity.cipher_suite
+            ));
+        }
+
         // Verify server transcript signature.
         // Transcript doesn't include transcript signature from the server identity message.
         let mut server_identity_no_signature = server_identity.clone();
@@ -240,12 +342,10 @@ impl<G: AttestationGenerator, V: AttestationVerifier> ClientHandshaker<G, V> {
             .append(&server_identity_no_signature)
             .context("Couldn't append server identity to the transcript")?;
         let server_signing_public_key = &server_identity.signing_public_key;
+        let transcript_hash = self.transcript.get_sha256();
         let transcript_signature_verifier = SignatureVerifier::new(server_signing_public_key)?;
         transcript_signature_verifier
-            .verify(
-                &self.transcript.get_sha256(),
-                &server_identity.transcript_signature,
-            )
+            .verify(&transcript_hash, &server_identity.transcript_signature)
             .context("Couldn't verify server transcript")?;
 
         let expected_attested_data = attestation_data(
@@ -253,11 +353,27 @@ impl<G: AttestationGenerator, V: AttestationVerifier> ClientHandshaker<G, V> {
             server_signing_public_key,
         );
 
-        // Verify server attestation info.
+        // Verify server attestation info, and record the evidence together with the result.
         let server_attestation_report = &server_identity.attestation_report;
-        self.behavior
+        let verification_result = self
+            .behavior
             .verifier
-            .verify_attestation(server_attestation_report, &expected_attested_data)?;
+            .verify_attestation(server_attestation_report, &expected_attested_data);
+        let evidence = HandshakeEvidence {
+            protocol_version: server_identity.version,
+            cipher_suite: server_identity.cipher_suite,
+            attestation_report: server_attestation_report.clone(),
+            ephemeral_public_key: server_identity.ephemeral_public_key,
+            signing_public_key: *server_signing_public_key,
+            transcript_hash,
+            transcript_signature: server_identity.transcript_signature,
+            verification_error: verification_result
+                .as_ref()
+                .err()
+                .map(|error| format!("{:#}", error)),
+        };
+        self.evidence = Some(evidence.clone());
+        verification_result.map_err(|error| error.context(RejectedEvidence(evidence)))?;
 
         // Create client identity message.
         let ephemeral_public_key = key_negotiator
@@ -294,9 +410,16 @@ impl<G: AttestationGenerator, V: AttestationVerifier> ClientHandshaker<G, V> {
         client_identity.set_transcript_signature(&transcript_signature);
 
         // Agree on session keys and create an encryptor.
-        let encryptor = key_negotiator
-            .create_encryptor(&server_identity.ephemeral_public_key)
+        let (encryptor, resumption_secret) = key_negotiator
+            .create_encryptor_with_resumption_secret(
+                &server_identity.ephemeral_public_key,
+                server_identity.cipher_suite,
+                nonce_mode(server_identity.version),
+            )
             .context("Couldn't derive session key")?;
+        if server_identity.version != LEGACY_PROTOCOL_VERSION {
+            self.resumption_secret = Some((server_identity.cipher_suite, resumption_secret));
+        }
         self.state = ClientHandshakerState::Completed(encryptor);
 
         Ok(client_identity)
@@ -315,20 +438,49 @@ pub struct ServerHandshaker<G: AttestationGenerator, V: AttestationVerifier> {
     /// Signer containing a key which public part is signed by the TEE firmware key.
     /// Used for signing protocol transcripts and preventing replay attacks.
     transcript_signer: Signer,
+    /// Cipher suites accepted from clients, in the order of preference.
+    cipher_suites: Vec<CipherSuite>,
+    /// Session tickets issued to clients after the handshake, if enabled.
+    session_tickets: Option<SessionTickets>,
+    /// Expiration time of the session ticket, if the session was resumed.
+    resumed_session_expiration_time: Option<Duration>,
 }
 
 impl<G: AttestationGenerator, V: AttestationVerifier> ServerHandshaker<G, V> {
     /// Creates [`ServerHandshaker`] with `ServerHandshakerState::ExpectingClientIdentity`
-    /// state.
+    /// state, which accepts all the [`SUPPORTED_CIPHER_SUITES`].
     pub fn new(behavior: AttestationBehavior<G, V>) -> anyhow::Result<Self> {
+        Self::new_with_cipher_suites(behavior, SUPPORTED_CIPHER_SUITES)
+    }
+
+    /// Creates [`ServerHandshaker`] with `ServerHandshakerState::ExpectingClientIdentity`
+    /// state, which only accepts the given cipher suites, in the order of preference.
+    pub fn new_with_cipher_suites(
+        behavior: AttestationBehavior<G, V>,
+        cipher_suites: &[CipherSuite],
+    ) -> anyhow::Result<Self> {
+        if cipher_suites.is_empty() {
+            return Err(anyhow!("At least one cipher suite must be accepted"));
+        }
         Ok(Self {
             behavior,
             state: ServerHandshakerState::ExpectingClientHello,
             transcript: Transcript::new(),
             transcript_signer: Signer::create().context("Couldn't create signer")?,
+            cipher_suites: cipher_suites.to_vec(),
+            session_tickets: None,
+            resumed_session_expiration_time: None,
         })
     }
 
+    /// Enables issuing session tickets after the handshake, and resuming sessions with tickets
+    /// sealed with the same key.
+    ///
+    /// Session tickets are not issued to clients using the legacy protocol version.
+    pub fn set_session_tickets(&mut self, session_tickets: SessionTickets) {
+        self.session_tickets = Some(session_tickets);
+    }
+
     /// Processes incoming `message` and returns a serialized remote attestation message.
     /// If [`None`] is returned, then no messages should be sent out to the client.
     pub fn next_step(&mut self, message: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
@@ -365,10 +517,31 @@ impl<G: AttestationGenerator, V: AttestationVerifier> ServerHandshaker<G, V> {
             },
             MessageWrapper::ClientIdentity(client_identity) => {
                 match core::mem::take(&mut self.state) {
-                    ServerHandshakerState::ExpectingClientIdentity(key_negotiator) => {
-                        self.process_client_identity(client_identity, key_negotiator)
+                    ServerHandshakerState::ExpectingClientIdentity(
+                        key_negotiator,
+                        version,
+                        cipher_suite,
+                    ) => {
+                        let resumption_secret = self
+                            .process_client_identity(
+                                client_identity,
+                                key_negotiator,
+                                version,
+                                cipher_suite,
+                            )
                             .context("Couldn't process client identity message")?;
-                        Ok(None)
+                        match &self.session_tickets {
+                            Some(session_tickets) if version != LEGACY_PROTOCOL_VERSION => {
+                                let ticket = session_tickets
+                                    .seal(version, cipher_suite, &resumption_secret)
+                                    .context("Couldn't seal session ticket")?;
+                                let serialized_session_ticket = SessionTicket::new(ticket)
+                                    .serialize()
+                                    .context("Couldn't serialize session ticket message")?;
+                                Ok(Some(serialized_session_ticket))
+                            }
+                            _ => Ok(None),
+                        }
                     }
                     ServerHandshakerState::MessageProcessing => Err(anyhow!(
                         "Cannot process new messages while in the MessageProcessing state",
@@ -379,6 +552,48 @@ impl<G: AttestationGenerator, V: AttestationVerifier> ServerHandshaker<G, V> {
                     )),
                 }
             }
+            // Encrypted data can only be received by a server handshaker that was created for an
+            // unknown session, so the client is asked to perform a new handshake.
+            MessageWrapper::EncryptedData(_) => match &self.state {
+                ServerHandshakerState::ExpectingClientHello => {
+                    let serialized_session_expired = SessionExpired
+                        .serialize()
+                        .context("Couldn't serialize session expired message")?;
+                    Ok(Some(serialized_session_expired))
+                }
+                _ => Err(anyhow!(
+                    "Incorrect handshake message received, in state {:?}, found EncryptedData",
+                    self.state
+                )),
+            },
+            // Sessions can be resumed instead of performing a handshake. If the ticket cannot be
+            // used (e.g. because it expired or session tickets are disabled), the client is asked
+            // to perform a new handshake.
+            MessageWrapper::ResumeSession(resume_session) => match &self.state {
+                ServerHandshakerState::ExpectingClientHello => {
+                    let resumed_session = self
+                        .session_tickets
+                        .as_ref()
+                        .and_then(|session_tickets| session_tickets.resume(&resume_session).ok());
+                    match resumed_session {
+                        Some((encryptor, serialized_session_resumed, expiration_time)) => {
+                            self.state = ServerHandshakerState::Completed(encryptor);
+                            self.resumed_session_expiration_time = Some(expiration_time);
+                            Ok(Some(serialized_session_resumed))
+                        }
+                        None => {
+                            let serialized_session_expired = SessionExpired
+                                .serialize()
+                                .context("Couldn't serialize session expired message")?;
+                            Ok(Some(serialized_session_expired))
+                        }
+                    }
+                }
+                _ => Err(anyhow!(
+                    "Incorrect handshake message received, in state {:?}, found ResumeSession",
+                    self.state
+                )),
+            },
             unsupported_message => Err(anyhow!(
                 "Receiving {:?} is not supported by the server handshaker",
                 unsupported_message
@@ -394,6 +609,15 @@ impl<G: AttestationGenerator, V: AttestationVerifier> ServerHandshaker<G, V> {
         matches!(self.state, ServerHandshakerState::Aborted)
     }
 
+    /// Returns the expiration time of the session ticket if the session was resumed, measured with
+    /// the clock of [`SessionTickets`].
+    ///
+    /// A resumed session must not outlive its ticket, which expires after
+    /// [`SessionTickets::lifetime`] measured from the handshake that the ticket was issued in.
+    pub fn get_resumed_session_expiration_time(&self) -> Option<Duration> {
+        self.resumed_session_expiration_time
+    }
+
     pub fn get_encryptor(self) -> anyhow::Result<Encryptor> {
         match self.state {
             ServerHandshakerState::Completed(encryptor) => Ok(Encryptor { encryptor }),
@@ -403,6 +627,8 @@ impl<G: AttestationGenerator, V: AttestationVerifier> ServerHandshaker<G, V> {
 
     /// Responds to [`ClientHello`] message by creating a [`ServerIdentity`] message.
     ///
+    /// [`ServerIdentity`] message contains the protocol version and the cipher suite selected from
+    /// the ones offered by the client.
     /// [`ServerIdentity`] message contains an ephemeral public key for negotiating session keys.
     /// If self attestation is enabled this message also provides necessary information to perform
     /// remote attestation.
@@ -413,6 +639,10 @@ impl<G: AttestationGenerator, V: AttestationVerifier> ServerHandshaker<G, V> {
         &mut self,
         client_hello: ClientHello,
     ) -> anyhow::Result<ServerIdentity> {
+        let (version, cipher_suite) = self
+            .negotiate(&client_hello)
+            .context("Couldn't negotiate protocol parameters")?;
+
         // Create server identity message.
         let key_negotiator = KeyNegotiator::create(KeyNegotiatorType::Server)
             .context("Couldn't create key negotiator")?;
@@ -428,6 +658,8 @@ impl<G: AttestationGenerator, V: AttestationVerifier> ServerHandshaker<G, V> {
             .generate_attestation(&attestation_data)?;
 
         let mut server_identity = ServerIdentity::new(
+            version,
+            cipher_suite,
             ephemeral_public_key,
             get_random().context("Couldn't generate random array")?,
             self.transcript_signer
@@ -452,13 +684,52 @@ impl<G: AttestationGenerator, V: AttestationVerifier> ServerHandshaker<G, V> {
             .context("Couldn't create transcript signature")?;
         server_identity.set_transcript_signature(&transcript_signature);
 
-        self.state = ServerHandshakerState::ExpectingClientIdentity(key_negotiator);
+        self.state =
+            ServerHandshakerState::ExpectingClientIdentity(key_negotiator, version, cipher_suite);
         Ok(server_identity)
     }
 
+    /// Selects the most preferred protocol version supported by both sides, and the most preferred
+    /// cipher suite (from the server's point of view) offered by the client.
+    fn negotiate(&self, client_hello: &ClientHello) -> anyhow::Result<(u8, CipherSuite)> {
+        let version = SUPPORTED_PROTOCOL_VERSIONS
+            .iter()
+            .find(|version| client_hello.offered_versions().contains(version))
+            .copied()
+            .ok_or_else(|| {
+                anyhow!(
+                    "No supported protocol version offered, found {:?}",
+                    client_hello.offered_versions()
+                )
+            })?;
+        let cipher_suite = self
+            .cipher_suites
+            .iter()
+            // The legacy protocol version cannot express any other cipher suite.
+            .filter(|cipher_suite| {
+                version != LEGACY_PROTOCOL_VERSION || **cipher_suite == LEGACY_CIPHER_SUITE
+            })
+            .find(|cipher_suite| {
+                client_hello
+                    .offered_cipher_suites()
+                    .contains(&cipher_suite.id())
+            })
+            .copied()
+            .ok_or_else(|| {
+                anyhow!(
+                    "No accepted cipher suite offered for protocol version {}, found {:?}",
+                    version,
+                    client_hello.offered_cipher_suites()
+                )
+            })?;
+        Ok((version, cipher_suite))
+    }
+
     /// Finishes the remote attestation protocol handshake and derives session keys for
     /// encrypting/decrypting messages from the client.
     ///
+    /// Returns the resumption secret of the session, which is sealed in session tickets.
+    ///
     /// Transitions [`ServerHandshaker`] state from
     /// [`ServerHandshakerState::ExpectingClientIdentity`] to [`ServerHandshakerState::Completed`]
     /// state.
@@ -466,7 +737,9 @@ impl<G: AttestationGenerator, V: AttestationVerifier> ServerHandshaker<G, V> {
         &mut self,
         client_identity: ClientIdentity,
         key_negotiator: KeyNegotiator,
-    ) -> anyhow::Result<()> {
+        version: u8,
+        cipher_suite: CipherSuite,
+    ) -> anyhow::Result<ResumptionSecret> {
         // Verify client transcript signature.
         // Transcript doesn't include transcript signature from the client identity message.
         let mut client_identity_no_signature = client_identity.clone();
@@ -504,12 +777,16 @@ impl<G: AttestationGenerator, V: AttestationVerifier> ServerHandshaker<G, V> {
             .verify_attestation(client_attestation_report, &expected_attested_data)?;
 
         // Agree on session keys and create an encryptor.
-        let encryptor = key_negotiator
-            .create_encryptor(&client_identity.ephemeral_public_key)
+        let (encryptor, resumption_secret) = key_negotiator
+            .create_encryptor_with_resumption_secret(
+                &client_identity.ephemeral_public_key,
+                cipher_suite,
+                nonce_mode(version),
+            )
             .context("Couldn't derive session key")?;
         self.state = ServerHandshakerState::Completed(encryptor);
 
-        Ok(())
+        Ok(resumption_secret)
     }
 }
 
@@ -537,6 +814,26 @@ impl Encryptor {
         }
     }
 
+    /// Changes the replay protection applied to the following decrypted messages, e.g. to allow
+    /// transports that may reorder messages to use a sliding window.
+    ///
+    /// By default messages must be decrypted in the order they were encrypted by the peer. Has no
+    /// effect on sessions using the legacy protocol version, which uses random nonces.
+    pub fn set_replay_protection(
+        &mut self,
+        replay_protection: ReplayProtection,
+    ) -> anyhow::Result<()> {
+        self.encryptor.set_replay_protection(replay_protection)
+    }
+
+    /// Changes when the key used for encrypting the following messages is rotated.
+    ///
+    /// By default keys are rotated according to [`KeyRotation::default`]. Has no effect on
+    /// sessions using the legacy protocol version, which never rotates keys.
+    pub fn set_key_rotation(&mut self, key_rotation: KeyRotation) -> anyhow::Result<()> {
+        self.encryptor.set_key_rotation(key_rotation)
+    }
+
     pub fn encrypt(&mut self, message: &[u8]) -> anyhow::Result<Vec<u8>> {
         let encrypted_message = self
             .encryptor
@@ -671,6 +968,18 @@ impl Transcript {
     }
 }
 
+/// Returns the nonce mode used by encryptors for the negotiated protocol version.
+///
+/// The legacy protocol version uses random nonces for compatibility with existing clients, while
+/// newer versions use sequential nonces, which protect against replayed and reordered messages.
+fn nonce_mode(version: u8) -> NonceMode {
+    if version == LEGACY_PROTOCOL_VERSION {
+        NonceMode::Random
+    } else {
+        NonceMode::Sequential(ReplayProtection::Strict)
+    }
+}
+
 /// Compute data to be attested based on the actual or expected parameters.
 ///
 /// In particular, the concatenation of the ephemeral public key, and the signing public key.
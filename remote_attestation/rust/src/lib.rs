@@ -18,8 +18,11 @@
 
 extern crate alloc;
 
+mod codec;
 pub mod crypto;
+pub mod evidence;
 pub mod handshaker;
 pub mod message;
+pub mod resumption;
 #[cfg(test)]
 mod tests;
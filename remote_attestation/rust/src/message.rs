@@ -20,26 +20,80 @@
 // Unlike other messages exchanged between client & server, these are expressed
 // as Rust structs and serialized with custom logic. This is done to maintain
 // binary-compatibility with other implementations of this protocol.
-
-use crate::crypto::{
-    KEY_AGREEMENT_ALGORITHM_KEY_LENGTH, NONCE_LENGTH, SIGNATURE_LENGTH,
-    SIGNING_ALGORITHM_KEY_LENGTH,
+//
+// The encoding of each message is defined by the sequence of fields it reads
+// and writes with the `codec` module, which checks the length of each
+// variable-size field against its limit.
+
+use crate::{
+    codec::{Reader, VecField, Writer},
+    crypto::{
+        CipherSuite, KEY_AGREEMENT_ALGORITHM_KEY_LENGTH, LEGACY_CIPHER_SUITE, NONCE_LENGTH,
+        SIGNATURE_LENGTH, SIGNING_ALGORITHM_KEY_LENGTH,
+    },
 };
 use alloc::vec::Vec;
 use anyhow::{anyhow, bail, Context};
-use bytes::{Buf, BufMut};
 
-/// Maximum handshake message size is set to be equal to 1KiB.
-pub const MAXIMUM_MESSAGE_SIZE: usize = 1_024;
+/// Maximum number of protocol versions or cipher suites offered in a [`ClientHello`].
+pub const MAXIMUM_OFFERED_LIST_LENGTH: usize = 255;
+
+/// Maximum length (in bytes) of the attestation report in identity messages, set to 32KiB.
+///
+/// Attestation reports must fit AMD SEV-SNP attestation evidence, which consists of the attestation
+/// report together with the VCEK and ASK certificates and takes around 4KiB, as well as the
+/// configuration report and the endorsements that accompany it.
+pub const MAXIMUM_ATTESTATION_REPORT_LENGTH: usize = 32_768;
+
+/// Maximum length (in bytes) of a session ticket.
+pub const MAXIMUM_SESSION_TICKET_LENGTH: usize = 1_024;
+
+const SUPPORTED_VERSIONS_FIELD: VecField = VecField {
+    name: "supported versions",
+    maximum_length: MAXIMUM_OFFERED_LIST_LENGTH,
+};
+const CIPHER_SUITES_FIELD: VecField = VecField {
+    name: "cipher suites",
+    maximum_length: MAXIMUM_OFFERED_LIST_LENGTH,
+};
+const ATTESTATION_REPORT_FIELD: VecField = VecField {
+    name: "attestation report",
+    maximum_length: MAXIMUM_ATTESTATION_REPORT_LENGTH,
+};
+const SESSION_TICKET_FIELD: VecField = VecField {
+    name: "session ticket",
+    maximum_length: MAXIMUM_SESSION_TICKET_LENGTH,
+};
+/// Encrypted data is only limited by the transport that delivers the messages.
+const ENCRYPTED_DATA_FIELD: VecField = VecField {
+    name: "encrypted data",
+    maximum_length: usize::MAX,
+};
 
 // Message header values.
 pub(crate) const CLIENT_HELLO_HEADER: u8 = 1;
 pub(crate) const SERVER_IDENTITY_HEADER: u8 = 2;
 pub(crate) const CLIENT_IDENTITY_HEADER: u8 = 3;
 pub(crate) const ENCRYPTED_DATA_HEADER: u8 = 4;
+pub(crate) const SESSION_EXPIRED_HEADER: u8 = 5;
+pub(crate) const SESSION_TICKET_HEADER: u8 = 6;
+pub(crate) const RESUME_SESSION_HEADER: u8 = 7;
+pub(crate) const SESSION_RESUMED_HEADER: u8 = 8;
 
 /// Remote attestation protocol version.
-pub const PROTOCOL_VERSION: u8 = 1;
+///
+/// Version 2 supports negotiating the protocol version and the cipher suite.
+pub const PROTOCOL_VERSION: u8 = 2;
+
+/// Version of the remote attestation protocol that does not support negotiation, and always uses
+/// [`LEGACY_CIPHER_SUITE`].
+pub const LEGACY_PROTOCOL_VERSION: u8 = 1;
+
+/// Protocol versions supported by this implementation, in the order of preference.
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[u8] = &[PROTOCOL_VERSION, LEGACY_PROTOCOL_VERSION];
+
+/// Cipher suites implicitly offered by a client hello in the legacy format.
+const LEGACY_CIPHER_SUITE_IDS: &[u8] = &[LEGACY_CIPHER_SUITE.id()];
 
 /// Length (in bytes) of the random vector sent in messages for preventing replay attacks.
 pub const REPLAY_PROTECTION_ARRAY_LENGTH: usize = 32;
@@ -50,6 +104,9 @@ pub const MESSAGE_HEADER_LENGTH: usize = 1;
 /// Length (in bytes) of the protocol version.
 pub const PROTOCOL_VERSION_LENGTH: usize = 1;
 
+/// Length (in bytes) of the cipher suite identifier.
+pub const CIPHER_SUITE_LENGTH: usize = 1;
+
 /// Length (in bytes) of the prefix that is used for Little-Endian encoding of the size of a vector
 /// during serailization.
 pub const VEC_SIZE_PREFIX_LENGTH: usize = 8;
@@ -63,6 +120,25 @@ pub enum MessageWrapper {
     ServerIdentity(ServerIdentity),
     ClientIdentity(ClientIdentity),
     EncryptedData(EncryptedData),
+    SessionExpired(SessionExpired),
+    SessionTicket(SessionTicket),
+    ResumeSession(ResumeSession),
+    SessionResumed(SessionResumed),
+}
+
+impl Serializable for MessageWrapper {
+    fn serialize(&self) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Self::ClientHello(message) => message.serialize(),
+            Self::ServerIdentity(message) => message.serialize(),
+            Self::ClientIdentity(message) => message.serialize(),
+            Self::EncryptedData(message) => message.serialize(),
+            Self::SessionExpired(message) => message.serialize(),
+            Self::SessionTicket(message) => message.serialize(),
+            Self::ResumeSession(message) => message.serialize(),
+            Self::SessionResumed(message) => message.serialize(),
+        }
+    }
 }
 
 impl core::fmt::Debug for MessageWrapper {
@@ -72,6 +148,10 @@ impl core::fmt::Debug for MessageWrapper {
             Self::ServerIdentity(_) => write!(f, "ServerIdentity"),
             Self::ClientIdentity(_) => write!(f, "ClientIdentity"),
             Self::EncryptedData(_) => write!(f, "EncryptedData"),
+            Self::SessionExpired(_) => write!(f, "SessionExpired"),
+            Self::SessionTicket(_) => write!(f, "SessionTicket"),
+            Self::ResumeSession(_) => write!(f, "ResumeSession"),
+            Self::SessionResumed(_) => write!(f, "SessionResumed"),
         }
     }
 }
@@ -79,18 +159,34 @@ impl core::fmt::Debug for MessageWrapper {
 // TODO(#2105): Implement challenge-response in remote attestation.
 // TODO(#2106): Support various claims in remote attestation.
 /// Initial message that starts remote attestation handshake.
+///
+/// A client hello that offers neither protocol versions nor cipher suites is serialized in the
+/// legacy format, which only contains the random vector, and implicitly offers
+/// [`LEGACY_PROTOCOL_VERSION`] with [`LEGACY_CIPHER_SUITE`].
 #[derive(Clone, PartialEq)]
 pub struct ClientHello {
     /// Random vector sent in messages for preventing replay attacks.
     pub random: [u8; REPLAY_PROTECTION_ARRAY_LENGTH],
+    /// Protocol versions supported by the client, in the order of preference.
+    pub supported_versions: Vec<u8>,
+    /// Identifiers of the cipher suites supported by the client, in the order of preference.
+    ///
+    /// Identifiers are kept as raw bytes, so that clients can offer cipher suites unknown to the
+    /// server.
+    pub cipher_suites: Vec<u8>,
 }
 
 /// Server identity message containing remote attestation information and a public key for
 /// Diffie-Hellman key negotiation.
+///
+/// The cipher suite is only serialized for [`PROTOCOL_VERSION`], since [`LEGACY_PROTOCOL_VERSION`]
+/// always uses [`LEGACY_CIPHER_SUITE`].
 #[derive(Clone, PartialEq)]
 pub struct ServerIdentity {
-    /// Remote attestation protocol version.
+    /// Remote attestation protocol version selected by the server.
     pub version: u8,
+    /// Cipher suite selected by the server.
+    pub cipher_suite: CipherSuite,
     /// Public key needed to establish a session key.
     pub ephemeral_public_key: [u8; KEY_AGREEMENT_ALGORITHM_KEY_LENGTH],
     /// Random vector sent in messages for preventing replay attacks.
@@ -141,12 +237,55 @@ pub struct ClientIdentity {
 /// Message containing data encrypted using a session key.
 #[derive(Clone, Debug, PartialEq)]
 pub struct EncryptedData {
-    /// Random nonce (initialization vector) used for encryption/decryption.
+    /// Nonce (initialization vector) used for encryption/decryption.
+    ///
+    /// Unless the session uses [`LEGACY_PROTOCOL_VERSION`], the nonce consists of the big-endian
+    /// 32-bit generation of the session key followed by the big-endian 64-bit sequence number of
+    /// the message.
     pub nonce: [u8; NONCE_LENGTH],
     /// Data encrypted using the session key.
     pub data: Vec<u8>,
 }
 
+/// Message sent by the server in response to encrypted data received for a session that does not
+/// exist (e.g. because it expired or was evicted), which signals the client to perform a new
+/// handshake.
+///
+/// The message is not authenticated, so it can only be used to make the client perform a new
+/// handshake.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SessionExpired;
+
+/// Message sent by the server in response to [`ClientIdentity`] if session tickets are enabled,
+/// which allows the client to resume the session with any server that shares the ticket key.
+///
+/// Session tickets are only issued for [`PROTOCOL_VERSION`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionTicket {
+    /// Session ticket sealed by the server, which is opaque to the client.
+    pub ticket: Vec<u8>,
+}
+
+/// Message sent by the client instead of [`ClientHello`] in order to resume a session using a
+/// previously issued [`SessionTicket`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResumeSession {
+    /// Random vector that is used for deriving the session keys of the resumed session.
+    pub random: [u8; REPLAY_PROTECTION_ARRAY_LENGTH],
+    /// Session ticket previously issued by the server.
+    pub ticket: Vec<u8>,
+}
+
+/// Message sent by the server in response to [`ResumeSession`] if the session ticket is valid.
+///
+/// If the session ticket is not valid (e.g. because it expired), the server responds with
+/// [`SessionExpired`] instead, so that the client performs a new handshake.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionResumed {
+    /// Random vector that is used for deriving the session keys of the resumed session.
+    pub random: [u8; REPLAY_PROTECTION_ARRAY_LENGTH],
+}
+
 pub trait Serializable {
     fn serialize(&self) -> anyhow::Result<Vec<u8>>;
 }
@@ -158,52 +297,117 @@ pub trait Deserializable {
 }
 
 impl ClientHello {
-    pub fn new(random: [u8; REPLAY_PROTECTION_ARRAY_LENGTH]) -> Self {
-        Self { random }
+    pub fn new(
+        random: [u8; REPLAY_PROTECTION_ARRAY_LENGTH],
+        supported_versions: Vec<u8>,
+        cipher_suites: Vec<u8>,
+    ) -> Self {
+        Self {
+            random,
+            supported_versions,
+            cipher_suites,
+        }
     }
 
-    const fn len() -> usize {
+    /// Creates a client hello message in the legacy format.
+    pub fn new_legacy(random: [u8; REPLAY_PROTECTION_ARRAY_LENGTH]) -> Self {
+        Self::new(random, Vec::new(), Vec::new())
+    }
+
+    /// Returns whether the message is serialized in the legacy format.
+    pub fn is_legacy(&self) -> bool {
+        self.supported_versions.is_empty() && self.cipher_suites.is_empty()
+    }
+
+    /// Returns the protocol versions offered by the client.
+    pub fn offered_versions(&self) -> &[u8] {
+        if self.is_legacy() {
+            &[LEGACY_PROTOCOL_VERSION]
+        } else {
+            &self.supported_versions
+        }
+    }
+
+    /// Returns the identifiers of the cipher suites offered by the client.
+    pub fn offered_cipher_suites(&self) -> &[u8] {
+        if self.is_legacy() {
+            LEGACY_CIPHER_SUITE_IDS
+        } else {
+            &self.cipher_suites
+        }
+    }
+
+    const fn legacy_len() -> usize {
         MESSAGE_HEADER_LENGTH + REPLAY_PROTECTION_ARRAY_LENGTH
     }
+
+    const fn min_len() -> usize {
+        ClientHello::legacy_len()
+            + VEC_SIZE_PREFIX_LENGTH // for supported_versions
+            + VEC_SIZE_PREFIX_LENGTH // for cipher_suites
+    }
 }
 
 impl Serializable for ClientHello {
     fn serialize(&self) -> anyhow::Result<Vec<u8>> {
-        let mut result = Vec::with_capacity(ClientHello::len());
-        result.put_u8(CLIENT_HELLO_HEADER);
-        result.put_slice(&self.random);
-        Ok(result)
+        if self.is_legacy() {
+            let mut writer = Writer::new(
+                "client hello",
+                CLIENT_HELLO_HEADER,
+                ClientHello::legacy_len(),
+            );
+            writer.write_array(&self.random);
+            return Ok(writer.finish());
+        }
+        let mut writer = Writer::new(
+            "client hello",
+            CLIENT_HELLO_HEADER,
+            ClientHello::min_len() + self.supported_versions.len() + self.cipher_suites.len(),
+        );
+        writer.write_array(&self.random);
+        writer.write_vec(&SUPPORTED_VERSIONS_FIELD, &self.supported_versions)?;
+        writer.write_vec(&CIPHER_SUITES_FIELD, &self.cipher_suites)?;
+        Ok(writer.finish())
     }
 }
 
 impl Deserializable for ClientHello {
     fn deserialize(input: &[u8]) -> anyhow::Result<Self> {
-        if input.len() != ClientHello::len() {
-            bail!(
-                "Invalid client hello message length: expected {}, found {}",
-                ClientHello::len(),
-                input.len(),
-            );
+        let mut reader = Reader::new(input, "client hello", CLIENT_HELLO_HEADER)?;
+        let random = reader.read_array("random")?;
+        if reader.is_empty() {
+            return Ok(Self::new_legacy(*random));
         }
-        let mut input = input;
-        if input.get_u8() != CLIENT_HELLO_HEADER {
-            bail!("Invalid client hello message header");
+
+        let supported_versions = reader.read_vec(&SUPPORTED_VERSIONS_FIELD)?;
+        let cipher_suites = reader.read_vec(&CIPHER_SUITES_FIELD)?;
+        reader.finish()?;
+        // An empty list of protocol versions and cipher suites would be serialized in the legacy
+        // format, so it is rejected to keep the encoding unique.
+        if supported_versions.is_empty() && cipher_suites.is_empty() {
+            bail!("Invalid client hello message: empty protocol versions and cipher suites");
         }
-        let mut random = [0u8; REPLAY_PROTECTION_ARRAY_LENGTH];
-        input.copy_to_slice(&mut random);
-        Ok(Self { random })
+
+        Ok(Self::new(
+            *random,
+            supported_versions.to_vec(),
+            cipher_suites.to_vec(),
+        ))
     }
 }
 
 impl ServerIdentity {
     pub fn new(
+        version: u8,
+        cipher_suite: CipherSuite,
         ephemeral_public_key: [u8; KEY_AGREEMENT_ALGORITHM_KEY_LENGTH],
         random: [u8; REPLAY_PROTECTION_ARRAY_LENGTH],
         signing_public_key: [u8; SIGNING_ALGORITHM_KEY_LENGTH],
         attestation_report: Vec<u8>,
     ) -> Self {
         Self {
-            version: PROTOCOL_VERSION,
+            version,
+            cipher_suite,
             ephemeral_public_key,
             random,
             transcript_signature: [Default::default(); SIGNATURE_LENGTH],
@@ -220,9 +424,16 @@ impl ServerIdentity {
         self.transcript_signature = *transcript_signature;
     }
 
-    const fn min_len() -> usize {
+    /// Returns the minimum message length for the given protocol version.
+    const fn min_len(version: u8) -> usize {
+        let cipher_suite_length = if version == LEGACY_PROTOCOL_VERSION {
+            0
+        } else {
+            CIPHER_SUITE_LENGTH
+        };
         MESSAGE_HEADER_LENGTH
             + PROTOCOL_VERSION_LENGTH
+            + cipher_suite_length
             + KEY_AGREEMENT_ALGORITHM_KEY_LENGTH
             + REPLAY_PROTECTION_ARRAY_LENGTH
             + SIGNATURE_LENGTH
@@ -233,65 +444,57 @@ impl ServerIdentity {
 
 impl Serializable for ServerIdentity {
     fn serialize(&self) -> anyhow::Result<Vec<u8>> {
-        let mut result =
-            Vec::with_capacity(ServerIdentity::min_len() + self.attestation_report.len());
-        result.put_u8(SERVER_IDENTITY_HEADER);
-        result.put_u8(self.version);
-        result.put_slice(&self.ephemeral_public_key);
-        result.put_slice(&self.random);
-        result.put_slice(&self.transcript_signature);
-        result.put_slice(&self.signing_public_key);
-        put_vec(&mut result, &self.attestation_report);
-        Ok(result)
+        let mut writer = Writer::new(
+            "server identity",
+            SERVER_IDENTITY_HEADER,
+            ServerIdentity::min_len(self.version) + self.attestation_report.len(),
+        );
+        writer.write_u8(self.version);
+        match self.version {
+            LEGACY_PROTOCOL_VERSION => {
+                if self.cipher_suite != LEGACY_CIPHER_SUITE {
+                    bail!(
+                        "Cipher suite {:?} is not supported by the legacy protocol version",
+                        self.cipher_suite
+                    );
+                }
+            }
+            PROTOCOL_VERSION => writer.write_u8(self.cipher_suite.id()),
+            version => bail!("Unsupported protocol version: {}", version),
+        }
+        writer.write_array(&self.ephemeral_public_key);
+        writer.write_array(&self.random);
+        writer.write_array(&self.transcript_signature);
+        writer.write_array(&self.signing_public_key);
+        writer.write_vec(&ATTESTATION_REPORT_FIELD, &self.attestation_report)?;
+        Ok(writer.finish())
     }
 }
 
 impl Deserializable for ServerIdentity {
     fn deserialize(input: &[u8]) -> anyhow::Result<Self> {
-        if input.len() < ServerIdentity::min_len() {
-            bail!(
-                "Server identity message too short: expected at least {} bytes, found {}",
-                ServerIdentity::min_len(),
-                input.len(),
-            );
-        }
-        if input.len() > MAXIMUM_MESSAGE_SIZE {
-            bail!(
-                "Maximum handshake message size of {} exceeded, found {}",
-                MAXIMUM_MESSAGE_SIZE,
-                input.len(),
-            );
-        }
-        let mut input = input;
-        if input.get_u8() != SERVER_IDENTITY_HEADER {
-            bail!("Invalid server identity message header");
-        }
-
-        let version = input.get_u8();
-        let mut ephemeral_public_key = [0u8; KEY_AGREEMENT_ALGORITHM_KEY_LENGTH];
-        input.copy_to_slice(&mut ephemeral_public_key);
-        let mut random = [0u8; REPLAY_PROTECTION_ARRAY_LENGTH];
-        input.copy_to_slice(&mut random);
-        let mut transcript_signature = [0u8; SIGNATURE_LENGTH];
-        input.copy_to_slice(&mut transcript_signature);
-        let mut signing_public_key = [0u8; SIGNING_ALGORITHM_KEY_LENGTH];
-        input.copy_to_slice(&mut signing_public_key);
-        let attestation_report = get_vec(&mut input)?;
-
-        if input.has_remaining() {
-            bail!(
-                "Invalid server identity message: {} unused bytes detected",
-                input.remaining()
-            );
-        }
+        let mut reader = Reader::new(input, "server identity", SERVER_IDENTITY_HEADER)?;
+        let version = reader.read_u8("protocol version")?;
+        let cipher_suite = match version {
+            LEGACY_PROTOCOL_VERSION => LEGACY_CIPHER_SUITE,
+            PROTOCOL_VERSION => CipherSuite::from_id(reader.read_u8("cipher suite")?)?,
+            version => bail!("Unsupported protocol version: {}", version),
+        };
+        let ephemeral_public_key = reader.read_array("ephemeral public key")?;
+        let random = reader.read_array("random")?;
+        let transcript_signature = reader.read_array("transcript signature")?;
+        let signing_public_key = reader.read_array("signing public key")?;
+        let attestation_report = reader.read_vec(&ATTESTATION_REPORT_FIELD)?;
+        reader.finish()?;
 
         Ok(Self {
             version,
-            ephemeral_public_key,
-            random,
-            transcript_signature,
-            signing_public_key,
-            attestation_report,
+            cipher_suite,
+            ephemeral_public_key: *ephemeral_public_key,
+            random: *random,
+            transcript_signature: *transcript_signature,
+            signing_public_key: *signing_public_key,
+            attestation_report: attestation_report.to_vec(),
         })
     }
 }
@@ -329,58 +532,33 @@ impl ClientIdentity {
 
 impl Serializable for ClientIdentity {
     fn serialize(&self) -> anyhow::Result<Vec<u8>> {
-        let mut result =
-            Vec::with_capacity(ClientIdentity::min_len() + self.attestation_report.len());
-        result.put_u8(CLIENT_IDENTITY_HEADER);
-        result.put_slice(&self.ephemeral_public_key);
-        result.put_slice(&self.transcript_signature);
-        result.put_slice(&self.signing_public_key);
-        put_vec(&mut result, &self.attestation_report);
-        Ok(result)
+        let mut writer = Writer::new(
+            "client identity",
+            CLIENT_IDENTITY_HEADER,
+            ClientIdentity::min_len() + self.attestation_report.len(),
+        );
+        writer.write_array(&self.ephemeral_public_key);
+        writer.write_array(&self.transcript_signature);
+        writer.write_array(&self.signing_public_key);
+        writer.write_vec(&ATTESTATION_REPORT_FIELD, &self.attestation_report)?;
+        Ok(writer.finish())
     }
 }
 
 impl Deserializable for ClientIdentity {
     fn deserialize(input: &[u8]) -> anyhow::Result<Self> {
-        if input.len() < ClientIdentity::min_len() {
-            bail!(
-                "Client identity message too short: expected at least {} bytes, found {}",
-                ClientIdentity::min_len(),
-                input.len(),
-            );
-        }
-        if input.len() > MAXIMUM_MESSAGE_SIZE {
-            bail!(
-                "Maximum handshake message size of {} exceeded, found {}",
-                MAXIMUM_MESSAGE_SIZE,
-                input.len(),
-            );
-        }
-        let mut input = input;
-        if input.get_u8() != CLIENT_IDENTITY_HEADER {
-            bail!("Invalid client identity message header");
-        }
-
-        let mut ephemeral_public_key = [0u8; KEY_AGREEMENT_ALGORITHM_KEY_LENGTH];
-        input.copy_to_slice(&mut ephemeral_public_key);
-        let mut transcript_signature = [0u8; SIGNATURE_LENGTH];
-        input.copy_to_slice(&mut transcript_signature);
-        let mut signing_public_key = [0u8; SIGNING_ALGORITHM_KEY_LENGTH];
-        input.copy_to_slice(&mut signing_public_key);
-        let attestation_report = get_vec(&mut input)?;
-
-        if input.has_remaining() {
-            bail!(
-                "Invalid client identity message: {} unused bytes detected",
-                input.remaining()
-            );
-        }
+        let mut reader = Reader::new(input, "client identity", CLIENT_IDENTITY_HEADER)?;
+        let ephemeral_public_key = reader.read_array("ephemeral public key")?;
+        let transcript_signature = reader.read_array("transcript signature")?;
+        let signing_public_key = reader.read_array("signing public key")?;
+        let attestation_report = reader.read_vec(&ATTESTATION_REPORT_FIELD)?;
+        reader.finish()?;
 
         Ok(Self {
-            ephemeral_public_key,
-            transcript_signature,
-            signing_public_key,
-            attestation_report,
+            ephemeral_public_key: *ephemeral_public_key,
+            transcript_signature: *transcript_signature,
+            signing_public_key: *signing_public_key,
+            attestation_report: attestation_report.to_vec(),
         })
     }
 }
@@ -397,40 +575,156 @@ impl EncryptedData {
 
 impl Serializable for EncryptedData {
     fn serialize(&self) -> anyhow::Result<Vec<u8>> {
-        let mut result = Vec::with_capacity(EncryptedData::min_len() + self.data.len());
-        result.put_u8(ENCRYPTED_DATA_HEADER);
-        result.put_slice(&self.nonce);
-        put_vec(&mut result, &self.data);
-        Ok(result)
+        let mut writer = Writer::new(
+            "encrypted data",
+            ENCRYPTED_DATA_HEADER,
+            EncryptedData::min_len() + self.data.len(),
+        );
+        writer.write_array(&self.nonce);
+        writer.write_vec(&ENCRYPTED_DATA_FIELD, &self.data)?;
+        Ok(writer.finish())
     }
 }
 
 impl Deserializable for EncryptedData {
     fn deserialize(input: &[u8]) -> anyhow::Result<Self> {
-        if input.len() < EncryptedData::min_len() {
-            bail!(
-                "Encrypted data message too short: expected at least {} bytes, found {}",
-                EncryptedData::min_len(),
-                input.len(),
-            );
-        }
-        let mut input = input;
-        if input.get_u8() != ENCRYPTED_DATA_HEADER {
-            bail!("Invalid encrypted data message header");
-        }
+        let mut reader = Reader::new(input, "encrypted data", ENCRYPTED_DATA_HEADER)?;
+        let nonce = reader.read_array("nonce")?;
+        let data = reader.read_vec(&ENCRYPTED_DATA_FIELD)?;
+        reader.finish()?;
 
-        let mut nonce = [0u8; NONCE_LENGTH];
-        input.copy_to_slice(&mut nonce);
-        let data = get_vec(&mut input)?;
+        Ok(Self {
+            nonce: *nonce,
+            data: data.to_vec(),
+        })
+    }
+}
 
-        if input.has_remaining() {
-            bail!(
-                "Invalid encrypted data message: {} unused bytes detected",
-                input.remaining()
-            );
-        }
+impl SessionExpired {
+    const fn len() -> usize {
+        MESSAGE_HEADER_LENGTH
+    }
+}
+
+impl Serializable for SessionExpired {
+    fn serialize(&self) -> anyhow::Result<Vec<u8>> {
+        let writer = Writer::new(
+            "session expired",
+            SESSION_EXPIRED_HEADER,
+            SessionExpired::len(),
+        );
+        Ok(writer.finish())
+    }
+}
+
+impl Deserializable for SessionExpired {
+    fn deserialize(input: &[u8]) -> anyhow::Result<Self> {
+        let reader = Reader::new(input, "session expired", SESSION_EXPIRED_HEADER)?;
+        reader.finish()?;
+        Ok(Self)
+    }
+}
+
+impl SessionTicket {
+    pub fn new(ticket: Vec<u8>) -> Self {
+        Self { ticket }
+    }
+
+    const fn min_len() -> usize {
+        MESSAGE_HEADER_LENGTH + VEC_SIZE_PREFIX_LENGTH
+    }
+}
+
+impl Serializable for SessionTicket {
+    fn serialize(&self) -> anyhow::Result<Vec<u8>> {
+        let mut writer = Writer::new(
+            "session ticket",
+            SESSION_TICKET_HEADER,
+            SessionTicket::min_len() + self.ticket.len(),
+        );
+        writer.write_vec(&SESSION_TICKET_FIELD, &self.ticket)?;
+        Ok(writer.finish())
+    }
+}
+
+impl Deserializable for SessionTicket {
+    fn deserialize(input: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(input, "session ticket", SESSION_TICKET_HEADER)?;
+        let ticket = reader.read_vec(&SESSION_TICKET_FIELD)?;
+        reader.finish()?;
+
+        Ok(Self {
+            ticket: ticket.to_vec(),
+        })
+    }
+}
 
-        Ok(Self { nonce, data })
+impl ResumeSession {
+    pub fn new(random: [u8; REPLAY_PROTECTION_ARRAY_LENGTH], ticket: Vec<u8>) -> Self {
+        Self { random, ticket }
+    }
+
+    const fn min_len() -> usize {
+        MESSAGE_HEADER_LENGTH + REPLAY_PROTECTION_ARRAY_LENGTH + VEC_SIZE_PREFIX_LENGTH
+    }
+}
+
+impl Serializable for ResumeSession {
+    fn serialize(&self) -> anyhow::Result<Vec<u8>> {
+        let mut writer = Writer::new(
+            "resume session",
+            RESUME_SESSION_HEADER,
+            ResumeSession::min_len() + self.ticket.len(),
+        );
+        writer.write_array(&self.random);
+        writer.write_vec(&SESSION_TICKET_FIELD, &self.ticket)?;
+        Ok(writer.finish())
+    }
+}
+
+impl Deserializable for ResumeSession {
+    fn deserialize(input: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(input, "resume session", RESUME_SESSION_HEADER)?;
+        let random = reader.read_array("random")?;
+        let ticket = reader.read_vec(&SESSION_TICKET_FIELD)?;
+        reader.finish()?;
+
+        Ok(Self {
+            random: *random,
+            ticket: ticket.to_vec(),
+        })
+    }
+}
+
+impl SessionResumed {
+    pub fn new(random: [u8; REPLAY_PROTECTION_ARRAY_LENGTH]) -> Self {
+        Self { random }
+    }
+
+    const fn len() -> usize {
+        MESSAGE_HEADER_LENGTH + REPLAY_PROTECTION_ARRAY_LENGTH
+    }
+}
+
+impl Serializable for SessionResumed {
+    fn serialize(&self) -> anyhow::Result<Vec<u8>> {
+        let mut writer = Writer::new(
+            "session resumed",
+            SESSION_RESUMED_HEADER,
+            SessionResumed::len(),
+        );
+        writer.write_array(&self.random);
+        Ok(writer.finish())
+    }
+}
+
+impl Deserializable for SessionResumed {
+    fn deserialize(input: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(input, "session resumed", SESSION_RESUMED_HEADER)?;
+        let random = reader.read_array("random")?;
+        reader.finish()?;
+
+        Ok(Self { random: *random })
     }
 }
 
@@ -461,23 +755,26 @@ pub fn deserialize_message(input: &[u8]) -> anyhow::Result<MessageWrapper> {
                 .context("Couldn't deserialize encrypted data message")?;
             Ok(MessageWrapper::EncryptedData(message))
         }
+        SESSION_EXPIRED_HEADER => {
+            let message: SessionExpired = Deserializable::deserialize(input)
+                .context("Couldn't deserialize session expired message")?;
+            Ok(MessageWrapper::SessionExpired(message))
+        }
+        SESSION_TICKET_HEADER => {
+            let message: SessionTicket = Deserializable::deserialize(input)
+                .context("Couldn't deserialize session ticket message")?;
+            Ok(MessageWrapper::SessionTicket(message))
+        }
+        RESUME_SESSION_HEADER => {
+            let message: ResumeSession = Deserializable::deserialize(input)
+                .context("Couldn't deserialize resume session message")?;
+            Ok(MessageWrapper::ResumeSession(message))
+        }
+        SESSION_RESUMED_HEADER => {
+            let message: SessionResumed = Deserializable::deserialize(input)
+                .context("Couldn't deserialize session resumed message")?;
+            Ok(MessageWrapper::SessionResumed(message))
+        }
         header => Err(anyhow!("Unknown message header: {:#02x}", header)),
     }
 }
-
-fn put_vec(target: &mut Vec<u8>, source: &[u8]) {
-    target.put_u64_le(source.len() as u64);
-    target.put_slice(source);
-}
-
-fn get_vec(source: &mut &[u8]) -> anyhow::Result<Vec<u8>> {
-    let length = source.get_u64_le();
-    if length > source.remaining() as u64 {
-        bail!(
-            "Invalid vector serialization: required length is {} but only {} bytes provided",
-            length,
-            source.remaining()
-        )
-    }
-    Ok(source.copy_to_bytes(length as usize).to_vec())
-}
@@ -0,0 +1,331 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Resumption of sessions with session tickets.
+//!
+//! If session tickets are enabled, the server responds to [`ClientIdentity`] with a
+//! [`SessionTicket`], which contains the resumption secret of the session sealed with a
+//! [`SessionTicketKey`]. The ticket key is shared by all the servers of a deployment (e.g. by
+//! replicas behind a load balancer, which provision the key to each other after attesting each
+//! other), so that any of them can resume the session without keeping per-session state.
+//!
+//! A session is resumed with the following messages:
+//!
+//! - Client -> Server: [`ResumeSession`]
+//! - Server -> Client: [`SessionResumed`] (or [`SessionExpired`] if the ticket is not valid)
+//!
+//! Both sides derive new session keys from the resumption secret and the random vectors of both
+//! messages, so that each resumed session uses fresh keys. Replaying messages of a previous session
+//! (including [`ResumeSession`]) to any server therefore doesn't allow decrypting them again, and
+//! tickets can only be used until they expire.
+//!
+//! [`ClientIdentity`]: crate::message::ClientIdentity
+//! [`SessionExpired`]: crate::message::SessionExpired
+
+use crate::{
+    crypto::{
+        get_random, AeadEncryptor, CipherSuite, DecryptionKey, EncryptionKey, KeyNegotiator,
+        KeyNegotiatorType, NonceMode, ReplayProtection, ResumptionSecret,
+        AEAD_ALGORITHM_KEY_LENGTH, CLIENT_KEY_PURPOSE, KEY_AGREEMENT_ALGORITHM_KEY_LENGTH,
+        SERVER_KEY_PURPOSE,
+    },
+    handshaker::Encryptor,
+    message::{
+        deserialize_message, MessageWrapper, ResumeSession, Serializable, SessionResumed,
+        LEGACY_PROTOCOL_VERSION, PROTOCOL_VERSION, REPLAY_PROTECTION_ARRAY_LENGTH,
+    },
+};
+use alloc::vec::Vec;
+use anyhow::{anyhow, bail, Context};
+use bytes::{Buf, BufMut};
+use core::{convert::TryInto, time::Duration};
+
+/// Length (in bytes) of a [`SessionTicketKey`].
+pub const SESSION_TICKET_KEY_LENGTH: usize = AEAD_ALGORITHM_KEY_LENGTH;
+
+/// Cipher suite used for sealing session tickets.
+const SESSION_TICKET_CIPHER_SUITE: CipherSuite = CipherSuite::Aes256Gcm;
+
+/// Length (in bytes) of the expiration time in a sealed session ticket.
+const EXPIRATION_TIME_LENGTH: usize = 8;
+
+/// Length (in bytes) of the contents of a session ticket before it is sealed.
+const SESSION_TICKET_CONTENTS_LENGTH: usize =
+    2 + EXPIRATION_TIME_LENGTH + KEY_AGREEMENT_ALGORITHM_KEY_LENGTH;
+
+/// Key used for sealing session tickets, which must be shared by all servers that resume each
+/// other's sessions.
+///
+/// Tickets are sealed with AES-256-GCM using random nonces, so a single key should not be used for
+/// sealing more than 2^32 tickets. Replacing the key invalidates all previously issued tickets, in
+/// which case clients fall back to a new handshake.
+#[derive(Clone)]
+pub struct SessionTicketKey([u8; SESSION_TICKET_KEY_LENGTH]);
+
+impl SessionTicketKey {
+    pub fn new(key: [u8; SESSION_TICKET_KEY_LENGTH]) -> Self {
+        Self(key)
+    }
+
+    /// Generates a random ticket key.
+    pub fn generate() -> anyhow::Result<Self> {
+        Ok(Self(get_random().context("Couldn't generate ticket key")?))
+    }
+
+    fn encryptor(&self) -> anyhow::Result<AeadEncryptor> {
+        AeadEncryptor::new(
+            SESSION_TICKET_CIPHER_SUITE,
+            NonceMode::Random,
+            EncryptionKey(self.0),
+            DecryptionKey(self.0),
+        )
+    }
+}
+
+/// Configuration of the session tickets issued by a server.
+#[derive(Clone)]
+pub struct SessionTickets {
+    /// Key used for sealing and opening session tickets.
+    pub key: SessionTicketKey,
+    /// Duration for which a ticket can be used for resuming sessions, measured from the handshake.
+    pub lifetime: Duration,
+    /// Returns the current time, measured from an arbitrary fixed point in time (e.g. the Unix
+    /// epoch), which must be the same for all servers sharing the ticket key.
+    pub clock: fn() -> Duration,
+}
+
+impl SessionTickets {
+    /// Seals a ticket for a session with the given parameters, which expires after
+    /// [`SessionTickets::lifetime`].
+    pub(crate) fn seal(
+        &self,
+        version: u8,
+        cipher_suite: CipherSuite,
+        resumption_secret: &ResumptionSecret,
+    ) -> anyhow::Result<Vec<u8>> {
+        let expiration_time: u64 = (self.clock)()
+            .saturating_add(self.lifetime)
+            .as_millis()
+            .try_into()
+            .map_err(anyhow::Error::msg)
+            .context("Couldn't convert expiration time")?;
+        let mut contents = Vec::with_capacity(SESSION_TICKET_CONTENTS_LENGTH);
+        contents.put_u8(version);
+        contents.put_u8(cipher_suite.id());
+        contents.put_u64_le(expiration_time);
+        contents.put_slice(&resumption_secret.0);
+        self.key
+            .encryptor()?
+            .encrypt(&contents)
+            .context("Couldn't seal session ticket")?
+            .serialize()
+    }
+
+    /// Opens a ticket sealed by [`SessionTickets::seal`], and returns the protocol version, the
+    /// cipher suite and the resumption secret of the session, together with the expiration time of
+    /// the ticket.
+    ///
+    /// Returns an error if the ticket was not sealed with the same key or has expired.
+    pub(crate) fn open(
+        &self,
+        ticket: &[u8],
+    ) -> anyhow::Result<(u8, CipherSuite, ResumptionSecret, Duration)> {
+        let sealed_ticket =
+            match deserialize_message(ticket).context("Couldn't deserialize session ticket")? {
+                MessageWrapper::EncryptedData(sealed_ticket) => sealed_ticket,
+                message => bail!("Invalid session ticket, found {:?}", message),
+            };
+        let contents = self
+            .key
+            .encryptor()?
+            .decrypt(&sealed_ticket)
+            .context("Couldn't open session ticket")?;
+        if contents.len() != SESSION_TICKET_CONTENTS_LENGTH {
+            bail!(
+                "Incorrect session ticket length: expected {}, found {}",
+                SESSION_TICKET_CONTENTS_LENGTH,
+                contents.len()
+            );
+        }
+        let mut contents = contents.as_slice();
+        let version = contents.get_u8();
+        let cipher_suite = CipherSuite::from_id(contents.get_u8())?;
+        let expiration_time = Duration::from_millis(contents.get_u64_le());
+        let mut resumption_secret = [0u8; KEY_AGREEMENT_ALGORITHM_KEY_LENGTH];
+        contents.copy_to_slice(&mut resumption_secret);
+
+        if version == LEGACY_PROTOCOL_VERSION {
+            bail!("Sessions using the legacy protocol version cannot be resumed");
+        }
+        if (self.clock)() >= expiration_time {
+            bail!("Session ticket expired");
+        }
+        Ok((
+            version,
+            cipher_suite,
+            ResumptionSecret(resumption_secret),
+            expiration_time,
+        ))
+    }
+
+    /// Responds to a [`ResumeSession`] message, and returns the encryptor of the resumed session
+    /// together with a serialized [`SessionResumed`] message.
+    ///
+    /// The resumed session expires together with the ticket, i.e. after
+    /// [`SessionTickets::lifetime`] measured from the original handshake, so the expiration
+    /// time of the ticket is returned as well.
+    pub(crate) fn resume(
+        &self,
+        resume_session: &ResumeSession,
+    ) -> anyhow::Result<(AeadEncryptor, Vec<u8>, Duration)> {
+        let (version, cipher_suite, resumption_secret, expiration_time) = self
+            .open(&resume_session.ticket)
+            .context("Couldn't open session ticket")?;
+        let session_resumed =
+            SessionResumed::new(get_random().context("Couldn't generate random array")?);
+        let encryptor = resumed_encryptor(
+            KeyNegotiatorType::Server,
+            version,
+            cipher_suite,
+            &resumption_secret,
+            &session_resumed.random,
+            &resume_session.random,
+        )
+        .context("Couldn't create encryptor")?;
+        let serialized_session_resumed = session_resumed
+            .serialize()
+            .context("Couldn't serialize session resumed message")?;
+        Ok((encryptor, serialized_session_resumed, expiration_time))
+    }
+}
+
+/// Session ticket received by the client, together with the parameters needed for resuming the
+/// session.
+#[derive(Clone)]
+pub struct ResumptionTicket {
+    ticket: Vec<u8>,
+    cipher_suite: CipherSuite,
+    resumption_secret: ResumptionSecret,
+}
+
+impl ResumptionTicket {
+    pub(crate) fn new(
+        ticket: Vec<u8>,
+        cipher_suite: CipherSuite,
+        resumption_secret: ResumptionSecret,
+    ) -> Self {
+        Self {
+            ticket,
+            cipher_suite,
+            resumption_secret,
+        }
+    }
+}
+
+/// Client side of the session resumption.
+pub struct SessionResumer {
+    ticket: ResumptionTicket,
+    random: [u8; REPLAY_PROTECTION_ARRAY_LENGTH],
+}
+
+impl SessionResumer {
+    /// Starts resuming the session of `ticket`, and returns the resumer together with a serialized
+    /// [`ResumeSession`] message that must be sent to the server.
+    pub fn create(ticket: &ResumptionTicket) -> anyhow::Result<(Self, Vec<u8>)> {
+        let resume_session = ResumeSession::new(
+            get_random().context("Couldn't generate random array")?,
+            ticket.ticket.clone(),
+        );
+        let serialized_resume_session = resume_session
+            .serialize()
+            .context("Couldn't serialize resume session message")?;
+        Ok((
+            Self {
+                ticket: ticket.clone(),
+                random: resume_session.random,
+            },
+            serialized_resume_session,
+        ))
+    }
+
+    /// Processes the response of the server, and returns the encryptor of the resumed session.
+    ///
+    /// Returns [`None`] if the server responded with [`crate::message::SessionExpired`], in which
+    /// case the client must perform a new handshake.
+    pub fn finish(self, message: &[u8]) -> anyhow::Result<Option<Encryptor>> {
+        match deserialize_message(message).context("Couldn't deserialize message")? {
+            MessageWrapper::SessionResumed(session_resumed) => {
+                let encryptor = resumed_encryptor(
+                    KeyNegotiatorType::Client,
+                    PROTOCOL_VERSION,
+                    self.ticket.cipher_suite,
+                    &self.ticket.resumption_secret,
+                    &session_resumed.random,
+                    &self.random,
+                )
+                .context("Couldn't create encryptor")?;
+                Ok(Some(Encryptor::new(encryptor)))
+            }
+            MessageWrapper::SessionExpired(_) => Ok(None),
+            message => Err(anyhow!(
+                "Incorrect session resumption message received, found {:?}",
+                message
+            )),
+        }
+    }
+}
+
+/// Creates the encryptor of a resumed session.
+///
+/// Session keys are derived with HKDF from the resumption secret in the same way as they are
+/// derived from the key material in the handshake, using the random vectors of the server and the
+/// client instead of their public keys.
+fn resumed_encryptor(
+    type_: KeyNegotiatorType,
+    version: u8,
+    cipher_suite: CipherSuite,
+    resumption_secret: &ResumptionSecret,
+    server_random: &[u8; REPLAY_PROTECTION_ARRAY_LENGTH],
+    client_random: &[u8; REPLAY_PROTECTION_ARRAY_LENGTH],
+) -> anyhow::Result<AeadEncryptor> {
+    if version != PROTOCOL_VERSION {
+        bail!("Unsupported resumed protocol version {}", version);
+    }
+    let server_key = KeyNegotiator::key_derivation_function(
+        &resumption_secret.0,
+        SERVER_KEY_PURPOSE,
+        server_random,
+        client_random,
+    )
+    .context("Couldn't derive server session key")?;
+    let client_key = KeyNegotiator::key_derivation_function(
+        &resumption_secret.0,
+        CLIENT_KEY_PURPOSE,
+        server_random,
+        client_random,
+    )
+    .context("Couldn't derive client session key")?;
+    let (encryption_key, decryption_key) = match type_ {
+        KeyNegotiatorType::Server => (EncryptionKey(server_key), DecryptionKey(client_key)),
+        KeyNegotiatorType::Client => (EncryptionKey(client_key), DecryptionKey(server_key)),
+    };
+    AeadEncryptor::new(
+        cipher_suite,
+        NonceMode::Sequential(ReplayProtection::Strict),
+        encryption_key,
+        decryption_key,
+    )
+}
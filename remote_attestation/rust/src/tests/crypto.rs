@@ -16,10 +16,12 @@
 
 use crate::{
     crypto::{
-        get_sha256, AeadEncryptor, DecryptionKey, EncryptionKey, KeyNegotiator, KeyNegotiatorType,
-        SignatureVerifier, Signer, AEAD_ALGORITHM_KEY_LENGTH, CLIENT_KEY_PURPOSE,
-        KEY_AGREEMENT_ALGORITHM_KEY_LENGTH, NONCE_LENGTH, SERVER_KEY_PURPOSE, SHA256_HASH_LENGTH,
-        SIGNATURE_LENGTH, SIGNING_ALGORITHM_KEY_LENGTH,
+        derive_next_session_key, get_sha256, sequential_nonce, AeadEncryptor, CipherSuite,
+        DecryptionKey, EncryptionKey, KeyNegotiator, KeyNegotiatorType, KeyRotation, NonceMode,
+        ReplayProtection, SignatureVerifier, Signer, AEAD_ALGORITHM_KEY_LENGTH, CLIENT_KEY_PURPOSE,
+        KEY_AGREEMENT_ALGORITHM_KEY_LENGTH, MAXIMUM_KEY_GENERATION_GAP, MAXIMUM_REPLAY_WINDOW_SIZE,
+        NONCE_LENGTH, SERVER_KEY_PURPOSE, SHA256_HASH_LENGTH, SIGNATURE_LENGTH,
+        SIGNING_ALGORITHM_KEY_LENGTH,
     },
     message::EncryptedData,
 };
@@ -61,6 +63,32 @@ const ENCRYPTED_DATA: [u8; 26] = [
     255, 242, 249, 3, 114, 107, 147, 122, 38, 153, 12, 33, 62, 56, 172, 90, 234, 207, 50, 219, 22,
     212, 169, 40, 113, 28,
 ];
+// [`DATA`] encrypted with [`CLIENT_ENCRYPTION_KEY`] using ChaCha20-Poly1305.
+const CHACHA20_POLY1305_ENCRYPTED_DATA: [u8; 26] = [
+    255, 122, 163, 166, 130, 70, 138, 51, 89, 60, 145, 253, 213, 64, 148, 29, 97, 42, 43, 45, 65,
+    26, 50, 120, 110, 132,
+];
+// [`DATA`] encrypted with [`CLIENT_ENCRYPTION_KEY`] as the first message with sequential nonces.
+const SEQUENTIAL_ENCRYPTED_DATA: [u8; 26] = [
+    17, 179, 98, 1, 196, 136, 123, 242, 147, 26, 3, 226, 87, 29, 141, 36, 165, 135, 252, 155, 201,
+    167, 44, 98, 172, 182,
+];
+// [`DATA`] encrypted with [`CLIENT_ENCRYPTION_KEY`] as the second message with sequential nonces.
+const SECOND_SEQUENTIAL_ENCRYPTED_DATA: [u8; 26] = [
+    176, 63, 174, 184, 64, 217, 214, 45, 16, 100, 185, 183, 144, 228, 16, 240, 22, 28, 99, 192,
+    173, 51, 8, 13, 175, 132,
+];
+// [`DATA`] encrypted with the next generation of [`CLIENT_ENCRYPTION_KEY`] as the second message
+// with sequential nonces.
+const ROTATED_SEQUENTIAL_ENCRYPTED_DATA: [u8; 26] = [
+    165, 252, 104, 152, 113, 194, 21, 29, 60, 160, 163, 224, 48, 122, 58, 122, 245, 220, 95, 118,
+    60, 75, 96, 134, 236, 54,
+];
+// Next generation of [`CLIENT_ENCRYPTION_KEY`].
+const NEXT_CLIENT_ENCRYPTION_KEY: [u8; AEAD_ALGORITHM_KEY_LENGTH] = [
+    227, 3, 101, 173, 3, 168, 58, 209, 239, 181, 0, 92, 230, 135, 157, 101, 138, 171, 180, 182,
+    164, 150, 238, 137, 155, 196, 157, 238, 122, 186, 49, 20,
+];
 const INVALID_ENCRYPTED_DATA: [u8; 26] = [0; 26];
 const ENCRYPTED_DATA_NONCE: [u8; NONCE_LENGTH] =
     [106, 176, 114, 112, 226, 142, 211, 123, 95, 187, 120, 206];
@@ -79,9 +107,12 @@ const DATA_SHA256_HASH: [u8; SHA256_HASH_LENGTH] = [
 #[test]
 fn test_decrypt() {
     let mut encryptor = AeadEncryptor::new(
+        CipherSuite::Aes256Gcm,
+        NonceMode::Random,
         EncryptionKey(SERVER_ENCRYPTION_KEY),
         DecryptionKey(CLIENT_ENCRYPTION_KEY),
-    );
+    )
+    .unwrap();
     let result = encryptor.decrypt(&EncryptedData::new(
         ENCRYPTED_DATA_NONCE,
         ENCRYPTED_DATA.to_vec(),
@@ -100,24 +131,348 @@ fn test_decrypt() {
     assert!(result.is_err());
 }
 
+#[test]
+fn test_decrypt_chacha20_poly1305() {
+    let mut encryptor = AeadEncryptor::new(
+        CipherSuite::ChaCha20Poly1305,
+        NonceMode::Random,
+        EncryptionKey(SERVER_ENCRYPTION_KEY),
+        DecryptionKey(CLIENT_ENCRYPTION_KEY),
+    )
+    .unwrap();
+    let result = encryptor.decrypt(&EncryptedData::new(
+        ENCRYPTED_DATA_NONCE,
+        CHACHA20_POLY1305_ENCRYPTED_DATA.to_vec(),
+    ));
+    assert_eq!(result.unwrap(), DATA);
+
+    // Data encrypted with a different cipher suite is rejected.
+    let result = encryptor.decrypt(&EncryptedData::new(
+        ENCRYPTED_DATA_NONCE,
+        ENCRYPTED_DATA.to_vec(),
+    ));
+    assert!(result.is_err());
+}
+
 #[quickcheck]
 fn test_encrypt(data: Vec<u8>) -> bool {
-    let mut server_encryptor = AeadEncryptor::new(
+    [CipherSuite::Aes256Gcm, CipherSuite::ChaCha20Poly1305]
+        .iter()
+        .all(|cipher_suite| {
+            let mut server_encryptor = AeadEncryptor::new(
+                *cipher_suite,
+                NonceMode::Sequential(ReplayProtection::Strict),
+                EncryptionKey(SERVER_ENCRYPTION_KEY),
+                DecryptionKey(CLIENT_ENCRYPTION_KEY),
+            )
+            .unwrap();
+            let mut client_encryptor = AeadEncryptor::new(
+                *cipher_suite,
+                NonceMode::Sequential(ReplayProtection::Strict),
+                EncryptionKey(CLIENT_ENCRYPTION_KEY),
+                DecryptionKey(SERVER_ENCRYPTION_KEY),
+            )
+            .unwrap();
+
+            let encrypted_data = server_encryptor
+                .encrypt(&data)
+                .expect("Couldn't encrypt data");
+            let decrypted_data = client_encryptor
+                .decrypt(&encrypted_data)
+                .expect("Couldn't decrypt data");
+            data == decrypted_data
+        })
+}
+
+/// Creates a client and a server encryptor with sequential nonces, where the server applies the
+/// given replay protection.
+fn create_sequential_encryptors(
+    replay_protection: ReplayProtection,
+) -> (AeadEncryptor, AeadEncryptor) {
+    let client_encryptor = AeadEncryptor::new(
+        CipherSuite::Aes256Gcm,
+        NonceMode::Sequential(ReplayProtection::Strict),
+        EncryptionKey(CLIENT_ENCRYPTION_KEY),
+        DecryptionKey(SERVER_ENCRYPTION_KEY),
+    )
+    .unwrap();
+    let server_encryptor = AeadEncryptor::new(
+        CipherSuite::Aes256Gcm,
+        NonceMode::Sequential(replay_protection),
         EncryptionKey(SERVER_ENCRYPTION_KEY),
         DecryptionKey(CLIENT_ENCRYPTION_KEY),
+    )
+    .unwrap();
+    (client_encryptor, server_encryptor)
+}
+
+#[test]
+fn test_sequential_nonce() {
+    assert_eq!(sequential_nonce(0, 0), [0; NONCE_LENGTH]);
+    assert_eq!(
+        sequential_nonce(0x090a0b0c, 0x0102030405060708),
+        [9, 10, 11, 12, 1, 2, 3, 4, 5, 6, 7, 8]
     );
-    let mut client_encryptor = AeadEncryptor::new(
-        EncryptionKey(CLIENT_ENCRYPTION_KEY),
-        DecryptionKey(SERVER_ENCRYPTION_KEY),
+}
+
+#[test]
+fn test_encrypt_sequential() {
+    // Sequential nonces must be encoded identically by all crypto implementations.
+    let (mut client_encryptor, _) = create_sequential_encryptors(ReplayProtection::Strict);
+    let encrypted_data = client_encryptor.encrypt(&DATA).unwrap();
+    assert_eq!(
+        encrypted_data,
+        EncryptedData::new(sequential_nonce(0, 0), SEQUENTIAL_ENCRYPTED_DATA.to_vec())
     );
+    let encrypted_data = client_encryptor.encrypt(&DATA).unwrap();
+    assert_eq!(
+        encrypted_data,
+        EncryptedData::new(
+            sequential_nonce(0, 1),
+            SECOND_SEQUENTIAL_ENCRYPTED_DATA.to_vec()
+        )
+    );
+}
+
+#[test]
+fn test_decrypt_replayed() {
+    let (mut client_encryptor, mut server_encryptor) =
+        create_sequential_encryptors(ReplayProtection::Strict);
+    let first = client_encryptor.encrypt(&DATA).unwrap();
+    let second = client_encryptor.encrypt(&DATA).unwrap();
+
+    assert_eq!(server_encryptor.decrypt(&first).unwrap(), DATA);
+    assert!(server_encryptor.decrypt(&first).is_err());
+    assert_eq!(server_encryptor.decrypt(&second).unwrap(), DATA);
+    assert!(server_encryptor.decrypt(&second).is_err());
+}
 
-    let encrypted_data = server_encryptor
-        .encrypt(&data)
-        .expect("Couldn't encrypt data");
-    let decrypted_data = client_encryptor
-        .decrypt(&encrypted_data)
-        .expect("Couldn't decrypt data");
-    data == decrypted_data
+#[test]
+fn test_decrypt_reordered() {
+    let (mut client_encryptor, mut server_encryptor) =
+        create_sequential_encryptors(ReplayProtection::Strict);
+    let first = client_encryptor.encrypt(&DATA).unwrap();
+    let second = client_encryptor.encrypt(&DATA).unwrap();
+    let third = client_encryptor.encrypt(&DATA).unwrap();
+
+    assert!(server_encryptor.decrypt(&second).is_err());
+    assert_eq!(server_encryptor.decrypt(&first).unwrap(), DATA);
+    // Dropped messages are detected when the following message is received.
+    assert!(server_encryptor.decrypt(&third).is_err());
+    assert_eq!(server_encryptor.decrypt(&second).unwrap(), DATA);
+    assert_eq!(server_encryptor.decrypt(&third).unwrap(), DATA);
+}
+
+#[test]
+fn test_decrypt_truncated() {
+    let (mut client_encryptor, mut server_encryptor) =
+        create_sequential_encryptors(ReplayProtection::Strict);
+    let encrypted_data = client_encryptor.encrypt(&DATA).unwrap();
+
+    let mut truncated_data = encrypted_data.clone();
+    truncated_data.data.pop();
+    assert!(server_encryptor.decrypt(&truncated_data).is_err());
+    let truncated_data = EncryptedData::new(encrypted_data.nonce, Vec::new());
+    assert!(server_encryptor.decrypt(&truncated_data).is_err());
+
+    // Messages that fail authentication don't change the expected sequence number.
+    assert_eq!(server_encryptor.decrypt(&encrypted_data).unwrap(), DATA);
+}
+
+#[test]
+fn test_decrypt_sliding_window() {
+    let (mut client_encryptor, mut server_encryptor) =
+        create_sequential_encryptors(ReplayProtection::SlidingWindow(2));
+    let messages: Vec<EncryptedData> = (0..5)
+        .map(|_| client_encryptor.encrypt(&DATA).unwrap())
+        .collect();
+
+    // Messages can be received out of order and with gaps.
+    assert_eq!(server_encryptor.decrypt(&messages[1]).unwrap(), DATA);
+    assert_eq!(server_encryptor.decrypt(&messages[0]).unwrap(), DATA);
+    assert_eq!(server_encryptor.decrypt(&messages[3]).unwrap(), DATA);
+    assert!(server_encryptor.decrypt(&messages[3]).is_err());
+    assert_eq!(server_encryptor.decrypt(&messages[2]).unwrap(), DATA);
+    assert!(server_encryptor.decrypt(&messages[2]).is_err());
+    // Messages older than the window are rejected.
+    assert!(server_encryptor.decrypt(&messages[0]).is_err());
+    assert_eq!(server_encryptor.decrypt(&messages[4]).unwrap(), DATA);
+    assert!(server_encryptor.decrypt(&messages[2]).is_err());
+}
+
+#[test]
+fn test_set_replay_protection() {
+    let (mut client_encryptor, mut server_encryptor) =
+        create_sequential_encryptors(ReplayProtection::Strict);
+    assert!(server_encryptor
+        .set_replay_protection(ReplayProtection::SlidingWindow(0))
+        .is_err());
+    assert!(server_encryptor
+        .set_replay_protection(ReplayProtection::SlidingWindow(65))
+        .is_err());
+    assert!(server_encryptor
+        .set_replay_protection(ReplayProtection::SlidingWindow(64))
+        .is_ok());
+
+    let first = client_encryptor.encrypt(&DATA).unwrap();
+    let second = client_encryptor.encrypt(&DATA).unwrap();
+    assert_eq!(server_encryptor.decrypt(&second).unwrap(), DATA);
+    assert_eq!(server_encryptor.decrypt(&first).unwrap(), DATA);
+}
+
+#[test]
+fn test_derive_next_session_key() {
+    let next_key = derive_next_session_key(&CLIENT_ENCRYPTION_KEY);
+    assert!(next_key.is_ok());
+    assert_eq!(next_key.unwrap(), NEXT_CLIENT_ENCRYPTION_KEY);
+}
+
+#[test]
+fn test_key_rotation() {
+    let (mut client_encryptor, mut server_encryptor) =
+        create_sequential_encryptors(ReplayProtection::Strict);
+    client_encryptor
+        .set_key_rotation(KeyRotation {
+            max_messages: 1,
+            max_bytes: u64::MAX,
+        })
+        .unwrap();
+
+    // Rotated keys must be derived identically by all crypto implementations.
+    let first = client_encryptor.encrypt(&DATA).unwrap();
+    assert_eq!(
+        first,
+        EncryptedData::new(sequential_nonce(0, 0), SEQUENTIAL_ENCRYPTED_DATA.to_vec())
+    );
+    let second = client_encryptor.encrypt(&DATA).unwrap();
+    assert_eq!(
+        second,
+        EncryptedData::new(
+            sequential_nonce(1, 1),
+            ROTATED_SEQUENTIAL_ENCRYPTED_DATA.to_vec()
+        )
+    );
+
+    assert_eq!(server_encryptor.decrypt(&first).unwrap(), DATA);
+    assert_eq!(server_encryptor.decrypt(&second).unwrap(), DATA);
+}
+
+#[test]
+fn test_key_rotation_bytes() {
+    let (mut client_encryptor, mut server_encryptor) =
+        create_sequential_encryptors(ReplayProtection::Strict);
+    client_encryptor
+        .set_key_rotation(KeyRotation {
+            max_messages: u64::MAX,
+            max_bytes: 2 * DATA.len() as u64,
+        })
+        .unwrap();
+
+    for (sequence_number, key_generation) in [0, 0, 1, 1, 2].iter().enumerate() {
+        let encrypted_data = client_encryptor.encrypt(&DATA).unwrap();
+        assert_eq!(
+            encrypted_data.nonce,
+            sequential_nonce(*key_generation, sequence_number as u64)
+        );
+        assert_eq!(server_encryptor.decrypt(&encrypted_data).unwrap(), DATA);
+    }
+}
+
+#[test]
+fn test_key_rotation_reordered() {
+    let (mut client_encryptor, mut server_encryptor) =
+        create_sequential_encryptors(ReplayProtection::SlidingWindow(4));
+    client_encryptor
+        .set_key_rotation(KeyRotation {
+            max_messages: 1,
+            max_bytes: u64::MAX,
+        })
+        .unwrap();
+    let messages: Vec<EncryptedData> = (0..3)
+        .map(|_| client_encryptor.encrypt(&DATA).unwrap())
+        .collect();
+
+    // Keys of newer generations are derived from the current key.
+    assert_eq!(server_encryptor.decrypt(&messages[2]).unwrap(), DATA);
+    // The key of the preceding generation is kept.
+    assert_eq!(server_encryptor.decrypt(&messages[1]).unwrap(), DATA);
+    // Older keys are discarded.
+    assert!(server_encryptor.decrypt(&messages[0]).is_err());
+}
+
+#[test]
+fn test_key_generation_gap() {
+    let (mut client_encryptor, mut server_encryptor) =
+        create_sequential_encryptors(ReplayProtection::SlidingWindow(MAXIMUM_REPLAY_WINDOW_SIZE));
+    client_encryptor
+        .set_key_rotation(KeyRotation {
+            max_messages: 1,
+            max_bytes: u64::MAX,
+        })
+        .unwrap();
+    let messages: Vec<EncryptedData> = (0..MAXIMUM_KEY_GENERATION_GAP + 2)
+        .map(|_| client_encryptor.encrypt(&DATA).unwrap())
+        .collect();
+
+    let last_message = &messages[MAXIMUM_KEY_GENERATION_GAP as usize + 1];
+    assert!(server_encryptor.decrypt(last_message).is_err());
+    let last_reachable_message = &messages[MAXIMUM_KEY_GENERATION_GAP as usize];
+    assert_eq!(
+        server_encryptor.decrypt(last_reachable_message).unwrap(),
+        DATA
+    );
+    assert_eq!(server_encryptor.decrypt(last_message).unwrap(), DATA);
+}
+
+#[test]
+fn test_set_key_rotation() {
+    let (mut client_encryptor, _) = create_sequential_encryptors(ReplayProtection::Strict);
+    assert!(client_encryptor
+        .set_key_rotation(KeyRotation {
+            max_messages: 0,
+            max_bytes: u64::MAX,
+        })
+        .is_err());
+    assert!(client_encryptor
+        .set_key_rotation(KeyRotation {
+            max_messages: u64::MAX,
+            max_bytes: 0,
+        })
+        .is_err());
+}
+
+#[test]
+fn test_decrypt_random_nonces() {
+    // Random nonces are only used by the legacy protocol version, which doesn't detect replays.
+    let mut encryptor = AeadEncryptor::new(
+        CipherSuite::Aes256Gcm,
+        NonceMode::Random,
+        EncryptionKey(SERVER_ENCRYPTION_KEY),
+        DecryptionKey(CLIENT_ENCRYPTION_KEY),
+    )
+    .unwrap();
+    let encrypted_data = EncryptedData::new(ENCRYPTED_DATA_NONCE, ENCRYPTED_DATA.to_vec());
+    assert_eq!(encryptor.decrypt(&encrypted_data).unwrap(), DATA);
+    assert_eq!(encryptor.decrypt(&encrypted_data).unwrap(), DATA);
+
+    // Keys are never rotated, since the nonces can't contain the key generation.
+    encryptor
+        .set_key_rotation(KeyRotation {
+            max_messages: 1,
+            max_bytes: 1,
+        })
+        .unwrap();
+    let mut peer_encryptor = AeadEncryptor::new(
+        CipherSuite::Aes256Gcm,
+        NonceMode::Random,
+        EncryptionKey(CLIENT_ENCRYPTION_KEY),
+        DecryptionKey(SERVER_ENCRYPTION_KEY),
+    )
+    .unwrap();
+    for _ in 0..3 {
+        let encrypted_data = encryptor.encrypt(&DATA).unwrap();
+        assert_eq!(peer_encryptor.decrypt(&encrypted_data).unwrap(), DATA);
+    }
 }
 
 #[test]
@@ -164,14 +519,17 @@ fn test_derive_session_keys() {
 
     let result = server_key_negotiator.derive_session_keys(&client_ephemeral_public_key);
     assert!(result.is_ok());
-    let (server_encryption_key, server_decryption_key) = result.unwrap();
+    let (server_encryption_key, server_decryption_key, server_resumption_secret) = result.unwrap();
 
     let result = client_key_negotiator.derive_session_keys(&server_ephemeral_public_key);
     assert!(result.is_ok());
-    let (client_encryption_key, client_decryption_key) = result.unwrap();
+    let (client_encryption_key, client_decryption_key, client_resumption_secret) = result.unwrap();
 
     assert_eq!(server_encryption_key.0, client_decryption_key.0);
     assert_eq!(server_decryption_key.0, client_encryption_key.0);
+    assert_eq!(server_resumption_secret.0, client_resumption_secret.0);
+    assert_ne!(server_resumption_secret.0, server_encryption_key.0);
+    assert_ne!(server_resumption_secret.0, server_decryption_key.0);
 }
 
 #[test]
@@ -183,10 +541,18 @@ fn test_create_encryptor() {
     let client_ephemeral_public_key = client_key_negotiator.public_key().unwrap();
 
     let mut server_encryptor = server_key_negotiator
-        .create_encryptor(&client_ephemeral_public_key)
+        .create_encryptor(
+            &client_ephemeral_public_key,
+            CipherSuite::Aes256Gcm,
+            NonceMode::Sequential(ReplayProtection::Strict),
+        )
         .expect("Couldn't create server encryptor");
     let mut client_encryptor = client_key_negotiator
-        .create_encryptor(&server_ephemeral_public_key)
+        .create_encryptor(
+            &server_ephemeral_public_key,
+            CipherSuite::Aes256Gcm,
+            NonceMode::Sequential(ReplayProtection::Strict),
+        )
         .expect("Couldn't create client encryptor");
 
     let encrypted_server_data = server_encryptor.encrypt(&DATA).unwrap();
@@ -15,17 +15,27 @@
 //
 
 use crate::{
-    crypto::{get_sha256, SHA256_HASH_LENGTH},
+    crypto::{get_sha256, CipherSuite, SHA256_HASH_LENGTH, SUPPORTED_CIPHER_SUITES},
+    evidence::RejectedEvidence,
     handshaker::{
         hash_concat_hash, AttestationBehavior, AttestationGenerator, AttestationVerifier,
-        ClientHandshaker, ServerHandshaker,
+        ClientHandshaker, EmptyAttestationGenerator, EmptyAttestationVerifier, ServerHandshaker,
     },
+    message::{
+        deserialize_message, ClientHello, MessageWrapper, Serializable, ServerIdentity,
+        SessionExpired, LEGACY_PROTOCOL_VERSION, PROTOCOL_VERSION,
+    },
+    resumption::{ResumptionTicket, SessionResumer, SessionTicketKey, SessionTickets},
     tests::message::INVALID_MESSAGE_HEADER,
 };
 use alloc::vec;
 use assert_matches::assert_matches;
+use core::time::Duration;
 
 const DATA: [u8; 10] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+const SESSION_TICKET_KEY: [u8; 32] = [1; 32];
+const OTHER_SESSION_TICKET_KEY: [u8; 32] = [2; 32];
+const SESSION_TICKET_LIFETIME: Duration = Duration::from_secs(3600);
 
 /// An attestation generator that simply uses the provided data as the attestation itself (no
 /// signature or any other verification is performed).
@@ -62,19 +72,87 @@ impl AttestationVerifier for TestAttestationVerifier {
 fn create_handshakers() -> (
     ClientHandshaker<TestAttestationGenerator, TestAttestationVerifier>,
     ServerHandshaker<TestAttestationGenerator, TestAttestationVerifier>,
+) {
+    create_handshakers_with_cipher_suites(SUPPORTED_CIPHER_SUITES, SUPPORTED_CIPHER_SUITES)
+}
+
+fn create_handshakers_with_cipher_suites(
+    client_cipher_suites: &[CipherSuite],
+    server_cipher_suites: &[CipherSuite],
+) -> (
+    ClientHandshaker<TestAttestationGenerator, TestAttestationVerifier>,
+    ServerHandshaker<TestAttestationGenerator, TestAttestationVerifier>,
 ) {
     let bidirectional_attestation =
         AttestationBehavior::create(TestAttestationGenerator, TestAttestationVerifier);
-    let client_handshaker = ClientHandshaker::new(bidirectional_attestation).unwrap();
+    let client_handshaker =
+        ClientHandshaker::new_with_cipher_suites(bidirectional_attestation, client_cipher_suites)
+            .unwrap();
 
     let bidirectional_attestation =
         AttestationBehavior::create(TestAttestationGenerator, TestAttestationVerifier);
 
-    let server_handshaker = ServerHandshaker::new(bidirectional_attestation).unwrap();
+    let server_handshaker =
+        ServerHandshaker::new_with_cipher_suites(bidirectional_attestation, server_cipher_suites)
+            .unwrap();
 
     (client_handshaker, server_handshaker)
 }
 
+fn handshake_clock() -> Duration {
+    Duration::from_secs(1_000_000)
+}
+
+fn resumption_clock() -> Duration {
+    handshake_clock() + SESSION_TICKET_LIFETIME / 2
+}
+
+fn expired_clock() -> Duration {
+    handshake_clock() + SESSION_TICKET_LIFETIME
+}
+
+fn session_tickets(key: [u8; 32], clock: fn() -> Duration) -> SessionTickets {
+    SessionTickets {
+        key: SessionTicketKey::new(key),
+        lifetime: SESSION_TICKET_LIFETIME,
+        clock,
+    }
+}
+
+/// Performs a handshake with a server that issues session tickets, and returns the session ticket
+/// received by the client.
+fn handshake_with_session_tickets() -> ResumptionTicket {
+    let (mut client_handshaker, mut server_handshaker) = create_handshakers();
+    server_handshaker.set_session_tickets(session_tickets(SESSION_TICKET_KEY, handshake_clock));
+    let client_hello = client_handshaker.create_client_hello().unwrap();
+    let server_identity = server_handshaker.next_step(&client_hello).unwrap().unwrap();
+    let client_identity = client_handshaker
+        .next_step(&server_identity)
+        .unwrap()
+        .unwrap();
+    let session_ticket = server_handshaker
+        .next_step(&client_identity)
+        .unwrap()
+        .unwrap();
+    assert!(server_handshaker.is_completed());
+    assert_matches!(
+        deserialize_message(&session_ticket),
+        Ok(MessageWrapper::SessionTicket(_))
+    );
+    assert!(client_handshaker.get_session_ticket().is_none());
+    let result = client_handshaker.next_step(&session_ticket);
+    assert_matches!(result, Ok(None));
+    assert!(client_handshaker.is_completed());
+    client_handshaker.get_session_ticket().unwrap()
+}
+
+fn deserialize_server_identity(message: &[u8]) -> ServerIdentity {
+    match deserialize_message(message).expect("Couldn't deserialize server identity") {
+        MessageWrapper::ServerIdentity(server_identity) => server_identity,
+        message => panic!("Expected server identity, found {:?}", message),
+    }
+}
+
 #[test]
 fn test_handshake() {
     let (mut client_handshaker, mut server_handshaker) = create_handshakers();
@@ -124,6 +202,305 @@ fn test_handshake() {
     assert_eq!(decrypted_server_data, DATA);
 }
 
+#[test]
+fn test_cipher_suite_negotiation() {
+    // The server selects its most preferred cipher suite among the offered ones.
+    let (mut client_handshaker, mut server_handshaker) = create_handshakers_with_cipher_suites(
+        &[CipherSuite::Aes256Gcm, CipherSuite::ChaCha20Poly1305],
+        &[CipherSuite::ChaCha20Poly1305, CipherSuite::Aes256Gcm],
+    );
+    let client_hello = client_handshaker.create_client_hello().unwrap();
+    let server_identity = server_handshaker.next_step(&client_hello).unwrap().unwrap();
+    let deserialized_server_identity = deserialize_server_identity(&server_identity);
+    assert_eq!(deserialized_server_identity.version, PROTOCOL_VERSION);
+    assert_eq!(
+        deserialized_server_identity.cipher_suite,
+        CipherSuite::ChaCha20Poly1305
+    );
+
+    let client_identity = client_handshaker
+        .next_step(&server_identity)
+        .unwrap()
+        .unwrap();
+    server_handshaker.next_step(&client_identity).unwrap();
+
+    let mut client_encryptor = client_handshaker.get_encryptor().unwrap();
+    let mut server_encryptor = server_handshaker.get_encryptor().unwrap();
+    let encrypted_client_data = client_encryptor.encrypt(&DATA).unwrap();
+    let decrypted_client_data = server_encryptor.decrypt(&encrypted_client_data).unwrap();
+    assert_eq!(decrypted_client_data, DATA);
+}
+
+#[test]
+fn test_no_common_cipher_suite() {
+    let (mut client_handshaker, mut server_handshaker) = create_handshakers_with_cipher_suites(
+        &[CipherSuite::ChaCha20Poly1305],
+        &[CipherSuite::Aes256Gcm],
+    );
+    let client_hello = client_handshaker.create_client_hello().unwrap();
+    let result = server_handshaker.next_step(&client_hello);
+    assert_matches!(result, Err(_));
+    assert!(server_handshaker.is_aborted());
+}
+
+#[test]
+fn test_legacy_client_hello() {
+    let (_, mut server_handshaker) = create_handshakers();
+
+    // Clients that don't support negotiation get a server identity in the legacy format.
+    let client_hello = ClientHello::new_legacy([1; 32]).serialize().unwrap();
+    let server_identity = server_handshaker.next_step(&client_hello).unwrap().unwrap();
+    let deserialized_server_identity = deserialize_server_identity(&server_identity);
+    assert_eq!(
+        deserialized_server_identity.version,
+        LEGACY_PROTOCOL_VERSION
+    );
+    assert_eq!(
+        deserialized_server_identity.cipher_suite,
+        CipherSuite::Aes256Gcm
+    );
+
+    // The legacy protocol version can't be negotiated if the server doesn't accept its cipher
+    // suite.
+    let (_, mut server_handshaker) = create_handshakers_with_cipher_suites(
+        SUPPORTED_CIPHER_SUITES,
+        &[CipherSuite::ChaCha20Poly1305],
+    );
+    let result = server_handshaker.next_step(&client_hello);
+    assert_matches!(result, Err(_));
+}
+
+#[test]
+fn test_downgraded_client_hello() {
+    let (mut client_handshaker, mut server_handshaker) = create_handshakers();
+
+    // The network replaces the offer with a legacy client hello, which makes the server select the
+    // legacy protocol version.
+    let client_hello = client_handshaker.create_client_hello().unwrap();
+    let random = match deserialize_message(&client_hello).unwrap() {
+        MessageWrapper::ClientHello(client_hello) => client_hello.random,
+        message => panic!("Expected client hello, found {:?}", message),
+    };
+    let downgraded_client_hello = ClientHello::new_legacy(random).serialize().unwrap();
+    let server_identity = server_handshaker
+        .next_step(&downgraded_client_hello)
+        .unwrap()
+        .unwrap();
+
+    // The client detects the modification, since the server signed a different transcript.
+    let result = client_handshaker.next_step(&server_identity);
+    assert_matches!(result, Err(_));
+    assert!(client_handshaker.is_aborted());
+}
+
+#[test]
+fn test_replay_encrypted_message() {
+    let (mut client_handshaker, mut server_handshaker) = create_handshakers();
+    let client_hello = client_handshaker.create_client_hello().unwrap();
+    let server_identity = server_handshaker.next_step(&client_hello).unwrap().unwrap();
+    let client_identity = client_handshaker
+        .next_step(&server_identity)
+        .unwrap()
+        .unwrap();
+    server_handshaker.next_step(&client_identity).unwrap();
+    let mut client_encryptor = client_handshaker.get_encryptor().unwrap();
+    let mut server_encryptor = server_handshaker.get_encryptor().unwrap();
+
+    let encrypted_client_data = client_encryptor.encrypt(&DATA).unwrap();
+    assert_eq!(
+        server_encryptor.decrypt(&encrypted_client_data).unwrap(),
+        DATA
+    );
+    let result = server_encryptor.decrypt(&encrypted_client_data);
+    assert_matches!(result, Err(_));
+}
+
+#[test]
+fn test_session_expired() {
+    let (mut client_handshaker, mut server_handshaker) = create_handshakers();
+    let client_hello = client_handshaker.create_client_hello().unwrap();
+    let server_identity = server_handshaker.next_step(&client_hello).unwrap().unwrap();
+    let client_identity = client_handshaker
+        .next_step(&server_identity)
+        .unwrap()
+        .unwrap();
+    server_handshaker.next_step(&client_identity).unwrap();
+    let mut client_encryptor = client_handshaker.get_encryptor().unwrap();
+    let encrypted_client_data = client_encryptor.encrypt(&DATA).unwrap();
+
+    // A server handshaker for an unknown session asks the client to perform a new handshake.
+    let (mut client_handshaker, mut server_handshaker) = create_handshakers();
+    let response = server_handshaker
+        .next_step(&encrypted_client_data)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        deserialize_message(&response).unwrap(),
+        MessageWrapper::SessionExpired(SessionExpired)
+    );
+    assert!(!server_handshaker.is_aborted());
+    let result = client_encryptor.decrypt(&response);
+    assert_matches!(result, Err(_));
+
+    let client_hello = client_handshaker.create_client_hello().unwrap();
+    let result = server_handshaker.next_step(&client_hello);
+    assert_matches!(result, Ok(Some(_)));
+}
+
+#[test]
+fn test_session_resumption() {
+    let ticket = handshake_with_session_tickets();
+
+    // A different server sharing the ticket key resumes the session.
+    let (_, mut server_handshaker) = create_handshakers();
+    server_handshaker.set_session_tickets(session_tickets(SESSION_TICKET_KEY, resumption_clock));
+    let (session_resumer, resume_session) = SessionResumer::create(&ticket).unwrap();
+    let session_resumed = server_handshaker
+        .next_step(&resume_session)
+        .unwrap()
+        .unwrap();
+    assert!(server_handshaker.is_completed());
+    // The resumed session expires together with the ticket issued in the handshake.
+    assert_eq!(
+        server_handshaker.get_resumed_session_expiration_time(),
+        Some(handshake_clock() + SESSION_TICKET_LIFETIME)
+    );
+    let mut server_encryptor = server_handshaker.get_encryptor().unwrap();
+    let mut client_encryptor = session_resumer.finish(&session_resumed).unwrap().unwrap();
+
+    let encrypted_client_data = client_encryptor.encrypt(&DATA).unwrap();
+    let decrypted_client_data = server_encryptor.decrypt(&encrypted_client_data).unwrap();
+    assert_eq!(decrypted_client_data, DATA);
+
+    let encrypted_server_data = server_encryptor.encrypt(&DATA).unwrap();
+    let decrypted_server_data = client_encryptor.decrypt(&encrypted_server_data).unwrap();
+    assert_eq!(decrypted_server_data, DATA);
+}
+
+#[test]
+fn test_replay_resume_session() {
+    let ticket = handshake_with_session_tickets();
+    let (session_resumer, resume_session) = SessionResumer::create(&ticket).unwrap();
+
+    let (_, mut server_handshaker) = create_handshakers();
+    server_handshaker.set_session_tickets(session_tickets(SESSION_TICKET_KEY, resumption_clock));
+    let session_resumed = server_handshaker
+        .next_step(&resume_session)
+        .unwrap()
+        .unwrap();
+    let mut client_encryptor = session_resumer.finish(&session_resumed).unwrap().unwrap();
+    let encrypted_client_data = client_encryptor.encrypt(&DATA).unwrap();
+
+    // Replaying the resumption to another server creates a session with different keys, which
+    // cannot decrypt the messages of the original session.
+    let (_, mut replayed_server_handshaker) = create_handshakers();
+    replayed_server_handshaker
+        .set_session_tickets(session_tickets(SESSION_TICKET_KEY, resumption_clock));
+    let result = replayed_server_handshaker.next_step(&resume_session);
+    assert_matches!(result, Ok(Some(_)));
+    let mut replayed_server_encryptor = replayed_server_handshaker.get_encryptor().unwrap();
+    let result = replayed_server_encryptor.decrypt(&encrypted_client_data);
+    assert_matches!(result, Err(_));
+}
+
+#[test]
+fn test_resume_session_with_invalid_ticket() {
+    let ticket = handshake_with_session_tickets();
+
+    for server_session_tickets in [
+        // The ticket expired.
+        Some(session_tickets(SESSION_TICKET_KEY, expired_clock)),
+        // The ticket was sealed with a different key.
+        Some(session_tickets(OTHER_SESSION_TICKET_KEY, resumption_clock)),
+        // The server doesn't support session tickets.
+        None,
+    ] {
+        let (mut client_handshaker, mut server_handshaker) = create_handshakers();
+        if let Some(server_session_tickets) = server_session_tickets {
+            server_handshaker.set_session_tickets(server_session_tickets);
+        }
+        let (session_resumer, resume_session) = SessionResumer::create(&ticket).unwrap();
+        let response = server_handshaker
+            .next_step(&resume_session)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            deserialize_message(&response).unwrap(),
+            MessageWrapper::SessionExpired(SessionExpired)
+        );
+        assert!(!server_handshaker.is_aborted());
+        assert!(session_resumer.finish(&response).unwrap().is_none());
+
+        // The client falls back to a new handshake.
+        let client_hello = client_handshaker.create_client_hello().unwrap();
+        let result = server_handshaker.next_step(&client_hello);
+        assert_matches!(result, Ok(Some(_)));
+    }
+}
+
+#[test]
+fn test_handshake_evidence() {
+    let (mut client_handshaker, mut server_handshaker) = create_handshakers();
+    assert!(client_handshaker.get_evidence().is_none());
+
+    let client_hello = client_handshaker.create_client_hello().unwrap();
+    let server_identity = server_handshaker.next_step(&client_hello).unwrap().unwrap();
+    client_handshaker.next_step(&server_identity).unwrap();
+    assert!(client_handshaker.is_completed());
+
+    let server_identity = deserialize_server_identity(&server_identity);
+    let evidence = client_handshaker.get_evidence().unwrap();
+    assert!(evidence.is_accepted());
+    assert_eq!(evidence.protocol_version, PROTOCOL_VERSION);
+    assert_eq!(evidence.cipher_suite, server_identity.cipher_suite);
+    assert_eq!(
+        evidence.attestation_report,
+        server_identity.attestation_report
+    );
+    assert_eq!(
+        evidence.signing_public_key,
+        server_identity.signing_public_key
+    );
+    assert!(evidence.verify(&TestAttestationVerifier).is_ok());
+
+    // The evidence is only valid for the attested keys.
+    let mut invalid_evidence = evidence.clone();
+    invalid_evidence.ephemeral_public_key[0] ^= 1;
+    assert!(invalid_evidence.verify(&TestAttestationVerifier).is_err());
+
+    // The transcript hash must be signed by the server.
+    let mut invalid_evidence = evidence;
+    invalid_evidence.transcript_hash[0] ^= 1;
+    assert!(invalid_evidence.verify_transcript_signature().is_err());
+    assert!(invalid_evidence.verify(&EmptyAttestationVerifier).is_err());
+}
+
+#[test]
+fn test_rejected_handshake_evidence() {
+    let (mut client_handshaker, _) = create_handshakers();
+    let mut server_handshaker = ServerHandshaker::new(AttestationBehavior::create(
+        EmptyAttestationGenerator,
+        TestAttestationVerifier,
+    ))
+    .unwrap();
+
+    let client_hello = client_handshaker.create_client_hello().unwrap();
+    let server_identity = server_handshaker.next_step(&client_hello).unwrap().unwrap();
+    let error = client_handshaker.next_step(&server_identity).unwrap_err();
+    assert!(client_handshaker.is_aborted());
+
+    // The evidence is recorded even though the attestation was rejected, and is also carried by
+    // the error.
+    let evidence = client_handshaker.get_evidence().unwrap();
+    assert!(!evidence.is_accepted());
+    assert_eq!(
+        error.downcast_ref::<RejectedEvidence>(),
+        Some(&RejectedEvidence(evidence.clone()))
+    );
+    assert!(evidence.verify(&TestAttestationVerifier).is_err());
+    assert!(evidence.verify(&EmptyAttestationVerifier).is_ok());
+}
+
 #[test]
 fn test_invalid_message_after_initialization() {
     let (mut client_handshaker, mut server_handshaker) = create_handshakers();
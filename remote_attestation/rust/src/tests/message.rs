@@ -16,13 +16,17 @@
 
 use crate::{
     crypto::{
-        KEY_AGREEMENT_ALGORITHM_KEY_LENGTH, NONCE_LENGTH, SIGNATURE_LENGTH,
+        CipherSuite, KEY_AGREEMENT_ALGORITHM_KEY_LENGTH, NONCE_LENGTH, SIGNATURE_LENGTH,
         SIGNING_ALGORITHM_KEY_LENGTH,
     },
     message::{
         deserialize_message, ClientHello, ClientIdentity, Deserializable, EncryptedData,
-        MessageWrapper, Serializable, ServerIdentity, CLIENT_HELLO_HEADER, CLIENT_IDENTITY_HEADER,
-        MAXIMUM_MESSAGE_SIZE, REPLAY_PROTECTION_ARRAY_LENGTH, SERVER_IDENTITY_HEADER,
+        MessageWrapper, ResumeSession, Serializable, ServerIdentity, SessionExpired,
+        SessionResumed, SessionTicket, CLIENT_HELLO_HEADER, CLIENT_IDENTITY_HEADER,
+        LEGACY_PROTOCOL_VERSION, MAXIMUM_ATTESTATION_REPORT_LENGTH, MAXIMUM_OFFERED_LIST_LENGTH,
+        MAXIMUM_SESSION_TICKET_LENGTH, PROTOCOL_VERSION, REPLAY_PROTECTION_ARRAY_LENGTH,
+        SERVER_IDENTITY_HEADER, SESSION_EXPIRED_HEADER, SESSION_TICKET_HEADER,
+        VEC_SIZE_PREFIX_LENGTH,
     },
 };
 use alloc::{vec, vec::Vec};
@@ -30,8 +34,8 @@ use anyhow::{anyhow, Context};
 use assert_matches::assert_matches;
 use quickcheck::{quickcheck, TestResult};
 
-pub const INVALID_MESSAGE_HEADER: u8 = 5;
-const INVALID_PROTOCOL_VERSION: u8 = 2;
+pub const INVALID_MESSAGE_HEADER: u8 = 9;
+const INVALID_PROTOCOL_VERSION: u8 = 3;
 
 /// Creates a zero initialized array.
 fn default_array<T, const L: usize>() -> [T; L]
@@ -72,11 +76,19 @@ fn test_serialize_template<M: Serializable + Deserializable + PartialEq>(
 
 #[test]
 fn test_serialize_client_hello() {
-    fn property(random: Vec<u8>) -> TestResult {
+    fn property(
+        random: Vec<u8>,
+        supported_versions: Vec<u8>,
+        cipher_suites: Vec<u8>,
+    ) -> TestResult {
         if random.len() > REPLAY_PROTECTION_ARRAY_LENGTH {
             return TestResult::discard();
         }
-        let client_hello = ClientHello::new(to_array(&random).unwrap());
+        let client_hello = ClientHello::new(
+            to_array(&random).unwrap(),
+            supported_versions,
+            cipher_suites,
+        );
         let result = test_serialize_template(&client_hello);
         assert!(result.is_ok());
         TestResult::from_bool(result.unwrap())
@@ -85,7 +97,35 @@ fn test_serialize_client_hello() {
     // fails.
     // `quickcheck` requires `Testable` to be implemented:
     // https://github.com/BurntSushi/quickcheck/blob/defde6fb0ce20b0c8c4e672aa9ae821f7d1f5b38/src/tester.rs#L386-L394
-    quickcheck(property as fn(Vec<u8>) -> TestResult);
+    quickcheck(property as fn(Vec<u8>, Vec<u8>, Vec<u8>) -> TestResult);
+}
+
+#[test]
+fn test_serialize_legacy_client_hello() {
+    let random = [1; REPLAY_PROTECTION_ARRAY_LENGTH];
+    let client_hello = ClientHello::new_legacy(random);
+    assert!(client_hello.is_legacy());
+    assert_eq!(client_hello.offered_versions(), [LEGACY_PROTOCOL_VERSION]);
+    assert_eq!(
+        client_hello.offered_cipher_suites(),
+        [CipherSuite::Aes256Gcm.id()]
+    );
+
+    // Legacy client hello messages only contain the header and the random vector.
+    let serialized_client_hello = client_hello.serialize().unwrap();
+    let mut expected = vec![CLIENT_HELLO_HEADER];
+    expected.extend_from_slice(&random);
+    assert_eq!(serialized_client_hello, expected);
+    assert!(ClientHello::deserialize(&serialized_client_hello)
+        .unwrap()
+        .is_legacy());
+
+    // Truncated client hello messages are rejected.
+    let client_hello = ClientHello::new(random, vec![PROTOCOL_VERSION], vec![]);
+    let serialized_client_hello = client_hello.serialize().unwrap();
+    let result =
+        ClientHello::deserialize(&serialized_client_hello[..serialized_client_hello.len() - 1]);
+    assert!(result.is_err());
 }
 
 #[test]
@@ -105,6 +145,8 @@ fn test_serialize_server_identity() {
             return TestResult::discard();
         }
         let mut server_identity = ServerIdentity::new(
+            PROTOCOL_VERSION,
+            CipherSuite::ChaCha20Poly1305,
             to_array(&ephemeral_public_key).unwrap(),
             to_array(&random).unwrap(),
             to_array(&signing_public_key).unwrap(),
@@ -132,6 +174,39 @@ fn test_serialize_server_identity() {
     quickcheck(property as fn(Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>) -> TestResult);
 }
 
+#[test]
+fn test_serialize_legacy_server_identity() {
+    let server_identity = ServerIdentity::new(
+        LEGACY_PROTOCOL_VERSION,
+        CipherSuite::Aes256Gcm,
+        default_array(),
+        default_array(),
+        default_array(),
+        vec![],
+    );
+    let result = test_serialize_template(&server_identity);
+    assert_matches!(result, Ok(true));
+
+    // Legacy server identity messages don't contain a cipher suite.
+    let legacy_length = server_identity.serialize().unwrap().len();
+    let server_identity = ServerIdentity {
+        version: PROTOCOL_VERSION,
+        ..server_identity
+    };
+    assert_eq!(
+        server_identity.serialize().unwrap().len(),
+        legacy_length + 1
+    );
+
+    // The legacy protocol version only supports a single cipher suite.
+    let server_identity = ServerIdentity {
+        version: LEGACY_PROTOCOL_VERSION,
+        cipher_suite: CipherSuite::ChaCha20Poly1305,
+        ..server_identity
+    };
+    assert_matches!(server_identity.serialize(), Err(_));
+}
+
 #[test]
 fn test_serialize_client_identity() {
     fn property(
@@ -185,9 +260,43 @@ fn test_serialize_encrypted_data() {
     quickcheck(property as fn(Vec<u8>, Vec<u8>) -> TestResult);
 }
 
+#[test]
+fn test_serialize_session_resumption() {
+    fn property(random: Vec<u8>, ticket: Vec<u8>) -> TestResult {
+        if random.len() > REPLAY_PROTECTION_ARRAY_LENGTH {
+            return TestResult::discard();
+        }
+        let random = to_array(&random).unwrap();
+
+        let session_ticket = SessionTicket::new(ticket.clone());
+        let result = test_serialize_template(&session_ticket);
+        assert!(result.is_ok());
+        if !result.unwrap() {
+            return TestResult::failed();
+        }
+
+        let resume_session = ResumeSession::new(random, ticket);
+        let result = test_serialize_template(&resume_session);
+        assert!(result.is_ok());
+        if !result.unwrap() {
+            return TestResult::failed();
+        }
+
+        let session_resumed = SessionResumed::new(random);
+        let result = test_serialize_template(&session_resumed);
+        assert!(result.is_ok());
+        TestResult::from_bool(result.unwrap())
+    }
+    quickcheck(property as fn(Vec<u8>, Vec<u8>) -> TestResult);
+}
+
 #[test]
 fn test_deserialize_message() {
-    let client_hello = ClientHello::new(default_array());
+    let client_hello = ClientHello::new(
+        default_array(),
+        vec![PROTOCOL_VERSION],
+        vec![CipherSuite::Aes256Gcm.id()],
+    );
     let deserialized_client_hello = deserialize_message(&client_hello.serialize().unwrap());
     assert_matches!(deserialized_client_hello, Ok(_));
     assert_eq!(
@@ -195,8 +304,14 @@ fn test_deserialize_message() {
         MessageWrapper::ClientHello(client_hello)
     );
 
-    let server_identity =
-        ServerIdentity::new(default_array(), default_array(), default_array(), vec![]);
+    let server_identity = ServerIdentity::new(
+        PROTOCOL_VERSION,
+        CipherSuite::Aes256Gcm,
+        default_array(),
+        default_array(),
+        default_array(),
+        vec![],
+    );
     let deserialized_server_identity = deserialize_message(&server_identity.serialize().unwrap());
     assert_matches!(deserialized_server_identity, Ok(_));
     assert_eq!(
@@ -220,31 +335,167 @@ fn test_deserialize_message() {
         MessageWrapper::EncryptedData(encrypted_data)
     );
 
+    let deserialized_session_expired = deserialize_message(&SessionExpired.serialize().unwrap());
+    assert_matches!(deserialized_session_expired, Ok(_));
+    assert_eq!(
+        deserialized_session_expired.unwrap(),
+        MessageWrapper::SessionExpired(SessionExpired)
+    );
+
+    let session_ticket = SessionTicket::new(vec![]);
+    let deserialized_session_ticket = deserialize_message(&session_ticket.serialize().unwrap());
+    assert_matches!(deserialized_session_ticket, Ok(_));
+    assert_eq!(
+        deserialized_session_ticket.unwrap(),
+        MessageWrapper::SessionTicket(session_ticket)
+    );
+
+    let resume_session = ResumeSession::new(default_array(), vec![]);
+    let deserialized_resume_session = deserialize_message(&resume_session.serialize().unwrap());
+    assert_matches!(deserialized_resume_session, Ok(_));
+    assert_eq!(
+        deserialized_resume_session.unwrap(),
+        MessageWrapper::ResumeSession(resume_session)
+    );
+
+    let session_resumed = SessionResumed::new(default_array());
+    let deserialized_session_resumed = deserialize_message(&session_resumed.serialize().unwrap());
+    assert_matches!(deserialized_session_resumed, Ok(_));
+    assert_eq!(
+        deserialized_session_resumed.unwrap(),
+        MessageWrapper::SessionResumed(session_resumed)
+    );
+
+    let big_session_ticket = [SESSION_TICKET_HEADER; MAXIMUM_ATTESTATION_REPORT_LENGTH + 1];
+    let deserialized_big_session_ticket = deserialize_message(&big_session_ticket);
+    assert_matches!(deserialized_big_session_ticket, Err(_));
+
+    let long_session_expired = [SESSION_EXPIRED_HEADER; 2];
+    let deserialized_long_session_expired = deserialize_message(&long_session_expired);
+    assert_matches!(deserialized_long_session_expired, Err(_));
+
     let invalid_message = vec![INVALID_MESSAGE_HEADER];
     let deserialized_invalid_message = deserialize_message(&invalid_message);
     assert_matches!(deserialized_invalid_message, Err(_));
 
-    let big_client_hello = [CLIENT_HELLO_HEADER; MAXIMUM_MESSAGE_SIZE + 1];
+    let big_client_hello = [CLIENT_HELLO_HEADER; MAXIMUM_ATTESTATION_REPORT_LENGTH + 1];
     let deserialized_big_client_hello = deserialize_message(&big_client_hello);
     assert_matches!(deserialized_big_client_hello, Err(_));
 
-    let big_server_identity = [SERVER_IDENTITY_HEADER; MAXIMUM_MESSAGE_SIZE + 1];
+    let big_server_identity = [SERVER_IDENTITY_HEADER; MAXIMUM_ATTESTATION_REPORT_LENGTH + 1];
     let deserialized_big_server_identity = deserialize_message(&big_server_identity);
     assert_matches!(deserialized_big_server_identity, Err(_));
 
-    let mut invalid_server_identity = [SERVER_IDENTITY_HEADER; MAXIMUM_MESSAGE_SIZE];
+    let mut invalid_server_identity = [SERVER_IDENTITY_HEADER; MAXIMUM_ATTESTATION_REPORT_LENGTH];
     invalid_server_identity[1] = INVALID_PROTOCOL_VERSION;
     let deserialized_invalid_server_identity = deserialize_message(&invalid_server_identity);
     assert_matches!(deserialized_invalid_server_identity, Err(_));
 
-    let big_client_identity = [CLIENT_IDENTITY_HEADER; MAXIMUM_MESSAGE_SIZE + 1];
+    let big_client_identity = [CLIENT_IDENTITY_HEADER; MAXIMUM_ATTESTATION_REPORT_LENGTH + 1];
     let deserialized_big_client_identity = deserialize_message(&big_client_identity);
     assert_matches!(deserialized_big_client_identity, Err(_));
 
-    let big_encrypted_data =
-        EncryptedData::new([0; NONCE_LENGTH], vec![0; MAXIMUM_MESSAGE_SIZE + 1])
-            .serialize()
-            .unwrap();
+    let big_encrypted_data = EncryptedData::new(
+        [0; NONCE_LENGTH],
+        vec![0; MAXIMUM_ATTESTATION_REPORT_LENGTH + 1],
+    )
+    .serialize()
+    .unwrap();
     let deserialized_big_encrypted_data = deserialize_message(&big_encrypted_data);
     assert_matches!(deserialized_big_encrypted_data, Ok(_));
 }
+
+/// Creates a raw message consisting of the header, the given fixed-size fields and a single
+/// length-prefixed field with the given length prefix and contents.
+fn raw_message(header: u8, fixed: &[u8], length_prefix: u64, contents: &[u8]) -> Vec<u8> {
+    let mut message = vec![header];
+    message.extend_from_slice(fixed);
+    message.extend_from_slice(&length_prefix.to_le_bytes());
+    message.extend_from_slice(contents);
+    message
+}
+
+#[test]
+fn test_field_limits() {
+    // AMD SEV-SNP attestation evidence with certificates and endorsements fits in identity
+    // messages.
+    let attestation_report = vec![0xab; 5_000];
+    let client_identity = ClientIdentity::new(default_array(), default_array(), attestation_report);
+    test_serialize_template(&client_identity).unwrap();
+
+    let maximum_server_identity = ServerIdentity::new(
+        PROTOCOL_VERSION,
+        CipherSuite::Aes256Gcm,
+        default_array(),
+        default_array(),
+        default_array(),
+        vec![0; MAXIMUM_ATTESTATION_REPORT_LENGTH],
+    );
+    test_serialize_template(&maximum_server_identity).unwrap();
+
+    let big_server_identity = ServerIdentity::new(
+        PROTOCOL_VERSION,
+        CipherSuite::Aes256Gcm,
+        default_array(),
+        default_array(),
+        default_array(),
+        vec![0; MAXIMUM_ATTESTATION_REPORT_LENGTH + 1],
+    );
+    assert_matches!(big_server_identity.serialize(), Err(_));
+
+    let big_client_hello = ClientHello::new(
+        default_array(),
+        vec![PROTOCOL_VERSION; MAXIMUM_OFFERED_LIST_LENGTH + 1],
+        vec![CipherSuite::Aes256Gcm.id()],
+    );
+    assert_matches!(big_client_hello.serialize(), Err(_));
+
+    let big_session_ticket = SessionTicket::new(vec![0; MAXIMUM_SESSION_TICKET_LENGTH + 1]);
+    assert_matches!(big_session_ticket.serialize(), Err(_));
+
+    // Length prefixes above the field limit are rejected before the field contents are read.
+    let over_limit_session_ticket = raw_message(
+        SESSION_TICKET_HEADER,
+        &[],
+        MAXIMUM_SESSION_TICKET_LENGTH as u64 + 1,
+        &vec![0; MAXIMUM_SESSION_TICKET_LENGTH + 1],
+    );
+    assert_matches!(deserialize_message(&over_limit_session_ticket), Err(_));
+
+    let over_limit_client_identity = raw_message(
+        CLIENT_IDENTITY_HEADER,
+        &[0; KEY_AGREEMENT_ALGORITHM_KEY_LENGTH + SIGNATURE_LENGTH + SIGNING_ALGORITHM_KEY_LENGTH],
+        u64::MAX,
+        &[],
+    );
+    assert_matches!(deserialize_message(&over_limit_client_identity), Err(_));
+
+    // Length prefixes that exceed the remaining input are rejected.
+    let truncated_session_ticket = raw_message(SESSION_TICKET_HEADER, &[], 16, &[0; 15]);
+    assert_matches!(deserialize_message(&truncated_session_ticket), Err(_));
+
+    // Truncated length prefixes are rejected.
+    let mut truncated_prefix = raw_message(SESSION_TICKET_HEADER, &[], 0, &[]);
+    truncated_prefix.truncate(VEC_SIZE_PREFIX_LENGTH);
+    assert_matches!(deserialize_message(&truncated_prefix), Err(_));
+
+    let session_ticket = raw_message(SESSION_TICKET_HEADER, &[], 16, &[0; 16]);
+    assert_eq!(
+        deserialize_message(&session_ticket).unwrap(),
+        MessageWrapper::SessionTicket(SessionTicket::new(vec![0; 16]))
+    );
+}
+
+#[test]
+fn test_serialize_message_wrapper() {
+    let messages = vec![
+        MessageWrapper::ClientHello(ClientHello::new_legacy(default_array())),
+        MessageWrapper::SessionExpired(SessionExpired),
+        MessageWrapper::SessionTicket(SessionTicket::new(vec![1, 2, 3])),
+        MessageWrapper::SessionResumed(SessionResumed::new(default_array())),
+    ];
+    for message in messages {
+        let serialized = message.serialize().unwrap();
+        assert_eq!(deserialize_message(&serialized).unwrap(), message);
+    }
+}
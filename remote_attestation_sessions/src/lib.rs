@@ -20,75 +20,233 @@
 
 extern crate alloc;
 
-use alloc::boxed::Box;
+use alloc::{boxed::Box, sync::Arc};
+use anyhow::Context;
+use core::time::Duration;
 use lru::LruCache;
-use oak_remote_attestation::handshaker::{
-    AttestationBehavior, AttestationGenerator, AttestationVerifier, Encryptor, ServerHandshaker,
+use oak_remote_attestation::{
+    crypto::{KeyRotation, ReplayProtection},
+    handshaker::{
+        AttestationBehavior, AttestationGenerator, AttestationVerifier, Encryptor, ServerHandshaker,
+    },
+    resumption::SessionTickets,
 };
+use spin::Mutex;
 
 pub const SESSION_ID_LENGTH: usize = 8;
 pub type SessionId = [u8; SESSION_ID_LENGTH];
 
+/// Encryptor of an established session, which is shared by the concurrent requests of the
+/// session.
+///
+/// The encryptor must only be locked while decrypting a request or encrypting a response, so that
+/// requests of the session can be handled concurrently.
+pub type SessionEncryptor = Arc<Mutex<Encryptor>>;
+
 pub enum SessionState<G: AttestationGenerator, V: AttestationVerifier> {
     // Boxed due to large size difference, ref: https://rust-lang.github.io/rust-clippy/master/index.html#large_enum_variant
     HandshakeInProgress(Box<ServerHandshaker<G, V>>),
-    EncryptedMessageExchange(Encryptor),
+    EncryptedMessageExchange(SessionEncryptor),
+}
+
+impl<G: AttestationGenerator, V: AttestationVerifier> SessionState<G, V> {
+    /// Returns whether the handshake of the session has completed.
+    fn is_established(&self) -> bool {
+        match self {
+            SessionState::HandshakeInProgress(handshaker) => handshaker.is_completed(),
+            SessionState::EncryptedMessageExchange(_) => true,
+        }
+    }
+}
+
+/// Absolute lifetime of sessions, measured from the completion of the handshake.
+///
+/// Once a session expires it is treated as unknown, so that the server responds to encrypted
+/// messages with a `SessionExpired` message, and the client performs a new handshake.
+#[derive(Clone, Copy)]
+pub struct SessionLifetime {
+    /// Maximum duration of a session.
+    pub duration: Duration,
+    /// Returns the current time, measured from an arbitrary fixed point in time (e.g. the Unix
+    /// epoch).
+    pub clock: fn() -> Duration,
+}
+
+/// Configuration of the sessions maintained by a [`SessionTracker`].
+#[derive(Clone)]
+pub struct SessionConfig {
+    /// Replay protection applied to the encrypted messages of established sessions.
+    ///
+    /// Unary transports may deliver requests of a session out of order, in which case
+    /// [`ReplayProtection::SlidingWindow`] can be used instead of the default
+    /// [`ReplayProtection::Strict`].
+    pub replay_protection: ReplayProtection,
+    /// Rotation of the keys used for encrypting the responses of established sessions.
+    pub key_rotation: KeyRotation,
+    /// Absolute lifetime of sessions, or [`None`] if sessions are only removed when they are
+    /// evicted from the tracker.
+    pub lifetime: Option<SessionLifetime>,
+    /// Session tickets issued to clients after the handshake, or [`None`] if sessions cannot be
+    /// resumed.
+    ///
+    /// Session tickets allow sessions to be resumed by any tracker that shares the ticket key,
+    /// e.g. by all replicas of a server behind a load balancer without sticky sessions.
+    pub session_tickets: Option<SessionTickets>,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            replay_protection: ReplayProtection::Strict,
+            key_rotation: KeyRotation::default(),
+            lifetime: None,
+            session_tickets: None,
+        }
+    }
 }
 
 /// Maintains remote attestation state for a number of sessions
 pub struct SessionTracker<G: AttestationGenerator, V: AttestationVerifier> {
     attestation_behavior: AttestationBehavior<G, V>,
     known_sessions: LruCache<SessionId, SessionState<G, V>>,
+    /// Times at which established sessions expire, if [`SessionConfig::lifetime`] is set.
+    ///
+    /// Expiration times are kept separately from the session state, since the state of a
+    /// handshake is removed from the tracker while a handshake message is processed. They are only
+    /// set once the handshake of a session completes, so that messages that start a new handshake
+    /// with the ID of a session don't extend its lifetime.
+    expiration_times: LruCache<SessionId, Duration>,
+    config: SessionConfig,
 }
 
 impl<G: AttestationGenerator, V: AttestationVerifier> SessionTracker<G, V> {
     pub fn create(cache_size: usize, attestation_behavior: AttestationBehavior<G, V>) -> Self {
-        let known_sessions = LruCache::new(cache_size);
         Self {
             attestation_behavior,
-            known_sessions,
+            known_sessions: LruCache::new(cache_size),
+            expiration_times: LruCache::new(cache_size),
+            config: SessionConfig::default(),
         }
     }
 
-    /// Consumes remote attestation state of an existing session. Creates
-    /// initial state if the session is not known.
+    /// Creates a tracker whose sessions are configured according to `config`.
+    pub fn create_with_config(
+        cache_size: usize,
+        attestation_behavior: AttestationBehavior<G, V>,
+        config: SessionConfig,
+    ) -> anyhow::Result<Self> {
+        config
+            .replay_protection
+            .validate()
+            .context("Couldn't validate replay protection")?;
+        config
+            .key_rotation
+            .validate()
+            .context("Couldn't validate key rotation")?;
+        Ok(Self {
+            attestation_behavior,
+            known_sessions: LruCache::new(cache_size),
+            expiration_times: LruCache::new(cache_size),
+            config,
+        })
+    }
+
+    /// Returns the remote attestation state of an existing session. Creates initial state if the
+    /// session is not known or has expired.
     ///
-    /// Note that getting the remote attestation state of a session always
-    /// implicitly removes it from the set of tracked sessions. After
-    /// using the state to process a request with this state it must explicitly
-    /// be put back into the SessionTracker. This an intentional choice meant
-    /// to ensure that faulty state that leads to errors when processing
-    /// a request is not persistent.
-    pub fn pop_or_create_session_state(
+    /// The state of a handshake in progress is removed from the tracker. After using it to process
+    /// a handshake message it must explicitly be put back with
+    /// [`SessionTracker::put_handshake_state`]. This an intentional choice meant to ensure that
+    /// faulty state that leads to errors when processing a handshake message is not persistent.
+    ///
+    /// Established sessions instead stay in the tracker, and their [`SessionEncryptor`] is shared
+    /// by all the requests of the session, so that concurrent requests can be handled. A request
+    /// that can't be decrypted (e.g. a replayed request) leaves the encryptor unchanged, so that it
+    /// doesn't affect the session.
+    pub fn get_or_create_session_state(
         &mut self,
         session_id: SessionId,
     ) -> anyhow::Result<SessionState<G, V>> {
-        match self.known_sessions.pop(&session_id) {
-            None => Ok(SessionState::HandshakeInProgress(Box::new(
-                ServerHandshaker::new(self.attestation_behavior.clone())?,
-            ))),
+        let session_state = self.known_sessions.pop(&session_id);
+        let session_state = match session_state {
+            Some(session_state)
+                if session_state.is_established() && self.is_expired(&session_id) =>
+            {
+                None
+            }
+            session_state => session_state,
+        };
+        let encryptor = match session_state {
+            None => {
+                let mut handshaker = ServerHandshaker::new(self.attestation_behavior.clone())?;
+                if let Some(session_tickets) = &self.config.session_tickets {
+                    handshaker.set_session_tickets(session_tickets.clone());
+                }
+                return Ok(SessionState::HandshakeInProgress(Box::new(handshaker)));
+            }
             Some(SessionState::HandshakeInProgress(handshaker)) => {
                 // Completed handshakers are functionally just wrap an
                 // encryptor. In that case the underlying handshaker is
                 // returned, ensuring consistent state representation.
-                match handshaker.is_completed() {
-                    false => Ok(SessionState::HandshakeInProgress(handshaker)),
-                    true => match handshaker.get_encryptor() {
-                        Ok(encryptor) => Ok(SessionState::EncryptedMessageExchange(encryptor)),
-                        Err(error) => Err(error.context("Couldn't get encryptor")),
-                    },
+                if !handshaker.is_completed() {
+                    return Ok(SessionState::HandshakeInProgress(handshaker));
                 }
+                let mut encryptor = handshaker
+                    .get_encryptor()
+                    .context("Couldn't get encryptor")?;
+                encryptor
+                    .set_replay_protection(self.config.replay_protection)
+                    .context("Couldn't set replay protection")?;
+                encryptor
+                    .set_key_rotation(self.config.key_rotation)
+                    .context("Couldn't set key rotation")?;
+                Arc::new(Mutex::new(encryptor))
             }
-            Some(SessionState::EncryptedMessageExchange(encryptor)) => {
-                Ok(SessionState::EncryptedMessageExchange(encryptor))
+            Some(SessionState::EncryptedMessageExchange(encryptor)) => encryptor,
+        };
+        self.known_sessions.put(
+            session_id,
+            SessionState::EncryptedMessageExchange(encryptor.clone()),
+        );
+        Ok(SessionState::EncryptedMessageExchange(encryptor))
+    }
+
+    /// Records the state of a handshake in the tracker, after it processed a handshake message.
+    /// Unlike `get_or_create_session_state` it does not normalize session state, instead relying
+    /// on normalization occuring at retrieval time.
+    ///
+    /// If the handshake has just completed, the lifetime of the session starts.
+    pub fn put_handshake_state(
+        &mut self,
+        session_id: SessionId,
+        handshaker: Box<ServerHandshaker<G, V>>,
+    ) {
+        if let Some(lifetime) = self.config.lifetime {
+            if handshaker.is_completed() {
+                let mut expiration_time = (lifetime.clock)().saturating_add(lifetime.duration);
+                // Resumed sessions expire together with their ticket, rather than after a full
+                // lifetime measured from the resumption.
+                if let Some(ticket_expiration_time) =
+                    handshaker.get_resumed_session_expiration_time()
+                {
+                    expiration_time = expiration_time.min(ticket_expiration_time);
+                }
+                self.expiration_times.put(session_id, expiration_time);
             }
         }
+        self.known_sessions
+            .put(session_id, SessionState::HandshakeInProgress(handshaker));
     }
 
-    /// Record a session in the tracker. Unlike `pop_or_create_session_state` it does not
-    /// normalize session state, instead relying on normalization occuring
-    /// at retrieval time.
-    pub fn put_session_state(&mut self, session_id: SessionId, session_state: SessionState<G, V>) {
-        self.known_sessions.put(session_id, session_state);
+    /// Returns whether the lifetime of the established session has passed. Sessions whose
+    /// expiration time was evicted from the tracker are considered expired as well.
+    fn is_expired(&mut self, session_id: &SessionId) -> bool {
+        match self.config.lifetime {
+            Some(lifetime) => match self.expiration_times.get(session_id) {
+                Some(expiration_time) => (lifetime.clock)() >= *expiration_time,
+                None => true,
+            },
+            None => false,
+        }
     }
 }
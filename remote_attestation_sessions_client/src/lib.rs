@@ -14,10 +14,15 @@
 // limitations under the License.
 //
 
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use async_trait::async_trait;
-use oak_remote_attestation::handshaker::{
-    AttestationBehavior, AttestationGenerator, AttestationVerifier, ClientHandshaker, Encryptor,
+use oak_remote_attestation::{
+    evidence::HandshakeEvidence,
+    handshaker::{
+        AttestationBehavior, AttestationGenerator, AttestationVerifier, ClientHandshaker, Encryptor,
+    },
+    message::{deserialize_message, MessageWrapper},
+    resumption::{ResumptionTicket, SessionResumer},
 };
 use oak_remote_attestation_sessions::SessionId;
 
@@ -30,66 +35,111 @@ pub trait UnaryClient {
     async fn message(&mut self, session_id: SessionId, body: Vec<u8>) -> anyhow::Result<Vec<u8>>;
 }
 
+/// Object-safe version of the [`ClientHandshaker`] methods used by [`GenericAttestationClient`],
+/// which allows the client to perform new handshakes without depending on the attestation
+/// behavior types.
+trait Handshaker {
+    fn create_client_hello(&mut self) -> anyhow::Result<Vec<u8>>;
+    fn next_step(&mut self, message: &[u8]) -> anyhow::Result<Option<Vec<u8>>>;
+    fn is_completed(&self) -> bool;
+    fn get_session_ticket(&self) -> Option<ResumptionTicket>;
+    fn get_evidence(&self) -> Option<HandshakeEvidence>;
+    fn get_encryptor(self: Box<Self>) -> anyhow::Result<Encryptor>;
+}
+
+impl<G: AttestationGenerator, V: AttestationVerifier> Handshaker for ClientHandshaker<G, V> {
+    fn create_client_hello(&mut self) -> anyhow::Result<Vec<u8>> {
+        ClientHandshaker::create_client_hello(self)
+    }
+
+    fn next_step(&mut self, message: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        ClientHandshaker::next_step(self, message)
+    }
+
+    fn is_completed(&self) -> bool {
+        ClientHandshaker::is_completed(self)
+    }
+
+    fn get_session_ticket(&self) -> Option<ResumptionTicket> {
+        ClientHandshaker::get_session_ticket(self)
+    }
+
+    fn get_evidence(&self) -> Option<HandshakeEvidence> {
+        ClientHandshaker::get_evidence(self)
+    }
+
+    fn get_encryptor(self: Box<Self>) -> anyhow::Result<Encryptor> {
+        ClientHandshaker::get_encryptor(*self)
+    }
+}
+
+type HandshakerFactory = Box<dyn Fn() -> anyhow::Result<Box<dyn Handshaker>> + Send + Sync>;
+
 /// gRPC Attestation Service client implementation.
+///
+/// If the server responds that the session expired, the client transparently resumes the session
+/// with the session ticket issued by the server (if any) or performs a new handshake, in a new
+/// session, and sends the request again.
 pub struct GenericAttestationClient<T: UnaryClient> {
     session_id: SessionId,
     encryptor: Encryptor,
     client: T,
+    create_handshaker: HandshakerFactory,
+    /// Session ticket issued by the server in the last handshake.
+    session_ticket: Option<ResumptionTicket>,
+    /// Attestation evidence of the server in the last handshake.
+    evidence: Option<HandshakeEvidence>,
 }
 
 impl<T: UnaryClient> GenericAttestationClient<T> {
-    pub async fn create<G: AttestationGenerator, V: AttestationVerifier>(
+    pub async fn create<G: 'static + AttestationGenerator, V: 'static + AttestationVerifier>(
         mut client: T,
         attestation_behavior: AttestationBehavior<G, V>,
     ) -> anyhow::Result<Self> {
-        let session_id: SessionId = rand::random();
-
-        let mut handshaker = ClientHandshaker::new(attestation_behavior)?;
-        let client_hello = handshaker
-            .create_client_hello()
-            .context("Couldn't create client hello message")?;
-
-        let mut response = client
-            .message(session_id, client_hello)
-            .await
-            .context("Couldn't message client hello message")?;
-
-        while !handshaker.is_completed() {
-            let request = handshaker
-                .next_step(&response)
-                .context("Couldn't process handshake message")?;
-
-            if let Some(request) = request {
-                response = client
-                    .message(session_id, request)
-                    .await
-                    .context("Couldn't message client hello message")?;
-            }
-        }
-
-        let encryptor = handshaker
-            .get_encryptor()
-            .context("Couldn't get encryptor")?;
+        let create_handshaker: HandshakerFactory = Box::new(move || {
+            let handshaker = ClientHandshaker::new(attestation_behavior.clone())?;
+            Ok(Box::new(handshaker) as Box<dyn Handshaker>)
+        });
+        let (session_id, encryptor, session_ticket, evidence) =
+            handshake(&mut client, &create_handshaker).await?;
 
         Ok(Self {
             session_id,
             encryptor,
             client,
+            create_handshaker,
+            session_ticket,
+            evidence,
         })
     }
 
+    /// Returns the attestation evidence of the server in the last handshake.
+    ///
+    /// Resumed sessions are not attested again, so the evidence remains the one of the handshake
+    /// that the session ticket was issued in.
+    pub fn evidence(&self) -> Option<&HandshakeEvidence> {
+        self.evidence.as_ref()
+    }
+
     /// Sends data encrypted by the [`Encryptor`] to the server and decrypts the server responses.
     pub async fn message(&mut self, request_as_plaintext_bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
-        let encrypted_request = self
-            .encryptor
-            .encrypt(request_as_plaintext_bytes)
-            .context("Couldn't encrypt request")?;
-
         let encrypted_response = self
-            .client
-            .message(self.session_id, encrypted_request)
-            .await
-            .context("Couldn't message encrypted data request")?;
+            .send_encrypted_request(request_as_plaintext_bytes)
+            .await?;
+
+        let encrypted_response = if is_session_expired(&encrypted_response) {
+            self.renew_session().await?;
+
+            let encrypted_response = self
+                .send_encrypted_request(request_as_plaintext_bytes)
+                .await?;
+            if is_session_expired(&encrypted_response) {
+                return Err(anyhow!("New session expired immediately"));
+            }
+            encrypted_response
+        } else {
+            encrypted_response
+        };
 
         // The runtime responds with an encrypted response that contains an
         // encoded proto containing the plaintext response and status code.
@@ -101,4 +151,136 @@ impl<T: UnaryClient> GenericAttestationClient<T> {
 
         Ok(encoded_response)
     }
+
+    /// Replaces the expired session by resuming it with the session ticket, or by performing a new
+    /// handshake if there is no ticket or the server doesn't accept it anymore.
+    async fn renew_session(&mut self) -> anyhow::Result<()> {
+        if let Some(session_ticket) = &self.session_ticket {
+            if let Some((session_id, encryptor)) = resume(&mut self.client, session_ticket)
+                .await
+                .context("Couldn't resume the session after it expired")?
+            {
+                self.session_id = session_id;
+                self.encryptor = encryptor;
+                return Ok(());
+            }
+        }
+
+        let (session_id, encryptor, session_ticket, evidence) =
+            handshake(&mut self.client, &self.create_handshaker)
+                .await
+                .context("Couldn't perform a new handshake after the session expired")?;
+        self.session_id = session_id;
+        self.encryptor = encryptor;
+        self.session_ticket = session_ticket;
+        self.evidence = evidence;
+        Ok(())
+    }
+
+    async fn send_encrypted_request(
+        &mut self,
+        request_as_plaintext_bytes: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        let encrypted_request = self
+            .encryptor
+            .encrypt(request_as_plaintext_bytes)
+            .context("Couldn't encrypt request")?;
+
+        self.client
+            .message(self.session_id, encrypted_request)
+            .await
+            .context("Couldn't message encrypted data request")
+    }
 }
+
+/// Performs a handshake in a new session, and returns the session ID together with the
+/// [`Encryptor`], the session ticket (if issued by the server) and the attestation evidence of the
+/// server for the session.
+async fn handshake<T: UnaryClient>(
+    client: &mut T,
+    create_handshaker: &HandshakerFactory,
+) -> anyhow::Result<(
+    SessionId,
+    Encryptor,
+    Option<ResumptionTicket>,
+    Option<HandshakeEvidence>,
+)> {
+    let session_id: SessionId = rand::random();
+
+    let mut handshaker = create_handshaker()?;
+    let client_hello = handshaker
+        .create_client_hello()
+        .context("Couldn't create client hello message")?;
+
+    let mut response = client
+        .message(session_id, client_hello)
+        .await
+        .context("Couldn't message client hello message")?;
+
+    while !handshaker.is_completed() {
+        let request = handshaker
+            .next_step(&response)
+            .context("Couldn't process handshake message")?;
+
+        if let Some(request) = request {
+            response = client
+                .message(session_id, request)
+                .await
+                .context("Couldn't message client hello message")?;
+        }
+    }
+
+    // Servers that issue session tickets respond to the last handshake message with a ticket.
+    if matches!(
+        deserialize_message(&response),
+        Ok(MessageWrapper::SessionTicket(_))
+    ) {
+        handshaker
+            .next_step(&response)
+            .context("Couldn't process session ticket message")?;
+    }
+    let session_ticket = handshaker.get_session_ticket();
+    let evidence = handshaker.get_evidence();
+
+    let encryptor = handshaker
+        .get_encryptor()
+        .context("Couldn't get encryptor")?;
+
+    Ok((session_id, encryptor, session_ticket, evidence))
+}
+
+/// Resumes a session with `session_ticket` in a new session, and returns the session ID together
+/// with the [`Encryptor`] for the session.
+///
+/// Returns [`None`] if the server doesn't accept the ticket, in which case a new handshake is
+/// required.
+async fn resume<T: UnaryClient>(
+    client: &mut T,
+    session_ticket: &ResumptionTicket,
+) -> anyhow::Result<Option<(SessionId, Encryptor)>> {
+    let session_id: SessionId = rand::random();
+
+    let (session_resumer, resume_session) =
+        SessionResumer::create(session_ticket).context("Couldn't create resume session message")?;
+    let response = client
+        .message(session_id, resume_session)
+        .await
+        .context("Couldn't message resume session message")?;
+    let encryptor = session_resumer
+        .finish(&response)
+        .context("Couldn't process session resumption message")?;
+
+    Ok(encryptor.map(|encryptor| (session_id, encryptor)))
+}
+
+/// Returns whether `response` is a `SessionExpired` message, which signals that the server doesn't
+/// know the session anymore and a new handshake is required.
+fn is_session_expired(response: &[u8]) -> bool {
+    matches!(
+        deserialize_message(response),
+        Ok(MessageWrapper::SessionExpired(_))
+    )
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,298 @@
+//
+// Copyright 2022 The Project Oak Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::{GenericAttestationClient, UnaryClient};
+use async_trait::async_trait;
+use core::time::Duration;
+use oak_remote_attestation::{
+    handshaker::{
+        AttestationBehavior, ClientHandshaker, EmptyAttestationGenerator, EmptyAttestationVerifier,
+    },
+    message::{deserialize_message, MessageWrapper},
+    resumption::{SessionTicketKey, SessionTickets},
+};
+use oak_remote_attestation_sessions::{
+    SessionConfig, SessionId, SessionLifetime, SessionState, SessionTracker,
+};
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+const SESSIONS_CACHE_SIZE: usize = 10;
+const SESSION_LIFETIME: Duration = Duration::from_secs(60);
+
+/// Current time of the test clock in seconds, which is only advanced explicitly.
+static CURRENT_TIME_SECS: AtomicU64 = AtomicU64::new(0);
+
+fn test_clock() -> Duration {
+    Duration::from_secs(CURRENT_TIME_SECS.load(Ordering::SeqCst))
+}
+
+/// Current time of the clock used for testing the expiration of resumed sessions, which is only
+/// advanced explicitly.
+static RESUMPTION_TIME_SECS: AtomicU64 = AtomicU64::new(0);
+
+fn resumption_clock() -> Duration {
+    Duration::from_secs(RESUMPTION_TIME_SECS.load(Ordering::SeqCst))
+}
+
+/// Current time of the clock used for testing the lifetime of sessions that receive concurrent
+/// messages, which is only advanced explicitly.
+static CONCURRENT_TIME_SECS: AtomicU64 = AtomicU64::new(0);
+
+fn concurrent_clock() -> Duration {
+    Duration::from_secs(CONCURRENT_TIME_SECS.load(Ordering::SeqCst))
+}
+
+fn fixed_clock() -> Duration {
+    Duration::from_secs(1_000_000)
+}
+
+/// [`UnaryClient`] that processes messages with local [`SessionTracker`] replicas and responds to
+/// encrypted requests with the same data.
+struct EchoClient {
+    replicas: Vec<SessionTracker<EmptyAttestationGenerator, EmptyAttestationVerifier>>,
+    /// Index of the replica that processes the following messages.
+    current_replica: usize,
+    /// Sessions that received messages.
+    session_ids: HashSet<SessionId>,
+    /// Number of handshakes started by the client.
+    handshakes: usize,
+}
+
+impl EchoClient {
+    fn new() -> Self {
+        Self::with_config(
+            1,
+            SessionConfig {
+                lifetime: Some(SessionLifetime {
+                    duration: SESSION_LIFETIME,
+                    clock: test_clock,
+                }),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn with_config(replica_count: usize, config: SessionConfig) -> Self {
+        let replicas = (0..replica_count)
+            .map(|_| {
+                SessionTracker::create_with_config(
+                    SESSIONS_CACHE_SIZE,
+                    AttestationBehavior::create(
+                        EmptyAttestationGenerator,
+                        EmptyAttestationVerifier,
+                    ),
+                    config.clone(),
+                )
+                .expect("Couldn't create session tracker")
+            })
+            .collect();
+        Self {
+            replicas,
+            current_replica: 0,
+            session_ids: HashSet::new(),
+            handshakes: 0,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl UnaryClient for EchoClient {
+    async fn message(&mut self, session_id: SessionId, body: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        self.session_ids.insert(session_id);
+        if let Ok(MessageWrapper::ClientHello(_)) = deserialize_message(&body) {
+            self.handshakes += 1;
+        }
+        let session_tracker = &mut self.replicas[self.current_replica];
+        match session_tracker.get_or_create_session_state(session_id)? {
+            SessionState::HandshakeInProgress(mut handshaker) => {
+                let response = handshaker.next_step(&body)?.unwrap_or_default();
+                session_tracker.put_handshake_state(session_id, handshaker);
+                Ok(response)
+            }
+            SessionState::EncryptedMessageExchange(encryptor) => {
+                let mut encryptor = encryptor.lock();
+                let request = encryptor.decrypt(&body)?;
+                encryptor.encrypt(&request)
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_session_expired() {
+    let mut client = GenericAttestationClient::create(
+        EchoClient::new(),
+        AttestationBehavior::create(EmptyAttestationGenerator, EmptyAttestationVerifier),
+    )
+    .await
+    .expect("Couldn't create client");
+    assert_eq!(client.message(b"first").await.unwrap(), b"first");
+    assert_eq!(client.message(b"second").await.unwrap(), b"second");
+    assert_eq!(client.client.session_ids.len(), 1);
+
+    // The client transparently performs a new handshake once the session expired.
+    CURRENT_TIME_SECS.fetch_add(SESSION_LIFETIME.as_secs(), Ordering::SeqCst);
+    assert_eq!(client.message(b"third").await.unwrap(), b"third");
+    assert_eq!(client.client.session_ids.len(), 2);
+    assert_eq!(client.message(b"fourth").await.unwrap(), b"fourth");
+    assert_eq!(client.client.session_ids.len(), 2);
+}
+
+#[tokio::test]
+async fn test_concurrent_message_does_not_extend_session_lifetime() {
+    let mut client = GenericAttestationClient::create(
+        EchoClient::with_config(
+            1,
+            SessionConfig {
+                lifetime: Some(SessionLifetime {
+                    duration: SESSION_LIFETIME,
+                    clock: concurrent_clock,
+                }),
+                ..Default::default()
+            },
+        ),
+        AttestationBehavior::create(EmptyAttestationGenerator, EmptyAttestationVerifier),
+    )
+    .await
+    .expect("Couldn't create client");
+    assert_eq!(client.message(b"first").await.unwrap(), b"first");
+    let session_id = *client.client.session_ids.iter().next().unwrap();
+
+    // Halfway through the lifetime of the session, a message with the same session ID that starts
+    // a new handshake arrives while a request of the session is in flight. Both get the encryptor
+    // of the session, which stays in the tracker.
+    CONCURRENT_TIME_SECS.fetch_add(SESSION_LIFETIME.as_secs() / 2, Ordering::SeqCst);
+    let session_tracker = &mut client.client.replicas[0];
+    let encryptor = match session_tracker
+        .get_or_create_session_state(session_id)
+        .unwrap()
+    {
+        SessionState::EncryptedMessageExchange(encryptor) => encryptor,
+        SessionState::HandshakeInProgress(_) => panic!("Session is not established"),
+    };
+    match session_tracker
+        .get_or_create_session_state(session_id)
+        .unwrap()
+    {
+        SessionState::EncryptedMessageExchange(concurrent_encryptor) => {
+            assert!(Arc::ptr_eq(&encryptor, &concurrent_encryptor));
+            // The handshake message is rejected, without affecting the session.
+            let client_hello = ClientHandshaker::new(AttestationBehavior::create(
+                EmptyAttestationGenerator,
+                EmptyAttestationVerifier,
+            ))
+            .unwrap()
+            .create_client_hello()
+            .unwrap();
+            assert!(concurrent_encryptor.lock().decrypt(&client_hello).is_err());
+        }
+        SessionState::HandshakeInProgress(_) => panic!("Session is not established"),
+    }
+    assert_eq!(client.message(b"second").await.unwrap(), b"second");
+    assert_eq!(client.client.handshakes, 1);
+
+    // The session still expires at the end of its original lifetime.
+    CONCURRENT_TIME_SECS.fetch_add(SESSION_LIFETIME.as_secs() / 2, Ordering::SeqCst);
+    assert_eq!(client.message(b"third").await.unwrap(), b"third");
+    assert_eq!(client.client.handshakes, 2);
+}
+
+#[tokio::test]
+async fn test_session_resumption() {
+    let session_tickets = SessionTickets {
+        key: SessionTicketKey::generate().expect("Couldn't generate session ticket key"),
+        lifetime: SESSION_LIFETIME,
+        clock: fixed_clock,
+    };
+    let mut client = GenericAttestationClient::create(
+        EchoClient::with_config(
+            2,
+            SessionConfig {
+                session_tickets: Some(session_tickets),
+                ..Default::default()
+            },
+        ),
+        AttestationBehavior::create(EmptyAttestationGenerator, EmptyAttestationVerifier),
+    )
+    .await
+    .expect("Couldn't create client");
+    assert_eq!(client.message(b"first").await.unwrap(), b"first");
+    assert_eq!(client.client.handshakes, 1);
+    let evidence = client
+        .evidence()
+        .cloned()
+        .expect("Missing handshake evidence");
+
+    // Another replica, which doesn't know the session, resumes it without a new handshake.
+    client.client.current_replica = 1;
+    assert_eq!(client.message(b"second").await.unwrap(), b"second");
+    assert_eq!(client.message(b"third").await.unwrap(), b"third");
+    assert_eq!(client.client.session_ids.len(), 2);
+    assert_eq!(client.client.handshakes, 1);
+
+    // The original replica resumes the session again, since the session ID changed.
+    client.client.current_replica = 0;
+    assert_eq!(client.message(b"fourth").await.unwrap(), b"fourth");
+    assert_eq!(client.client.session_ids.len(), 3);
+    assert_eq!(client.client.handshakes, 1);
+
+    // Resumed sessions keep the evidence of the original handshake.
+    assert_eq!(client.evidence(), Some(&evidence));
+}
+
+#[tokio::test]
+async fn test_resumed_session_expired() {
+    let session_tickets = SessionTickets {
+        key: SessionTicketKey::generate().expect("Couldn't generate session ticket key"),
+        lifetime: SESSION_LIFETIME,
+        clock: resumption_clock,
+    };
+    let mut client = GenericAttestationClient::create(
+        EchoClient::with_config(
+            2,
+            SessionConfig {
+                lifetime: Some(SessionLifetime {
+                    duration: SESSION_LIFETIME,
+                    clock: resumption_clock,
+                }),
+                session_tickets: Some(session_tickets),
+                ..Default::default()
+            },
+        ),
+        AttestationBehavior::create(EmptyAttestationGenerator, EmptyAttestationVerifier),
+    )
+    .await
+    .expect("Couldn't create client");
+    assert_eq!(client.message(b"first").await.unwrap(), b"first");
+
+    // Another replica resumes the session halfway through its lifetime.
+    RESUMPTION_TIME_SECS.fetch_add(SESSION_LIFETIME.as_secs() / 2, Ordering::SeqCst);
+    client.client.current_replica = 1;
+    assert_eq!(client.message(b"second").await.unwrap(), b"second");
+    assert_eq!(client.client.handshakes, 1);
+
+    // The resumed session expires together with the ticket, so the client has to perform a new
+    // handshake.
+    RESUMPTION_TIME_SECS.fetch_add(SESSION_LIFETIME.as_secs() / 2, Ordering::SeqCst);
+    assert_eq!(client.message(b"third").await.unwrap(), b"third");
+    assert_eq!(client.client.handshakes, 2);
+}
@@ -133,6 +133,7 @@ fn run_client(request: &str, expected_response: &str, iterations: usize) -> Step
                 command: Cmd::new(
                     CLIENT_PATH,
                     vec![
+                        "--skip-endorsement-verification".to_string(),
                         format!("--request={}", request),
                         format!("--expected-response-pattern={}", expected_response),
                         format!("--iterations={}", iterations),
@@ -141,7 +142,10 @@ fn run_client(request: &str, expected_response: &str, iterations: usize) -> Step
             },
             Step::Single {
                 name: "run client with a large message".to_string(),
-                command: Cmd::new(CLIENT_PATH, vec!["--test-large-message"]),
+                command: Cmd::new(
+                    CLIENT_PATH,
+                    vec!["--skip-endorsement-verification", "--test-large-message"],
+                ),
             },
         ],
     }